@@ -0,0 +1,85 @@
+//! Count heap allocations and time per embed call, to track allocation churn
+//! on the hot path. Run it on two revisions to compare them.
+//!
+//! The last two lines embed a 1k-document corpus of mixed lengths in one
+//! call, as a Vec per document and as one flat buffer.
+//!
+//! cargo run --release --example allocations -- [model.onnx] [tokenizer]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use arrow_embed::Embedder;
+
+const CALLS: usize = 1000;
+const BATCH: usize = 32;
+/// Documents in the corpus batch comparing embed_batch with embed_batch_flat
+const CORPUS: usize = 1000;
+
+/// System allocator that counts what passes through it
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() -> Result<(), arrow_embed::EmbedError> {
+    let mut args = std::env::args().skip(1);
+    let model = args.next().unwrap_or_else(|| "models/all-MiniLM-L6-v2.onnx".to_string());
+    let tokenizer = args
+        .next()
+        .unwrap_or_else(|| "sentence-transformers/all-MiniLM-L6-v2".to_string());
+
+    let mut embedder = Embedder::new(&model, &tokenizer)?;
+    embedder.warmup(1)?;
+    let text = "a short query of the kind a search service embeds thousands of times a second";
+    let batch = vec![text; BATCH];
+
+    measure("embed", CALLS, || embedder.embed(text).map(drop))?;
+    measure("embed_batch x32", CALLS / BATCH, || embedder.embed_batch(&batch).map(drop))?;
+
+    let corpus: Vec<String> = (0..CORPUS)
+        .map(|i| format!("document {i} of a corpus indexed at once, {}", "word ".repeat(i % 40)))
+        .collect();
+    let corpus: Vec<&str> = corpus.iter().map(String::as_str).collect();
+    measure("embed_batch 1k", 1, || embedder.embed_batch(&corpus).map(drop))?;
+    measure("embed_batch_flat 1k", 1, || embedder.embed_batch_flat(&corpus).map(drop))?;
+    Ok(())
+}
+
+fn measure(
+    name: &str,
+    calls: usize,
+    mut call: impl FnMut() -> Result<(), arrow_embed::EmbedError>,
+) -> Result<(), arrow_embed::EmbedError> {
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed));
+    let start = Instant::now();
+    for _ in 0..calls {
+        call()?;
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<20} {:>8.1} allocs/call {:>10.0} bytes/call {:>8.3}ms/call",
+        name,
+        (ALLOCATIONS.load(Ordering::Relaxed) - allocations) as f64 / calls as f64,
+        (BYTES.load(Ordering::Relaxed) - bytes) as f64 / calls as f64,
+        elapsed.as_secs_f64() * 1000.0 / calls as f64
+    );
+    Ok(())
+}
@@ -0,0 +1,46 @@
+//! Serve embeddings over HTTP from an axum handler without blocking the
+//! runtime: POST text to /embed and get the embedding back as a JSON array.
+//!
+//! cargo run --release --features async --example axum_server -- [model.onnx] [tokenizer]
+//! curl -d "hello world" localhost:3000/embed
+
+use std::sync::Arc;
+
+use arrow_embed::{AsyncEmbedder, Embedder};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::{Json, Router};
+
+/// Requests allowed to wait for the model before handlers are held back
+const QUEUE_CAPACITY: usize = 256;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let model = args.next().unwrap_or_else(|| "models/all-MiniLM-L6-v2.onnx".to_string());
+    let tokenizer = args
+        .next()
+        .unwrap_or_else(|| "sentence-transformers/all-MiniLM-L6-v2".to_string());
+
+    let embedder = Embedder::new(&model, &tokenizer)?;
+    let embedder = Arc::new(AsyncEmbedder::with_capacity(embedder, QUEUE_CAPACITY)?);
+    let app = Router::new().route("/embed", post(embed)).with_state(embedder);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("listening on {}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn embed(
+    State(embedder): State<Arc<AsyncEmbedder>>,
+    text: String,
+) -> Result<Json<Vec<f32>>, (StatusCode, String)> {
+    // A client that disconnects drops this future, cancelling its request
+    embedder
+        .embed_async(text)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+}
@@ -0,0 +1,43 @@
+//! Compare the latency of the first real embedding with and without warmup.
+//!
+//! cargo run --release --example first_call_latency -- [model.onnx] [tokenizer]
+
+use std::time::{Duration, Instant};
+
+use arrow_embed::Embedder;
+
+const RUNS: usize = 20;
+
+fn main() -> Result<(), arrow_embed::EmbedError> {
+    let mut args = std::env::args().skip(1);
+    let model = args.next().unwrap_or_else(|| "models/all-MiniLM-L6-v2.onnx".to_string());
+    let tokenizer = args
+        .next()
+        .unwrap_or_else(|| "sentence-transformers/all-MiniLM-L6-v2".to_string());
+
+    for warmup in [false, true] {
+        let mut first_calls = Vec::with_capacity(RUNS);
+        for _ in 0..RUNS {
+            let mut embedder = Embedder::new(&model, &tokenizer)?;
+            if warmup {
+                embedder.warmup(1)?;
+            }
+            let start = Instant::now();
+            embedder.embed("the first real query after startup")?;
+            first_calls.push(start.elapsed());
+        }
+        first_calls.sort_unstable();
+
+        println!(
+            "warmup={:<5} p50={:>8.3}ms p99={:>8.3}ms",
+            warmup,
+            millis(first_calls[RUNS / 2]),
+            millis(first_calls[(RUNS * 99 / 100).min(RUNS - 1)])
+        );
+    }
+    Ok(())
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,47 @@
+//! Measure embedding throughput at different intra-op thread counts.
+//!
+//! cargo run --release --example thread_scaling -- [model.onnx] [tokenizer]
+
+use std::time::Instant;
+
+use arrow_embed::{Embedder, EmbedderOptions};
+
+const ITERATIONS: usize = 200;
+
+fn main() -> Result<(), arrow_embed::EmbedError> {
+    let mut args = std::env::args().skip(1);
+    let model = args.next().unwrap_or_else(|| "models/all-MiniLM-L6-v2.onnx".to_string());
+    let tokenizer = args
+        .next()
+        .unwrap_or_else(|| "sentence-transformers/all-MiniLM-L6-v2".to_string());
+    let text = "the quick brown fox jumps over the lazy dog ".repeat(8);
+
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut thread_counts = vec![1, 2, 4, cores];
+    thread_counts.sort_unstable();
+    thread_counts.dedup();
+
+    println!("{:>8} {:>12} {:>12}", "threads", "ms/embed", "embeds/sec");
+    for threads in thread_counts {
+        let options = EmbedderOptions {
+            intra_threads: threads,
+            ..Default::default()
+        };
+        let mut embedder = Embedder::with_options(&model, &tokenizer, options)?;
+        embedder.embed(&text)?; // Keep first-call allocation out of the timing
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            embedder.embed(&text)?;
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+
+        println!(
+            "{:>8} {:>12.3} {:>12.1}",
+            threads,
+            elapsed * 1000.0 / ITERATIONS as f64,
+            ITERATIONS as f64 / elapsed
+        );
+    }
+    Ok(())
+}
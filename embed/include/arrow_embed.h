@@ -11,17 +11,385 @@
 #include <ostream>
 #include <new>
 
-/// Embedding dimension for all-MiniLM-L6-v2
+/// Embedding dimension for all-MiniLM-L6-v2; loaded models report their own
+/// through [`Embedder::dim`]
 constexpr static const uintptr_t EMBEDDING_DIM = 384;
 
+/// Default maximum sequence length, matching the all-MiniLM-L6-v2 training
+/// config (the model's position table itself allows up to 512)
+constexpr static const uintptr_t DEFAULT_MAX_SEQ_LEN = 256;
+
+/// Success
+constexpr static const int32_t ERROR_OK = 0;
+
+/// A required pointer argument was null
+constexpr static const int32_t ERROR_NULL_POINTER = -1;
+
+/// A string argument was not valid UTF-8
+constexpr static const int32_t ERROR_INVALID_UTF8 = -2;
+
+/// A previous panic poisoned a lock. No longer returned: index, corpus and
+/// embedder handles recover from a panic and keep working
+constexpr static const int32_t ERROR_LOCK_POISONED = -3;
+
+/// arrow_embed_init() has not been called successfully
+constexpr static const int32_t ERROR_NOT_INITIALIZED = -4;
+
+/// The ONNX model could not be loaded
+constexpr static const int32_t ERROR_MODEL_LOAD = -5;
+
+/// The tokenizer could not be loaded from the HuggingFace Hub
+constexpr static const int32_t ERROR_TOKENIZER_LOAD = -6;
+
+/// A tokenizer file path was given but nothing exists there
+constexpr static const int32_t ERROR_TOKENIZER_NOT_FOUND = -7;
+
+/// The tokenizer file exists, or tokenizer JSON was given, but could not
+/// be parsed
+constexpr static const int32_t ERROR_INVALID_TOKENIZER = -8;
+
+/// An option value passed to an init function is out of range
+constexpr static const int32_t ERROR_INVALID_OPTION = -9;
+
+/// Text has more tokens than allowed in strict mode
+constexpr static const int32_t ERROR_INPUT_TOO_LONG = -10;
+
+/// The tokenizer failed to encode the text
+constexpr static const int32_t ERROR_TOKENIZATION = -11;
+
+/// ONNX Runtime failed while running the model
+constexpr static const int32_t ERROR_INFERENCE = -12;
+
+/// The model produced an output of unexpected shape
+constexpr static const int32_t ERROR_SHAPE_MISMATCH = -13;
+
+/// An argument was present but unusable
+constexpr static const int32_t ERROR_INVALID_INPUT = -14;
+
+/// A handle was never returned by arrow_embed_create() or was already destroyed
+constexpr static const int32_t ERROR_INVALID_HANDLE = -15;
+
+/// A vector's length does not match the index dimension
+constexpr static const int32_t ERROR_DIMENSION_MISMATCH = -16;
+
+/// A caller-provided output buffer cannot hold the embedding
+constexpr static const int32_t ERROR_BUFFER_TOO_SMALL = -17;
+
+/// Reading or writing a file failed
+constexpr static const int32_t ERROR_IO = -18;
+
+/// An index or corpus file was written by an unsupported format version
+constexpr static const int32_t ERROR_INDEX_VERSION = -19;
+
+/// An index or corpus file is truncated or not such a file at all
+constexpr static const int32_t ERROR_CORRUPT_INDEX = -20;
+
+/// Writing embeddings in an export format failed
+constexpr static const int32_t ERROR_EXPORT = -21;
+
+/// Rust code panicked; the panic was caught at the FFI boundary
+constexpr static const int32_t ERROR_PANIC = -22;
+
+/// The text is empty or tokenizes to nothing but special tokens
+constexpr static const int32_t ERROR_EMPTY_INPUT = -23;
+
+/// Too much of the text is outside the tokenizer's vocabulary to embed
+/// meaningfully
+constexpr static const int32_t ERROR_TOO_MANY_UNKNOWN = -24;
+
+/// ONNX Runtime itself could not be set up, before any model was read
+constexpr static const int32_t ERROR_RUNTIME_INIT = -25;
+
+/// Inference did not finish within the timeout and was stopped
+constexpr static const int32_t ERROR_TIMEOUT = -26;
+
+/// The call was cancelled through its cancel token
+constexpr static const int32_t ERROR_CANCELLED = -27;
+
+/// `provider` values accepted by arrow_embed_init_ex()
+constexpr static const int32_t EXECUTION_PROVIDER_CPU = 0;
+
+constexpr static const int32_t EXECUTION_PROVIDER_CUDA = 1;
+
+constexpr static const int32_t EXECUTION_PROVIDER_COREML = 2;
+
+constexpr static const int32_t EXECUTION_PROVIDER_TENSORRT = 3;
+
+/// `pooling` values accepted by arrow_embed_init_ex()
+constexpr static const int32_t POOLING_MEAN = 0;
+
+constexpr static const int32_t POOLING_CLS = 1;
+
+constexpr static const int32_t POOLING_MAX = 2;
+
+/// `normalize` values accepted by arrow_embed_init_ex()
+constexpr static const int32_t NORMALIZE_NONE = 0;
+
+constexpr static const int32_t NORMALIZE_L2 = 1;
+
+constexpr static const int32_t NORMALIZE_L1 = 2;
+
+/// `flags` values accepted by arrow_embed_text_len()
+constexpr static const uint32_t TEXT_UTF8_STRICT = 0;
+
+constexpr static const uint32_t TEXT_UTF8_LOSSY = 1;
+
+/// `aggregation` values accepted by arrow_embed_text_long()
+constexpr static const int32_t CHUNK_AGGREGATION_MEAN = 0;
+
+constexpr static const int32_t CHUNK_AGGREGATION_ALL = 1;
+
+/// `kind` values accepted by arrow_embed_count_tokens_as()
+constexpr static const int32_t EMBED_KIND_RAW = 0;
+
+constexpr static const int32_t EMBED_KIND_QUERY = 1;
+
+constexpr static const int32_t EMBED_KIND_DOCUMENT = 2;
+
+/// `optimization_level` values accepted in ArrowEmbedOptions
+constexpr static const int32_t GRAPH_OPTIMIZATION_DEFAULT = 0;
+
+constexpr static const int32_t GRAPH_OPTIMIZATION_DISABLE = 1;
+
+constexpr static const int32_t GRAPH_OPTIMIZATION_BASIC = 2;
+
+constexpr static const int32_t GRAPH_OPTIMIZATION_EXTENDED = 3;
+
+constexpr static const int32_t GRAPH_OPTIMIZATION_ALL = 4;
+
+/// Most a similarity may move from its reference, or a batched embedding
+/// from the same text embedded alone, for [`SelfTestReport::passed`]
+constexpr static const float SELF_TEST_TOLERANCE = 1e-3;
+
+/// The POOLING_* values as an enum, for ArrowEmbedOptions.pooling
+enum class ArrowEmbedPooling : int32_t {
+  Mean = POOLING_MEAN,
+  Cls = POOLING_CLS,
+  Max = POOLING_MAX,
+};
+
+/// The EXECUTION_PROVIDER_* values as an enum, for ArrowEmbedOptions.provider.
+///
+/// The field itself stays an int32_t so an out-of-range value from C is
+/// rejected with ERROR_INVALID_OPTION rather than being undefined behavior.
+enum class ArrowEmbedProvider : int32_t {
+  Cpu = EXECUTION_PROVIDER_CPU,
+  Cuda = EXECUTION_PROVIDER_CUDA,
+  CoreMl = EXECUTION_PROVIDER_COREML,
+  TensorRt = EXECUTION_PROVIDER_TENSORRT,
+};
+
+/// Opaque handle to a binary index created with arrow_binary_index_create()
+///
+/// Searches may run concurrently; adds wait for them.
+struct ArrowBinaryIndex;
+
+/// Opaque handle to a corpus created with arrow_corpus_new()
+///
+/// Searches may run concurrently; adds wait for them.
+struct ArrowCorpus;
+
+/// Opaque handle to an embedder created with arrow_embed_create()
+///
+/// Each handle owns its own model session behind its own lock.
+struct ArrowEmbedder;
+
+/// Opaque handle to a vector index created with arrow_index_create()
+///
+/// Searches may run concurrently; adds wait for them.
+struct ArrowIndex;
+
+/// Opaque handle to a cross-encoder created with arrow_rerank_create()
+///
+/// Calls on one handle take turns; use one handle per thread to score in
+/// parallel.
+struct ArrowReranker;
+
 /// Result returned to C/C++ containing the embedding vector
 struct EmbeddingResult {
   /// Pointer to embedding data (caller must free with free_embedding)
   float *data;
-  /// Length of the embedding vector (384 for MiniLM)
+  /// Length of the embedding vector, the model's hidden size (384 for MiniLM)
+  uintptr_t len;
+  /// Error code: 0 = success, non-zero = error
+  int32_t error_code;
+};
+
+/// Result returned to C/C++ containing a half-precision embedding vector
+struct EmbeddingF16Result {
+  /// Pointer to IEEE 754 binary16 values (caller must free with
+  /// arrow_embed_free_f16)
+  uint16_t *data;
+  /// Length of the embedding vector, the model's hidden size (384 for MiniLM)
   uintptr_t len;
   /// Error code: 0 = success, non-zero = error
   int32_t error_code;
 };
 
+/// Result returned to C/C++ containing an int8-quantized embedding vector
+struct EmbeddingInt8Result {
+  /// Pointer to quantized components (caller must free with
+  /// arrow_embed_free_int8)
+  int8_t *data;
+  /// Length of the embedding vector, the model's hidden size (384 for MiniLM)
+  uintptr_t len;
+  /// Error code: 0 = success, non-zero = error
+  int32_t error_code;
+};
+
+/// Result returned to C/C++ containing a batch of embedding vectors
+struct EmbeddingBatchResult {
+  /// Pointer to `count * dim` floats, one embedding after another
+  /// (caller must free with arrow_embed_free_batch)
+  float *data;
+  /// Number of embeddings in the batch
+  uintptr_t count;
+  /// Length of each embedding vector, the model's hidden size (384 for MiniLM)
+  uintptr_t dim;
+  /// Error code: 0 = success, non-zero = error
+  int32_t error_code;
+};
+
+/// Options for arrow_embed_init_opts() and arrow_embed_init_with_options()
+///
+/// Start from arrow_embed_options_default(), which sets `struct_size`, so
+/// fields added later keep their defaults. New fields are only ever added
+/// at the end: the library reads the first `struct_size` bytes, so callers
+/// built against an older header keep working.
+struct ArrowEmbedOptions {
+  /// sizeof(ArrowEmbedOptions) as the caller was compiled
+  uintptr_t struct_size;
+  /// Path to the ONNX model file, for arrow_embed_init_opts()
+  const char *model_path;
+  /// HuggingFace tokenizer name or path to a local tokenizer.json, for
+  /// arrow_embed_init_opts()
+  const char *tokenizer_path;
+  /// Maximum tokens per text, 0 for DEFAULT_MAX_SEQ_LEN
+  uintptr_t max_seq_len;
+  /// Non-zero to reject longer texts with ERROR_INPUT_TOO_LONG instead of truncating
+  int32_t strict;
+  /// One of the EXECUTION_PROVIDER_* values
+  int32_t provider;
+  /// GPU device for EXECUTION_PROVIDER_CUDA, ignored otherwise
+  int32_t device_id;
+  /// One of the POOLING_* values
+  int32_t pooling;
+  /// One of the NORMALIZE_* values; NORMALIZE_NONE returns embeddings as
+  /// pooled
+  int32_t normalize;
+  /// Threads ONNX Runtime uses within one operator, 0 for ONNX Runtime's
+  /// default of one per physical core; a negative count means one
+  int32_t intra_threads;
+  /// One of the GRAPH_OPTIMIZATION_* values
+  int32_t optimization_level;
+  /// Non-zero for bit-identical embeddings across runs: one thread and
+  /// deterministic kernels, overriding both thread counts
+  int32_t deterministic;
+  /// Model sessions the global embedder keeps, so that many threads can
+  /// embed at once and arrow_embed_text_batch_parallel() can split one
+  /// batch across them; 0 for one
+  uintptr_t num_sessions;
+  /// Prepended to every arrow_embed_query() text, e.g. "query: "; null for none
+  const char *query_prefix;
+  /// Prepended to every arrow_embed_passage() text, e.g. "passage: "; null for none
+  const char *passage_prefix;
+  /// Threads ONNX Runtime uses to run independent operators at the same
+  /// time, 0 for ONNX Runtime's default of running them one after
+  /// another; a negative count means one
+  int32_t inter_threads;
+  /// Embeddings of recently seen texts each session keeps so repeats skip
+  /// inference; 0 to disable caching
+  uintptr_t cache_capacity;
+  /// Non-zero to trim texts and collapse runs of whitespace to one space
+  /// before tokenizing
+  int32_t trim_input;
+  /// Largest fraction of a text's tokens that may be the tokenizer's
+  /// unknown token before embedding fails with ERROR_TOO_MANY_UNKNOWN;
+  /// 1.0 to accept anything
+  float max_unknown_fraction;
+  /// Length the model's embeddings must have, so a model of another size
+  /// fails at init with ERROR_DIMENSION_MISMATCH; 0 to accept any
+  uintptr_t expected_dim;
+  /// Pad every sequence to exactly this many tokens, for models exported
+  /// with a static sequence length, overriding `max_seq_len`; 0 to pad a
+  /// batch only to its longest sequence
+  uintptr_t fixed_seq_len;
+  /// Keep only the first this many dimensions of each embedding, then
+  /// renormalize, for Matryoshka-trained models; at most the model's
+  /// hidden size, 0 to keep every dimension. arrow_embed_dimension()
+  /// reports this size
+  uintptr_t output_dim;
+  /// Cap ONNX Runtime's CPU arena at this many bytes, growing it only by
+  /// what each allocation needs; shared by every session in the process,
+  /// so only the first init sets it. 0 leaves the arena unbounded
+  uintptr_t max_memory_bytes;
+  /// Non-zero to allocate per run instead of keeping an arena: slightly
+  /// slower, with a lower peak resident size. Not allowed with
+  /// `max_memory_bytes`
+  int32_t disable_cpu_arena;
+  /// Stop any inference pass still running after this many milliseconds,
+  /// failing the call with ERROR_TIMEOUT, e.g. to keep a batch within a
+  /// request budget; 0 lets passes run to the end
+  uint64_t timeout_ms;
+  /// Times a HuggingFace Hub tokenizer download that failed transiently
+  /// is retried, waiting 100ms, then 200ms, 400ms and so on; 3 by default,
+  /// 0 to fail on the first error. A missing repo is never retried
+  uint32_t tokenizer_retries;
+};
+
+/// Embedding cache counters filled in by arrow_embed_stats()
+struct ArrowEmbedStats {
+  /// Texts answered from the cache
+  uint64_t hits;
+  /// Texts looked up but not cached, so embedded by the model
+  uint64_t misses;
+  /// Embeddings currently cached
+  uintptr_t entries;
+  /// Most embeddings the cache holds, 0 when caching is disabled
+  uintptr_t capacity;
+};
+
+/// Self-test results filled in by arrow_embed_self_test()
+struct ArrowEmbedSelfTest {
+  /// Reference pairs compared; 0 if none are bundled
+  uintptr_t pairs;
+  /// Largest difference between a pair's cosine similarity and the
+  /// all-MiniLM-L6-v2 reference
+  float max_deviation;
+  /// Largest difference in any dimension between a text embedded in a
+  /// batch and on its own
+  float batch_deviation;
+  /// Bound both deviations are held to
+  float tolerance;
+  /// 1 if there were pairs and both deviations are within `tolerance`
+  int32_t passed;
+};
+
+/// Model description filled in by arrow_embed_model_info()
+///
+/// The strings belong to the caller; free each with arrow_embed_free_string().
+/// arrow_embed_model_io() describes the graph's inputs and outputs as text.
+struct ArrowEmbedModelInfo {
+  /// File name of the model without its extension, e.g. "all-MiniLM-L6-v2"
+  char *name;
+  /// Length of the vectors the model produces, matching EmbeddingResult.len
+  uintptr_t dim;
+  /// Most tokens fed to the model per text
+  uintptr_t max_seq_len;
+  /// Graph input names separated by commas, e.g. "input_ids,attention_mask"
+  char *input_names;
+  /// Graph output the embeddings are read from
+  char *output_name;
+  /// Non-zero if the model reads its embeddings from a pooled
+  /// sentence_embedding output rather than pooling token vectors
+  int32_t pooled_by_model;
+  /// Non-zero if the model's file name or description marks it as quantized
+  int32_t quantized;
+};
+
+/// Called by arrow_embed_text_batch_cb() with the number of texts embedded
+/// so far, the batch size and the caller's `user_data`; returning non-zero
+/// cancels the batch
+using ArrowEmbedProgressCallback = int32_t(*)(uintptr_t done, uintptr_t total, void *user_data);
+
 #endif  // ARROW_EMBED_H
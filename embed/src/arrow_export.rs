@@ -0,0 +1,177 @@
+//! Export embeddings as Arrow record batches and IPC files (`arrow` feature)
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, StringArray};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+
+use crate::error::EmbedError;
+
+/// Schema with a non-null `id` string column and an `embedding` column of
+/// `FixedSizeList<Float32, dim>`
+pub fn embedding_schema(dim: usize) -> SchemaRef {
+    let item = Arc::new(Field::new("item", DataType::Float32, false));
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("embedding", DataType::FixedSizeList(item, dim as i32), false),
+    ]))
+}
+
+/// Build a record batch pairing each id with its embedding.
+///
+/// All vectors must have the same length; it becomes the list size. The
+/// batch can go straight to any Arrow writer, such as Parquet's `ArrowWriter`.
+#[doc(alias = "embeddings_to_record_batch")]
+pub fn embeddings_to_arrow(
+    ids: &[String],
+    vectors: &[Vec<f32>],
+) -> Result<RecordBatch, EmbedError> {
+    let dim = vectors.first().map_or(crate::EMBEDDING_DIM, Vec::len);
+    batch_with_dim(ids, vectors, dim)
+}
+
+fn batch_with_dim(
+    ids: &[String],
+    vectors: &[Vec<f32>],
+    dim: usize,
+) -> Result<RecordBatch, EmbedError> {
+    if ids.len() != vectors.len() {
+        return Err(EmbedError::InvalidInput(format!(
+            "{} ids for {} vectors",
+            ids.len(),
+            vectors.len()
+        )));
+    }
+    if let Some(bad) = vectors.iter().find(|v| v.len() != dim) {
+        return Err(EmbedError::DimensionMismatch {
+            expected: dim,
+            actual: bad.len(),
+        });
+    }
+
+    let schema = embedding_schema(dim);
+    let DataType::FixedSizeList(item, size) = schema.field(1).data_type().clone() else {
+        unreachable!("embedding_schema always builds a FixedSizeList column");
+    };
+    let values = Float32Array::from_iter_values(vectors.iter().flatten().copied());
+    let embeddings = FixedSizeListArray::try_new(item, size, Arc::new(values), None)
+        .map_err(arrow_error)?;
+    let ids = StringArray::from_iter_values(ids);
+
+    RecordBatch::try_new(schema, vec![Arc::new(ids) as ArrayRef, Arc::new(embeddings)])
+        .map_err(arrow_error)
+}
+
+/// Streams batches of embeddings into an Arrow IPC file, so only one batch
+/// needs to be in memory at a time.
+pub struct EmbeddingIpcWriter {
+    writer: FileWriter<File>,
+    dim: usize,
+}
+
+impl EmbeddingIpcWriter {
+    /// Create `path` for embeddings of `dim` floats.
+    pub fn create(path: impl AsRef<Path>, dim: usize) -> Result<Self, EmbedError> {
+        let file = File::create(path).map_err(|e| EmbedError::Io(e.to_string()))?;
+        let writer = FileWriter::try_new(file, &embedding_schema(dim)).map_err(arrow_error)?;
+        Ok(EmbeddingIpcWriter { writer, dim })
+    }
+
+    /// Append one batch of rows.
+    pub fn write(&mut self, ids: &[String], vectors: &[Vec<f32>]) -> Result<(), EmbedError> {
+        let batch = batch_with_dim(ids, vectors, self.dim)?;
+        self.write_batch(&batch)
+    }
+
+    /// Append a batch built by [`embeddings_to_arrow`].
+    pub fn write_batch(&mut self, batch: &RecordBatch) -> Result<(), EmbedError> {
+        self.writer.write(batch).map_err(arrow_error)
+    }
+
+    /// Write the file footer; the file is unreadable without it.
+    pub fn finish(mut self) -> Result<(), EmbedError> {
+        self.writer.finish().map_err(arrow_error)
+    }
+}
+
+/// Write `batches` to an Arrow IPC file at `path`.
+///
+/// The schema is taken from the first batch; an empty iterator writes a
+/// file with the EMBEDDING_DIM schema and no rows.
+pub fn write_embeddings_ipc(
+    path: impl AsRef<Path>,
+    batches: impl IntoIterator<Item = RecordBatch>,
+) -> Result<(), EmbedError> {
+    let mut batches = batches.into_iter().peekable();
+    let dim = match batches.peek().map(|b| b.schema().field(1).data_type().clone()) {
+        Some(DataType::FixedSizeList(_, size)) => size as usize,
+        _ => crate::EMBEDDING_DIM,
+    };
+
+    let mut writer = EmbeddingIpcWriter::create(path, dim)?;
+    for batch in batches {
+        writer.write_batch(&batch)?;
+    }
+    writer.finish()
+}
+
+fn arrow_error(e: ArrowError) -> EmbedError {
+    EmbedError::Export(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+
+    #[test]
+    fn batch_has_id_and_fixed_size_embedding_columns() {
+        let ids = vec!["a".to_string(), "b".to_string()];
+        let vectors = random_unit_vectors(2, 384, 1);
+
+        let batch = embeddings_to_arrow(&ids, &vectors).unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        let item = Arc::new(Field::new("item", DataType::Float32, false));
+        assert_eq!(batch.schema().field(1).data_type(), &DataType::FixedSizeList(item, 384));
+        let embeddings = batch.column(1).as_any().downcast_ref::<FixedSizeListArray>().unwrap();
+        let second = embeddings.value(1);
+        let second = second.as_any().downcast_ref::<Float32Array>().unwrap();
+        assert_eq!(second.values().as_ref(), vectors[1].as_slice());
+        let read_ids = batch.column(0).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(read_ids.value(1), "b");
+    }
+
+    #[test]
+    fn ragged_vectors_are_rejected() {
+        let ids = vec!["a".to_string(), "b".to_string()];
+        let vectors = vec![vec![0.0; 4], vec![0.0; 3]];
+
+        let err = embeddings_to_arrow(&ids, &vectors).unwrap_err();
+
+        assert!(matches!(err, EmbedError::DimensionMismatch { expected: 4, actual: 3 }));
+    }
+
+    #[test]
+    fn ipc_file_round_trips() {
+        let path = temp_path("embeddings.arrow");
+        let ids: Vec<String> = (0..5).map(|i| format!("doc-{}", i)).collect();
+        let vectors = random_unit_vectors(5, 8, 3);
+        let batches = vec![
+            embeddings_to_arrow(&ids[..3], &vectors[..3]).unwrap(),
+            embeddings_to_arrow(&ids[3..], &vectors[3..]).unwrap(),
+        ];
+
+        write_embeddings_ipc(&path, batches.clone()).unwrap();
+
+        let reader = FileReader::try_new(File::open(&path).unwrap(), None).unwrap();
+        let read: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, batches);
+    }
+}
@@ -0,0 +1,361 @@
+//! Async front end for an [`Embedder`] running on its own thread (`async` feature)
+
+use std::future::{Future, poll_fn};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+
+use futures_channel::oneshot;
+
+use crate::cancel::CancelToken;
+use crate::embedder::Embedder;
+use crate::error::EmbedError;
+use crate::pool::lock_recovering;
+
+/// Requests [`AsyncEmbedder::new`] lets wait before callers are held back
+const DEFAULT_QUEUE_CAPACITY: usize = 64;
+
+/// Work for the embedding thread; it replies through a channel it captured
+type Job = Box<dyn FnOnce(&mut Embedder) + Send>;
+
+/// Bounded queue of jobs, shared by the embedder, its futures and the worker
+struct Queue {
+    /// Taken when the embedder is dropped, which stops the worker
+    jobs: Mutex<Option<mpsc::SyncSender<Job>>>,
+    /// Futures waiting for room in `jobs`
+    waiting: Mutex<Vec<Waker>>,
+}
+
+impl Queue {
+    /// Queue `job` if there is room, taking it out of the option.
+    fn try_push(&self, job: &mut Option<Job>) -> Result<bool, EmbedError> {
+        let jobs = lock_recovering(&self.jobs);
+        let Some(jobs) = jobs.as_ref() else {
+            return Err(worker_stopped());
+        };
+        match jobs.try_send(job.take().expect("job already queued")) {
+            Ok(()) => Ok(true),
+            Err(TrySendError::Full(full)) => {
+                *job = Some(full);
+                Ok(false)
+            }
+            Err(TrySendError::Disconnected(_)) => Err(worker_stopped()),
+        }
+    }
+
+    /// Queue `job`, or wait to be woken when a slot frees up.
+    fn poll_push(
+        &self,
+        cx: &mut Context<'_>,
+        job: &mut Option<Job>,
+    ) -> Poll<Result<(), EmbedError>> {
+        if self.try_push(job)? {
+            return Poll::Ready(Ok(()));
+        }
+        lock_recovering(&self.waiting).push(cx.waker().clone());
+        // The worker may have freed a slot before the waker was registered
+        if self.try_push(job)? {
+            return Poll::Ready(Ok(()));
+        }
+        Poll::Pending
+    }
+
+    /// Let every waiting future retry; those that miss out wait again
+    fn wake_waiting(&self) {
+        let waiting = std::mem::take(&mut *lock_recovering(&self.waiting));
+        waiting.into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Embeds text on a dedicated OS thread that owns the model session, so
+/// async tasks await results instead of blocking a runtime worker on
+/// inference.
+///
+/// Works with any executor; results come back over a oneshot channel.
+///
+/// # Backpressure
+///
+/// At most `capacity` requests wait for the worker. Beyond that, futures
+/// stay pending until a queued request starts, so a flood of callers slows
+/// down instead of queuing without bound.
+///
+/// # Ordering and cancellation
+///
+/// Requests run one at a time. They are queued when
+/// [`embed_async`](Self::embed_async) is called if there is room, in call
+/// order, and otherwise when a slot frees up while the future is polled.
+/// Each future resolves when its own request finishes, so futures may be
+/// awaited in any order. Dropping a future cancels its request unless it
+/// has already started running.
+pub struct AsyncEmbedder {
+    queue: Arc<Queue>,
+    worker: Option<JoinHandle<()>>,
+    dim: usize,
+}
+
+impl AsyncEmbedder {
+    /// Move `embedder` onto a new worker thread with room for 64 waiting
+    /// requests.
+    pub fn new(embedder: Embedder) -> Result<Self, EmbedError> {
+        Self::with_capacity(embedder, DEFAULT_QUEUE_CAPACITY)
+    }
+
+    /// Move `embedder` onto a new worker thread that lets up to `capacity`
+    /// requests wait, at least one.
+    pub fn with_capacity(mut embedder: Embedder, capacity: usize) -> Result<Self, EmbedError> {
+        let dim = embedder.dim();
+        let (jobs, received) = mpsc::sync_channel::<Job>(capacity.max(1));
+        let queue = Arc::new(Queue {
+            jobs: Mutex::new(Some(jobs)),
+            waiting: Mutex::new(Vec::new()),
+        });
+        let worker_queue = Arc::clone(&queue);
+        let worker = thread::Builder::new()
+            .name("arrow_embed".to_string())
+            .spawn(move || {
+                for job in received {
+                    worker_queue.wake_waiting();
+                    job(&mut embedder);
+                }
+            })
+            .map_err(|e| EmbedError::Io(format!("spawning embedding worker: {}", e)))?;
+
+        Ok(AsyncEmbedder {
+            queue,
+            worker: Some(worker),
+            dim,
+        })
+    }
+
+    /// Length of the embeddings this embedder produces
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Embed `text` on the worker thread.
+    ///
+    /// ```no_run
+    /// # async fn run() -> Result<(), arrow_embed::EmbedError> {
+    /// use arrow_embed::{AsyncEmbedder, Embedder};
+    ///
+    /// let embedder = Embedder::new(
+    ///     "models/all-MiniLM-L6-v2.onnx",
+    ///     "sentence-transformers/all-MiniLM-L6-v2",
+    /// )?;
+    /// let embedder = AsyncEmbedder::new(embedder)?;
+    /// let embedding = embedder.embed_async("hello world".to_string()).await?;
+    /// assert_eq!(embedding.len(), embedder.dim());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn embed_async(
+        &self,
+        text: String,
+    ) -> impl Future<Output = Result<Vec<f32>, EmbedError>> + Send + 'static {
+        self.submit(move |embedder| embedder.embed(&text))
+    }
+
+    /// Embed several texts in one inference pass on the worker thread.
+    pub fn embed_batch_async(
+        &self,
+        texts: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<Vec<f32>>, EmbedError>> + Send + 'static {
+        self.submit(move |embedder| {
+            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+            embedder.embed_batch(&texts)
+        })
+    }
+
+    /// Embed several texts in one inference pass on the worker thread,
+    /// stopping with [`EmbedError::Cancelled`] once `token` is cancelled.
+    ///
+    /// Unlike dropping the future, cancelling the token also stops the
+    /// request after it has started running.
+    pub fn embed_batch_cancellable_async(
+        &self,
+        texts: Vec<String>,
+        token: CancelToken,
+    ) -> impl Future<Output = Result<Vec<Vec<f32>>, EmbedError>> + Send + 'static {
+        self.submit(move |embedder| {
+            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+            embedder.embed_batch_cancellable(&texts, &token)
+        })
+    }
+
+    fn submit<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&mut Embedder) -> Result<T, EmbedError> + Send + 'static,
+    ) -> impl Future<Output = Result<T, EmbedError>> + Send + 'static {
+        let (reply, result) = oneshot::channel();
+        let mut job: Option<Job> = Some(Box::new(move |embedder| {
+            // The caller dropped its future while the job was queued
+            if reply.is_canceled() {
+                return;
+            }
+            // A panic fails this request only; the worker and its embedder
+            // carry on, since every call refills the input buffers
+            let result = panic::catch_unwind(AssertUnwindSafe(|| work(embedder)))
+                .unwrap_or_else(|_| Err(EmbedError::Inference("embedding panicked".to_string())));
+            let _ = reply.send(result);
+        }));
+        let queue = Arc::clone(&self.queue);
+        let queued = queue.try_push(&mut job);
+
+        async move {
+            if !queued? {
+                poll_fn(|cx| queue.poll_push(cx, &mut job)).await?;
+            }
+            result.await.unwrap_or_else(|_| Err(worker_stopped()))
+        }
+    }
+}
+
+impl Drop for AsyncEmbedder {
+    /// Finish the queued requests, then stop the worker thread.
+    fn drop(&mut self) {
+        lock_recovering(&self.queue.jobs).take();
+        // Futures still waiting for room fail instead of waiting forever
+        self.queue.wake_waiting();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The worker stops when the embedder is dropped
+fn worker_stopped() -> EmbedError {
+    EmbedError::Inference("embedding worker thread has stopped".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    /// Minimal executor: poll on this thread, parking until woken
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(thread::Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut context = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut context) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn full_queue_waits_until_woken() {
+        struct CountWakes(AtomicUsize);
+        impl Wake for CountWakes {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let (jobs, received) = mpsc::sync_channel::<Job>(1);
+        let queue = Queue {
+            jobs: Mutex::new(Some(jobs)),
+            waiting: Mutex::new(Vec::new()),
+        };
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut context = Context::from_waker(&waker);
+        let job = || -> Option<Job> { Some(Box::new(|_| {})) };
+
+        assert!(queue.try_push(&mut job()).unwrap());
+        let mut second = job();
+        assert!(queue.poll_push(&mut context, &mut second).is_pending());
+        assert!(second.is_some());
+
+        // The worker taking a job frees a slot and wakes the waiter
+        drop(received.recv().unwrap());
+        queue.wake_waiting();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert!(matches!(queue.poll_push(&mut context, &mut second), Poll::Ready(Ok(()))));
+        assert!(second.is_none());
+
+        queue.jobs.lock().unwrap().take();
+        assert!(queue.try_push(&mut job()).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn in_flight_requests_match_blocking_embed() {
+        let mut blocking = test_embedder();
+        let texts = ["first request", "second request", "third request"];
+        let expected: Vec<Vec<f32>> = texts.iter().map(|t| blocking.embed(t).unwrap()).collect();
+        let embedder = AsyncEmbedder::new(test_embedder()).unwrap();
+
+        let futures: Vec<_> = texts.iter().map(|t| embedder.embed_async(t.to_string())).collect();
+
+        // Awaiting out of order still pairs each future with its own text
+        for (future, expected) in futures.into_iter().zip(expected).rev() {
+            let embedding = block_on(future).unwrap();
+            assert_eq!(embedding.len(), embedder.dim());
+            for (a, b) in embedding.iter().zip(&expected) {
+                assert!((a - b).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn worker_survives_a_panicking_request() {
+        let embedder = AsyncEmbedder::new(test_embedder()).unwrap();
+
+        let panicked = block_on(embedder.submit(|_| -> Result<(), EmbedError> {
+            panic!("crafted failure inside an embed call")
+        }));
+
+        assert!(matches!(panicked, Err(EmbedError::Inference(m)) if m == "embedding panicked"));
+        let embedding = block_on(embedder.embed_async("still embedding".to_string())).unwrap();
+        assert_eq!(embedding.len(), embedder.dim());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn cancelling_stops_a_running_request() {
+        let embedder = AsyncEmbedder::new(test_embedder()).unwrap();
+        let texts = vec!["a long document to embed ".repeat(100); 64];
+        let token = CancelToken::new();
+
+        let future = embedder.embed_batch_cancellable_async(texts, token.clone());
+        thread::sleep(std::time::Duration::from_millis(20));
+        token.cancel();
+
+        assert!(matches!(block_on(future), Err(EmbedError::Cancelled)));
+        let embedding = block_on(embedder.embed_async("still embedding".to_string())).unwrap();
+        assert_eq!(embedding.len(), embedder.dim());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn more_requests_than_capacity_all_complete() {
+        let embedder = AsyncEmbedder::with_capacity(test_embedder(), 2).unwrap();
+        let texts: Vec<String> = (0..10).map(|i| format!("request {i}")).collect();
+
+        let futures: Vec<_> = texts.iter().map(|t| embedder.embed_async(t.clone())).collect();
+        // Dropped before running, so the worker skips it
+        drop(embedder.embed_async("cancelled".to_string()));
+        let batch = block_on(embedder.embed_batch_async(texts)).unwrap();
+
+        for (future, expected) in futures.into_iter().zip(batch) {
+            let embedding = block_on(future).unwrap();
+            for (a, b) in embedding.iter().zip(&expected) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+    }
+}
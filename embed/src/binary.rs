@@ -0,0 +1,255 @@
+//! Sign-quantized (1-bit) embeddings searched by Hamming distance
+
+use std::collections::BinaryHeap;
+
+use crate::error::EmbedError;
+use crate::similarity::cosine_similarity;
+
+/// Bits packed into each word of a binary code
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+/// Pack the signs of `vector` into bits, bit `i % 64` of word `i / 64` set
+/// when component `i` is positive.
+///
+/// A 384-dimensional embedding becomes 6 words, 48 bytes. When the length
+/// is not a multiple of 64 the unused high bits of the last word are zero,
+/// so they never add to a Hamming distance.
+pub fn binarize(vector: &[f32]) -> Vec<u64> {
+    let mut code = vec![0u64; vector.len().div_ceil(BITS_PER_WORD)];
+    for (i, &x) in vector.iter().enumerate() {
+        if x > 0.0 {
+            code[i / BITS_PER_WORD] |= 1 << (i % BITS_PER_WORD);
+        }
+    }
+    code
+}
+
+/// Number of bits that differ between two codes from [`binarize`].
+///
+/// Returns `u32::MAX`, further than any real pair, if the lengths differ.
+pub fn hamming_distance(a: &[u64], b: &[u64]) -> u32 {
+    if a.len() != b.len() {
+        return u32::MAX;
+    }
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// Stores binarized embeddings by id and finds the nearest ones to a query
+/// by Hamming distance, as a cheap first stage before exact scoring.
+///
+/// An index made with [`with_rescoring`](Self::with_rescoring) also keeps
+/// the f32 vectors, so [`search_rescored`](Self::search_rescored) can
+/// re-rank the best candidates by cosine similarity.
+#[derive(Debug, Clone)]
+pub struct BinaryIndex {
+    dim: usize,
+    ids: Vec<u64>,
+    /// Row-major, `ids.len() * words` words
+    codes: Vec<u64>,
+    /// Row-major f32 vectors for rescoring, if kept
+    vectors: Option<Vec<f32>>,
+}
+
+impl BinaryIndex {
+    /// Create an empty index for vectors of `dim` floats, keeping only
+    /// their binary codes.
+    pub fn new(dim: usize) -> Self {
+        BinaryIndex {
+            dim,
+            ids: Vec::new(),
+            codes: Vec::new(),
+            vectors: None,
+        }
+    }
+
+    /// Create an empty index that also keeps every vector as f32, 32 times
+    /// the memory of the codes, for [`search_rescored`](Self::search_rescored).
+    pub fn with_rescoring(dim: usize) -> Self {
+        BinaryIndex {
+            vectors: Some(Vec::new()),
+            ..Self::new(dim)
+        }
+    }
+
+    /// Length of the vectors this index accepts
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of stored vectors
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Store `vector` under `id`. Ids are not deduplicated.
+    pub fn add(&mut self, id: u64, vector: &[f32]) -> Result<(), EmbedError> {
+        self.check_dim(vector)?;
+        self.ids.push(id);
+        self.codes.extend(binarize(vector));
+        if let Some(vectors) = &mut self.vectors {
+            vectors.extend_from_slice(vector);
+        }
+        Ok(())
+    }
+
+    /// Find the `k` stored vectors whose codes are nearest to `query`'s,
+    /// nearest first, with their Hamming distances.
+    ///
+    /// Ties keep insertion order. Returns fewer than `k` results if the
+    /// index holds fewer vectors.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<(u64, u32)>, EmbedError> {
+        self.check_dim(query)?;
+        Ok(self
+            .nearest_rows(&binarize(query), k)
+            .into_iter()
+            .map(|(distance, row)| (self.ids[row], distance))
+            .collect())
+    }
+
+    /// Take the `candidates` vectors nearest to `query` by Hamming distance,
+    /// then return the `k` of them most similar to it by cosine similarity,
+    /// best first.
+    ///
+    /// Fails with [`EmbedError::InvalidInput`] unless the index was made
+    /// with [`with_rescoring`](Self::with_rescoring). `candidates` below `k`
+    /// is raised to `k`.
+    pub fn search_rescored(
+        &self,
+        query: &[f32],
+        k: usize,
+        candidates: usize,
+    ) -> Result<Vec<(u64, f32)>, EmbedError> {
+        self.check_dim(query)?;
+        let Some(vectors) = &self.vectors else {
+            return Err(EmbedError::InvalidInput(
+                "index keeps no f32 vectors to rescore with; create it with rescoring".to_string(),
+            ));
+        };
+
+        let mut rescored: Vec<(usize, f32)> = self
+            .nearest_rows(&binarize(query), candidates.max(k))
+            .into_iter()
+            .map(|(_, row)| {
+                let vector = &vectors[row * self.dim..(row + 1) * self.dim];
+                (row, cosine_similarity(query, vector))
+            })
+            .collect();
+        rescored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        rescored.truncate(k);
+        Ok(rescored.into_iter().map(|(row, score)| (self.ids[row], score)).collect())
+    }
+
+    /// Distance and row of the `k` codes nearest to `code`, nearest first
+    fn nearest_rows(&self, code: &[u64], k: usize) -> Vec<(u32, usize)> {
+        if k == 0 || code.is_empty() {
+            return Vec::new();
+        }
+        // Max-heap of the best k seen so far; its top is the one to evict
+        let mut best = BinaryHeap::with_capacity(k.min(self.len()) + 1);
+        for (row, stored) in self.codes.chunks_exact(code.len()).enumerate() {
+            best.push((hamming_distance(code, stored), row));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+        best.into_sorted_vec()
+    }
+
+    fn check_dim(&self, vector: &[f32]) -> Result<(), EmbedError> {
+        if vector.len() != self.dim {
+            return Err(EmbedError::DimensionMismatch {
+                expected: self.dim,
+                actual: vector.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ERROR_DIMENSION_MISMATCH, ERROR_INVALID_INPUT};
+    use crate::index::VectorIndex;
+    use crate::test_util::*;
+
+    #[test]
+    fn signs_are_packed_with_zero_padding() {
+        let mut vector = vec![-0.5f32; 70];
+        vector[0] = 0.1;
+        vector[63] = 2.0;
+        vector[64] = 0.3;
+        vector[69] = 1.0;
+
+        let code = binarize(&vector);
+
+        assert_eq!(code, vec![1 | 1 << 63, 1 | 1 << 5]);
+        assert_eq!(binarize(&[1.0; 384]).len(), 6);
+        // Every real bit flipped; the 58 padding bits still agree
+        let flipped: Vec<f32> = vector.iter().map(|x| -x).collect();
+        assert_eq!(hamming_distance(&code, &binarize(&flipped)), 70);
+    }
+
+    #[test]
+    fn hamming_distance_counts_differing_bits() {
+        assert_eq!(hamming_distance(&[0b1011, 0], &[0b0001, 1 << 40]), 3);
+        assert_eq!(hamming_distance(&[7], &[7]), 0);
+        assert_eq!(hamming_distance(&[7], &[7, 0]), u32::MAX);
+    }
+
+    #[test]
+    fn search_returns_nearest_codes_first() {
+        let mut index = BinaryIndex::new(4);
+        index.add(10, &[1.0, 1.0, 1.0, 1.0]).unwrap();
+        index.add(20, &[-1.0, -1.0, -1.0, -1.0]).unwrap();
+        index.add(30, &[1.0, 1.0, -1.0, 1.0]).unwrap();
+        index.add(40, &[1.0, 1.0, 1.0, -1.0]).unwrap();
+
+        let results = index.search(&[0.5, 0.2, 0.9, 0.1], 3).unwrap();
+
+        assert_eq!(results, vec![(10, 0), (30, 1), (40, 1)]);
+        assert_eq!(index.search(&[1.0; 4], 10).unwrap().len(), 4);
+        assert!(index.search(&[1.0; 4], 0).unwrap().is_empty());
+        assert!(BinaryIndex::new(4).search(&[1.0; 4], 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rescoring_recovers_the_exact_ranking() {
+        let vectors = random_unit_vectors(2000, 96, 5);
+        let mut binary = BinaryIndex::with_rescoring(96);
+        let mut exact = VectorIndex::new(96);
+        for (id, vector) in vectors.iter().enumerate() {
+            binary.add(id as u64, vector).unwrap();
+            exact.add(id as u64, vector).unwrap();
+        }
+
+        let mut agreeing = 0;
+        for query in random_unit_vectors(20, 96, 6) {
+            let expected = exact.search(&query, 10).unwrap();
+            let rescored = binary.search_rescored(&query, 10, 200).unwrap();
+            assert_eq!(rescored.len(), 10);
+            assert!(rescored.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+            agreeing += rescored.iter().filter(|r| expected.contains(r)).count();
+        }
+
+        // Out of 200; candidates outside the top Hamming 200 are missed
+        assert!(agreeing >= 150, "{}", agreeing);
+    }
+
+    #[test]
+    fn wrong_dimension_and_missing_vectors_are_rejected() {
+        let mut index = BinaryIndex::new(3);
+
+        let err = index.add(1, &[1.0, 0.0]).unwrap_err();
+        assert_eq!(err.code(), ERROR_DIMENSION_MISMATCH);
+        assert!(index.search(&[1.0; 4], 1).is_err());
+        assert!(index.is_empty());
+        index.add(1, &[1.0; 3]).unwrap();
+        let err = index.search_rescored(&[1.0; 3], 1, 10).unwrap_err();
+        assert_eq!(err.code(), ERROR_INVALID_INPUT);
+    }
+}
@@ -0,0 +1,142 @@
+//! Least-recently-used cache of embeddings keyed by text
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Hit and miss counts of an embedder's cache, from [`Embedder::stats`]
+///
+/// [`Embedder::stats`]: crate::Embedder::stats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Texts answered from the cache
+    pub hits: u64,
+    /// Texts looked up but not cached, so embedded by the model
+    pub misses: u64,
+    /// Embeddings currently cached
+    pub entries: usize,
+    /// Most embeddings the cache holds, 0 when caching is disabled
+    pub capacity: usize,
+}
+
+/// Embeddings of recently embedded texts, dropping the least recently used
+/// once `capacity` are held
+#[derive(Debug, Default)]
+pub(crate) struct EmbeddingCache {
+    capacity: usize,
+    /// Embedding and last use of each cached text
+    entries: HashMap<String, (Vec<f32>, u64)>,
+    /// Cached texts by last use, oldest first
+    by_use: BTreeMap<u64, String>,
+    /// Incremented on every lookup and insert
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl EmbeddingCache {
+    /// Cache of up to `capacity` embeddings; 0 caches nothing
+    pub(crate) fn new(capacity: usize) -> Self {
+        EmbeddingCache {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Look up the embedding of `text`, marking it as just used
+    pub(crate) fn get(&mut self, text: &str) -> Option<Vec<f32>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        self.clock += 1;
+        let Some((embedding, used)) = self.entries.get_mut(text) else {
+            self.misses += 1;
+            return None;
+        };
+        let key = self.by_use.remove(used).expect("cached text is ordered by use");
+        *used = self.clock;
+        self.by_use.insert(self.clock, key);
+        self.hits += 1;
+        Some(embedding.clone())
+    }
+
+    /// Cache `embedding` for `text`, evicting the least recently used entry
+    /// if the cache is full
+    pub(crate) fn insert(&mut self, text: &str, embedding: Vec<f32>) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.clock += 1;
+        if let Some((old, used)) = self.entries.get_mut(text) {
+            let key = self.by_use.remove(used).expect("cached text is ordered by use");
+            *old = embedding;
+            *used = self.clock;
+            self.by_use.insert(self.clock, key);
+            return;
+        }
+        if self.entries.len() == self.capacity
+            && let Some((_, oldest)) = self.by_use.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(text.to_string(), (embedding, self.clock));
+        self.by_use.insert(self.clock, text.to_string());
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let mut cache = EmbeddingCache::new(2);
+        cache.insert("a", vec![1.0]);
+        cache.insert("b", vec![2.0]);
+
+        // Using "a" leaves "b" as the oldest
+        assert_eq!(cache.get("a"), Some(vec![1.0]));
+        cache.insert("c", vec![3.0]);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(vec![1.0]));
+        assert_eq!(cache.get("c"), Some(vec![3.0]));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (3, 1));
+        assert_eq!((stats.entries, stats.capacity), (2, 2));
+    }
+
+    #[test]
+    fn reinserting_a_text_replaces_it_without_evicting() {
+        let mut cache = EmbeddingCache::new(2);
+        cache.insert("a", vec![1.0]);
+        cache.insert("b", vec![2.0]);
+
+        cache.insert("a", vec![1.5]);
+
+        assert_eq!(cache.get("a"), Some(vec![1.5]));
+        assert_eq!(cache.get("b"), Some(vec![2.0]));
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[test]
+    fn zero_capacity_caches_and_counts_nothing() {
+        let mut cache = EmbeddingCache::new(0);
+
+        cache.insert("a", vec![1.0]);
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+}
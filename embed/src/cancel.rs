@@ -0,0 +1,231 @@
+//! Stopping an inference pass early, on a deadline or from another thread
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ort::io_binding::IoBinding;
+use ort::session::{RunOptions, Session, SessionInputValue, SessionOutputs};
+
+use crate::error::EmbedError;
+use crate::pool::lock_recovering;
+
+/// Lets another thread or task stop embedding calls made with it.
+///
+/// Pass a clone to [`Embedder::embed_batch_cancellable`] and call
+/// [`cancel`](Self::cancel): the inference pass in flight is terminated and
+/// the call fails with [`EmbedError::Cancelled`]. A token stays cancelled,
+/// so later calls made with it fail before running.
+///
+/// [`Embedder::embed_batch_cancellable`]: crate::Embedder::embed_batch_cancellable
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<CancelState>);
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    /// Run options of the pass currently running under this token
+    running: Mutex<Option<Arc<RunOptions>>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the pass running under this token, if any, and every later one
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        if let Some(run) = lock_recovering(&self.0.running).as_ref() {
+            let _ = run.terminate();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancelToken").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+/// What may cut the inference passes of one embedding call short
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Interrupt {
+    /// When the call must be done by, and the budget it was given
+    deadline: Option<(Instant, Duration)>,
+    token: Option<CancelToken>,
+}
+
+impl Interrupt {
+    /// Fail passes still running `timeout` from now
+    pub(crate) fn after(timeout: Duration) -> Self {
+        Interrupt {
+            deadline: Some((Instant::now() + timeout, timeout)),
+            token: None,
+        }
+    }
+
+    /// Fail passes running when `token` is cancelled
+    pub(crate) fn on_cancel(token: &CancelToken) -> Self {
+        Interrupt {
+            deadline: None,
+            token: Some(token.clone()),
+        }
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.deadline.is_some() || self.token.is_some()
+    }
+
+    /// Get ready to run one pass, failing at once if the deadline has
+    /// passed or the token was cancelled
+    pub(crate) fn start(&self) -> Result<InterruptibleRun, EmbedError> {
+        if !self.is_set() {
+            return Ok(InterruptibleRun::default());
+        }
+        self.check()?;
+
+        let options = RunOptions::new()
+            .map(Arc::new)
+            .map_err(|e| EmbedError::Inference(format!("creating run options: {}", e)))?;
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let mut run = InterruptibleRun {
+            options: Some(Arc::clone(&options)),
+            timed_out: Arc::clone(&timed_out),
+            token: self.token.clone(),
+            watchdog: None,
+            budget: Duration::ZERO,
+        };
+        if let Some(token) = &self.token {
+            *lock_recovering(&token.0.running) = Some(Arc::clone(&options));
+            // Cancelled between the check above and being attached
+            if token.is_cancelled() {
+                return Err(EmbedError::Cancelled);
+            }
+        }
+        if let Some((deadline, budget)) = self.deadline {
+            let (stop, stopped) = mpsc::channel::<()>();
+            let watchdog = thread::Builder::new()
+                .name("arrow_embed_watchdog".to_string())
+                .spawn(move || {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if stopped.recv_timeout(remaining) == Err(RecvTimeoutError::Timeout) {
+                        timed_out.store(true, Ordering::SeqCst);
+                        let _ = options.terminate();
+                    }
+                })
+                .map_err(|e| EmbedError::Io(format!("spawning inference watchdog: {}", e)))?;
+            run.watchdog = Some((stop, watchdog));
+            run.budget = budget;
+        }
+        Ok(run)
+    }
+
+    /// Fail if the token was cancelled or the deadline has passed
+    fn check(&self) -> Result<(), EmbedError> {
+        if self.token.as_ref().is_some_and(CancelToken::is_cancelled) {
+            return Err(EmbedError::Cancelled);
+        }
+        match self.deadline {
+            Some((deadline, budget)) if Instant::now() >= deadline => {
+                Err(EmbedError::Timeout(budget))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// One inference pass that a watchdog or cancel token can terminate;
+/// dropping it stops the watchdog and detaches the token
+#[derive(Default)]
+pub(crate) struct InterruptibleRun {
+    options: Option<Arc<RunOptions>>,
+    timed_out: Arc<AtomicBool>,
+    token: Option<CancelToken>,
+    watchdog: Option<(mpsc::Sender<()>, JoinHandle<()>)>,
+    budget: Duration,
+}
+
+impl InterruptibleRun {
+    /// Run `session` on `inputs`, failing with [`EmbedError::Timeout`] or
+    /// [`EmbedError::Cancelled`] if the pass was terminated
+    pub(crate) fn run<'r>(
+        &'r self,
+        session: &'r mut Session,
+        inputs: Vec<(Cow<'_, str>, SessionInputValue<'_>)>,
+    ) -> Result<SessionOutputs<'r>, EmbedError> {
+        let outputs = match &self.options {
+            Some(options) => session.run_with_options(inputs, &**options),
+            None => session.run(inputs),
+        };
+        outputs.map_err(|e| self.error(e))
+    }
+
+    /// Run `session` on the inputs and outputs bound to `binding`, failing
+    /// like [`run`](Self::run)
+    pub(crate) fn run_binding<'b>(
+        &self,
+        session: &'b mut Session,
+        binding: &'b IoBinding,
+    ) -> Result<SessionOutputs<'b>, EmbedError> {
+        let outputs = match &self.options {
+            Some(options) => session.run_binding_with_options(binding, options),
+            None => session.run_binding(binding),
+        };
+        outputs.map_err(|e| self.error(e))
+    }
+
+    /// What a failed pass reports: cancelled or timed out if it was
+    /// terminated, otherwise ONNX Runtime's own error
+    fn error(&self, e: ort::Error) -> EmbedError {
+        if self.token.as_ref().is_some_and(CancelToken::is_cancelled) {
+            EmbedError::Cancelled
+        } else if self.timed_out.load(Ordering::SeqCst) {
+            EmbedError::Timeout(self.budget)
+        } else {
+            EmbedError::Inference(e.to_string())
+        }
+    }
+}
+
+impl Drop for InterruptibleRun {
+    fn drop(&mut self) {
+        if let Some((stop, watchdog)) = self.watchdog.take() {
+            drop(stop);
+            let _ = watchdog.join();
+        }
+        if let Some(token) = &self.token {
+            lock_recovering(&token.0.running).take();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spent_deadlines_and_cancelled_tokens_fail_before_running() {
+        let spent = Interrupt::after(Duration::ZERO);
+        assert!(matches!(spent.start(), Err(EmbedError::Timeout(budget)) if budget.is_zero()));
+
+        let token = CancelToken::new();
+        let cancellable = Interrupt::on_cancel(&token);
+        assert!(!token.is_cancelled());
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+        assert!(matches!(cancellable.start(), Err(EmbedError::Cancelled)));
+
+        let unset = Interrupt::default();
+        assert!(!unset.is_set());
+        assert!(unset.start().unwrap().options.is_none());
+    }
+}
@@ -0,0 +1,36 @@
+//! `arrow check` subcommand: run the embedder self-test and print its report
+//!
+//! arrow check [--model models/all-MiniLM-L6-v2.onnx] [--tokenizer NAME_OR_PATH]
+//!
+//! Compares the model's similarities on bundled reference texts with
+//! sentence-transformers' all-MiniLM-L6-v2, to spot an ONNX Runtime or
+//! tokenizer change before it invalidates a stored index. A mismatch is
+//! printed, not treated as an error, since other models aren't expected to
+//! match.
+
+use anyhow::{Result, anyhow, bail};
+use arrow_embed::Embedder;
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut model = "models/all-MiniLM-L6-v2.onnx".to_string();
+    let mut tokenizer = "sentence-transformers/all-MiniLM-L6-v2".to_string();
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .cloned()
+            .ok_or_else(|| anyhow!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--model" => model = value,
+            "--tokenizer" => tokenizer = value,
+            other => bail!("unknown option {}", other),
+        }
+    }
+
+    let mut embedder = Embedder::new(&model, &tokenizer)?;
+    let report = embedder.self_test()?;
+    println!("model: {}", model);
+    println!("{}", report);
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! In-memory corpus of embeddings keyed by string ids
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::error::EmbedError;
+use crate::index::{VectorIndex, io_error, read_array, read_exact};
+use crate::similarity::normalize_in_place;
+
+/// First bytes of every corpus file
+const CORPUS_MAGIC: &[u8; 8] = b"ARROWCRP";
+/// Format version written by save() and accepted by load()
+const CORPUS_FORMAT_VERSION: u32 = 1;
+
+/// Embeddings stored under string ids, searchable by cosine similarity.
+///
+/// Vectors and queries are normalized before scoring, so results match
+/// [`cosine_similarity`](crate::cosine_similarity) on `embed` output whether
+/// or not the embedder normalizes.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    ids: Vec<String>,
+    /// Rows are keyed by their position in `ids`
+    index: VectorIndex,
+}
+
+impl Corpus {
+    /// Create an empty corpus for embeddings of `dim` floats.
+    pub fn new(dim: usize) -> Self {
+        Corpus {
+            ids: Vec::new(),
+            index: VectorIndex::new(dim),
+        }
+    }
+
+    /// Length of the embeddings this corpus accepts
+    pub fn dim(&self) -> usize {
+        self.index.dim()
+    }
+
+    /// Number of stored embeddings
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Id of the embedding stored at `row`, in insertion order
+    pub fn id(&self, row: usize) -> Option<&str> {
+        self.ids.get(row).map(String::as_str)
+    }
+
+    /// Store `embedding` under `id`. Ids are not deduplicated.
+    pub fn add(&mut self, id: impl Into<String>, embedding: &[f32]) -> Result<(), EmbedError> {
+        let mut embedding = embedding.to_vec();
+        normalize_in_place(&mut embedding);
+        self.index.add(self.ids.len() as u64, &embedding)?;
+        self.ids.push(id.into());
+        Ok(())
+    }
+
+    /// Find the `k` embeddings most similar to `query`, best first.
+    ///
+    /// Returns fewer than `k` results if the corpus holds fewer embeddings.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<(String, f32)>, EmbedError> {
+        Ok(self
+            .search_rows(query, k)?
+            .into_iter()
+            .map(|(row, score)| (self.ids[row].clone(), score))
+            .collect())
+    }
+
+    /// Like [`search`](Self::search), returning rows instead of ids
+    pub(crate) fn search_rows(
+        &self,
+        query: &[f32],
+        k: usize,
+    ) -> Result<Vec<(usize, f32)>, EmbedError> {
+        let mut query = query.to_vec();
+        normalize_in_place(&mut query);
+        Ok(self
+            .index
+            .search(&query, k)?
+            .into_iter()
+            .map(|(row, score)| (row as usize, score))
+            .collect())
+    }
+
+    /// Write the corpus to `path`, replacing any existing file.
+    ///
+    /// Layout, all little-endian: magic "ARROWCRP", u32 version, u64 dim,
+    /// u64 count, `count` ids each as a u32 byte length and UTF-8 bytes, then
+    /// `count * dim` f32 values.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), EmbedError> {
+        let file = File::create(path).map_err(io_error)?;
+        let mut out = BufWriter::new(file);
+
+        out.write_all(CORPUS_MAGIC).map_err(io_error)?;
+        out.write_all(&CORPUS_FORMAT_VERSION.to_le_bytes()).map_err(io_error)?;
+        out.write_all(&(self.dim() as u64).to_le_bytes()).map_err(io_error)?;
+        out.write_all(&(self.ids.len() as u64).to_le_bytes()).map_err(io_error)?;
+        for id in &self.ids {
+            let len = u32::try_from(id.len()).map_err(|_| {
+                EmbedError::InvalidInput(format!("id of {} bytes is too long to save", id.len()))
+            })?;
+            out.write_all(&len.to_le_bytes()).map_err(io_error)?;
+            out.write_all(id.as_bytes()).map_err(io_error)?;
+        }
+        for value in self.index.raw_vectors() {
+            out.write_all(&value.to_le_bytes()).map_err(io_error)?;
+        }
+        out.flush().map_err(io_error)
+    }
+
+    /// Read a corpus written by [`save`](Self::save).
+    ///
+    /// Fails with [`EmbedError::UnsupportedIndexVersion`] for files from a
+    /// different format version and [`EmbedError::CorruptIndex`] for
+    /// anything that is not a complete corpus file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EmbedError> {
+        let file = File::open(path).map_err(io_error)?;
+        let file_len = file.metadata().map_err(io_error)?.len();
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        read_exact(&mut input, &mut magic, "magic")?;
+        if &magic != CORPUS_MAGIC {
+            return Err(EmbedError::CorruptIndex("not an arrow_embed corpus file".to_string()));
+        }
+        let version = u32::from_le_bytes(read_array(&mut input, "version")?);
+        if version != CORPUS_FORMAT_VERSION {
+            return Err(EmbedError::UnsupportedIndexVersion {
+                found: version,
+                supported: CORPUS_FORMAT_VERSION,
+            });
+        }
+        let dim = u64::from_le_bytes(read_array(&mut input, "dimension")?);
+        let count = u64::from_le_bytes(read_array(&mut input, "count")?);
+
+        // Bound every allocation by what is left of the file, so a corrupt
+        // header or id length can't trigger a huge one
+        let mut remaining = file_len - (8 + 4 + 8 + 8);
+        let vectors_len = dim.checked_mul(4).and_then(|row| row.checked_mul(count));
+        let mut corpus = Corpus::new(dim as usize);
+        for _ in 0..count {
+            let len = u32::from_le_bytes(read_array(&mut input, "ids")?) as u64;
+            remaining = remaining
+                .checked_sub(4 + len)
+                .ok_or_else(|| EmbedError::CorruptIndex("file ends inside the ids".to_string()))?;
+            let mut id = vec![0u8; len as usize];
+            read_exact(&mut input, &mut id, "ids")?;
+            let id = String::from_utf8(id)
+                .map_err(|e| EmbedError::CorruptIndex(format!("id is not valid UTF-8: {}", e)))?;
+            corpus.ids.push(id);
+        }
+        if vectors_len != Some(remaining) {
+            return Err(EmbedError::CorruptIndex(format!(
+                "header describes {} vectors of {} floats but {} bytes follow the ids",
+                count, dim, remaining
+            )));
+        }
+
+        let mut vector = Vec::new();
+        for row in 0..corpus.ids.len() {
+            vector.clear();
+            for _ in 0..dim {
+                vector.push(f32::from_le_bytes(read_array(&mut input, "vectors")?));
+            }
+            // Stored vectors are already normalized
+            corpus.index.add(row as u64, &vector)?;
+        }
+        Ok(corpus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ERROR_CORRUPT_INDEX, ERROR_DIMENSION_MISMATCH, ERROR_INDEX_VERSION};
+    use crate::test_util::*;
+
+    #[test]
+    fn search_returns_ids_best_first() {
+        let mut corpus = Corpus::new(2);
+        corpus.add("east", &[3.0, 0.0]).unwrap();
+        corpus.add("north", &[0.0, 0.5]).unwrap();
+        corpus.add("north-east", &[1.0, 1.0]).unwrap();
+
+        let results = corpus.search(&[2.0, 0.4], 2).unwrap();
+
+        let ids: Vec<&str> = results.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["east", "north-east"]);
+        // Unnormalized inputs still score as cosine similarities
+        assert!((results[0].1 - 2.0 / 4.16f32.sqrt()).abs() < 1e-6);
+        assert_eq!(corpus.id(1), Some("north"));
+        assert_eq!(corpus.id(3), None);
+    }
+
+    #[test]
+    fn wrong_dimension_is_rejected() {
+        let mut corpus = Corpus::new(3);
+
+        let err = corpus.add("short", &[1.0, 0.0]).unwrap_err();
+        assert_eq!(err.code(), ERROR_DIMENSION_MISMATCH);
+        assert!(corpus.search(&[1.0; 4], 1).is_err());
+        assert!(corpus.is_empty());
+    }
+
+    #[test]
+    fn save_and_load_round_trip_preserves_ids_and_search() {
+        let vectors = random_unit_vectors(500, 16, 11);
+        let mut corpus = Corpus::new(16);
+        for (i, vector) in vectors.iter().enumerate() {
+            corpus.add(format!("doc-{}-\u{e9}", i), vector).unwrap();
+        }
+        let path = temp_path("round_trip.corpus");
+
+        corpus.save(&path).unwrap();
+        let loaded = Corpus::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.dim(), loaded.len()), (16, 500));
+        assert_eq!(loaded.id(42), Some("doc-42-\u{e9}"));
+        for query in random_unit_vectors(10, 16, 5) {
+            assert_eq!(corpus.search(&query, 5).unwrap(), loaded.search(&query, 5).unwrap());
+        }
+    }
+
+    #[test]
+    fn load_rejects_bad_files() {
+        let mut corpus = Corpus::new(2);
+        corpus.add("a", &[1.0, 0.0]).unwrap();
+        let path = temp_path("versioned.corpus");
+        corpus.save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut future = bytes.clone();
+        future[8..12].copy_from_slice(&2u32.to_le_bytes());
+        let mut huge_id = bytes.clone();
+        huge_id[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        let cases = [
+            ("future.corpus", future, ERROR_INDEX_VERSION),
+            ("huge_id.corpus", huge_id, ERROR_CORRUPT_INDEX),
+            ("truncated.corpus", bytes[..bytes.len() - 1].to_vec(), ERROR_CORRUPT_INDEX),
+            ("index.corpus", b"ARROWIDX".to_vec(), ERROR_CORRUPT_INDEX),
+        ];
+        for (name, contents, code) in cases {
+            let path = write_temp_file(name, &contents);
+            let err = Corpus::load(&path).unwrap_err();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(err.code(), code, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! `arrow embed` subcommand: embed every line of a text file or stdin
+//!
+//! arrow embed [--input docs.txt] [--out docs.jsonl] [--output jsonl]
+//!             [--model models/all-MiniLM-L6-v2.onnx] [--tokenizer NAME_OR_PATH]
+//!             [--batch-size 32]
+//!
+//! Each input line is one document, identified by its 0-based line number.
+//! Blank lines have nothing to embed and are skipped. Input is read from
+//! stdin and output written to stdout unless `--input`/`--out` are given,
+//! and lines are streamed through in bounded batches, with progress on
+//! stderr.
+//!
+//! `--output jsonl` (the default) writes `{"index": n, "embedding": [...]}`
+//! lines; `--output tsv` writes `id<TAB>v0,v1,...` lines; `--output arrow`
+//! (requires the `arrow` feature and `--out`) writes an Arrow IPC file.
+//! A line that fails to embed does not stop the run: jsonl gets an
+//! `{"index": n, "error": "..."}` line for it, the other formats report it
+//! on stderr and leave it out.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use anyhow::{Context, Result, anyhow, bail};
+use arrow_embed::{EmbedError, Embedder};
+
+/// Rows buffered before each write, bounding memory for large corpora
+const ROWS_PER_BATCH: usize = 1024;
+/// Default texts per inference call; smaller than a batch to limit padding
+const TEXTS_PER_INFERENCE: usize = 32;
+
+enum Output {
+    Tsv(Box<dyn Write>),
+    Jsonl(Box<dyn Write>),
+    #[cfg(feature = "arrow")]
+    Arrow(arrow_embed::EmbeddingIpcWriter),
+}
+
+impl Output {
+    fn write(&mut self, lines: &[usize], results: &[Result<Vec<f32>, EmbedError>]) -> Result<()> {
+        let rows = lines.iter().zip(results);
+        match self {
+            Output::Tsv(out) => {
+                for (line, result) in rows {
+                    match result {
+                        Ok(vector) => writeln!(out, "{}\t{}", line, join_values(vector))?,
+                        Err(e) => eprintln!("line {}: {}", line, e),
+                    }
+                }
+            }
+            Output::Jsonl(out) => {
+                for (line, result) in rows {
+                    match result {
+                        Ok(vector) => {
+                            let values = join_values(vector);
+                            writeln!(out, "{{\"index\":{},\"embedding\":[{}]}}", line, values)?;
+                        }
+                        Err(e) => {
+                            let message = json_string(&e.to_string());
+                            writeln!(out, "{{\"index\":{},\"error\":{}}}", line, message)?;
+                        }
+                    }
+                }
+            }
+            #[cfg(feature = "arrow")]
+            Output::Arrow(writer) => {
+                let mut ids = Vec::with_capacity(lines.len());
+                let mut vectors = Vec::with_capacity(lines.len());
+                for (line, result) in rows {
+                    match result {
+                        Ok(vector) => {
+                            ids.push(line.to_string());
+                            vectors.push(vector.clone());
+                        }
+                        Err(e) => eprintln!("line {}: {}", line, e),
+                    }
+                }
+                writer.write(&ids, &vectors)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Output::Tsv(mut out) | Output::Jsonl(mut out) => out.flush()?,
+            #[cfg(feature = "arrow")]
+            Output::Arrow(writer) => writer.finish()?,
+        }
+        Ok(())
+    }
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut input = None;
+    let mut out_path = None;
+    let mut format = "jsonl".to_string();
+    let mut model = "models/all-MiniLM-L6-v2.onnx".to_string();
+    let mut tokenizer = "sentence-transformers/all-MiniLM-L6-v2".to_string();
+    let mut batch_size = TEXTS_PER_INFERENCE;
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .cloned()
+            .ok_or_else(|| anyhow!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--input" => input = Some(value),
+            "--out" => out_path = Some(value),
+            "--output" => format = value,
+            "--model" => model = value,
+            "--tokenizer" => tokenizer = value,
+            "--batch-size" => {
+                batch_size = value
+                    .parse()
+                    .with_context(|| format!("--batch-size must be a number, got {}", value))?
+            }
+            other => bail!("unknown option {}", other),
+        }
+    }
+    if batch_size == 0 {
+        bail!("--batch-size must be at least 1");
+    }
+
+    let mut embedder = Embedder::new(&model, &tokenizer)?;
+    let writer = || -> Result<Box<dyn Write>> {
+        Ok(match &out_path {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(io::stdout().lock())),
+        })
+    };
+    let mut output = match format.as_str() {
+        "tsv" => Output::Tsv(writer()?),
+        "jsonl" => Output::Jsonl(writer()?),
+        #[cfg(feature = "arrow")]
+        "arrow" => Output::Arrow(arrow_embed::EmbeddingIpcWriter::create(
+            out_path.as_ref().context("--output arrow needs --out")?,
+            embedder.dim(),
+        )?),
+        #[cfg(not(feature = "arrow"))]
+        "arrow" => bail!("--output arrow requires building with --features arrow"),
+        other => bail!("unknown output format {}", other),
+    };
+
+    let reader: Box<dyn BufRead> = match &input {
+        Some(path) => Box::new(BufReader::new(File::open(path).with_context(|| path.clone())?)),
+        None => Box::new(io::stdin().lock()),
+    };
+    let mut batch = Batch {
+        lines: Vec::with_capacity(ROWS_PER_BATCH),
+        texts: Vec::with_capacity(ROWS_PER_BATCH),
+        batch_size,
+        embedded: 0,
+        failed: 0,
+    };
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        batch.lines.push(line_no);
+        batch.texts.push(line);
+        if batch.texts.len() == ROWS_PER_BATCH {
+            batch.flush(&mut embedder, &mut output)?;
+            eprintln!("Embedded {} lines", batch.embedded);
+        }
+    }
+    batch.flush(&mut embedder, &mut output)?;
+    output.finish()?;
+
+    let destination = out_path.as_deref().unwrap_or("stdout");
+    eprintln!("Embedded {} lines into {}", batch.embedded, destination);
+    if batch.failed > 0 {
+        eprintln!("{} lines could not be embedded", batch.failed);
+    }
+    Ok(())
+}
+
+/// Lines read but not yet written, and counts of those already written
+struct Batch {
+    lines: Vec<usize>,
+    texts: Vec<String>,
+    /// Texts per inference call
+    batch_size: usize,
+    embedded: usize,
+    failed: usize,
+}
+
+impl Batch {
+    /// Embed the buffered texts, write them, and empty the buffers.
+    ///
+    /// A chunk that fails as a whole is retried a line at a time, so one
+    /// bad line does not take its neighbours down with it.
+    fn flush(&mut self, embedder: &mut Embedder, output: &mut Output) -> Result<()> {
+        if self.texts.is_empty() {
+            return Ok(());
+        }
+        let mut results = Vec::with_capacity(self.texts.len());
+        for chunk in self.texts.chunks(self.batch_size) {
+            let chunk: Vec<&str> = chunk.iter().map(String::as_str).collect();
+            match embedder.embed_batch(&chunk) {
+                Ok(vectors) => results.extend(vectors.into_iter().map(Ok)),
+                Err(_) => results.extend(chunk.iter().map(|text| embedder.embed(text))),
+            }
+        }
+        output.write(&self.lines, &results)?;
+
+        let failed = results.iter().filter(|result| result.is_err()).count();
+        self.failed += failed;
+        self.embedded += results.len() - failed;
+        self.lines.clear();
+        self.texts.clear();
+        Ok(())
+    }
+}
+
+/// `v0,v1,...`, as both text formats write vectors
+fn join_values(vector: &[f32]) -> String {
+    let values: Vec<String> = vector.iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+/// Quote `text` as a JSON string
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_messages_are_escaped_as_json() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(json_string("say \"hi\"\\\n\u{1}"), r#""say \"hi\"\\\n\u0001""#);
+    }
+}
@@ -0,0 +1,3701 @@
+//! Embedder built on ONNX Runtime and HuggingFace tokenizers
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use half::f16;
+use hf_hub::api::sync::ApiError;
+use ndarray::{Array1, Array2, ArrayD, ArrayView2, ArrayView3, ArrayViewMut1, Axis, s};
+use once_cell::sync::Lazy;
+use ort::ep::{self, ExecutionProvider as _};
+use ort::inputs;
+use ort::io_binding::IoBinding;
+use ort::memory::Allocator;
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+use ort::session::{Session, SessionInputValue};
+use ort::tensor::TensorElementType;
+use ort::value::{Tensor, TensorRef, ValueType};
+use tokenizers::{
+    Encoding, ModelWrapper, PostProcessor, Tokenizer, TruncationDirection, TruncationParams,
+};
+
+use crate::DEFAULT_MAX_SEQ_LEN;
+use crate::cache::{CacheStats, EmbeddingCache};
+use crate::cancel::{CancelToken, Interrupt};
+use crate::environment::{DEFAULT_ENVIRONMENT_NAME, OrtLogLevel, cap_cpu_arena, init_environment};
+use crate::error::EmbedError;
+use crate::model_dir::ModelDir;
+use crate::pool::lock_recovering;
+use crate::self_test::{self, SelfTestReport};
+use crate::similarity::{cosine_similarity, normalize_in_place, similarity_matrix};
+
+/// Hardware backend the model runs on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionProvider {
+    #[default]
+    Cpu,
+    /// NVIDIA GPU (requires the `cuda` feature)
+    Cuda { device_id: i32 },
+    /// Apple Neural Engine / GPU (requires the `coreml` feature)
+    CoreMl,
+    /// NVIDIA TensorRT (requires the `tensorrt` feature)
+    TensorRt,
+}
+
+/// How aggressively ONNX Runtime rewrites the model graph at load time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GraphOptimization {
+    /// Run the graph exactly as exported
+    Disable,
+    /// Semantics-preserving rewrites such as constant folding
+    Basic,
+    /// Basic plus operator fusions
+    Extended,
+    /// Every optimization, including layout changes
+    #[default]
+    All,
+}
+
+impl GraphOptimization {
+    fn level(self) -> GraphOptimizationLevel {
+        match self {
+            GraphOptimization::Disable => GraphOptimizationLevel::Disable,
+            GraphOptimization::Basic => GraphOptimizationLevel::Level1,
+            GraphOptimization::Extended => GraphOptimizationLevel::Level2,
+            GraphOptimization::All => GraphOptimizationLevel::Level3,
+        }
+    }
+}
+
+/// How token vectors are combined into a single sentence embedding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PoolingStrategy {
+    /// Average of all unmasked token vectors (sentence-transformers default)
+    #[default]
+    Mean,
+    /// Vector of the first ([CLS]) token, as BGE and many BERT fine-tunes expect
+    Cls,
+    /// Element-wise maximum over unmasked token vectors
+    Max,
+}
+
+/// Norm embeddings are scaled to unit length in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Normalization {
+    /// Keep the pooled magnitude
+    None,
+    /// Divide by the sum of absolute values
+    L1,
+    /// Divide by the Euclidean length, so dot products are cosine similarities
+    #[default]
+    L2,
+}
+
+impl Normalization {
+    /// Scale `v` to unit norm in place; vectors with a norm below 1e-12 are
+    /// left unchanged
+    fn apply(self, mut v: ArrayViewMut1<f32>) {
+        let norm = match self {
+            Normalization::None => return,
+            Normalization::L1 => v.iter().map(|x| x.abs()).sum::<f32>(),
+            Normalization::L2 => v.dot(&v).sqrt(),
+        };
+        if norm > 1e-12 {
+            v /= norm;
+        }
+    }
+}
+
+/// How [`Embedder::embed_long`] combines the embeddings of a text's chunks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkAggregation {
+    /// One embedding: the average of the chunk embeddings
+    #[default]
+    MeanOfChunks,
+    /// One embedding per chunk, in text order
+    ReturnAll,
+}
+
+/// Where [`Embedder::with_tokenizer`] and [`Embedder::from_bytes`] load
+/// their tokenizer from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenizerSource {
+    /// Name of a tokenizer on the HuggingFace Hub, fetched once per process
+    /// and copied after that
+    Pretrained(String),
+    /// Path to a local tokenizer.json, read on every load
+    File(PathBuf),
+    /// Contents of a tokenizer.json, e.g. bundled with the application,
+    /// needing neither the filesystem nor the network
+    Json(String),
+}
+
+impl TokenizerSource {
+    /// What a `tokenizer_source` string names: a file if one exists there
+    /// or it ends in `.json`, so a typo in a local path fails fast instead
+    /// of falling through to a network lookup, and a Hub name otherwise
+    pub(crate) fn from_name_or_path(source: &str) -> Self {
+        let path = Path::new(source);
+        if path.is_file() || path.extension().is_some_and(|ext| ext == "json") {
+            TokenizerSource::File(path.to_path_buf())
+        } else {
+            TokenizerSource::Pretrained(source.to_string())
+        }
+    }
+
+    /// Load the tokenizer, retrying a Hub download that failed for a
+    /// transient reason up to `retries` times
+    pub(crate) fn load(&self, retries: usize) -> Result<Tokenizer, EmbedError> {
+        match self {
+            TokenizerSource::Pretrained(name) => load_pretrained(name, retries),
+            TokenizerSource::File(path) => {
+                if !path.is_file() {
+                    return Err(EmbedError::TokenizerNotFound(path.display().to_string()));
+                }
+                Tokenizer::from_file(path).map_err(|e| {
+                    EmbedError::InvalidTokenizer(format!("{}: {}", path.display(), e))
+                })
+            }
+            TokenizerSource::Json(json) => json
+                .parse()
+                .map_err(|e| EmbedError::InvalidTokenizer(format!("tokenizer JSON: {}", e))),
+        }
+    }
+}
+
+/// Where the ONNX model is read from
+#[derive(Clone, Copy)]
+enum ModelSource<'a> {
+    File(&'a str),
+    Memory(&'a [u8]),
+}
+
+impl ModelSource<'_> {
+    /// What [`Embedder::info`] names the model after
+    fn path(&self) -> &str {
+        match self {
+            ModelSource::File(path) => path,
+            ModelSource::Memory(_) => "<memory>",
+        }
+    }
+}
+
+/// Which configured prefix [`Embedder::embed_as`] puts before a text, for
+/// asymmetric models such as E5 and BGE that embed queries and documents
+/// differently
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmbedKind {
+    /// A search query, prefixed with `query_prefix`
+    Query,
+    /// A document searched over, prefixed with `passage_prefix`
+    Document,
+    /// The text as given
+    #[default]
+    Raw,
+}
+
+/// What an ONNX graph input is fed, for naming inputs that
+/// [`EmbedderOptions::input_names`] must map by hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputRole {
+    /// Token ids
+    InputIds,
+    /// 1 for real tokens, 0 for padding
+    AttentionMask,
+    /// Segment ids, all 0 for a single sentence
+    TokenTypeIds,
+}
+
+/// Which kind of model output embeddings are read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingOutput {
+    /// `[batch, seq_len, hidden]` token vectors, pooled by the embedder
+    TokenStates,
+    /// `[batch, hidden]` sentence embeddings the model pooled itself
+    SentenceEmbedding,
+}
+
+impl EmbeddingOutput {
+    /// Number of dimensions the output has
+    fn rank(self) -> usize {
+        match self {
+            EmbeddingOutput::TokenStates => 3,
+            EmbeddingOutput::SentenceEmbedding => 2,
+        }
+    }
+
+    /// Pick the output to read embeddings from, given each output's name and
+    /// declared shape, returning its position among the outputs.
+    ///
+    /// A `[batch, hidden]` sentence_embedding output is used as is. Otherwise
+    /// token vectors come from last_hidden_state, or failing that the first
+    /// `[batch, seq_len, hidden]` output; pooler_output and other already
+    /// pooled outputs are never pooled again.
+    fn select<'a>(
+        outputs: impl IntoIterator<Item = (&'a str, &'a [i64])>,
+    ) -> Result<(usize, Self), EmbedError> {
+        let outputs: Vec<_> = outputs.into_iter().collect();
+        let named = |name, rank| outputs.iter().position(|&(n, s)| n == name && s.len() == rank);
+        if let Some(index) = named("sentence_embedding", 2) {
+            return Ok((index, EmbeddingOutput::SentenceEmbedding));
+        }
+        token_states_output(&outputs)
+            .map(|index| (index, EmbeddingOutput::TokenStates))
+            .ok_or_else(|| {
+                let names: Vec<&str> = outputs.iter().map(|(name, _)| *name).collect();
+                EmbedError::ModelLoad(format!(
+                    "model has no [batch, seq_len, hidden] or sentence_embedding output \
+                     (outputs: {})",
+                    names_list(&names)
+                ))
+            })
+    }
+}
+
+/// Position of the output holding unpooled token vectors: last_hidden_state,
+/// or failing that the first `[batch, seq_len, hidden]` output
+fn token_states_output(outputs: &[(&str, &[i64])]) -> Option<usize> {
+    outputs
+        .iter()
+        .position(|&(name, shape)| name == "last_hidden_state" && shape.len() == 3)
+        .or_else(|| outputs.iter().position(|(_, shape)| shape.len() == 3))
+}
+
+/// Chunks of a long text embedded per inference pass
+const CHUNKS_PER_PASS: usize = 16;
+
+/// Default cap on tokens per inference pass, padding included: 64 texts of
+/// 256 tokens
+const DEFAULT_MAX_TOKENS_PER_BATCH: usize = 16 * 1024;
+
+/// Retries of a Hub tokenizer download that failed transiently, e.g. when
+/// rate limited
+pub(crate) const DEFAULT_TOKENIZER_RETRIES: usize = 3;
+
+/// Wait before the first retry of a Hub download, doubled for each next one
+const TOKENIZER_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Sequence lengths [`Embedder::warmup`] runs at, besides max_seq_len
+const WARMUP_SEQ_LENS: [usize; 2] = [16, 128];
+
+/// Texts embedded per inference pass by batch calls that report progress
+pub(crate) const TEXTS_PER_PROGRESS_PASS: usize = 64;
+
+/// Options controlling how an Embedder is built and tokenizes its input
+#[derive(Debug, Clone)]
+pub struct EmbedderOptions {
+    /// Maximum number of tokens fed to the model per text
+    pub max_seq_len: usize,
+    /// Reject texts longer than max_seq_len instead of truncating them
+    pub strict_length: bool,
+    /// Pad every sequence to exactly this many tokens, for models exported
+    /// with a static sequence length; texts are cut to it, overriding
+    /// max_seq_len. `None` pads a batch only to its longest sequence
+    pub fixed_seq_len: Option<usize>,
+    /// Wrap each text in [CLS] ... [SEP] as sentence-transformers does
+    pub add_special_tokens: bool,
+    /// Trim texts and collapse runs of whitespace to one space before
+    /// tokenizing, so scraped text embeds the same as its clean form
+    pub trim_input: bool,
+    /// Largest fraction of a text's tokens, special tokens aside, that may be
+    /// the tokenizer's unknown token before the text is rejected as
+    /// garbage; 1.0 to accept anything
+    pub max_unknown_fraction: f32,
+    /// Backend to run the model on; falls back to CPU if it can't be registered
+    pub execution_provider: ExecutionProvider,
+    /// How token vectors are pooled into the sentence embedding; unused for
+    /// models that export their own pooled sentence_embedding output
+    pub pooling: PoolingStrategy,
+    /// Norm embeddings are scaled to, L2 by default
+    pub normalization: Normalization,
+    /// Keep only the first this many dimensions of each pooled embedding,
+    /// before normalization, as Matryoshka-trained models allow; at most
+    /// the model's hidden size. Token vectors from `encode_hidden` and
+    /// `embed_tokens` keep every dimension
+    pub output_dim: Option<usize>,
+    /// Threads ONNX Runtime uses within one operator, 0 for ONNX Runtime's
+    /// default of one per physical core
+    pub intra_threads: usize,
+    /// Threads ONNX Runtime uses to run independent operators at the same
+    /// time, 0 for ONNX Runtime's default of running them one after another
+    pub inter_threads: usize,
+    /// Graph optimizations applied when the model is loaded
+    pub optimization: GraphOptimization,
+    /// Stop any inference pass still running after this long, failing the
+    /// call with [`EmbedError::Timeout`]; `None` lets passes run to the end.
+    /// [`Embedder::embed_with_deadline`] and its kin replace it per call
+    pub timeout: Option<Duration>,
+    /// Keep ONNX Runtime's CPU allocations in an arena that grows to the
+    /// largest run's needs and holds on to it. Disabling it allocates per
+    /// run, slightly slower, for a lower peak resident size
+    pub cpu_arena: bool,
+    /// Cap the CPU arena at this many bytes, growing it only by what each
+    /// allocation needs, for containers where the default arena's
+    /// preallocation gets the process killed. The capped arena is shared
+    /// by every embedder in the process, so only the first one to ask sets
+    /// it. Requires `cpu_arena`; `None` leaves the arena unbounded
+    pub max_memory_bytes: Option<usize>,
+    /// Run on one thread with ONNX Runtime's deterministic kernels, so a text
+    /// embeds to the same bits on every run; overrides both thread counts
+    pub deterministic: bool,
+    /// Prepended by `embed_query`, e.g. "query: " for E5 models
+    pub query_prefix: String,
+    /// Prepended by `embed_passage`, e.g. "passage: " for E5 models
+    pub passage_prefix: String,
+    /// Most tokens, padding included, fed to the model in one pass. Larger
+    /// batches are split into passes of similar-length texts, bounding
+    /// memory and padding less; 0 runs every batch as one pass
+    pub max_tokens_per_batch: usize,
+    /// Embeddings of recently seen texts kept so repeats skip inference,
+    /// least recently used dropped first; 0 to disable caching
+    pub cache_capacity: usize,
+    /// Roles of graph inputs whose names don't give them away, e.g.
+    /// `{"x": InputRole::InputIds}`; inputs not listed are told apart by name
+    pub input_names: HashMap<String, InputRole>,
+    /// Name of the ONNX Runtime environment, "arrow_embed" by default.
+    /// The environment is shared by the whole process, so only the first
+    /// embedder loaded sets this and `ort_log_level`
+    pub environment_name: String,
+    /// Least severe ONNX Runtime message logged to stderr; `None` leaves it
+    /// to the `ORT_LOG` variable
+    pub ort_log_level: Option<OrtLogLevel>,
+    /// Length the model's embeddings must have, e.g. `Some(384)` when
+    /// downstream buffers assume it; loading another model fails with
+    /// [`EmbedError::DimensionMismatch`]. `None` accepts any length
+    pub expected_dim: Option<usize>,
+    /// Times a HuggingFace Hub tokenizer download that failed transiently,
+    /// from a dropped connection, rate limiting or a server error, is
+    /// retried, waiting 100ms, then 200ms, 400ms and so on; 0 fails on the
+    /// first error. A missing repo is never retried
+    pub tokenizer_retries: usize,
+}
+
+impl Default for EmbedderOptions {
+    fn default() -> Self {
+        EmbedderOptions {
+            max_seq_len: DEFAULT_MAX_SEQ_LEN,
+            strict_length: false,
+            fixed_seq_len: None,
+            add_special_tokens: true,
+            trim_input: false,
+            max_unknown_fraction: 0.5,
+            execution_provider: ExecutionProvider::Cpu,
+            pooling: PoolingStrategy::Mean,
+            normalization: Normalization::L2,
+            output_dim: None,
+            intra_threads: 0,
+            inter_threads: 0,
+            optimization: GraphOptimization::All,
+            timeout: None,
+            cpu_arena: true,
+            max_memory_bytes: None,
+            deterministic: false,
+            query_prefix: String::new(),
+            passage_prefix: String::new(),
+            max_tokens_per_batch: DEFAULT_MAX_TOKENS_PER_BATCH,
+            cache_capacity: 0,
+            input_names: HashMap::new(),
+            environment_name: DEFAULT_ENVIRONMENT_NAME.to_string(),
+            ort_log_level: None,
+            expected_dim: None,
+            tokenizer_retries: DEFAULT_TOKENIZER_RETRIES,
+        }
+    }
+}
+
+/// What [`Embedder::info`] reports about the loaded model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelInfo {
+    /// File name of the model without its extension, e.g. "all-MiniLM-L6-v2"
+    pub name: String,
+    /// Length of the vectors the model produces
+    pub dim: usize,
+    /// Most tokens fed to the model per text, from the tokenizer's
+    /// truncation or, for strict length, the configured limit
+    pub max_seq_len: usize,
+    /// Graph input names, in declaration order
+    pub input_names: Vec<String>,
+    /// Graph output the embeddings are read from
+    pub output_name: String,
+    /// Whether embeddings come pooled from the model or pooled by the embedder
+    pub embedding_output: EmbeddingOutput,
+    /// Whether the model looks quantized, judged from its file name (e.g.
+    /// "model_qint8_avx512.onnx") and its metadata description
+    pub quantized: bool,
+}
+
+/// Text embedder holding an ONNX Runtime session and its tokenizer.
+///
+/// ```no_run
+/// use arrow_embed::Embedder;
+///
+/// let mut embedder = Embedder::new(
+///     "models/all-MiniLM-L6-v2.onnx",
+///     "sentence-transformers/all-MiniLM-L6-v2",
+/// )?;
+/// let embedding = embedder.embed("hello world")?;
+/// assert_eq!(embedding.len(), arrow_embed::EMBEDDING_DIM);
+/// # Ok::<(), arrow_embed::EmbedError>(())
+/// ```
+pub struct Embedder {
+    session: Session,
+    model_path: String,
+    tokenizer: Tokenizer,
+    max_seq_len: usize,
+    strict_length: bool,
+    fixed_seq_len: Option<usize>,
+    add_special_tokens: bool,
+    trim_input: bool,
+    /// The tokenizer's unknown token, if it has one, and how much of a text
+    /// it may make up
+    unknown_token: Option<u32>,
+    max_unknown_fraction: f32,
+    pooling: PoolingStrategy,
+    normalization: Normalization,
+    inputs: ModelInputs,
+    /// Position of the output embeddings are read from, and its kind
+    output_index: usize,
+    output: EmbeddingOutput,
+    /// Hidden size of the model's output
+    dim: usize,
+    /// Dimensions pooled embeddings are cut to, if fewer than `dim`
+    output_dim: Option<usize>,
+    provider_warning: Option<String>,
+    query_prefix: String,
+    passage_prefix: String,
+    buffers: InputBuffers,
+    /// Output tensor of the last pass's shape, created on the first pass
+    output_binding: Option<OutputBinding>,
+    cache: EmbeddingCache,
+    max_tokens_per_batch: usize,
+    /// Limit on every inference pass, from `timeout`
+    timeout: Option<Duration>,
+    /// Deadline or cancel token of the call in progress, replacing `timeout`
+    interrupt: Interrupt,
+}
+
+/// Padded model inputs, row-major `[batch, seq_len]`, kept on the embedder
+/// so each call refills them in place instead of allocating
+#[derive(Debug, Default)]
+pub(crate) struct InputBuffers {
+    input_ids: Vec<i64>,
+    attention_mask: Vec<i64>,
+    token_type_ids: Vec<i64>,
+}
+
+impl InputBuffers {
+    /// Buffers that hold one sequence of `seq_len` tokens without growing
+    pub(crate) fn with_capacity(seq_len: usize) -> Self {
+        InputBuffers {
+            input_ids: Vec::with_capacity(seq_len),
+            attention_mask: Vec::with_capacity(seq_len),
+            token_type_ids: Vec::with_capacity(seq_len),
+        }
+    }
+
+    /// Overwrite the buffers with `encodings`, each zero-padded to `seq_len`
+    pub(crate) fn fill<'e, I>(&mut self, encodings: I, seq_len: usize)
+    where
+        I: IntoIterator<Item = &'e Encoding>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let encodings = encodings.into_iter();
+        let len = encodings.len() * seq_len;
+        for buffer in [&mut self.input_ids, &mut self.attention_mask, &mut self.token_type_ids] {
+            buffer.clear();
+            buffer.resize(len, 0);
+        }
+
+        for (b, encoding) in encodings.enumerate() {
+            let row = b * seq_len..(b + 1) * seq_len;
+            let tokens = encoding
+                .get_ids()
+                .iter()
+                .zip(encoding.get_attention_mask())
+                .zip(encoding.get_type_ids());
+            let slots = self.input_ids[row.clone()]
+                .iter_mut()
+                .zip(&mut self.attention_mask[row.clone()])
+                .zip(&mut self.token_type_ids[row]);
+            for (((id, mask), type_id), ((&new_id, &new_mask), &new_type_id)) in slots.zip(tokens) {
+                *id = new_id as i64;
+                *mask = new_mask as i64;
+                *type_id = new_type_id as i64;
+            }
+        }
+    }
+
+    /// Overwrite the buffers with one already tokenized sequence, with zero
+    /// token types, zero-padded to `seq_len`
+    fn fill_ids(&mut self, input_ids: &[i64], attention_mask: &[i64], seq_len: usize) {
+        self.input_ids.clear();
+        self.input_ids.extend_from_slice(input_ids);
+        self.input_ids.resize(seq_len, 0);
+        self.attention_mask.clear();
+        self.attention_mask.extend_from_slice(attention_mask);
+        self.attention_mask.resize(seq_len, 0);
+        self.token_type_ids.clear();
+        self.token_type_ids.resize(seq_len, 0);
+    }
+}
+
+/// The embeddings output bound to a tensor the embedder owns, so passes of
+/// one shape write into the same memory rather than ONNX Runtime
+/// allocating a new output for each
+#[derive(Debug)]
+struct OutputBinding {
+    binding: IoBinding,
+    /// Shape of the bound tensor; a pass of any other shape rebinds it
+    shape: Vec<usize>,
+}
+
+/// Graph input names to feed each standard BERT input to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ModelInputs {
+    input_ids: String,
+    attention_mask: Option<String>,
+    token_type_ids: Option<String>,
+}
+
+impl ModelInputs {
+    /// Work out which graph input takes which tensor from each input's name
+    /// and whether it is an int64 `[batch, seq_len]` tensor we can feed.
+    ///
+    /// `overrides` settles names the heuristics don't recognize. input_ids is
+    /// required, the others are fed only if declared (distilled exports often
+    /// drop token_type_ids); a lone unrecognized input is taken as input_ids.
+    pub(crate) fn classify<'a>(
+        inputs: impl IntoIterator<Item = (&'a str, bool)>,
+        overrides: &HashMap<String, InputRole>,
+    ) -> Result<Self, EmbedError> {
+        let mut names = Vec::new();
+        let mut matched: [Vec<&str>; 3] = Default::default();
+        let mut unknown = Vec::new();
+        for (name, token_matrix) in inputs {
+            names.push(name);
+            if !token_matrix {
+                return Err(EmbedError::ModelLoad(format!(
+                    "model input '{}' is not an int64 [batch, seq_len] tensor (inputs: {})",
+                    name,
+                    names_list(&names)
+                )));
+            }
+            match overrides.get(name).copied().or_else(|| role_from_name(name)) {
+                Some(role) => matched[role as usize].push(name),
+                None => unknown.push(name),
+            }
+        }
+        if let Some(missing) = overrides.keys().find(|name| !names.contains(&name.as_str())) {
+            return Err(EmbedError::ModelLoad(format!(
+                "input_names maps '{}', which the model does not declare (inputs: {})",
+                missing,
+                names_list(&names)
+            )));
+        }
+
+        let [input_ids, attention_mask, token_type_ids] = matched;
+        let input_ids = match (&input_ids[..], &unknown[..]) {
+            ([name], []) | ([], [name]) => Some(name.to_string()),
+            _ => None,
+        };
+        let optional = |matched: &[&str]| match matched {
+            [] => Ok(None),
+            [name] => Ok(Some(name.to_string())),
+            _ => Err(()),
+        };
+        match (input_ids, optional(&attention_mask), optional(&token_type_ids)) {
+            (Some(input_ids), Ok(attention_mask), Ok(token_type_ids)) => Ok(ModelInputs {
+                input_ids,
+                attention_mask,
+                token_type_ids,
+            }),
+            _ => Err(EmbedError::ModelLoad(format!(
+                "can't tell input_ids, attention_mask and token_type_ids apart from the \
+                 model's inputs ({}); name them in EmbedderOptions::input_names",
+                names_list(&names)
+            ))),
+        }
+    }
+}
+
+/// Role suggested by an input's name, e.g. "input.1" or "mask"
+fn role_from_name(name: &str) -> Option<InputRole> {
+    let name = name.to_ascii_lowercase();
+    if name.contains("type") || name.contains("segment") {
+        Some(InputRole::TokenTypeIds)
+    } else if name.contains("mask") {
+        Some(InputRole::AttentionMask)
+    } else if name.contains("ids") || name.starts_with("input") {
+        Some(InputRole::InputIds)
+    } else {
+        None
+    }
+}
+
+/// Whether an input is fed tensors shaped like the tokenizer's output
+pub(crate) fn is_token_matrix(dtype: &ValueType) -> bool {
+    match dtype {
+        ValueType::Tensor { ty, shape, .. } => *ty == TensorElementType::Int64 && shape.len() == 2,
+        _ => false,
+    }
+}
+
+fn names_list(names: &[&str]) -> String {
+    names.join(", ")
+}
+
+impl Embedder {
+    /// Load a model with default options.
+    ///
+    /// `tokenizer_source` is either a HuggingFace tokenizer name or a path
+    /// to a local tokenizer.json.
+    pub fn new(model_path: &str, tokenizer_source: &str) -> Result<Self, EmbedError> {
+        Self::with_options(model_path, tokenizer_source, EmbedderOptions::default())
+    }
+
+    /// Load a sentence-transformers export directory: the model from
+    /// `onnx/model.onnx` or `model.onnx`, its tokenizer.json, and the
+    /// pooling and max_seq_length its config files give.
+    ///
+    /// ```no_run
+    /// let mut embedder = arrow_embed::Embedder::from_dir("models/all-MiniLM-L6-v2")?;
+    /// # Ok::<(), arrow_embed::EmbedError>(())
+    /// ```
+    pub fn from_dir(dir: &str) -> Result<Self, EmbedError> {
+        let found = ModelDir::locate(Path::new(dir))?;
+        let mut options = EmbedderOptions::default();
+        found.apply(&mut options);
+        Self::with_options(&found.model, &found.tokenizer, options)
+    }
+
+    /// Load a model and tokenizer held in memory, with default options.
+    ///
+    /// For models bundled into the executable where there is no filesystem
+    /// to load from. ONNX Runtime copies `model_bytes` while building the
+    /// session and the tokenizer is parsed into its own structures, so both
+    /// buffers can be dropped as soon as this returns.
+    ///
+    /// ```no_run
+    /// use arrow_embed::{Embedder, TokenizerSource};
+    ///
+    /// // Typically include_bytes!("model.onnx") and include_str!("tokenizer.json")
+    /// # let (model, tokenizer): (&[u8], &str) = (&[], "");
+    /// let embedder = Embedder::from_bytes(model, TokenizerSource::Json(tokenizer.to_string()))?;
+    /// # Ok::<(), arrow_embed::EmbedError>(())
+    /// ```
+    pub fn from_bytes(model_bytes: &[u8], tokenizer: TokenizerSource) -> Result<Self, EmbedError> {
+        Self::load(ModelSource::Memory(model_bytes), &tokenizer, EmbedderOptions::default())
+    }
+
+    /// Load a model with explicit options.
+    pub fn with_options(
+        model_path: &str,
+        tokenizer_source: &str,
+        options: EmbedderOptions,
+    ) -> Result<Self, EmbedError> {
+        let tokenizer = TokenizerSource::from_name_or_path(tokenizer_source);
+        Self::with_tokenizer(model_path, tokenizer, options)
+    }
+
+    /// Load a model with explicit options and a tokenizer given as a
+    /// [`TokenizerSource`] rather than a name or path to tell apart.
+    pub fn with_tokenizer(
+        model_path: &str,
+        tokenizer: TokenizerSource,
+        options: EmbedderOptions,
+    ) -> Result<Self, EmbedError> {
+        Self::load(ModelSource::File(model_path), &tokenizer, options)
+    }
+
+    fn load(
+        model: ModelSource<'_>,
+        tokenizer: &TokenizerSource,
+        mut options: EmbedderOptions,
+    ) -> Result<Self, EmbedError> {
+        if let Some(fixed_seq_len) = options.fixed_seq_len {
+            options.max_seq_len = fixed_seq_len;
+        }
+        init_environment(&options.environment_name, options.ort_log_level);
+
+        // Load model
+        let mut builder = Session::builder()
+            .map_err(|e| EmbedError::RuntimeInit(format!("creating session builder: {}", e)))?
+            .with_optimization_level(options.optimization.level())
+            .map_err(|e| EmbedError::ModelLoad(format!("setting optimization: {}", e)))?;
+        let threads = if options.deterministic { 1 } else { options.intra_threads };
+        if threads > 0 {
+            builder = builder
+                .with_intra_threads(threads)
+                .map_err(|e| EmbedError::ModelLoad(format!("setting threads: {}", e)))?;
+        }
+        if options.deterministic {
+            // Parallel reductions can sum in a different order on each run
+            builder = builder
+                .with_parallel_execution(false)
+                .and_then(|b| b.with_deterministic_compute(true))
+                .map_err(|e| EmbedError::ModelLoad(format!("enabling determinism: {}", e)))?;
+        } else if options.inter_threads > 0 {
+            // The inter-op pool is only used when operators run in parallel
+            builder = builder
+                .with_parallel_execution(true)
+                .and_then(|b| b.with_inter_threads(options.inter_threads))
+                .map_err(|e| EmbedError::ModelLoad(format!("setting inter-op threads: {}", e)))?;
+        }
+        builder = configure_memory(builder, &options)?;
+        let provider_warning = register_provider(&mut builder, options.execution_provider);
+        let session = match model {
+            ModelSource::File(path) => builder.commit_from_file(path),
+            ModelSource::Memory(bytes) => builder.commit_from_memory(bytes),
+        }
+        .map_err(|e| EmbedError::ModelLoad(e.to_string()))?;
+        // map_err expects a error handler 
+        // |e| is closure aka lambda capture group in cpp terms
+        // the part after |e| is the lambda body
+        // each line between a map_err is setting up params/opts for the session
+        let inputs = session.inputs().iter().map(|i| (i.name(), is_token_matrix(i.dtype())));
+        let inputs = ModelInputs::classify(inputs, &options.input_names)?;
+        let outputs = session.outputs().iter().map(|o| {
+            let shape = o.dtype().tensor_shape().map_or(&[][..], |shape| &shape[..]);
+            (o.name(), shape)
+        });
+        let (output_index, output) = EmbeddingOutput::select(outputs)?;
+        let declared_dim = session.outputs()[output_index]
+            .dtype()
+            .tensor_shape()
+            .and_then(|shape| static_hidden_size(shape, output.rank()));
+
+        // Load tokenizer
+        let mut tokenizer = tokenizer.load(options.tokenizer_retries)?;
+        configure_truncation(&mut tokenizer, &options)?;
+        let unknown_token = unknown_token_id(&tokenizer);
+
+        let mut embedder = Embedder {
+            session,
+            model_path: model.path().to_string(),
+            tokenizer,
+            max_seq_len: options.max_seq_len,
+            strict_length: options.strict_length,
+            fixed_seq_len: options.fixed_seq_len,
+            add_special_tokens: options.add_special_tokens,
+            trim_input: options.trim_input,
+            unknown_token,
+            max_unknown_fraction: options.max_unknown_fraction,
+            pooling: options.pooling,
+            normalization: options.normalization,
+            inputs,
+            output_index,
+            output,
+            dim: declared_dim.unwrap_or(0),
+            output_dim: None,
+            provider_warning,
+            query_prefix: options.query_prefix,
+            passage_prefix: options.passage_prefix,
+            buffers: InputBuffers::with_capacity(options.max_seq_len),
+            output_binding: None,
+            cache: EmbeddingCache::new(options.cache_capacity),
+            max_tokens_per_batch: options.max_tokens_per_batch,
+            timeout: options.timeout,
+            interrupt: Interrupt::default(),
+        };
+        if declared_dim.is_none() {
+            // Hidden size is symbolic in the graph; learn it from a real run
+            embedder.dim = embedder.embed_uncached(&["dimension probe"])?[0].len();
+        }
+        if let Some(output_dim) = options.output_dim {
+            if output_dim == 0 || output_dim > embedder.dim {
+                return Err(EmbedError::InvalidInput(format!(
+                    "output_dim must be between 1 and the model's {} dimensions, got {}",
+                    embedder.dim, output_dim
+                )));
+            }
+            embedder.output_dim = Some(output_dim);
+        }
+        if let Some(expected) = options.expected_dim.filter(|&dim| dim != embedder.dim()) {
+            return Err(EmbedError::DimensionMismatch {
+                expected,
+                actual: embedder.dim(),
+            });
+        }
+        Ok(embedder)
+    }
+
+    /// Length of the vectors this embedder produces, read from the model
+    /// or cut to `output_dim`.
+    pub fn dim(&self) -> usize {
+        self.output_dim.unwrap_or(self.dim)
+    }
+
+    /// Which kind of model output embeddings are read from
+    pub fn embedding_output(&self) -> EmbeddingOutput {
+        self.output
+    }
+
+    /// Name, sizes and inputs of the loaded model, for display before
+    /// embedding anything.
+    pub fn info(&self) -> ModelInfo {
+        let path = Path::new(&self.model_path);
+        let name = path.file_stem().map_or(self.model_path.clone(), |stem| {
+            stem.to_string_lossy().into_owned()
+        });
+        let description = self.session.metadata().ok().and_then(|m| m.description());
+        let max_seq_len = self
+            .tokenizer
+            .get_truncation()
+            .map_or(self.max_seq_len, |truncation| truncation.max_length);
+
+        ModelInfo {
+            quantized: looks_quantized(&name, description.as_deref()),
+            name,
+            dim: self.dim(),
+            max_seq_len,
+            input_names: self.session.inputs().iter().map(|i| i.name().to_string()).collect(),
+            output_name: self.session.outputs()[self.output_index].name().to_string(),
+            embedding_output: self.output,
+        }
+    }
+
+    /// Describe the model's inputs and outputs, one per line, e.g.
+    /// `input input_ids: Tensor<i64>(batch_size, sequence_length)`, then the
+    /// output embeddings come from, e.g. `embeddings last_hidden_state: pooled`.
+    pub fn model_info(&self) -> String {
+        let inputs = self.session.inputs().iter().map(|i| ("input", i.name(), i.dtype()));
+        let outputs = self.session.outputs().iter().map(|o| ("output", o.name(), o.dtype()));
+        let mut info: String = inputs
+            .chain(outputs)
+            .map(|(kind, name, dtype)| format!("{} {}: {}\n", kind, name, dtype))
+            .collect();
+        let source = match self.output {
+            EmbeddingOutput::TokenStates => "pooled",
+            EmbeddingOutput::SentenceEmbedding => "pooled by the model",
+        };
+        let name = self.session.outputs()[self.output_index].name();
+        info.push_str(&format!("embeddings {}: {}\n", name, source));
+        info
+    }
+
+    /// Look up an entry of the model's ONNX metadata, e.g. to check which
+    /// export was loaded.
+    ///
+    /// `producer_name`, `graph_name`, `description`, `graph_description`,
+    /// `domain` and `version` read the model's own fields; any other key is
+    /// looked up in its custom metadata map. `None` if the model doesn't set
+    /// the entry.
+    pub fn model_metadata(&self, key: &str) -> Option<String> {
+        let metadata = self.session.metadata().ok()?;
+        let value = match key {
+            "producer_name" => metadata.producer(),
+            "graph_name" => metadata.name(),
+            "description" => metadata.description(),
+            "graph_description" => metadata.graph_description(),
+            "domain" => metadata.domain(),
+            "version" => metadata.version().map(|version| version.to_string()),
+            _ => metadata.custom(key),
+        };
+        // Unset string fields read back as empty
+        value.filter(|value| !value.is_empty())
+    }
+
+    /// Why the requested execution provider was not used, if the embedder
+    /// fell back to CPU.
+    pub fn provider_warning(&self) -> Option<&str> {
+        self.provider_warning.as_deref()
+    }
+
+    /// Run throwaway inferences so ONNX Runtime allocates its kernels and
+    /// arenas now rather than on the first real requests.
+    ///
+    /// Each of the `iterations` rounds embeds one sequence of 16 tokens, one
+    /// of 128 and one of max_seq_len, whatever the embedder's length
+    /// settings, and discards the results. Safe to call again at any time.
+    /// Returns how long it took, for logging.
+    pub fn warmup(&mut self, iterations: usize) -> Result<Duration, EmbedError> {
+        let start = Instant::now();
+        let mut lengths = warmup_seq_lens(self.max_seq_len);
+        // A fixed sequence length pads them all to the same shape
+        lengths.dedup_by_key(|len| self.padded_len(*len));
+        let text = "warmup ".repeat(self.max_seq_len);
+        let longest = self.encode_batch(&[&text])?.remove(0);
+        let encodings: Vec<Encoding> = lengths
+            .into_iter()
+            .map(|len| {
+                let mut encoding = longest.clone();
+                encoding.truncate(len, 0, TruncationDirection::Right);
+                encoding
+            })
+            .collect();
+
+        for _ in 0..iterations {
+            for encoding in &encodings {
+                self.embed_pass([encoding])?;
+            }
+        }
+        Ok(start.elapsed())
+    }
+
+    /// Check that this environment embeds the bundled reference texts the
+    /// way sentence-transformers' all-MiniLM-L6-v2 does, e.g. after an ONNX
+    /// Runtime or tokenizer upgrade and before trusting a stored index.
+    ///
+    /// Compares every reference pair's cosine similarity with its expected
+    /// value, and each text embedded in one batch with the same text alone.
+    /// A model that doesn't match is reported, not an error: other models
+    /// are not expected to pass. The cache is bypassed.
+    pub fn self_test(&mut self) -> Result<SelfTestReport, EmbedError> {
+        let pairs = self_test::reference_pairs();
+        let mut texts: Vec<&str> = pairs.iter().flat_map(|pair| [pair.a, pair.b]).collect();
+        texts.sort_unstable();
+        texts.dedup();
+
+        let batch = self.embed_uncached(&texts)?;
+        let mut batch_deviation = 0.0f32;
+        for (text, batched) in texts.iter().zip(&batch) {
+            let single = self.embed_uncached(&[text])?;
+            let deviation = batched.iter().zip(&single[0]).map(|(a, b)| (a - b).abs());
+            batch_deviation = deviation.fold(batch_deviation, f32::max);
+        }
+
+        let embedding = |text: &str| {
+            let i = texts.binary_search(&text).expect("every pair text was embedded");
+            &batch[i][..]
+        };
+        Ok(self_test::compare(&pairs, embedding, batch_deviation))
+    }
+
+    /// Hit and miss counts of the embedding cache set by `cache_capacity`
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Embed a single text into a vector, L2-normalized unless configured
+    /// otherwise.
+    pub fn embed(&mut self, text: &str) -> Result<Vec<f32>, EmbedError> {
+        let mut embeddings = self.embed_batch(&[text])?;
+        embeddings
+            .pop()
+            .ok_or_else(|| EmbedError::Inference("no embeddings returned".to_string()))
+    }
+
+    /// Embed a single text as [`embed`](Self::embed) does, failing with
+    /// [`EmbedError::Timeout`] rather than running longer than `timeout`.
+    ///
+    /// The model pass in flight when time runs out is terminated, so the
+    /// call returns shortly after the deadline instead of blocking.
+    pub fn embed_with_deadline(
+        &mut self,
+        text: &str,
+        timeout: Duration,
+    ) -> Result<Vec<f32>, EmbedError> {
+        self.interrupted(Interrupt::after(timeout), |embedder| embedder.embed(text))
+    }
+
+    /// Embed several texts as [`embed_batch`](Self::embed_batch) does,
+    /// failing with [`EmbedError::Timeout`] rather than running longer than
+    /// `timeout`.
+    pub fn embed_batch_with_deadline(
+        &mut self,
+        texts: &[&str],
+        timeout: Duration,
+    ) -> Result<Vec<Vec<f32>>, EmbedError> {
+        self.interrupted(Interrupt::after(timeout), |embedder| embedder.embed_batch(texts))
+    }
+
+    /// Embed several texts as [`embed_batch`](Self::embed_batch) does,
+    /// failing with [`EmbedError::Cancelled`] once `token` is cancelled,
+    /// even from another thread while the model is running.
+    pub fn embed_batch_cancellable(
+        &mut self,
+        texts: &[&str],
+        token: &CancelToken,
+    ) -> Result<Vec<Vec<f32>>, EmbedError> {
+        self.interrupted(Interrupt::on_cancel(token), |embedder| embedder.embed_batch(texts))
+    }
+
+    /// Run `call` with `interrupt` limiting each of its model passes
+    fn interrupted<T>(
+        &mut self,
+        interrupt: Interrupt,
+        call: impl FnOnce(&mut Self) -> Result<T, EmbedError>,
+    ) -> Result<T, EmbedError> {
+        let previous = std::mem::replace(&mut self.interrupt, interrupt);
+        // Restored even on panic, since callers such as AsyncEmbedder keep
+        // using an embedder after catching one
+        let result = panic::catch_unwind(AssertUnwindSafe(|| call(self)));
+        self.interrupt = previous;
+        result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    /// What may stop the next model pass: the call's deadline or token, or
+    /// failing those the configured timeout
+    fn pass_interrupt(&self) -> Interrupt {
+        match self.timeout {
+            Some(timeout) if !self.interrupt.is_set() => Interrupt::after(timeout),
+            _ => self.interrupt.clone(),
+        }
+    }
+
+    /// Embed a single text as half-precision floats, half the size of
+    /// [`embed`](Self::embed)'s output. Inference still runs in f32; only the
+    /// result is rounded.
+    pub fn embed_f16(&mut self, text: &str) -> Result<Vec<f16>, EmbedError> {
+        self.embed(text).map(|embedding| to_f16(&embedding))
+    }
+
+    /// Embed a sentence pair, such as a premise and hypothesis, as one
+    /// sequence the way BERT-style models are trained on pairs: [CLS] a
+    /// [SEP] b [SEP], with token_type_ids of 0 for `a` and 1 for `b`.
+    ///
+    /// Joining the texts with a space instead loses the segment boundary.
+    /// Pairs longer than max_seq_len are truncated from the longer text, or
+    /// rejected in strict mode.
+    pub fn embed_pair(&mut self, a: &str, b: &str) -> Result<Vec<f32>, EmbedError> {
+        let pair = (self.clean(a).into_owned(), self.clean(b).into_owned());
+        let encoding = self
+            .tokenizer
+            .encode(pair, self.add_special_tokens)
+            .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+        self.embed_encodings(&[encoding])?
+            .pop()
+            .ok_or_else(|| EmbedError::Inference("no embeddings returned".to_string()))
+    }
+
+    /// Embed two texts in one inference pass and return their cosine similarity.
+    pub fn similarity(&mut self, a: &str, b: &str) -> Result<f32, EmbedError> {
+        let mut embeddings = self.embed_batch(&[a, b])?;
+        if self.normalization != Normalization::L2 {
+            embeddings.iter_mut().for_each(|e| normalize_in_place(e));
+        }
+        Ok(cosine_similarity(&embeddings[0], &embeddings[1]))
+    }
+
+    /// Cosine similarity of every pair of `texts`, embedded in one batch.
+    ///
+    /// Returns a row-major `n * n` matrix where entry `i * n + j` compares
+    /// `texts[i]` with `texts[j]`; the diagonal is 1.
+    pub fn similarity_matrix(&mut self, texts: &[&str]) -> Result<Vec<f32>, EmbedError> {
+        let mut embeddings = self.embed_batch(texts)?;
+        if self.normalization != Normalization::L2 {
+            embeddings.iter_mut().for_each(|e| normalize_in_place(e));
+        }
+        Ok(similarity_matrix(&embeddings))
+    }
+
+    /// Token ids `embed` would feed the model for `text`, including special
+    /// tokens and after truncation.
+    pub fn tokenize(&self, text: &str) -> Result<Vec<u32>, EmbedError> {
+        let encoding = self
+            .tokenizer
+            .encode(self.clean(text), self.add_special_tokens)
+            .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+        Ok(encoding.get_ids().to_vec())
+    }
+
+    /// Embed a sequence tokenized elsewhere, skipping the tokenizer.
+    ///
+    /// `input_ids` must already include the special tokens the model
+    /// expects, as [`tokenize`](Self::tokenize) returns them. Sequences
+    /// longer than max_seq_len are rejected rather than truncated, since
+    /// cutting them would drop the closing [SEP].
+    pub fn embed_ids(
+        &mut self,
+        input_ids: &[i64],
+        attention_mask: &[i64],
+    ) -> Result<Vec<f32>, EmbedError> {
+        if input_ids.len() != attention_mask.len() {
+            return Err(EmbedError::InvalidInput(format!(
+                "{} input ids but {} attention mask values",
+                input_ids.len(),
+                attention_mask.len()
+            )));
+        }
+        if !attention_mask.iter().any(|&mask| mask > 0) {
+            return Err(EmbedError::EmptyInput);
+        }
+        if input_ids.len() > self.max_seq_len {
+            return Err(EmbedError::InputTooLong {
+                tokens: input_ids.len(),
+                max_seq_len: self.max_seq_len,
+            });
+        }
+
+        let seq_len = self.padded_len(input_ids.len());
+        self.buffers.fill_ids(input_ids, attention_mask, seq_len);
+        let pooled = self.run_inference(1, seq_len)?;
+        let embeddings = normalize_rows(self.truncate_dims(pooled), self.normalization);
+        Ok(embeddings.row(0).to_vec())
+    }
+
+    /// Number of tokens in `text` before truncation, including special tokens.
+    ///
+    /// A count above `max_seq_len` means `embed` keeps only the first
+    /// `max_seq_len` tokens, or rejects the text in strict mode.
+    ///
+    /// Only the tokenizer is used; no inference runs.
+    pub fn count_tokens(&self, text: &str) -> Result<usize, EmbedError> {
+        count_tokens(&self.tokenizer, &self.clean(text), self.add_special_tokens)
+    }
+
+    /// Number of tokens in `text` with the prefix `kind` calls for, which
+    /// counts against max_seq_len like the text itself.
+    pub fn count_tokens_as(&self, kind: EmbedKind, text: &str) -> Result<usize, EmbedError> {
+        self.count_tokens(&self.prefixed(kind, text))
+    }
+
+    /// `text` with the configured prefix for `kind` in front
+    fn prefixed<'a>(&self, kind: EmbedKind, text: &'a str) -> Cow<'a, str> {
+        let prefix = match kind {
+            EmbedKind::Query => &self.query_prefix,
+            EmbedKind::Document => &self.passage_prefix,
+            EmbedKind::Raw => return Cow::Borrowed(text),
+        };
+        if prefix.is_empty() {
+            Cow::Borrowed(text)
+        } else {
+            Cow::Owned(format!("{}{}", prefix, text))
+        }
+    }
+
+    /// `text` as it is tokenized: trimmed and with whitespace collapsed if
+    /// `trim_input` is set, otherwise unchanged
+    fn clean<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.trim_input {
+            Cow::Owned(collapse_whitespace(text))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Embed `text` with `prefix` prepended before tokenization, as
+    /// instruction-tuned models such as E5 and Instructor expect.
+    pub fn embed_with_prefix(&mut self, prefix: &str, text: &str) -> Result<Vec<f32>, EmbedError> {
+        self.embed(&format!("{}{}", prefix, text))
+    }
+
+    /// Embed `text` with the prefix `kind` calls for, applied before
+    /// tokenization so it counts against max_seq_len.
+    pub fn embed_as(&mut self, kind: EmbedKind, text: &str) -> Result<Vec<f32>, EmbedError> {
+        let text = self.prefixed(kind, text);
+        self.embed(&text)
+    }
+
+    /// Embed a search query with the configured `query_prefix`.
+    pub fn embed_query(&mut self, text: &str) -> Result<Vec<f32>, EmbedError> {
+        self.embed_as(EmbedKind::Query, text)
+    }
+
+    /// Embed a document to search over with the configured `passage_prefix`.
+    pub fn embed_passage(&mut self, text: &str) -> Result<Vec<f32>, EmbedError> {
+        self.embed_as(EmbedKind::Document, text)
+    }
+
+    /// Embed several texts with a single inference pass.
+    ///
+    /// Every sequence is padded to the longest one in the batch. Padded
+    /// positions carry a zero attention mask so mean pooling ignores them.
+    /// Texts longer than max_seq_len are truncated, or rejected in strict mode.
+    ///
+    /// With a `cache_capacity` set, cached texts are answered from the cache
+    /// and only the rest go through the model.
+    ///
+    /// ```no_run
+    /// # let mut embedder = arrow_embed::Embedder::new("model.onnx", "tokenizer.json")?;
+    /// let embeddings = embedder.embed_batch(&["first document", "second document"])?;
+    /// assert_eq!(embeddings.len(), 2);
+    /// # Ok::<(), arrow_embed::EmbedError>(())
+    /// ```
+    pub fn embed_batch(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        if !self.cache.is_enabled() {
+            return self.embed_uncached(texts);
+        }
+
+        let mut embeddings: Vec<Option<Vec<f32>>> =
+            texts.iter().map(|text| self.cache.get(text)).collect();
+        let misses: Vec<&str> = texts
+            .iter()
+            .zip(&embeddings)
+            .filter(|(_, cached)| cached.is_none())
+            .map(|(text, _)| *text)
+            .collect();
+        if !misses.is_empty() {
+            let mut computed = self.embed_uncached(&misses)?.into_iter();
+            for (text, slot) in texts.iter().zip(&mut embeddings) {
+                if slot.is_none() {
+                    let embedding = computed.next().expect("one embedding per miss");
+                    self.cache.insert(text, embedding.clone());
+                    *slot = Some(embedding);
+                }
+            }
+        }
+        Ok(embeddings.into_iter().map(|e| e.expect("every text embedded")).collect())
+    }
+
+    /// Embed a large batch as [`embed_batch`](Self::embed_batch) does, in
+    /// passes of 64 texts, calling `progress(done, total)` after each.
+    ///
+    /// Returning [`ControlFlow::Break`] from `progress` stops the batch; the
+    /// call then fails with [`EmbedError::Cancelled`] and the embeddings of
+    /// the passes already run are dropped.
+    ///
+    /// ```no_run
+    /// # let mut embedder = arrow_embed::Embedder::new("model.onnx", "tokenizer.json")?;
+    /// use std::ops::ControlFlow;
+    ///
+    /// let texts = vec!["a document"; 1000];
+    /// let embeddings = embedder.embed_batch_with_progress(&texts, |done, total| {
+    ///     eprintln!("{}/{}", done, total);
+    ///     ControlFlow::Continue(())
+    /// })?;
+    /// assert_eq!(embeddings.len(), 1000);
+    /// # Ok::<(), arrow_embed::EmbedError>(())
+    /// ```
+    pub fn embed_batch_with_progress(
+        &mut self,
+        texts: &[&str],
+        progress: impl FnMut(usize, usize) -> ControlFlow<()>,
+    ) -> Result<Vec<Vec<f32>>, EmbedError> {
+        embed_in_passes(texts, |batch| self.embed_batch(batch), progress)
+    }
+
+    /// Tokenize `texts` and run them through the model, bypassing the cache
+    fn embed_uncached(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let encodings = self.encode_batch(texts)?;
+        self.embed_encodings(&encodings)
+    }
+
+    /// Embed `texts` into one row-major buffer of `texts.len() * dim()`
+    /// floats, embedding `i` at `[i * dim()..]`.
+    ///
+    /// Same vectors as [`embed_batch`](Self::embed_batch), but with the
+    /// cache disabled the pooled matrix itself is returned, skipping one
+    /// allocation and copy per text.
+    pub fn embed_batch_flat(&mut self, texts: &[&str]) -> Result<Vec<f32>, EmbedError> {
+        if self.cache.is_enabled() {
+            return Ok(self.embed_batch(texts)?.concat());
+        }
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let encodings = self.encode_batch(texts)?;
+        let embeddings = self.embed_encodings_matrix(&encodings)?;
+        Ok(into_row_major(embeddings))
+    }
+
+    /// Clean and tokenize `texts`
+    fn encode_batch(&self, texts: &[&str]) -> Result<Vec<Encoding>, EmbedError> {
+        let texts: Vec<Cow<str>> = texts.iter().map(|text| self.clean(text)).collect();
+        self.tokenizer
+            .encode_batch(texts, self.add_special_tokens)
+            .map_err(|e| EmbedError::Tokenization(e.to_string()))
+    }
+
+    /// Embed a text of any length by splitting it into overlapping windows
+    /// of whole tokens.
+    ///
+    /// Each chunk holds at most `chunk_tokens` tokens including [CLS]/[SEP],
+    /// and repeats the last `overlap` tokens of the previous chunk. A text
+    /// shorter than one chunk is embedded as a single chunk. Returns one
+    /// embedding for [`ChunkAggregation::MeanOfChunks`], or one per chunk for
+    /// [`ChunkAggregation::ReturnAll`].
+    ///
+    /// Fails with [`EmbedError::InvalidInput`] if `chunk_tokens` exceeds
+    /// max_seq_len or leaves no room for more than `overlap` text tokens.
+    pub fn embed_long(
+        &mut self,
+        text: &str,
+        chunk_tokens: usize,
+        overlap: usize,
+        aggregation: ChunkAggregation,
+    ) -> Result<Vec<Vec<f32>>, EmbedError> {
+        if chunk_tokens > self.max_seq_len {
+            return Err(EmbedError::InvalidInput(format!(
+                "chunk_tokens {} exceeds max_seq_len {}",
+                chunk_tokens, self.max_seq_len
+            )));
+        }
+        let chunks = chunk_encodings(
+            &self.tokenizer,
+            &self.clean(text),
+            chunk_tokens,
+            overlap,
+            self.add_special_tokens,
+        )?;
+
+        let mut embeddings = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(CHUNKS_PER_PASS) {
+            embeddings.extend(self.embed_encodings(batch)?);
+        }
+
+        match aggregation {
+            ChunkAggregation::ReturnAll => Ok(embeddings),
+            ChunkAggregation::MeanOfChunks => {
+                let mut mean = vec![0.0; self.dim()];
+                for embedding in &embeddings {
+                    mean.iter_mut().zip(embedding).for_each(|(m, v)| *m += v);
+                }
+                mean.iter_mut().for_each(|m| *m /= embeddings.len() as f32);
+                self.normalization.apply((&mut mean[..]).into());
+                Ok(vec![mean])
+            }
+        }
+    }
+
+    /// Embed a document of any length as one vector, from windows of
+    /// `window_tokens` tokens, special tokens included, that start every
+    /// `stride` text tokens.
+    ///
+    /// The text is tokenized once and each window's ids embedded with
+    /// [`embed_ids`](Self::embed_ids); the window vectors are averaged and
+    /// the mean scaled to unit L2 norm. Fails with
+    /// [`EmbedError::InvalidInput`] if `window_tokens` exceeds max_seq_len,
+    /// or if `stride` is 0 or longer than a window's text, which would skip
+    /// tokens.
+    pub fn embed_document(
+        &mut self,
+        text: &str,
+        window_tokens: usize,
+        stride: usize,
+    ) -> Result<Vec<f32>, EmbedError> {
+        if window_tokens > self.max_seq_len {
+            return Err(EmbedError::InvalidInput(format!(
+                "window_tokens {} exceeds max_seq_len {}",
+                window_tokens, self.max_seq_len
+            )));
+        }
+        let overlap =
+            stride_overlap(&self.tokenizer, window_tokens, stride, self.add_special_tokens)?;
+        let windows = chunk_encodings(
+            &self.tokenizer,
+            &self.clean(text),
+            window_tokens,
+            overlap,
+            self.add_special_tokens,
+        )?;
+        self.check_encodings(&windows)?;
+
+        let mut mean = vec![0.0; self.dim()];
+        for window in &windows {
+            let ids: Vec<i64> = window.get_ids().iter().map(|&id| id as i64).collect();
+            let mask: Vec<i64> = window.get_attention_mask().iter().map(|&m| m as i64).collect();
+            let embedding = self.embed_ids(&ids, &mask)?;
+            mean.iter_mut().zip(&embedding).for_each(|(m, v)| *m += v);
+        }
+        mean.iter_mut().for_each(|m| *m /= windows.len() as f32);
+        normalize_in_place(&mut mean);
+        Ok(mean)
+    }
+
+    /// Run the model over already tokenized sequences and pool the output
+    fn embed_encodings(&mut self, encodings: &[Encoding]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let embeddings = self.embed_encodings_matrix(encodings)?;
+        Ok(embeddings.rows().into_iter().map(|row| row.to_vec()).collect())
+    }
+
+    /// Pooled, normalized embeddings of `encodings`, one row each.
+    ///
+    /// Batches padding to more than `max_tokens_per_batch` tokens run as
+    /// several passes of similar-length sequences, put back in input order.
+    fn embed_encodings_matrix(
+        &mut self,
+        encodings: &[Encoding],
+    ) -> Result<Array2<f32>, EmbedError> {
+        self.check_encodings(encodings)?;
+        let lengths: Vec<usize> = encodings.iter().map(|e| e.len()).collect();
+        let passes = plan_passes(&lengths, |len| self.padded_len(len), self.max_tokens_per_batch);
+        if let [_] = &passes[..] {
+            return self.embed_pass(encodings);
+        }
+
+        let mut embeddings: Option<Array2<f32>> = None;
+        for pass in passes {
+            let pooled = self.embed_pass(pass.iter().map(|&i| &encodings[i]))?;
+            let embeddings = embeddings
+                .get_or_insert_with(|| Array2::zeros((encodings.len(), pooled.ncols())));
+            for (row, &i) in pooled.rows().into_iter().zip(&pass) {
+                embeddings.row_mut(i).assign(&row);
+            }
+        }
+        Ok(embeddings.expect("several passes ran"))
+    }
+
+    /// Run one inference pass over `encodings`, padded to the longest
+    fn embed_pass<'e, I>(&mut self, encodings: I) -> Result<Array2<f32>, EmbedError>
+    where
+        I: IntoIterator<Item = &'e Encoding>,
+        I::IntoIter: ExactSizeIterator + Clone,
+    {
+        let encodings = encodings.into_iter();
+        let batch_size = encodings.len();
+        let seq_len = self.padded_len(encodings.clone().map(|e| e.len()).max().unwrap_or(0));
+        self.buffers.fill(encodings, seq_len);
+        let pooled = self.run_inference(batch_size, seq_len)?;
+
+        Ok(normalize_rows(self.truncate_dims(pooled), self.normalization))
+    }
+
+    /// Keep the first `output_dim` dimensions of each pooled embedding
+    fn truncate_dims(&self, pooled: Array2<f32>) -> Array2<f32> {
+        match self.output_dim {
+            Some(dim) => pooled.slice_move(s![.., ..dim]),
+            None => pooled,
+        }
+    }
+
+    /// Length sequences are padded to when the longest is `longest` tokens:
+    /// `fixed_seq_len` if set, since the model accepts no other
+    fn padded_len(&self, longest: usize) -> usize {
+        self.fixed_seq_len.unwrap_or(longest)
+    }
+
+    /// Fail on sequences that can't be embedded: those with nothing but
+    /// special tokens, and in strict mode those over max_seq_len
+    fn check_encodings(&self, encodings: &[Encoding]) -> Result<(), EmbedError> {
+        // Pooling nothing but [CLS]/[SEP] gives a meaningless vector
+        if !encodings.iter().all(has_content) {
+            return Err(EmbedError::EmptyInput);
+        }
+        if self.strict_length
+            && let Some(encoding) = encodings.iter().find(|e| e.len() > self.max_seq_len)
+        {
+            return Err(EmbedError::InputTooLong {
+                tokens: encoding.len(),
+                max_seq_len: self.max_seq_len,
+            });
+        }
+        if let Some(unknown_token) = self.unknown_token {
+            for encoding in encodings {
+                let (unknown, tokens) = unknown_tokens(encoding, unknown_token);
+                if unknown as f32 > self.max_unknown_fraction * tokens as f32 {
+                    return Err(EmbedError::TooManyUnknownTokens { unknown, tokens });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `text` through the model and return its token vectors before
+    /// pooling, `[1, seq_len, hidden]`, with the `[1, seq_len]` attention
+    /// mask, for pooling strategies this crate doesn't implement.
+    ///
+    /// Token vectors come from last_hidden_state, or failing that the first
+    /// `[batch, seq_len, hidden]` output. The cache, pooling and
+    /// normalization options don't apply. Fails with
+    /// [`EmbedError::InvalidInput`] if the model only exports pooled
+    /// embeddings.
+    pub fn encode_hidden(&mut self, text: &str) -> Result<(ArrayD<f32>, Array2<i64>), EmbedError> {
+        let encoding = self
+            .tokenizer
+            .encode(self.clean(text), self.add_special_tokens)
+            .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+        self.hidden_states(&encoding)
+    }
+
+    /// Embed each token of `text` for late-interaction scoring such as
+    /// ColBERT's MaxSim, returning the row-major `[tokens, hidden]` vectors
+    /// with their row and column counts.
+    ///
+    /// Padding never appears. With `skip_special_tokens`, neither do
+    /// [CLS]/[SEP], which usually shouldn't take part in MaxSim; with
+    /// `normalize`, each token vector is scaled to unit L2 norm.
+    pub fn embed_tokens(
+        &mut self,
+        text: &str,
+        normalize: bool,
+        skip_special_tokens: bool,
+    ) -> Result<(Vec<f32>, usize, usize), EmbedError> {
+        let encoding = self
+            .tokenizer
+            .encode(self.clean(text), self.add_special_tokens)
+            .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+        let (hidden, mask) = self.hidden_states(&encoding)?;
+        let hidden = hidden
+            .into_dimensionality::<ndarray::Ix3>()
+            .map_err(|e| EmbedError::ShapeMismatch(format!("reading output tensor: {}", e)))?;
+
+        let cols = hidden.dim().2;
+        let mut data = Vec::with_capacity(hidden.len());
+        let mut rows = 0;
+        let tokens = hidden.index_axis(Axis(0), 0);
+        let special = encoding.get_special_tokens_mask();
+        for ((token, &mask), &special) in tokens.outer_iter().zip(mask.row(0)).zip(special) {
+            if mask == 0 || (skip_special_tokens && special != 0) {
+                continue;
+            }
+            let start = data.len();
+            data.extend(token.iter());
+            if normalize {
+                Normalization::L2.apply((&mut data[start..]).into());
+            }
+            rows += 1;
+        }
+        Ok((data, rows, cols))
+    }
+
+    /// Embed `text` both mean-pooled and by its [CLS] vector from a single
+    /// inference pass, returning `(mean, cls)`, each cut to `output_dim` and
+    /// normalized as [`embed`](Self::embed) would.
+    ///
+    /// The configured pooling and the cache are bypassed. Fails with
+    /// [`EmbedError::InvalidInput`] if the model only exports pooled
+    /// embeddings.
+    pub fn embed_multi(&mut self, text: &str) -> Result<(Vec<f32>, Vec<f32>), EmbedError> {
+        let encoding = self
+            .tokenizer
+            .encode(self.clean(text), self.add_special_tokens)
+            .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+        let (hidden, mask) = self.hidden_states(&encoding)?;
+        let hidden = hidden
+            .into_dimensionality::<ndarray::Ix3>()
+            .map_err(|e| EmbedError::ShapeMismatch(format!("reading output tensor: {}", e)))?;
+
+        let [mean, cls] = [mean_pooling(hidden.view(), mask.view()), cls_pooling(hidden.view())]
+            .map(|pooled| normalize_rows(self.truncate_dims(pooled), self.normalization));
+        Ok((mean.row(0).to_vec(), cls.row(0).to_vec()))
+    }
+
+    /// Run one tokenized text through the model and return its token vectors
+    /// and attention mask, as [`encode_hidden`](Self::encode_hidden) does
+    fn hidden_states(
+        &mut self,
+        encoding: &Encoding,
+    ) -> Result<(ArrayD<f32>, Array2<i64>), EmbedError> {
+        let outputs: Vec<_> = self
+            .session
+            .outputs()
+            .iter()
+            .map(|o| (o.name(), o.dtype().tensor_shape().map_or(&[][..], |shape| &shape[..])))
+            .collect();
+        let Some(index) = token_states_output(&outputs) else {
+            return Err(EmbedError::InvalidInput(format!(
+                "model has no [batch, seq_len, hidden] output (outputs: {})",
+                names_list(&outputs.iter().map(|(name, _)| *name).collect::<Vec<_>>())
+            )));
+        };
+
+        self.check_encodings(std::slice::from_ref(encoding))?;
+        let shape = [1, self.padded_len(encoding.len())];
+        self.buffers.fill([encoding], shape[1]);
+
+        let run = self.pass_interrupt().start()?;
+        let session_inputs = session_inputs(&self.inputs, &self.buffers, shape)?;
+        let outputs = run.run(&mut self.session, session_inputs)?;
+        let hidden = outputs[index]
+            .try_extract_array::<f32>()
+            .map_err(|e| EmbedError::ShapeMismatch(format!("extracting output tensor: {}", e)))?
+            .to_owned();
+        let attention_mask = Array2::from_shape_vec(shape, self.buffers.attention_mask.clone())
+            .map_err(|e| EmbedError::ShapeMismatch(format!("reading attention mask: {}", e)))?;
+        Ok((hidden, attention_mask))
+    }
+
+    /// Run the model over the filled input buffers and pool its output,
+    /// reading the output tensor in place rather than copying it.
+    ///
+    /// Once the hidden size is known the output is bound to a tensor the
+    /// embedder keeps, reused for every pass of the same padded shape.
+    fn run_inference(
+        &mut self,
+        batch_size: usize,
+        seq_len: usize,
+    ) -> Result<Array2<f32>, EmbedError> {
+        let shape = [batch_size, seq_len];
+        let buffers = &self.buffers;
+        let run = self.pass_interrupt().start()?;
+        // Until the hidden size is learned the output can't be preallocated
+        let outputs = if self.dim == 0 {
+            let session_inputs = session_inputs(&self.inputs, buffers, shape)?;
+            run.run(&mut self.session, session_inputs)?
+        } else {
+            let output_shape = match self.output {
+                EmbeddingOutput::TokenStates => vec![batch_size, seq_len, self.dim],
+                EmbeddingOutput::SentenceEmbedding => vec![batch_size, self.dim],
+            };
+            let session = &self.session;
+            let binding =
+                bind_output(&mut self.output_binding, session, self.output_index, output_shape)?;
+            bind_inputs(binding, &self.inputs, buffers, shape)?;
+            run.run_binding(&mut self.session, binding)?
+        };
+        // A bound run returns only the bound output
+        let output = if self.dim == 0 { &outputs[self.output_index] } else { &outputs[0] };
+
+        let (output_shape, data) = output
+            .try_extract_tensor::<f32>()
+            .map_err(|e| EmbedError::ShapeMismatch(format!("extracting output tensor: {}", e)))?;
+
+        if self.output == EmbeddingOutput::SentenceEmbedding {
+            // Already pooled by the model
+            let &[out_batch, hidden_size] = &output_shape[..] else {
+                return Err(EmbedError::ShapeMismatch(format!(
+                    "expected [batch, hidden] output, got {:?}",
+                    output_shape
+                )));
+            };
+            let hidden_size = hidden_size as usize;
+            check_dim(self.dim, hidden_size)?;
+            return ArrayView2::from_shape((out_batch as usize, hidden_size), data)
+                .map(|embeddings| embeddings.to_owned())
+                .map_err(|e| EmbedError::ShapeMismatch(format!("reading output tensor: {}", e)));
+        }
+
+        let &[out_batch, out_seq, hidden_size] = &output_shape[..] else {
+            return Err(EmbedError::ShapeMismatch(format!(
+                "expected [batch, seq_len, hidden] output, got {:?}",
+                output_shape
+            )));
+        };
+        let hidden_size = hidden_size as usize;
+        check_dim(self.dim, hidden_size)?;
+        let last_hidden_state =
+            ArrayView3::from_shape((out_batch as usize, out_seq as usize, hidden_size), data)
+                .map_err(|e| EmbedError::ShapeMismatch(format!("reading output tensor: {}", e)))?;
+        let attention_mask = ArrayView2::from_shape(shape, &buffers.attention_mask)
+            .map_err(|e| EmbedError::ShapeMismatch(format!("reading attention mask: {}", e)))?;
+
+        // Pooling
+        Ok(match self.pooling {
+            PoolingStrategy::Mean => mean_pooling(last_hidden_state, attention_mask),
+            PoolingStrategy::Cls => cls_pooling(last_hidden_state),
+            PoolingStrategy::Max => max_pooling(last_hidden_state, attention_mask),
+        })
+    }
+}
+
+/// Fail if the model produced vectors of another length than the `dim`
+/// learned at load time, 0 while it is still being learned
+fn check_dim(dim: usize, hidden_size: usize) -> Result<(), EmbedError> {
+    if dim != 0 && hidden_size != dim {
+        return Err(EmbedError::ShapeMismatch(format!(
+            "model produced {}-dimensional vectors, expected {}",
+            hidden_size, dim
+        )));
+    }
+    Ok(())
+}
+
+/// The filled input buffers as `shape` tensors under the graph's own input
+/// names, feeding only the inputs it declares since ORT rejects unknown ones
+pub(crate) fn session_inputs<'a>(
+    names: &'a ModelInputs,
+    buffers: &'a InputBuffers,
+    shape: [usize; 2],
+) -> Result<Vec<(Cow<'a, str>, SessionInputValue<'a>)>, EmbedError> {
+    let tensor = |data, name| input_tensor(shape, data, name);
+    let mut session_inputs =
+        inputs![names.input_ids.as_str() => tensor(&buffers.input_ids, "input_ids")?];
+    if let Some(name) = &names.attention_mask {
+        let attention_mask = tensor(&buffers.attention_mask, "attention_mask")?;
+        session_inputs.push((name.as_str().into(), attention_mask.into()));
+    }
+    if let Some(name) = &names.token_type_ids {
+        let token_type_ids = tensor(&buffers.token_type_ids, "token_type_ids")?;
+        session_inputs.push((name.as_str().into(), token_type_ids.into()));
+    }
+    Ok(session_inputs)
+}
+
+/// `binding`, created on first use, with output `output_index` of
+/// `session` bound to a tensor of `shape`. The tensor is only reallocated
+/// when a pass's padded shape differs from the one before it.
+fn bind_output<'b>(
+    binding: &'b mut Option<OutputBinding>,
+    session: &Session,
+    output_index: usize,
+    shape: Vec<usize>,
+) -> Result<&'b mut IoBinding, EmbedError> {
+    if binding.as_ref().is_none_or(|bound| bound.shape != shape) {
+        let name = session.outputs()[output_index].name();
+        let bind_error = |e| EmbedError::Inference(format!("binding {} output: {}", name, e));
+        let mut io_binding = match binding.take() {
+            Some(bound) => bound.binding,
+            None => session.create_binding().map_err(bind_error)?,
+        };
+        let tensor = Tensor::<f32>::new(&Allocator::default(), shape.clone()).map_err(bind_error)?;
+        io_binding.bind_output(name, tensor).map_err(bind_error)?;
+        *binding = Some(OutputBinding { binding: io_binding, shape });
+    }
+    Ok(&mut binding.as_mut().expect("output bound above").binding)
+}
+
+/// Bind the filled input buffers to `binding` as `shape` tensors under the
+/// names [`session_inputs`] feeds them by. On CPU ONNX Runtime reads them
+/// in place, so they are bound again before every run.
+fn bind_inputs(
+    binding: &mut IoBinding,
+    names: &ModelInputs,
+    buffers: &InputBuffers,
+    shape: [usize; 2],
+) -> Result<(), EmbedError> {
+    let mut bind = |name: &str, data: &[i64], input: &str| {
+        let tensor = input_tensor(shape, data, input)?;
+        binding
+            .bind_input(name, &*tensor)
+            .map_err(|e| EmbedError::Inference(format!("binding {} input: {}", input, e)))
+    };
+    bind(&names.input_ids, &buffers.input_ids, "input_ids")?;
+    if let Some(name) = &names.attention_mask {
+        bind(name, &buffers.attention_mask, "attention_mask")?;
+    }
+    if let Some(name) = &names.token_type_ids {
+        bind(name, &buffers.token_type_ids, "token_type_ids")?;
+    }
+    Ok(())
+}
+
+/// Borrow one of the input buffers as a `[batch, seq_len]` tensor
+fn input_tensor<'a>(
+    shape: [usize; 2],
+    data: &'a [i64],
+    name: &str,
+) -> Result<TensorRef<'a, i64>, EmbedError> {
+    TensorRef::from_array_view((shape, data))
+        .map_err(|e| EmbedError::Inference(format!("creating {} tensor: {}", name, e)))
+}
+
+/// Hidden size from the last dimension of an output shape of `rank`
+/// dimensions, if it is fixed
+fn static_hidden_size(shape: &[i64], rank: usize) -> Option<usize> {
+    match shape.last() {
+        Some(&hidden) if shape.len() == rank && hidden > 0 => Some(hidden as usize),
+        _ => None,
+    }
+}
+
+/// Whether a model's file name or description marks it as quantized, as
+/// ONNX Runtime's and Optimum's quantization tools name their exports
+fn looks_quantized(name: &str, description: Option<&str>) -> bool {
+    let marked = |text: &str| {
+        let text = text.to_ascii_lowercase();
+        ["quant", "int8", "uint8", "qint", "q8"].iter().any(|mark| text.contains(mark))
+    };
+    marked(name) || description.is_some_and(marked)
+}
+
+/// Round each value to the nearest half-precision float
+fn to_f16(embedding: &[f32]) -> Vec<f16> {
+    embedding.iter().map(|&value| f16::from_f32(value)).collect()
+}
+
+/// Tokenizers fetched from the HuggingFace Hub, by name, so reloading an
+/// embedder with the same tokenizer doesn't fetch it again
+static HUB_TOKENIZERS: Lazy<Mutex<HashMap<String, Tokenizer>>> = Lazy::new(Default::default);
+
+/// Load a tokenizer from a local tokenizer.json, or from the HuggingFace Hub
+/// when `source` is not a file on disk, as
+/// [`TokenizerSource::from_name_or_path`] tells them apart.
+///
+/// Local files are read every time, so edits to them are picked up.
+pub(crate) fn load_tokenizer(source: &str) -> Result<Tokenizer, EmbedError> {
+    TokenizerSource::from_name_or_path(source).load(DEFAULT_TOKENIZER_RETRIES)
+}
+
+/// Call `attempt` until it succeeds, fails with an error `transient` says
+/// will not go away, or has been retried `retries` times, sleeping `delay`
+/// before the first retry and twice as long before each next one. Returns
+/// the last error if every attempt fails
+fn retry_with_backoff<T, E>(
+    retries: usize,
+    mut delay: Duration,
+    mut attempt: impl FnMut() -> Result<T, E>,
+    transient: impl Fn(&E) -> bool,
+) -> Result<T, E> {
+    let mut result = attempt();
+    for _ in 0..retries {
+        match &result {
+            Err(e) if transient(e) => {}
+            _ => break,
+        }
+        thread::sleep(delay);
+        delay *= 2;
+        result = attempt();
+    }
+    result
+}
+
+/// Whether a failed Hub download may succeed if tried again: the
+/// connection failed, or the Hub was rate limiting or erroring. A missing
+/// repo or file (404), a rejected token or a malformed name fails the same
+/// way every time.
+fn is_transient_download_error(error: &tokenizers::Error) -> bool {
+    match error.downcast_ref::<ApiError>() {
+        Some(ApiError::RequestError(error)) => match &**error {
+            ureq::Error::Status(status, _) => *status == 429 || *status >= 500,
+            ureq::Error::Transport(transport) => matches!(
+                transport.kind(),
+                ureq::ErrorKind::Dns
+                    | ureq::ErrorKind::ConnectionFailed
+                    | ureq::ErrorKind::Io
+                    | ureq::ErrorKind::ProxyConnect
+            ),
+        },
+        Some(ApiError::IoError(_) | ApiError::TooManyRetries(_) | ApiError::LockAcquisition(_)) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Fetch a tokenizer from the HuggingFace Hub, retrying transient failures
+/// up to `retries` times, or copy it if this process already has
+fn load_pretrained(name: &str, retries: usize) -> Result<Tokenizer, EmbedError> {
+    if let Some(tokenizer) = lock_recovering(&HUB_TOKENIZERS).get(name) {
+        return Ok(tokenizer.clone());
+    }
+    // Not holding the lock while fetching; two first loads may both fetch
+    let fetch = || Tokenizer::from_pretrained(name, None);
+    let transient = is_transient_download_error;
+    let fetched = retry_with_backoff(retries, TOKENIZER_RETRY_DELAY, fetch, transient);
+    let tokenizer = fetched.map_err(|e| EmbedError::TokenizerLoad(e.to_string()))?;
+    lock_recovering(&HUB_TOKENIZERS).insert(name.to_string(), tokenizer.clone());
+    Ok(tokenizer)
+}
+
+/// Disable or cap the session's CPU arena as `options` ask
+fn configure_memory(
+    mut builder: SessionBuilder,
+    options: &EmbedderOptions,
+) -> Result<SessionBuilder, EmbedError> {
+    match (options.cpu_arena, options.max_memory_bytes) {
+        (true, None) => Ok(builder),
+        (false, None) => {
+            ep::CPU::default()
+                .with_arena_allocator(false)
+                .register(&mut builder)
+                .map_err(|e| {
+                    EmbedError::ModelLoad(format!("disabling CPU arena: {}", ort::Error::from(e)))
+                })?;
+            Ok(builder)
+        }
+        (false, Some(_)) => Err(EmbedError::InvalidInput(
+            "max_memory_bytes caps the CPU arena, which cpu_arena disables".to_string(),
+        )),
+        (true, Some(0)) => Err(EmbedError::InvalidInput(
+            "max_memory_bytes must be positive".to_string(),
+        )),
+        (true, Some(max_bytes)) => {
+            cap_cpu_arena(max_bytes)?;
+            builder
+                .with_env_allocators()
+                .map_err(|e| EmbedError::ModelLoad(format!("using capped CPU arena: {}", e)))
+        }
+    }
+}
+
+/// Register `provider` on the session, returning a warning instead of an
+/// error if it is unavailable so the session falls back to CPU.
+fn register_provider(builder: &mut SessionBuilder, provider: ExecutionProvider) -> Option<String> {
+    let registered = match provider {
+        ExecutionProvider::Cpu => return None,
+        ExecutionProvider::Cuda { device_id } => {
+            ep::CUDA::default().with_device_id(device_id).register(builder)
+        }
+        ExecutionProvider::CoreMl => ep::CoreML::default().register(builder),
+        ExecutionProvider::TensorRt => ep::TensorRT::default().register(builder),
+    };
+    registered.err().map(|e| {
+        format!(
+            "{:?} execution provider unavailable, falling back to CPU: {}",
+            provider,
+            ort::Error::from(e)
+        )
+    })
+}
+
+/// Truncate every encoding to `max_seq_len` tokens.
+///
+/// The tokenizer reserves room for special tokens, so a truncated sequence
+/// still ends in [SEP]. In strict mode the full sequence is kept so embed
+/// can reject it instead.
+pub(crate) fn configure_truncation(
+    tokenizer: &mut Tokenizer,
+    options: &EmbedderOptions,
+) -> Result<(), EmbedError> {
+    if options.strict_length {
+        return Ok(());
+    }
+    tokenizer
+        .with_truncation(Some(TruncationParams {
+            max_length: options.max_seq_len,
+            ..Default::default()
+        }))
+        .map_err(|e| EmbedError::TokenizerLoad(format!("configuring truncation: {}", e)))?;
+    Ok(())
+}
+
+/// Whether `encoding` has any token the tokenizer didn't add itself
+fn has_content(encoding: &Encoding) -> bool {
+    encoding.get_special_tokens_mask().contains(&0)
+}
+
+/// Id of the token the tokenizer puts in place of text outside its
+/// vocabulary, if it has one
+fn unknown_token_id(tokenizer: &Tokenizer) -> Option<u32> {
+    let token = match tokenizer.get_model() {
+        ModelWrapper::WordPiece(model) => Some(model.unk_token.as_str()),
+        ModelWrapper::WordLevel(model) => Some(model.unk_token.as_str()),
+        ModelWrapper::BPE(model) => model.unk_token.as_deref(),
+        // Unigram models don't expose theirs
+        ModelWrapper::Unigram(_) => None,
+    }?;
+    tokenizer.token_to_id(token)
+}
+
+/// How many of the encoding's non-special tokens are `unknown_token`, out of
+/// how many
+fn unknown_tokens(encoding: &Encoding, unknown_token: u32) -> (usize, usize) {
+    let content = encoding
+        .get_ids()
+        .iter()
+        .zip(encoding.get_special_tokens_mask())
+        .filter(|&(_, &special)| special == 0);
+    content.fold((0, 0), |(unknown, tokens), (&id, _)| {
+        (unknown + usize::from(id == unknown_token), tokens + 1)
+    })
+}
+
+/// Special tokens the post-processor wraps around a single sequence
+fn special_tokens(tokenizer: &Tokenizer, add_special_tokens: bool) -> usize {
+    match tokenizer.get_post_processor() {
+        Some(processor) if add_special_tokens => processor.added_tokens(false),
+        _ => 0,
+    }
+}
+
+/// Overlap between windows of `window_tokens` tokens, special tokens
+/// included, that start every `stride` text tokens
+fn stride_overlap(
+    tokenizer: &Tokenizer,
+    window_tokens: usize,
+    stride: usize,
+    add_special_tokens: bool,
+) -> Result<usize, EmbedError> {
+    let text_tokens = window_tokens.saturating_sub(special_tokens(tokenizer, add_special_tokens));
+    if stride == 0 || stride > text_tokens {
+        return Err(EmbedError::InvalidInput(format!(
+            "stride {} must be between 1 and the {} text tokens a window of {} holds",
+            stride, text_tokens, window_tokens
+        )));
+    }
+    Ok(text_tokens - stride)
+}
+
+/// Split `text` into windows of `chunk_tokens` tokens, special tokens
+/// included, each starting with the last `overlap` text tokens of the one
+/// before.
+fn chunk_encodings(
+    tokenizer: &Tokenizer,
+    text: &str,
+    chunk_tokens: usize,
+    overlap: usize,
+    add_special_tokens: bool,
+) -> Result<Vec<Encoding>, EmbedError> {
+    let special = special_tokens(tokenizer, add_special_tokens);
+    let window = chunk_tokens
+        .checked_sub(special)
+        .filter(|&window| window > overlap)
+        .ok_or_else(|| {
+            EmbedError::InvalidInput(format!(
+                "chunk_tokens {} must exceed overlap {} plus {} special tokens",
+                chunk_tokens, overlap, special
+            ))
+        })?;
+
+    // The tokenizer's own truncation splits long texts into overflowing
+    // pieces; stitch them back together before windowing
+    let mut text_tokens = tokenizer
+        .encode(text, false)
+        .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+    let overflowing = text_tokens.take_overflowing();
+    let mut text_tokens = Encoding::merge(std::iter::once(text_tokens).chain(overflowing), false);
+
+    text_tokens.truncate(window, overlap, TruncationDirection::Right);
+    let rest = text_tokens.take_overflowing();
+    std::iter::once(text_tokens)
+        .chain(rest)
+        .map(|chunk| {
+            tokenizer
+                .post_process(chunk, None, add_special_tokens)
+                .map_err(|e| EmbedError::Tokenization(e.to_string()))
+        })
+        .collect()
+}
+
+/// `text` without leading or trailing whitespace and with every internal
+/// run of whitespace, newlines included, replaced by one space
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Length of `text` before truncation.
+///
+/// Encoding without special tokens leaves the text's own tokens split
+/// between the encoding and its overflowing pieces; the post-processor
+/// says how many special tokens `embed` adds on top.
+fn count_tokens(
+    tokenizer: &Tokenizer,
+    text: &str,
+    add_special_tokens: bool,
+) -> Result<usize, EmbedError> {
+    let encoding = tokenizer
+        .encode(text, false)
+        .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+    let overflowing: usize = encoding.get_overflowing().iter().map(|o| o.len()).sum();
+    let content = encoding.len() + overflowing;
+    Ok(content + special_tokens(tokenizer, add_special_tokens))
+}
+
+/// Mean pooling over sequence dimension with attention mask.
+///
+/// Tokens are summed one after another in sequence order, never in
+/// parallel, so the result is the same on every run. Sums are kept in f64
+/// so hundreds of tokens don't drift from the reference implementation.
+fn mean_pooling(
+    last_hidden_state: ArrayView3<f32>,
+    attention_mask: ArrayView2<i64>,
+) -> Array2<f32> {
+    let (batch_size, _, hidden_dim) = last_hidden_state.dim();
+
+    let mut pooled = Array2::<f32>::zeros((batch_size, hidden_dim));
+    let mut sum = Array1::<f64>::zeros(hidden_dim);
+    let rows = pooled.rows_mut().into_iter().zip(last_hidden_state.outer_iter());
+    for ((mut pooled, tokens), mask) in rows.zip(attention_mask.rows()) {
+        // Whole contiguous token vectors at a time, in sequence order
+        sum.fill(0.0);
+        let mut count = 0.0f64;
+        for (token, &mask_val) in tokens.outer_iter().zip(mask) {
+            if mask_val > 0 {
+                let weight = mask_val as f64;
+                sum.zip_mut_with(&token, |sum, &value| *sum += value as f64 * weight);
+                count += weight;
+            }
+        }
+        if count > 0.0 {
+            pooled.zip_mut_with(&sum, |pooled, &sum| *pooled = (sum / count) as f32);
+        }
+    }
+
+    pooled
+}
+
+/// CLS pooling: take the first token's vector of each sequence
+fn cls_pooling(last_hidden_state: ArrayView3<f32>) -> Array2<f32> {
+    last_hidden_state.index_axis(Axis(1), 0).to_owned()
+}
+
+/// Max pooling over sequence dimension, skipping masked positions
+fn max_pooling(
+    last_hidden_state: ArrayView3<f32>,
+    attention_mask: ArrayView2<i64>,
+) -> Array2<f32> {
+    let (batch_size, _, hidden_dim) = last_hidden_state.dim();
+
+    let mut pooled = Array2::<f32>::zeros((batch_size, hidden_dim));
+    let rows = pooled.rows_mut().into_iter().zip(last_hidden_state.outer_iter());
+    for ((mut pooled, tokens), mask) in rows.zip(attention_mask.rows()) {
+        let mut valid = tokens.outer_iter().zip(mask).filter(|&(_, &m)| m > 0);
+        if let Some((first, _)) = valid.next() {
+            pooled.assign(&first);
+            for (token, _) in valid {
+                pooled.zip_mut_with(&token, |max, &value| *max = max.max(value));
+            }
+        }
+    }
+
+    pooled
+}
+
+/// Lengths [`Embedder::warmup`] runs at for a model taking up to
+/// `max_seq_len` tokens, shortest first
+fn warmup_seq_lens(max_seq_len: usize) -> Vec<usize> {
+    let shorter = WARMUP_SEQ_LENS.into_iter().filter(|&len| len < max_seq_len);
+    shorter.chain([max_seq_len]).collect()
+}
+
+/// Indices of sequences `lengths` tokens long, grouped into inference
+/// passes that each pad to at most `max_tokens` tokens, `padded_len`
+/// giving the length a pass pads its longest sequence to.
+///
+/// A batch within the budget, or any batch when `max_tokens` is 0, is one
+/// pass in input order. Otherwise sequences are taken shortest first, so
+/// short texts aren't padded to the longest in the whole batch; a sequence
+/// over the budget on its own gets a pass to itself.
+fn plan_passes(
+    lengths: &[usize],
+    padded_len: impl Fn(usize) -> usize,
+    max_tokens: usize,
+) -> Vec<Vec<usize>> {
+    let longest = lengths.iter().copied().max().unwrap_or(0);
+    if max_tokens == 0 || lengths.len() * padded_len(longest) <= max_tokens {
+        return vec![(0..lengths.len()).collect()];
+    }
+
+    let mut order: Vec<usize> = (0..lengths.len()).collect();
+    order.sort_by_key(|&i| lengths[i]);
+    let mut passes = Vec::new();
+    let mut pass: Vec<usize> = Vec::new();
+    for i in order {
+        // Sorted, so this sequence is the longest the pass would hold
+        if !pass.is_empty() && (pass.len() + 1) * padded_len(lengths[i]) > max_tokens {
+            passes.push(std::mem::take(&mut pass));
+        }
+        pass.push(i);
+    }
+    passes.push(pass);
+    passes
+}
+
+/// Embed `texts` with `embed`, [`TEXTS_PER_PROGRESS_PASS`] at a time,
+/// concatenating what each pass returns and telling `progress` how many
+/// texts are done after each. A `Break` fails with [`EmbedError::Cancelled`].
+///
+/// `progress` runs between calls to `embed`, so `embed` can take a lock
+/// that the callback never sees held.
+pub(crate) fn embed_in_passes<T>(
+    texts: &[&str],
+    mut embed: impl FnMut(&[&str]) -> Result<Vec<T>, EmbedError>,
+    mut progress: impl FnMut(usize, usize) -> ControlFlow<()>,
+) -> Result<Vec<T>, EmbedError> {
+    let mut embeddings = Vec::new();
+    let mut done = 0;
+    for batch in texts.chunks(TEXTS_PER_PROGRESS_PASS) {
+        embeddings.extend(embed(batch)?);
+        done += batch.len();
+        if progress(done, texts.len()).is_break() {
+            return Err(EmbedError::Cancelled);
+        }
+    }
+    Ok(embeddings)
+}
+
+/// The elements of `embeddings` in row-major order, reusing its buffer
+/// unless truncation left it strided
+fn into_row_major(embeddings: Array2<f32>) -> Vec<f32> {
+    let len = embeddings.len();
+    let embeddings = if embeddings.is_standard_layout() {
+        embeddings
+    } else {
+        embeddings.as_standard_layout().into_owned()
+    };
+    // A truncated single row is contiguous but still owns the dropped tail
+    let (mut data, offset) = embeddings.into_raw_vec_and_offset();
+    debug_assert_eq!(offset.unwrap_or(0), 0);
+    data.truncate(len);
+    data
+}
+
+/// Scale each embedding to unit norm as `normalization` asks
+fn normalize_rows(mut embeddings: Array2<f32>, normalization: Normalization) -> Array2<f32> {
+    for row in embeddings.rows_mut() {
+        normalization.apply(row);
+    }
+
+    embeddings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EMBEDDING_DIM;
+    use crate::error::*;
+    use crate::test_util::*;
+    use ndarray::{Array1, Array3, ArrayD};
+    use std::time::Instant;
+
+    /// Indexed-loop mean pooling, kept to check the ndarray version; it
+    /// accumulates in f64 the same way, so the two agree exactly
+    fn scalar_mean_pooling(
+        last_hidden_state: &ArrayD<f32>,
+        attention_mask: &Array2<i64>,
+    ) -> Array2<f32> {
+        let shape = last_hidden_state.shape();
+        let (batch_size, seq_len, hidden_dim) = (shape[0], shape[1], shape[2]);
+
+        let mut pooled = Array2::<f32>::zeros((batch_size, hidden_dim));
+
+        for b in 0..batch_size {
+            let mut sum = Array1::<f64>::zeros(hidden_dim);
+            let mut count = 0.0f64;
+
+            for s in 0..seq_len {
+                let mask_val = attention_mask[[b, s]] as f64;
+                if mask_val > 0.0 {
+                    for h in 0..hidden_dim {
+                        sum[h] += last_hidden_state[[b, s, h]] as f64 * mask_val;
+                    }
+                    count += mask_val;
+                }
+            }
+
+            if count > 0.0 {
+                for h in 0..hidden_dim {
+                    pooled[[b, h]] = (sum[h] / count) as f32;
+                }
+            }
+        }
+
+        pooled
+    }
+
+    /// The original indexed-loop max pooling
+    fn scalar_max_pooling(
+        last_hidden_state: &ArrayD<f32>,
+        attention_mask: &Array2<i64>,
+    ) -> Array2<f32> {
+        let shape = last_hidden_state.shape();
+        let (batch_size, seq_len, hidden_dim) = (shape[0], shape[1], shape[2]);
+
+        let mut pooled = Array2::<f32>::zeros((batch_size, hidden_dim));
+
+        for b in 0..batch_size {
+            let mut max = Array1::<f32>::from_elem(hidden_dim, f32::NEG_INFINITY);
+            let mut any_valid = false;
+
+            for s in 0..seq_len {
+                if attention_mask[[b, s]] > 0 {
+                    for h in 0..hidden_dim {
+                        max[h] = max[h].max(last_hidden_state[[b, s, h]]);
+                    }
+                    any_valid = true;
+                }
+            }
+
+            if any_valid {
+                for h in 0..hidden_dim {
+                    pooled[[b, h]] = max[h];
+                }
+            }
+        }
+
+        pooled
+    }
+
+    /// The original indexed-loop L2 normalization
+    fn scalar_normalize_l2(embeddings: &Array2<f32>) -> Array2<f32> {
+        let mut normalized = embeddings.clone();
+        let (batch_size, dim) = (embeddings.nrows(), embeddings.ncols());
+
+        for b in 0..batch_size {
+            let mut norm = 0.0f32;
+            for d in 0..dim {
+                norm += embeddings[[b, d]].powi(2);
+            }
+            norm = norm.sqrt();
+
+            if norm > 1e-12 {
+                for d in 0..dim {
+                    normalized[[b, d]] = embeddings[[b, d]] / norm;
+                }
+            }
+        }
+
+        normalized
+    }
+
+    /// Random hidden states with rows of varying length, including a fully
+    /// masked one
+    fn random_pooling_inputs(
+        batch: usize,
+        seq: usize,
+        hidden: usize,
+    ) -> (Array3<f32>, Array2<i64>) {
+        let values: Vec<f32> = random_unit_vectors(batch * seq, hidden, batch as u64)
+            .into_iter()
+            .flatten()
+            .map(|v| v * 8.0)
+            .collect();
+        let hidden_state = Array3::from_shape_vec((batch, seq, hidden), values).unwrap();
+        let mask = Array2::from_shape_fn((batch, seq), |(b, s)| (s < b * 7 % (seq + 1)) as i64);
+        (hidden_state, mask)
+    }
+
+    #[test]
+    fn mean_pooling_ignores_padded_positions() {
+        // Second row is padded after its first token; the padded value must not leak in.
+        let hidden = ndarray::Array3::from_shape_vec(
+            (2, 2, 2),
+            vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 100.0, 100.0],
+        )
+        .unwrap();
+        let mask = Array2::from_shape_vec((2, 2), vec![1, 1, 1, 0]).unwrap();
+
+        let pooled = mean_pooling(hidden.view(), mask.view());
+
+        assert_eq!(pooled.row(0).to_vec(), vec![2.0, 3.0]);
+        assert_eq!(pooled.row(1).to_vec(), vec![5.0, 6.0]);
+    }
+
+    #[test]
+    fn mean_pooling_stays_exact_over_long_sequences() {
+        // 400 real tokens padded to 512: an outlier of 1024 first, as BERT
+        // hidden states have, then values of 1 + (8k + 3)/2^16. All are exact
+        // in f32, so the reference mean can be worked out in integers, but
+        // once a running f32 sum passes 1024 each token's low 3/2^16 rounds
+        // away, leaving the mean about 4.6e-5 short.
+        let (seq, valid, hidden) = (512, 400, 8);
+        let step = |s: usize, h: usize| {
+            if s == 0 { 1023 * 65536 } else { 8 * ((s * 37 + h * 11) % 128) as i64 + 3 }
+        };
+        let values = Array3::from_shape_fn((1, seq, hidden), |(_, s, h)| {
+            if s < valid { 1.0 + step(s, h) as f32 / 65536.0 } else { 1000.0 }
+        });
+        let mask = Array2::from_shape_fn((1, seq), |(_, s)| (s < valid) as i64);
+
+        let pooled = mean_pooling(values.view(), mask.view());
+
+        for h in 0..hidden {
+            let steps: i64 = (0..valid).map(|s| step(s, h)).sum();
+            let reference = 1.0 + steps as f64 / 65536.0 / valid as f64;
+            let error = (pooled[[0, h]] as f64 - reference).abs();
+            assert!(error < 1e-5, "dim {h}: {} vs {reference}", pooled[[0, h]]);
+
+            let f32_sum: f32 = (0..valid).map(|s| values[[0, s, h]]).sum();
+            let f32_error = ((f32_sum / valid as f32) as f64 - reference).abs();
+            assert!(f32_error > 1e-5, "dim {h}: an f32 sum should drift, {f32_error}");
+        }
+    }
+
+    #[test]
+    fn warmup_covers_short_medium_and_longest_sequences() {
+        assert_eq!(warmup_seq_lens(256), [16, 128, 256]);
+        assert_eq!(warmup_seq_lens(128), [16, 128]);
+        assert_eq!(warmup_seq_lens(64), [16, 64]);
+        assert_eq!(warmup_seq_lens(8), [8]);
+    }
+
+    #[test]
+    fn passes_group_similar_lengths_within_the_budget() {
+        let lengths = [10, 3, 50, 4, 9, 200, 5];
+
+        let passes = plan_passes(&lengths, |len| len, 30);
+
+        assert_eq!(passes, vec![vec![1, 3, 6], vec![4, 0], vec![2], vec![5]]);
+        for pass in &passes {
+            let longest = pass.iter().map(|&i| lengths[i]).max().unwrap();
+            assert!(pass.len() == 1 || pass.len() * longest <= 30);
+        }
+        let mut all: Vec<usize> = passes.concat();
+        all.sort();
+        assert_eq!(all, (0..lengths.len()).collect::<Vec<_>>());
+
+        // Within budget, unlimited, or empty: one pass in input order
+        let one_pass = vec![(0..lengths.len()).collect::<Vec<_>>()];
+        assert_eq!(plan_passes(&lengths, |len| len, 2000), one_pass);
+        assert_eq!(plan_passes(&lengths, |len| len, 0), one_pass);
+        assert_eq!(plan_passes(&[], |len| len, 30), vec![Vec::<usize>::new()]);
+        // A fixed sequence length pads every text alike
+        let fixed = plan_passes(&lengths, |_| 64, 128);
+        assert!(fixed.iter().all(|pass| pass.len() <= 2));
+    }
+
+    #[test]
+    fn passes_report_progress_and_stop_on_break() {
+        let texts = vec!["text"; 150];
+        let mut passes = Vec::new();
+        let mut reports = Vec::new();
+
+        let embeddings = embed_in_passes(
+            &texts,
+            |batch| {
+                passes.push(batch.len());
+                Ok(vec![batch.len(); batch.len()])
+            },
+            |done, total| {
+                reports.push((done, total));
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(passes, [64, 64, 22]);
+        assert_eq!(reports, [(64, 150), (128, 150), (150, 150)]);
+        assert_eq!(embeddings.len(), 150);
+        assert_eq!(embeddings[149], 22);
+
+        let mut calls = 0;
+        let stopped = embed_in_passes(
+            &texts,
+            |batch| {
+                calls += 1;
+                Ok(vec![0; batch.len()])
+            },
+            |_, _| ControlFlow::Break(()),
+        );
+        assert!(matches!(stopped, Err(EmbedError::Cancelled)));
+        assert_eq!(calls, 1);
+        let nothing = embed_in_passes(&[], |_| Ok(vec![()]), |_, _| ControlFlow::Break(()));
+        assert!(nothing.unwrap().is_empty());
+    }
+
+    #[test]
+    fn row_major_drops_truncated_columns() {
+        let matrix = Array2::from_shape_fn((3, 4), |(r, c)| (r * 4 + c) as f32);
+
+        assert_eq!(into_row_major(matrix.clone()), (0..12).map(|x| x as f32).collect::<Vec<_>>());
+        let truncated = matrix.clone().slice_move(s![.., ..2]);
+        assert_eq!(into_row_major(truncated), vec![0.0, 1.0, 4.0, 5.0, 8.0, 9.0]);
+        let single_row = matrix.slice_move(s![..1, ..2]);
+        assert_eq!(into_row_major(single_row), vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn pooling_matches_scalar_loops_on_random_inputs() {
+        for (batch, seq, hidden) in [(1, 1, 1), (3, 5, 7), (8, 40, 64), (32, 64, 384)] {
+            let (hidden_state, mask) = random_pooling_inputs(batch, seq, hidden);
+            let close = |a: &Array2<f32>, b: &Array2<f32>| {
+                a.iter().zip(b).all(|(x, y)| (x - y).abs() <= 1e-6 * x.abs().max(1.0))
+            };
+
+            let hidden_dyn = hidden_state.clone().into_dyn();
+
+            let mean = mean_pooling(hidden_state.view(), mask.view());
+            assert_eq!(mean, scalar_mean_pooling(&hidden_dyn, &mask), "{batch}x{seq}");
+            let max = max_pooling(hidden_state.view(), mask.view());
+            assert_eq!(max, scalar_max_pooling(&hidden_dyn, &mask), "{batch}x{seq}");
+            let normalized = normalize_rows(mean.clone(), Normalization::L2);
+            assert!(close(&normalized, &scalar_normalize_l2(&mean)));
+        }
+    }
+
+    #[test]
+    #[ignore = "timing benchmark; run with --release"]
+    fn ndarray_pooling_is_faster_than_scalar_loops() {
+        let (hidden_state, mask) = random_pooling_inputs(32, 256, EMBEDDING_DIM);
+        let hidden_dyn = hidden_state.clone().into_dyn();
+        const RUNS: u32 = 50;
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            scalar_normalize_l2(&scalar_mean_pooling(&hidden_dyn, &mask));
+        }
+        let scalar = start.elapsed() / RUNS;
+
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            normalize_rows(mean_pooling(hidden_state.view(), mask.view()), Normalization::L2);
+        }
+        let vectorized = start.elapsed() / RUNS;
+
+        assert!(vectorized < scalar, "scalar {scalar:?}, ndarray {vectorized:?}");
+    }
+
+    #[test]
+    fn cls_pooling_takes_first_token() {
+        let hidden = ndarray::Array3::from_shape_vec(
+            (2, 2, 2),
+            vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0],
+        )
+        .unwrap();
+
+        let pooled = cls_pooling(hidden.view());
+
+        assert_eq!(pooled.row(0).to_vec(), vec![1.0, 2.0]);
+        assert_eq!(pooled.row(1).to_vec(), vec![5.0, 6.0]);
+    }
+
+    #[test]
+    fn max_pooling_ignores_padded_positions() {
+        let hidden = ndarray::Array3::from_shape_vec(
+            (2, 2, 2),
+            vec![1.0, 4.0, 3.0, 2.0, -5.0, -6.0, 100.0, 100.0],
+        )
+        .unwrap();
+        let mask = Array2::from_shape_vec((2, 2), vec![1, 1, 1, 0]).unwrap();
+
+        let pooled = max_pooling(hidden.view(), mask.view());
+
+        assert_eq!(pooled.row(0).to_vec(), vec![3.0, 4.0]);
+        assert_eq!(pooled.row(1).to_vec(), vec![-5.0, -6.0]);
+    }
+
+    #[test]
+    fn normalized_rows_have_unit_norm() {
+        let values = vec![3.0, -4.0, 0.0, 0.5, 0.25, -0.25, 0.0, 0.0, 0.0];
+        let embeddings = Array2::from_shape_vec((3, 3), values).unwrap();
+        let l1 = |row: ndarray::ArrayView1<f32>| row.iter().map(|v| v.abs()).sum::<f32>();
+        let l2 = |row: ndarray::ArrayView1<f32>| row.dot(&row).sqrt();
+
+        let by_l1 = normalize_rows(embeddings.clone(), Normalization::L1);
+        let by_l2 = normalize_rows(embeddings.clone(), Normalization::L2);
+
+        for row in 0..2 {
+            assert!((l1(by_l1.row(row)) - 1.0).abs() < 1e-6);
+            assert!((l2(by_l2.row(row)) - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(by_l1.row(0).to_vec(), vec![3.0 / 7.0, -4.0 / 7.0, 0.0]);
+        // Zero vectors are left alone rather than divided by zero
+        assert_eq!(by_l1.row(2).to_vec(), vec![0.0; 3]);
+        assert_eq!(by_l2.row(2).to_vec(), vec![0.0; 3]);
+        assert_eq!(normalize_rows(embeddings.clone(), Normalization::None), embeddings);
+    }
+
+    #[test]
+    fn hidden_size_is_read_from_fixed_output_shape() {
+        assert_eq!(static_hidden_size(&[-1, -1, 384], 3), Some(384));
+        assert_eq!(static_hidden_size(&[-1, -1, 768], 3), Some(768));
+        assert_eq!(static_hidden_size(&[-1, -1, -1], 3), None);
+        assert_eq!(static_hidden_size(&[-1, 384], 3), None);
+        assert_eq!(static_hidden_size(&[-1, 384], 2), Some(384));
+    }
+
+    #[test]
+    fn token_states_are_preferred_over_pooled_outputs() {
+        let token_states = EmbeddingOutput::TokenStates;
+        let exported: [(&str, &[i64]); 2] =
+            [("pooler_output", &[-1, 384]), ("last_hidden_state", &[-1, -1, 384])];
+        assert_eq!(EmbeddingOutput::select(exported).unwrap(), (1, token_states));
+
+        let unnamed: [(&str, &[i64]); 2] = [("1", &[-1, 384]), ("0", &[-1, -1, 384])];
+        assert_eq!(EmbeddingOutput::select(unnamed).unwrap(), (1, token_states));
+
+        let pooled: [(&str, &[i64]); 2] =
+            [("token_embeddings", &[-1, -1, 384]), ("sentence_embedding", &[-1, 384])];
+        let sentence_embedding = EmbeddingOutput::SentenceEmbedding;
+        assert_eq!(EmbeddingOutput::select(pooled).unwrap(), (1, sentence_embedding));
+        // encode_hidden still finds the token vectors behind a pooled output
+        assert_eq!(token_states_output(&pooled), Some(0));
+
+        let err = EmbeddingOutput::select([("pooler_output", &[-1i64, 384][..])]).unwrap_err();
+        assert_eq!(err.code(), ERROR_MODEL_LOAD);
+        assert!(err.to_string().contains("pooler_output"), "{}", err);
+    }
+
+    /// Classify inputs that are all int64 matrices
+    fn classify(
+        names: &[&str],
+        overrides: &[(&str, InputRole)],
+    ) -> Result<ModelInputs, EmbedError> {
+        let overrides = overrides.iter().map(|&(name, role)| (name.to_string(), role)).collect();
+        ModelInputs::classify(names.iter().map(|&name| (name, true)), &overrides)
+    }
+
+    fn model_inputs(ids: &str, mask: Option<&str>, types: Option<&str>) -> ModelInputs {
+        ModelInputs {
+            input_ids: ids.to_string(),
+            attention_mask: mask.map(str::to_string),
+            token_type_ids: types.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn quantized_models_are_recognized_by_name() {
+        assert!(looks_quantized("model_qint8_avx512", None));
+        assert!(looks_quantized("model_quantized", None));
+        assert!(looks_quantized("model_uint8", None));
+        assert!(looks_quantized("model", Some("Dynamic Quantization of all-MiniLM-L6-v2")));
+        assert!(!looks_quantized("all-MiniLM-L6-v2", None));
+        assert!(!looks_quantized("model", Some("sentence-transformers export")));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn info_describes_the_loaded_model() {
+        let info = test_embedder().info();
+
+        assert_eq!(info.name, "all-MiniLM-L6-v2");
+        assert_eq!(info.dim, EMBEDDING_DIM);
+        assert_eq!(info.max_seq_len, crate::DEFAULT_MAX_SEQ_LEN);
+        assert_eq!(info.input_names, vec!["input_ids", "attention_mask", "token_type_ids"]);
+        assert_eq!(info.output_name, "last_hidden_state");
+        assert_eq!(info.embedding_output, EmbeddingOutput::TokenStates);
+        assert!(!info.quantized);
+    }
+
+    #[test]
+    fn f16_rounding_keeps_embeddings_close() {
+        for vector in random_unit_vectors(100, EMBEDDING_DIM, 3) {
+            let rounded: Vec<f32> = to_f16(&vector).into_iter().map(f32::from).collect();
+            let similarity = cosine_similarity(&vector, &rounded);
+            assert!(similarity > 0.999, "{}", similarity);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn embed_f16_rounds_embed() {
+        let mut embedder = test_embedder();
+        let text = "stored at half precision";
+
+        let full = embedder.embed(text).unwrap();
+        let half = embedder.embed_f16(text).unwrap();
+
+        assert_eq!(half, to_f16(&full));
+        let half: Vec<f32> = half.into_iter().map(f32::from).collect();
+        assert!(cosine_similarity(&full, &half) > 0.999);
+    }
+
+    #[test]
+    fn model_inputs_follow_declared_names() {
+        let bert = classify(&["input_ids", "attention_mask", "token_type_ids"], &[]);
+        let distilled = classify(&["input_ids", "attention_mask"], &[]);
+        let exported = classify(&["input.1", "attention_mask", "token_type_ids"], &[]);
+        let short = classify(&["ids", "mask"], &[]);
+
+        let expected = model_inputs("input_ids", Some("attention_mask"), Some("token_type_ids"));
+        assert_eq!(bert.unwrap(), expected);
+        assert_eq!(distilled.unwrap(), model_inputs("input_ids", Some("attention_mask"), None));
+        assert_eq!(exported.unwrap().input_ids, "input.1");
+        assert_eq!(short.unwrap(), model_inputs("ids", Some("mask"), None));
+    }
+
+    #[test]
+    fn lone_unrecognized_input_is_taken_as_input_ids() {
+        let inputs = classify(&["x", "attention_mask"], &[]).unwrap();
+        assert_eq!(inputs, model_inputs("x", Some("attention_mask"), None));
+    }
+
+    #[test]
+    fn overrides_name_inputs_the_heuristics_miss() {
+        let overrides = [("tokens", InputRole::InputIds), ("keep", InputRole::AttentionMask)];
+
+        let inputs = classify(&["tokens", "keep", "segments"], &overrides).unwrap();
+
+        assert_eq!(inputs, model_inputs("tokens", Some("keep"), Some("segments")));
+        let err = classify(&["tokens", "keep"], &[("tokenz", InputRole::InputIds)]).unwrap_err();
+        assert!(err.to_string().contains("tokenz"), "{}", err);
+    }
+
+    #[test]
+    fn ambiguous_inputs_fail_listing_the_model_inputs() {
+        let cases: [&[&str]; 4] = [
+            &["attention_mask"],
+            &["input_ids", "pixel_values"],
+            &["x", "y"],
+            &["input_ids", "mask", "padding_mask"],
+        ];
+        for names in cases {
+            let err = classify(names, &[]).unwrap_err();
+            assert_eq!(err.code(), ERROR_MODEL_LOAD);
+            assert!(err.to_string().contains(&names.join(", ")), "{}", err);
+        }
+
+        let inputs = [("input_ids", true), ("pixel_values", false)];
+        let err = ModelInputs::classify(inputs, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("pixel_values"), "{}", err);
+    }
+
+    #[test]
+    #[ignore = "requires models/msmarco-distilbert-base-v4.onnx"]
+    fn two_input_model_embeds() {
+        let mut embedder = Embedder::new(TWO_INPUT_TEST_MODEL, TWO_INPUT_TEST_TOKENIZER).unwrap();
+
+        let embedding = embedder.embed("a distilled model without token_type_ids").unwrap();
+
+        assert_eq!(embedder.dim(), 768);
+        assert_eq!(embedding.len(), embedder.dim());
+        assert!(embedding.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn batch_matches_single_embeddings() {
+        let mut embedder = test_embedder();
+        let texts = [
+            "short",
+            "a considerably longer sentence that forces padding",
+            "mid length text",
+        ];
+
+        let batch = embedder.embed_batch(&texts).unwrap();
+        assert_eq!(batch.len(), texts.len());
+
+        for (text, batched) in texts.iter().zip(&batch) {
+            let single = embedder.embed(text).unwrap();
+            for (a, b) in single.iter().zip(batched) {
+                assert!((a - b).abs() < 1e-4, "{text}: {a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn output_binding_is_reused_until_the_shape_changes() {
+        let mut embedder = test_embedder();
+        let texts = ["red shoes", "blue shoes"];
+
+        let first = embedder.embed_batch(&texts).unwrap();
+        let shape = embedder.output_binding.as_ref().unwrap().shape.clone();
+        assert_eq!(embedder.embed_batch(&texts).unwrap(), first);
+        assert_eq!(embedder.output_binding.as_ref().unwrap().shape, shape);
+
+        let long = "a considerably longer sentence that pads the batch further";
+        embedder.embed_batch(&[texts[0], long]).unwrap();
+        assert_ne!(embedder.output_binding.as_ref().unwrap().shape, shape);
+        assert_eq!(embedder.embed_batch(&texts).unwrap(), first);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn batch_is_faster_than_single_calls() {
+        let mut embedder = test_embedder();
+        let texts: Vec<String> = (0..1000).map(|i| format!("document number {i}")).collect();
+        let refs: Vec<&str> = texts.iter().map(String::as_str).collect();
+
+        let start = Instant::now();
+        for text in &refs {
+            embedder.embed(text).unwrap();
+        }
+        let single = start.elapsed();
+
+        let start = Instant::now();
+        embedder.embed_batch(&refs).unwrap();
+        let batched = start.elapsed();
+
+        assert!(batched < single, "single calls {single:?}, one batch {batched:?}");
+    }
+
+    #[test]
+    fn tokenizer_loads_from_local_file() {
+        let path = write_temp_file("tokenizer.json", TINY_TOKENIZER_JSON);
+
+        let tokenizer = load_tokenizer(path.to_str().unwrap()).unwrap();
+        let encoding = tokenizer.encode("hello world", false).unwrap();
+
+        assert_eq!(encoding.get_ids(), &[1, 2]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hub_tokenizers_are_fetched_once() {
+        // Not a real Hub name, so a second fetch would fail
+        let name = "arrow-embed-tests/cached-tokenizer";
+        lock_recovering(&HUB_TOKENIZERS).insert(name.to_string(), bert_style_tokenizer());
+
+        let mut first = load_tokenizer(name).unwrap();
+        configure_truncation(&mut first, &EmbedderOptions::default()).unwrap();
+        let second = load_tokenizer(name).unwrap();
+
+        // Each load gets its own copy to configure
+        assert!(first.get_truncation().is_some());
+        assert!(second.get_truncation().is_none());
+        let ids = second.encode("hello world", true).unwrap().get_ids().to_vec();
+        assert_eq!(ids, [3, 1, 2, 4]);
+    }
+
+    #[test]
+    fn tokenizer_loads_from_json_bytes() {
+        let source = TokenizerSource::Json(TINY_TOKENIZER_JSON.to_string());
+        let tokenizer = source.load(0).unwrap();
+        let encoding = tokenizer.encode("hello world", false).unwrap();
+
+        assert_eq!(encoding.get_ids(), &[1, 2]);
+        let err = TokenizerSource::Json("{ not json".to_string()).load(0).unwrap_err();
+        assert_eq!(err.code(), ERROR_INVALID_TOKENIZER);
+        assert!(err.to_string().contains("tokenizer JSON"), "{}", err);
+    }
+
+    #[test]
+    fn tokenizer_sources_tell_paths_from_hub_names() {
+        let path = write_temp_file("source_tokenizer.json", TINY_TOKENIZER_JSON);
+        let pretrained = |name: &str| TokenizerSource::Pretrained(name.to_string());
+        let file = |path: &Path| TokenizerSource::File(path.to_path_buf());
+
+        let sources = [path.to_str().unwrap(), "missing/tokenizer.json", "org/model"]
+            .map(TokenizerSource::from_name_or_path);
+
+        assert_eq!(sources[0], file(&path));
+        assert_eq!(sources[1], file(Path::new("missing/tokenizer.json")));
+        assert_eq!(sources[2], pretrained("org/model"));
+        assert!(sources[0].load(0).is_ok());
+        assert_eq!(sources[1].load(0).unwrap_err().code(), ERROR_TOKENIZER_NOT_FOUND);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn model_and_tokenizer_load_from_memory() {
+        let model = std::fs::read(TEST_MODEL).unwrap();
+        let tokenizer = load_tokenizer(TEST_TOKENIZER).unwrap().to_string(false).unwrap();
+
+        let mut embedder = Embedder::from_bytes(&model, TokenizerSource::Json(tokenizer));
+        drop(model);
+
+        let embedding = embedder.as_mut().unwrap().embed("red shoes").unwrap();
+        assert_eq!(embedding, test_embedder().embed("red shoes").unwrap());
+        assert_eq!(embedder.unwrap().info().name, "<memory>");
+        let tokenizer = TokenizerSource::from_name_or_path(TEST_TOKENIZER);
+        let err = Embedder::from_bytes(b"not onnx", tokenizer);
+        assert_eq!(err.err().map(|e| e.code()), Some(ERROR_MODEL_LOAD));
+    }
+
+    #[test]
+    fn failed_downloads_are_retried_with_doubling_waits() {
+        let delay = Duration::from_millis(5);
+        let transient = |e: &String| e.starts_with("rate limited");
+        let flaky = |failures: usize, error: &'static str| {
+            let mut attempts = 0;
+            move || {
+                attempts += 1;
+                if attempts <= failures {
+                    Err(format!("{} ({})", error, attempts))
+                } else {
+                    Ok(attempts)
+                }
+            }
+        };
+
+        let start = Instant::now();
+        assert_eq!(
+            retry_with_backoff(3, delay, flaky(2, "rate limited"), transient).unwrap(),
+            3
+        );
+        // 5ms, then 10ms
+        assert!(start.elapsed() >= Duration::from_millis(15));
+
+        let err = retry_with_backoff(2, delay, flaky(5, "rate limited"), transient).unwrap_err();
+        assert_eq!(err, "rate limited (3)");
+        assert!(retry_with_backoff(0, delay, flaky(1, "rate limited"), transient).is_err());
+        assert_eq!(
+            retry_with_backoff(0, delay, flaky(0, "rate limited"), transient).unwrap(),
+            1
+        );
+        let err = retry_with_backoff(3, delay, flaky(5, "not found"), transient).unwrap_err();
+        assert_eq!(err, "not found (1)");
+    }
+
+    #[test]
+    fn only_transient_download_errors_are_retried() {
+        let status = |code| {
+            let response = ureq::Response::new(code, "status", "").unwrap();
+            let error: tokenizers::Error =
+                ApiError::RequestError(Box::new(ureq::Error::Status(code, response))).into();
+            error
+        };
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+
+        assert!(is_transient_download_error(&status(429)));
+        assert!(is_transient_download_error(&status(503)));
+        assert!(is_transient_download_error(&ApiError::IoError(io).into()));
+        assert!(!is_transient_download_error(&status(404)));
+        assert!(!is_transient_download_error(&status(401)));
+        assert!(!is_transient_download_error(
+            &"Model \"a b\" contains invalid characters".into()
+        ));
+    }
+
+    #[test]
+    fn missing_tokenizer_file_is_not_found() {
+        let err = load_tokenizer("/nonexistent/tokenizer.json").unwrap_err();
+
+        assert!(matches!(err, EmbedError::TokenizerNotFound(_)));
+        assert_eq!(err.code(), ERROR_TOKENIZER_NOT_FOUND);
+    }
+
+    #[test]
+    fn malformed_tokenizer_file_is_invalid() {
+        let path = write_temp_file("bad_tokenizer.json", "{ not json");
+
+        let err = load_tokenizer(path.to_str().unwrap()).unwrap_err();
+
+        assert!(matches!(err, EmbedError::InvalidTokenizer(_)));
+        assert_eq!(err.code(), ERROR_INVALID_TOKENIZER);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx and tests/fixtures/reference_embeddings.tsv"]
+    fn embeddings_match_sentence_transformers() {
+        // Fixture generated by tools/genembeddings/reference_vectors.py:
+        // one `text<TAB>space-separated floats` line per reference sentence
+        let fixture = std::fs::read_to_string("tests/fixtures/reference_embeddings.tsv").unwrap();
+        let mut embedder = test_embedder();
+
+        for line in fixture.lines() {
+            let (text, values) = line.split_once('\t').unwrap();
+            let expected: Vec<f32> = values.split(' ').map(|v| v.parse().unwrap()).collect();
+
+            let actual = embedder.embed(text).unwrap();
+
+            assert_eq!(actual.len(), expected.len());
+            for (a, e) in actual.iter().zip(&expected) {
+                assert!((a - e).abs() < 1e-4, "{text}: {a} vs {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn truncation_keeps_sep_token() {
+        let mut tokenizer = bert_style_tokenizer();
+        let options = EmbedderOptions {
+            max_seq_len: 4,
+            ..Default::default()
+        };
+        configure_truncation(&mut tokenizer, &options).unwrap();
+
+        let encoding = tokenizer.encode("hello world hello world", true).unwrap();
+
+        assert_eq!(encoding.get_ids(), &[3, 1, 2, 4]);
+        assert_eq!(encoding.get_attention_mask().len(), 4);
+        assert_eq!(encoding.get_type_ids().len(), 4);
+    }
+
+    #[test]
+    fn blank_text_has_no_content() {
+        let tokenizer = bert_style_tokenizer();
+        let content = |text| has_content(&tokenizer.encode(text, true).unwrap());
+
+        assert!(!content(""));
+        assert!(!content("   "));
+        assert!(!content("\t\n"));
+        // Punctuation is a real token, even when it is out of vocabulary
+        assert!(content("."));
+        assert!(content(" hello "));
+    }
+
+    #[test]
+    fn unknown_tokens_are_counted_apart_from_special_tokens() {
+        let tokenizer = bert_style_tokenizer();
+        let unknown_token = unknown_token_id(&tokenizer).unwrap();
+        let unknown = |text| unknown_tokens(&tokenizer.encode(text, true).unwrap(), unknown_token);
+
+        assert_eq!(unknown_token, 0);
+        assert_eq!(unknown("hello world"), (0, 2));
+        assert_eq!(unknown("hello \u{2603} \u{2604} world"), (2, 4));
+        assert_eq!(unknown("\u{2603}"), (1, 1));
+    }
+
+    #[test]
+    fn pairs_give_the_second_text_token_type_1() {
+        let tokenizer = bert_style_tokenizer();
+
+        let encoding = tokenizer.encode(("hello", "world hello"), true).unwrap();
+        let mut buffers = InputBuffers::default();
+        buffers.fill(&[encoding], 6);
+
+        // [CLS] hello [SEP] world hello [SEP]
+        assert_eq!(buffers.input_ids, vec![3, 1, 4, 2, 1, 4]);
+        assert_eq!(buffers.token_type_ids, vec![0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn input_buffers_are_padded_and_reused() {
+        let tokenizer = bert_style_tokenizer();
+        let long = tokenizer.encode("hello world hello", true).unwrap();
+        let short = tokenizer.encode("world", true).unwrap();
+        let mut buffers = InputBuffers::with_capacity(8);
+
+        buffers.fill(&[long.clone(), short.clone()], long.len());
+
+        assert_eq!(buffers.input_ids, vec![3, 1, 2, 1, 4, 3, 2, 4, 0, 0]);
+        assert_eq!(buffers.attention_mask, vec![1, 1, 1, 1, 1, 1, 1, 1, 0, 0]);
+        let capacity = buffers.input_ids.capacity();
+
+        // A smaller batch overwrites the old contents, leaving no stale tokens
+        buffers.fill(&[short], 4);
+        assert_eq!(buffers.input_ids, vec![3, 2, 4, 0]);
+        assert_eq!(buffers.token_type_ids, vec![0; 4]);
+        assert_eq!(buffers.input_ids.capacity(), capacity);
+    }
+
+    #[test]
+    fn id_buffers_copy_caller_tokens() {
+        let mut buffers = InputBuffers::with_capacity(8);
+        buffers.fill(&[bert_style_tokenizer().encode("hello world", true).unwrap()], 6);
+
+        buffers.fill_ids(&[3, 2, 4], &[1, 1, 0], 3);
+
+        assert_eq!(buffers.input_ids, vec![3, 2, 4]);
+        assert_eq!(buffers.attention_mask, vec![1, 1, 0]);
+        assert_eq!(buffers.token_type_ids, vec![0; 3]);
+
+        buffers.fill_ids(&[3, 2, 4], &[1, 1, 1], 5);
+        assert_eq!(buffers.input_ids, vec![3, 2, 4, 0, 0]);
+        assert_eq!(buffers.attention_mask, vec![1, 1, 1, 0, 0]);
+        assert_eq!(buffers.token_type_ids, vec![0; 5]);
+    }
+
+    #[test]
+    fn long_text_is_chunked_into_overlapping_windows() {
+        let mut tokenizer = bert_style_tokenizer();
+        let options = EmbedderOptions {
+            max_seq_len: 8,
+            ..Default::default()
+        };
+        configure_truncation(&mut tokenizer, &options).unwrap();
+        let ids = |chunks: Vec<Encoding>| -> Vec<Vec<u32>> {
+            chunks.iter().map(|c| c.get_ids().to_vec()).collect()
+        };
+
+        // Ten text tokens, past the tokenizer's own truncation, in windows of
+        // three overlapping by one
+        let text = "hello world hello world hello world hello world hello world";
+        let chunks = chunk_encodings(&tokenizer, text, 5, 1, true);
+        let full = vec![3, 1, 2, 1, 4];
+        let expected = vec![full.clone(), full.clone(), full.clone(), full, vec![3, 1, 2, 4]];
+        assert_eq!(ids(chunks.unwrap()), expected);
+
+        let chunks = chunk_encodings(&tokenizer, "hello world", 5, 1, true);
+        assert_eq!(ids(chunks.unwrap()), vec![vec![3, 1, 2, 4]]);
+
+        let chunks = chunk_encodings(&tokenizer, "", 5, 1, true).unwrap();
+        assert!(!has_content(&chunks[0]));
+    }
+
+    #[test]
+    fn stride_sets_the_overlap_between_windows() {
+        let tokenizer = bert_style_tokenizer();
+
+        // Windows of 5 hold 3 text tokens between [CLS] and [SEP]
+        assert_eq!(stride_overlap(&tokenizer, 5, 2, true).unwrap(), 1);
+        assert_eq!(stride_overlap(&tokenizer, 5, 3, true).unwrap(), 0);
+        assert_eq!(stride_overlap(&tokenizer, 5, 5, false).unwrap(), 0);
+        for (window, stride) in [(5, 0), (5, 4), (1, 1)] {
+            let err = stride_overlap(&tokenizer, window, stride, true).unwrap_err();
+            assert_eq!(err.code(), ERROR_INVALID_INPUT);
+        }
+    }
+
+    #[test]
+    fn chunk_overlap_must_leave_room_for_new_tokens() {
+        let tokenizer = bert_style_tokenizer();
+
+        for (chunk_tokens, overlap) in [(5, 3), (5, 4), (2, 0), (1, 0)] {
+            let chunks = chunk_encodings(&tokenizer, "hello", chunk_tokens, overlap, true);
+            assert_eq!(chunks.unwrap_err().code(), ERROR_INVALID_INPUT);
+        }
+        assert!(chunk_encodings(&tokenizer, "hello", 3, 0, true).is_ok());
+        assert!(chunk_encodings(&tokenizer, "hello", 2, 1, false).is_ok());
+    }
+
+    #[test]
+    fn count_tokens_ignores_truncation() {
+        let mut tokenizer = bert_style_tokenizer();
+        let options = EmbedderOptions {
+            max_seq_len: 4,
+            ..Default::default()
+        };
+        configure_truncation(&mut tokenizer, &options).unwrap();
+
+        let text = "hello world hello world hello world";
+
+        assert_eq!(count_tokens(&tokenizer, text, true).unwrap(), 8);
+        assert_eq!(count_tokens(&tokenizer, text, false).unwrap(), 6);
+        assert_eq!(count_tokens(&tokenizer, "hello", true).unwrap(), 3);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn blank_input_is_rejected() {
+        let mut embedder = test_embedder();
+
+        for text in ["", "   "] {
+            let err = embedder.embed(text).unwrap_err();
+            assert_eq!(err.code(), ERROR_EMPTY_INPUT, "{:?}", text);
+        }
+        assert!(matches!(
+            embedder.embed_batch(&["fine", " "]),
+            Err(EmbedError::EmptyInput)
+        ));
+        let punctuation = embedder.embed("!").unwrap();
+        assert!(punctuation.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn embed_long_matches_embed_for_short_text() {
+        let mut embedder = test_embedder();
+        let text = "a short text that fits in one chunk";
+
+        let single = embedder.embed(text).unwrap();
+        let all = embedder
+            .embed_long(text, 128, 16, ChunkAggregation::ReturnAll)
+            .unwrap();
+        let mean = embedder
+            .embed_long(text, 128, 16, ChunkAggregation::MeanOfChunks)
+            .unwrap();
+
+        assert_eq!(all.len(), 1);
+        for (a, b) in single.iter().zip(&mean[0]) {
+            assert!((a - b).abs() < 1e-5);
+        }
+        let long = "the quick brown fox jumps over the lazy dog ".repeat(100);
+        let chunks = embedder
+            .embed_long(&long, 128, 16, ChunkAggregation::ReturnAll)
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(matches!(
+            embedder.embed_long("", 128, 16, ChunkAggregation::MeanOfChunks),
+            Err(EmbedError::EmptyInput)
+        ));
+        assert!(embedder.embed_long(text, 1024, 16, ChunkAggregation::ReturnAll).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn embed_document_averages_strided_windows() {
+        let mut embedder = test_embedder();
+        let long = "the quick brown fox jumps over the lazy dog ".repeat(100);
+
+        let document = embedder.embed_document(&long, 128, 96).unwrap();
+
+        // 126 text tokens per window, 96 of them new, leaves an overlap of 30;
+        // embed_long batches the same windows instead of embedding each alone
+        let mean = embedder
+            .embed_long(&long, 128, 30, ChunkAggregation::MeanOfChunks)
+            .unwrap();
+        assert!(document.iter().zip(&mean[0]).all(|(a, b)| (a - b).abs() < 1e-4));
+        let norm = document.iter().map(|v| v * v).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+        assert!(embedder.embed_document(&long, 128, 0).is_err());
+        assert!(embedder.embed_document(&long, 1024, 96).is_err());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn prefixes_change_the_embedding() {
+        let options = EmbedderOptions {
+            query_prefix: "query: ".to_string(),
+            passage_prefix: "passage: ".to_string(),
+            ..Default::default()
+        };
+        let mut embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let text = "how do vector databases work";
+
+        let plain = embedder.embed(text).unwrap();
+        let query = embedder.embed_query(text).unwrap();
+        let passage = embedder.embed_passage(text).unwrap();
+
+        assert_ne!(plain, query);
+        assert_ne!(query, passage);
+        assert_eq!(query, embedder.embed("query: how do vector databases work").unwrap());
+        assert_eq!(passage, embedder.embed_with_prefix("passage: ", text).unwrap());
+        assert_eq!(plain, embedder.embed_as(EmbedKind::Raw, text).unwrap());
+        assert_eq!(query, embedder.embed_as(EmbedKind::Query, text).unwrap());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn prefixes_count_against_the_token_limit() {
+        let options = EmbedderOptions {
+            query_prefix: "query: ".to_string(),
+            ..Default::default()
+        };
+        let embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let text = "how do vector databases work";
+
+        let raw = embedder.count_tokens_as(EmbedKind::Raw, text).unwrap();
+
+        assert_eq!(raw, embedder.count_tokens(text).unwrap());
+        let query = embedder.count_tokens_as(EmbedKind::Query, text).unwrap();
+        assert_eq!(query, embedder.count_tokens("query: how do vector databases work").unwrap());
+        assert!(query > raw);
+        // No passage_prefix is configured, so documents are counted as given
+        assert_eq!(embedder.count_tokens_as(EmbedKind::Document, text).unwrap(), raw);
+    }
+
+    #[test]
+    fn whitespace_is_trimmed_and_collapsed() {
+        assert_eq!(collapse_whitespace("  hello   world  "), "hello world");
+        assert_eq!(collapse_whitespace("line one\n\n\tline two\r\n"), "line one line two");
+        assert_eq!(collapse_whitespace(" \n "), "");
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn trimmed_input_embeds_like_the_clean_text() {
+        let options = EmbedderOptions {
+            trim_input: true,
+            ..Default::default()
+        };
+        let mut trimming = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let mut plain = test_embedder();
+
+        let messy = trimming.embed("  hello   world  ").unwrap();
+
+        assert_eq!(messy, trimming.embed("hello world").unwrap());
+        assert_eq!(messy, plain.embed("hello world").unwrap());
+        let tokens = trimming.tokenize("\n\nhello \n world").unwrap();
+        assert_eq!(tokens, plain.tokenize("hello world").unwrap());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn deterministic_embeddings_are_bit_identical() {
+        let options = EmbedderOptions {
+            deterministic: true,
+            ..Default::default()
+        };
+        let load = || Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options.clone());
+        let (mut first, mut second) = (load().unwrap(), load().unwrap());
+        let text = "the same text should always give the same bits";
+        let bits = |v: Vec<f32>| v.into_iter().map(f32::to_bits).collect::<Vec<_>>();
+
+        let expected = bits(first.embed(text).unwrap());
+
+        assert_eq!(bits(first.embed(text).unwrap()), expected);
+        assert_eq!(bits(second.embed(text).unwrap()), expected);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn fixed_length_padding_is_masked_out_of_pooling() {
+        let options = EmbedderOptions {
+            fixed_seq_len: Some(128),
+            ..Default::default()
+        };
+        let mut fixed = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let mut dynamic = test_embedder();
+        let texts = ["short", "a somewhat longer sentence to pad"];
+
+        let padded = fixed.embed_batch(&texts).unwrap();
+
+        for (padded, text) in padded.iter().zip(texts) {
+            for (a, b) in padded.iter().zip(dynamic.embed(text).unwrap()) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+        let (hidden, mask) = fixed.encode_hidden("short").unwrap();
+        assert_eq!(hidden.shape()[1], 128);
+        assert_eq!(mask.row(0).sum(), 3);
+        let long = "word ".repeat(300);
+        assert_eq!(fixed.tokenize(&long).unwrap().len(), 128);
+        assert!(fixed.embed(&long).is_ok());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn multi_matches_mean_and_cls_pooling() {
+        let mut embedder = test_embedder();
+        let options = EmbedderOptions {
+            pooling: PoolingStrategy::Cls,
+            ..Default::default()
+        };
+        let mut cls_embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let text = "one pass, two poolings";
+
+        let (mean, cls) = embedder.embed_multi(text).unwrap();
+
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5);
+        assert!(close(&mean, &embedder.embed(text).unwrap()));
+        assert!(close(&cls, &cls_embedder.embed(text).unwrap()));
+        assert_ne!(mean, cls);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn tiny_deadline_times_out_instead_of_blocking() {
+        let mut embedder = test_embedder();
+        let document = "a long document that fills the whole sequence ".repeat(60);
+        let texts = vec![document.as_str(); 64];
+
+        let started = Instant::now();
+        let result = embedder.embed_batch_with_deadline(&texts, Duration::from_millis(5));
+
+        assert!(matches!(result, Err(EmbedError::Timeout(limit)) if limit.as_millis() == 5));
+        assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+        // The deadline was for that call only
+        assert_eq!(embedder.embed("short text").unwrap().len(), embedder.dim());
+        let roomy = embedder.embed_with_deadline("short text", Duration::from_secs(30));
+        assert_eq!(roomy.unwrap().len(), embedder.dim());
+
+        let options = EmbedderOptions {
+            timeout: Some(Duration::from_millis(5)),
+            ..Default::default()
+        };
+        let mut limited = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        assert!(matches!(limited.embed_batch(&texts), Err(EmbedError::Timeout(_))));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn cancelled_token_stops_a_running_batch() {
+        let mut embedder = test_embedder();
+        let document = "a long document that fills the whole sequence ".repeat(60);
+        let texts = vec![document.as_str(); 64];
+        let token = CancelToken::new();
+        let canceller = token.clone();
+
+        let cancelling = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            canceller.cancel();
+        });
+        let result = embedder.embed_batch_cancellable(&texts, &token);
+        cancelling.join().unwrap();
+
+        assert!(matches!(result, Err(EmbedError::Cancelled)));
+        let again = embedder.embed_batch_cancellable(&["short"], &token);
+        assert!(matches!(again, Err(EmbedError::Cancelled)));
+        assert_eq!(embedder.embed("short").unwrap().len(), embedder.dim());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn disabled_and_capped_arenas_embed_the_same() {
+        let load = |cpu_arena, max_memory_bytes| {
+            let options = EmbedderOptions {
+                cpu_arena,
+                max_memory_bytes,
+                ..Default::default()
+            };
+            Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options)
+        };
+        let texts = ["memory arenas", "a second, somewhat longer text to embed"];
+        let expected = test_embedder().embed_batch(&texts).unwrap();
+
+        for mut embedder in [load(false, None).unwrap(), load(true, Some(256 << 20)).unwrap()] {
+            let embeddings = embedder.embed_batch(&texts).unwrap();
+            for (a, b) in embeddings.iter().zip(&expected) {
+                assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-5));
+            }
+        }
+        assert!(matches!(load(false, Some(1 << 20)), Err(EmbedError::InvalidInput(_))));
+        assert!(matches!(load(true, Some(0)), Err(EmbedError::InvalidInput(_))));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn warmup_can_run_again_and_leaves_embeddings_unchanged() {
+        let mut embedder = test_embedder();
+        let before = embedder.embed("red shoes").unwrap();
+
+        embedder.warmup(2).unwrap();
+        embedder.warmup(0).unwrap();
+
+        assert_eq!(embedder.embed("red shoes").unwrap(), before);
+        assert_eq!(embedder.stats(), CacheStats::default());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn split_batches_match_one_pass_in_input_order() {
+        let load = |max_tokens_per_batch| {
+            let options = EmbedderOptions {
+                max_tokens_per_batch,
+                ..Default::default()
+            };
+            Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap()
+        };
+        let long = "a much longer document that pads everything else ".repeat(8);
+        let texts: Vec<String> = (0..40)
+            .map(|i| if i % 7 == 3 { format!("{} {}", long, i) } else { format!("short {}", i) })
+            .collect();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+
+        let expected = load(0).embed_batch(&texts).unwrap();
+        let mut split = load(256);
+        let embeddings = split.embed_batch(&texts).unwrap();
+
+        assert_eq!(embeddings.len(), texts.len());
+        for ((a, b), text) in embeddings.iter().zip(&expected).zip(&texts) {
+            assert!(a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-4), "{}", text);
+        }
+        assert_eq!(split.embed_batch_flat(&texts).unwrap(), embeddings.concat());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn flat_batch_matches_per_text_embeddings() {
+        let texts = ["first text", "a somewhat longer second text", "third"];
+        let options = EmbedderOptions {
+            output_dim: Some(64),
+            ..Default::default()
+        };
+        for mut embedder in [
+            test_embedder(),
+            Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap(),
+        ] {
+            let flat = embedder.embed_batch_flat(&texts).unwrap();
+
+            assert_eq!(flat.len(), texts.len() * embedder.dim());
+            assert_eq!(flat, embedder.embed_batch(&texts).unwrap().concat());
+            assert!(embedder.embed_batch_flat(&[]).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn output_dim_keeps_a_renormalized_prefix() {
+        let load = |output_dim| {
+            let options = EmbedderOptions {
+                output_dim,
+                cache_capacity: 4,
+                ..Default::default()
+            };
+            Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options)
+        };
+        let mut truncated = load(Some(128)).unwrap();
+        let mut full = test_embedder();
+        let text = "matryoshka embeddings nest";
+
+        let single = truncated.embed(text).unwrap();
+
+        assert_eq!(truncated.dim(), 128);
+        assert_eq!(truncated.info().dim, 128);
+        assert_eq!(single.len(), 128);
+        let mut expected = full.embed(text).unwrap()[..128].to_vec();
+        Normalization::L2.apply((&mut expected[..]).into());
+        for (a, b) in single.iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-5);
+        }
+        let batch = truncated.embed_batch(&[text, "another text"]).unwrap();
+        assert!(batch.iter().all(|e| e.len() == 128));
+        assert_eq!(batch[0], single);
+        assert!(matches!(load(Some(385)), Err(EmbedError::InvalidInput(_))));
+        assert!(matches!(load(Some(0)), Err(EmbedError::InvalidInput(_))));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn model_of_another_dimension_is_rejected_at_load() {
+        let load = |expected_dim| {
+            let options = EmbedderOptions {
+                expected_dim,
+                ..Default::default()
+            };
+            Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options)
+        };
+
+        let err = load(Some(768)).err().unwrap();
+
+        assert!(matches!(err, EmbedError::DimensionMismatch { expected: 768, actual: 384 }));
+        assert_eq!(load(Some(384)).unwrap().dim(), 384);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn embed_ids_matches_embed() {
+        let mut embedder = test_embedder();
+        let text = "tokenized somewhere else";
+        let ids: Vec<i64> = embedder.tokenize(text).unwrap().into_iter().map(i64::from).collect();
+        let mask = vec![1; ids.len()];
+
+        let from_ids = embedder.embed_ids(&ids, &mask).unwrap();
+
+        let from_text = embedder.embed(text).unwrap();
+        for (a, b) in from_ids.iter().zip(&from_text) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        let err = embedder.embed_ids(&ids, &mask[1..]).unwrap_err();
+        assert_eq!(err.code(), ERROR_INVALID_INPUT);
+        let err = embedder.embed_ids(&ids, &vec![0; ids.len()]).unwrap_err();
+        assert_eq!(err.code(), ERROR_EMPTY_INPUT);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn hidden_states_pool_to_the_embedding() {
+        let options = EmbedderOptions {
+            normalization: Normalization::None,
+            ..Default::default()
+        };
+        let mut embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let text = "pooled in my own code";
+
+        let (hidden, mask) = embedder.encode_hidden(text).unwrap();
+
+        let seq_len = embedder.tokenize(text).unwrap().len();
+        assert_eq!(hidden.shape(), &[1, seq_len, embedder.dim()]);
+        assert_eq!(mask.dim(), (1, seq_len));
+        let hidden = hidden.into_dimensionality::<ndarray::Ix3>().unwrap();
+        let pooled = mean_pooling(hidden.view(), mask.view());
+        let embedding = embedder.embed(text).unwrap();
+        for (a, b) in pooled.row(0).iter().zip(&embedding) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn token_embeddings_can_skip_special_tokens() {
+        let mut embedder = test_embedder();
+        let text = "late interaction retrieval";
+        let tokens = embedder.tokenize(text).unwrap().len();
+
+        let (all, rows, cols) = embedder.embed_tokens(text, false, false).unwrap();
+        assert_eq!((rows, cols), (tokens, embedder.dim()));
+        assert_eq!(all.len(), rows * cols);
+
+        let (content, rows, _) = embedder.embed_tokens(text, true, true).unwrap();
+        assert_eq!(rows, tokens - 2);
+        for token in content.chunks(cols) {
+            let norm: f32 = token.iter().map(|v| v * v).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn pair_differs_from_joined_texts() {
+        let mut embedder = test_embedder();
+        let (premise, hypothesis) = ("a man is playing a guitar", "someone makes music");
+
+        let pair = embedder.embed_pair(premise, hypothesis).unwrap();
+
+        assert_eq!(pair.len(), embedder.dim());
+        assert_ne!(pair, embedder.embed(&format!("{} {}", premise, hypothesis)).unwrap());
+        assert_ne!(pair, embedder.embed_pair(hypothesis, premise).unwrap());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn cached_batch_only_embeds_misses() {
+        let options = EmbedderOptions {
+            cache_capacity: 8,
+            ..Default::default()
+        };
+        let mut embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let mut uncached = test_embedder();
+        embedder.warmup(1).unwrap();
+        let first = embedder.embed("red shoes").unwrap();
+
+        let batch = embedder.embed_batch(&["blue shoes", "red shoes"]).unwrap();
+
+        assert_eq!(batch[1], first);
+        let expected = uncached.embed("blue shoes").unwrap();
+        for (a, b) in batch[0].iter().zip(&expected) {
+            assert!((a - b).abs() < 1e-5);
+        }
+        let stats = embedder.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+        assert_eq!(uncached.stats(), CacheStats::default());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn model_info_lists_inputs_and_outputs() {
+        let info = test_embedder().model_info();
+
+        assert!(info.lines().any(|l| l.starts_with("input input_ids: Tensor<i64>")));
+        assert!(info.lines().any(|l| l.starts_with("output last_hidden_state: Tensor<f32>")));
+        assert!(info.ends_with("embeddings last_hidden_state: pooled\n"));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn self_test_matches_the_bundled_references() {
+        let mut embedder = test_embedder();
+
+        let report = embedder.self_test().unwrap();
+
+        assert!(report.pairs > 0, "{}", report);
+        assert!(report.max_deviation <= report.tolerance, "{}", report);
+        assert!(report.batch_deviation <= report.tolerance, "{}", report);
+        assert!(report.passed(), "{}", report);
+        assert_eq!(embedder.stats(), CacheStats::default());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn model_metadata_reads_fields_and_custom_entries() {
+        let embedder = test_embedder();
+
+        assert_eq!(embedder.model_metadata("producer_name").as_deref(), Some("pytorch"));
+        assert!(embedder.model_metadata("version").is_some());
+        assert_eq!(embedder.model_metadata("no_such_key"), None);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn tokenize_includes_special_tokens() {
+        let embedder = test_embedder();
+
+        let ids = embedder.tokenize("hello world").unwrap();
+
+        // [CLS] hello world [SEP] in the MiniLM vocabulary
+        assert_eq!(ids, vec![101, 7592, 2088, 102]);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn long_input_is_truncated() {
+        let mut embedder = test_embedder();
+        let paragraph = "the quick brown fox jumps over the lazy dog ".repeat(500);
+
+        let embedding = embedder.embed(&paragraph).unwrap();
+
+        assert_eq!(embedding.len(), EMBEDDING_DIM);
+        assert!(embedding.iter().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn long_input_is_rejected_in_strict_mode() {
+        let options = EmbedderOptions {
+            max_seq_len: 16,
+            strict_length: true,
+            ..Default::default()
+        };
+        let mut embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+        let paragraph = "the quick brown fox jumps over the lazy dog ".repeat(10);
+
+        let err = embedder.embed(&paragraph).unwrap_err();
+
+        assert!(matches!(err, EmbedError::InputTooLong { max_seq_len: 16, .. }));
+        assert_eq!(err.code(), ERROR_INPUT_TOO_LONG);
+        assert!(embedder.embed("short text").is_ok());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn similar_texts_score_higher() {
+        let mut embedder = test_embedder();
+
+        let close = embedder.similarity("a cat sits on the mat", "a kitten on a rug").unwrap();
+        let far = embedder.similarity("a cat sits on the mat", "quarterly tax filing").unwrap();
+        let same = embedder.similarity("a cat sits on the mat", "a cat sits on the mat").unwrap();
+
+        assert!(close > far);
+        assert!((same - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn similarity_matrix_finds_near_duplicates() {
+        let mut embedder = test_embedder();
+        let texts = [
+            "the cat sat on the mat",
+            "quarterly tax filing deadline",
+            "the cat was sitting on the mat",
+        ];
+
+        let m = embedder.similarity_matrix(&texts).unwrap();
+
+        assert_eq!(m.len(), 9);
+        for i in 0..3 {
+            assert!((m[i * 3 + i] - 1.0).abs() < 1e-5);
+            for j in 0..3 {
+                assert!((m[i * 3 + j] - m[j * 3 + i]).abs() < 1e-6);
+            }
+        }
+        assert!(m[2] > 0.8);
+        assert!(m[2] > m[1] && m[2] > m[5]);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn normalization_can_be_disabled() {
+        let options = EmbedderOptions {
+            normalization: Normalization::None,
+            ..Default::default()
+        };
+        let mut embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+
+        let embedding = embedder.embed("hello world").unwrap();
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+
+        assert!((norm - 1.0).abs() > 1e-3, "norm was forced to {}", norm);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn l1_normalized_embeddings_have_unit_l1_norm() {
+        let options = EmbedderOptions {
+            normalization: Normalization::L1,
+            ..Default::default()
+        };
+        let mut embedder = Embedder::with_options(TEST_MODEL, TEST_TOKENIZER, options).unwrap();
+
+        let embedding = embedder.embed("hello world").unwrap();
+        let norm = embedding.iter().map(|v| v.abs()).sum::<f32>();
+
+        assert!((norm - 1.0).abs() < 1e-5, "L1 norm was {}", norm);
+    }
+}
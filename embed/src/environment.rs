@@ -0,0 +1,149 @@
+//! The process-wide ONNX Runtime environment, set up once and shared by
+//! every embedder
+
+use std::ffi::CStr;
+use std::ptr;
+use std::sync::{Arc, Mutex, Once};
+
+use ort::AsPointer;
+use ort::environment::Environment;
+use ort::logging::LogLevel;
+use ort::memory::{AllocationDevice, AllocatorType, MemoryInfo, MemoryType};
+use ort::sys::OrtStatusPtr;
+
+use crate::error::EmbedError;
+use crate::pool::lock_recovering;
+
+/// Name the environment gets unless the first embedder asks for another
+pub(crate) const DEFAULT_ENVIRONMENT_NAME: &str = "arrow_embed";
+
+/// Least severe ONNX Runtime log message printed to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OrtLogLevel {
+    Verbose,
+    Info,
+    Warning,
+    Error,
+    Fatal,
+}
+
+impl OrtLogLevel {
+    fn level(self) -> LogLevel {
+        match self {
+            OrtLogLevel::Verbose => LogLevel::Verbose,
+            OrtLogLevel::Info => LogLevel::Info,
+            OrtLogLevel::Warning => LogLevel::Warning,
+            OrtLogLevel::Error => LogLevel::Error,
+            OrtLogLevel::Fatal => LogLevel::Fatal,
+        }
+    }
+}
+
+static INIT: Once = Once::new();
+
+/// Set up the ONNX Runtime environment under `name`, logging messages of
+/// `log_level` and above to stderr, or as the `ORT_LOG` variable says when
+/// `None`.
+///
+/// Only the first call in a process does anything; later ones, including
+/// the call every [`Embedder`](crate::Embedder) makes when it loads, reuse
+/// that environment and ignore their arguments. Returns whether this call
+/// set it up.
+pub fn init_environment(name: &str, log_level: Option<OrtLogLevel>) -> bool {
+    let mut committed = false;
+    INIT.call_once(|| {
+        let mut builder = ort::init().with_name(name);
+        if let Some(min) = log_level {
+            builder = builder.with_logger(Arc::new(move |level, category, _, location, message| {
+                if level >= min.level() {
+                    eprintln!("[ort {:?}] {} {}: {}", level, category, location, message);
+                }
+            }));
+        }
+        committed = builder.commit();
+    });
+    committed
+}
+
+/// ONNX Runtime's code for growing an arena by exactly the size requested,
+/// rather than doubling it
+const ARENA_EXTEND_SAME_AS_REQUESTED: usize = 1;
+
+/// Environment holding the capped CPU arena, kept alive so the arena
+/// outlives every session using it
+static CAPPED_ARENA: Mutex<Option<Arc<Environment>>> = Mutex::new(None);
+
+/// Register a CPU arena on the environment that never holds more than
+/// `max_bytes`, for sessions built with `with_env_allocators` to share.
+///
+/// Only the first call in a process registers an arena; later ones reuse
+/// it and ignore `max_bytes`, as [`init_environment`] does its arguments.
+/// The arena grows by what each allocation needs instead of doubling, so
+/// it doesn't overshoot the cap.
+pub(crate) fn cap_cpu_arena(max_bytes: usize) -> Result<(), EmbedError> {
+    let mut capped = lock_recovering(&CAPPED_ARENA);
+    if capped.is_some() {
+        return Ok(());
+    }
+
+    let runtime_error =
+        |e: ort::Error| EmbedError::RuntimeInit(format!("capping CPU arena: {}", e));
+    let environment = ort::environment::get_environment().map_err(runtime_error)?;
+    let memory_info =
+        MemoryInfo::new(AllocationDevice::CPU, 0, AllocatorType::Arena, MemoryType::Default)
+            .map_err(runtime_error)?;
+    let api = ort::api();
+    let keys = [c"max_mem".as_ptr(), c"arena_extend_strategy".as_ptr()];
+    let values = [max_bytes, ARENA_EXTEND_SAME_AS_REQUESTED];
+    let mut config = ptr::null_mut();
+    // SAFETY: keys and values are the same length and outlive the call, and
+    // config is released once the allocator has copied it
+    unsafe {
+        check_status((api.CreateArenaCfgV2)(
+            keys.as_ptr(),
+            values.as_ptr(),
+            keys.len(),
+            &mut config,
+        ))?;
+        let registered = check_status((api.CreateAndRegisterAllocator)(
+            environment.ptr().cast_mut(),
+            memory_info.ptr(),
+            config,
+        ));
+        (api.ReleaseArenaCfg)(config);
+        registered?;
+    }
+
+    *capped = Some(environment);
+    Ok(())
+}
+
+/// Turn an ONNX Runtime status into a result, releasing it
+///
+/// # Safety
+/// `status` must be null or a status returned by the ONNX Runtime API that
+/// has not been released.
+unsafe fn check_status(status: OrtStatusPtr) -> Result<(), EmbedError> {
+    if status.0.is_null() {
+        return Ok(());
+    }
+    let api = ort::api();
+    let message = unsafe { CStr::from_ptr((api.GetErrorMessage)(status.0)) };
+    let message = message.to_string_lossy().into_owned();
+    unsafe { (api.ReleaseStatus)(status.0) };
+    Err(EmbedError::RuntimeInit(format!("capping CPU arena: {}", message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_first_init_configures_the_environment() {
+        // Other tests may have loaded an embedder already, so only the
+        // second call's result is known
+        init_environment(DEFAULT_ENVIRONMENT_NAME, None);
+
+        assert!(!init_environment("another_name", Some(OrtLogLevel::Verbose)));
+    }
+}
@@ -0,0 +1,192 @@
+//! Error type shared by the Rust API and the C FFI
+
+use std::fmt;
+use std::time::Duration;
+
+// Stable error codes returned by every arrow_embed_* function. Values are
+// never reused or renumbered; new failures get new codes.
+
+/// Success
+pub const ERROR_OK: i32 = 0;
+/// A required pointer argument was null
+pub const ERROR_NULL_POINTER: i32 = -1;
+/// A string argument was not valid UTF-8
+pub const ERROR_INVALID_UTF8: i32 = -2;
+/// A previous panic poisoned a lock. No longer returned: index, corpus and
+/// embedder handles recover from a panic and keep working
+#[allow(dead_code)]
+pub const ERROR_LOCK_POISONED: i32 = -3;
+/// arrow_embed_init() has not been called successfully
+pub const ERROR_NOT_INITIALIZED: i32 = -4;
+/// The ONNX model could not be loaded
+pub const ERROR_MODEL_LOAD: i32 = -5;
+/// The tokenizer could not be loaded from the HuggingFace Hub
+pub const ERROR_TOKENIZER_LOAD: i32 = -6;
+/// A tokenizer file path was given but nothing exists there
+pub const ERROR_TOKENIZER_NOT_FOUND: i32 = -7;
+/// The tokenizer file exists, or tokenizer JSON was given, but could not
+/// be parsed
+pub const ERROR_INVALID_TOKENIZER: i32 = -8;
+/// An option value passed to an init function is out of range
+pub const ERROR_INVALID_OPTION: i32 = -9;
+/// Text has more tokens than allowed in strict mode
+pub const ERROR_INPUT_TOO_LONG: i32 = -10;
+/// The tokenizer failed to encode the text
+pub const ERROR_TOKENIZATION: i32 = -11;
+/// ONNX Runtime failed while running the model
+pub const ERROR_INFERENCE: i32 = -12;
+/// The model produced an output of unexpected shape
+pub const ERROR_SHAPE_MISMATCH: i32 = -13;
+/// An argument was present but unusable
+pub const ERROR_INVALID_INPUT: i32 = -14;
+/// A handle was never returned by arrow_embed_create() or was already destroyed
+pub const ERROR_INVALID_HANDLE: i32 = -15;
+/// A vector's length does not match the index dimension
+pub const ERROR_DIMENSION_MISMATCH: i32 = -16;
+/// A caller-provided output buffer cannot hold the embedding
+pub const ERROR_BUFFER_TOO_SMALL: i32 = -17;
+/// Reading or writing a file failed
+pub const ERROR_IO: i32 = -18;
+/// An index or corpus file was written by an unsupported format version
+pub const ERROR_INDEX_VERSION: i32 = -19;
+/// An index or corpus file is truncated or not such a file at all
+pub const ERROR_CORRUPT_INDEX: i32 = -20;
+/// Writing embeddings in an export format failed
+pub const ERROR_EXPORT: i32 = -21;
+/// Rust code panicked; the panic was caught at the FFI boundary
+pub const ERROR_PANIC: i32 = -22;
+/// The text is empty or tokenizes to nothing but special tokens
+pub const ERROR_EMPTY_INPUT: i32 = -23;
+/// Too much of the text is outside the tokenizer's vocabulary to embed
+/// meaningfully
+pub const ERROR_TOO_MANY_UNKNOWN: i32 = -24;
+/// ONNX Runtime itself could not be set up, before any model was read
+pub const ERROR_RUNTIME_INIT: i32 = -25;
+/// Inference did not finish within the timeout and was stopped
+pub const ERROR_TIMEOUT: i32 = -26;
+/// The call was cancelled through its cancel token
+pub const ERROR_CANCELLED: i32 = -27;
+
+/// Errors produced while loading an embedder or embedding text
+#[derive(Debug)]
+pub enum EmbedError {
+    /// ONNX Runtime itself could not be set up, before any model was read
+    RuntimeInit(String),
+    /// The ONNX model could not be loaded or its session configured
+    ModelLoad(String),
+    /// The tokenizer could not be loaded from the HuggingFace Hub
+    TokenizerLoad(String),
+    /// A tokenizer file path was given but nothing exists there
+    TokenizerNotFound(String),
+    /// The tokenizer file exists, or tokenizer JSON was given, but could
+    /// not be parsed
+    InvalidTokenizer(String),
+    /// The tokenizer failed to encode the text
+    Tokenization(String),
+    /// The text is empty or tokenizes to nothing but special tokens
+    EmptyInput,
+    /// Text has more tokens than the configured maximum (strict mode only)
+    InputTooLong { tokens: usize, max_seq_len: usize },
+    /// More of the text's tokens than `max_unknown_fraction` allows map to
+    /// the tokenizer's unknown token
+    TooManyUnknownTokens { unknown: usize, tokens: usize },
+    /// ONNX Runtime failed while running the model
+    Inference(String),
+    /// Inference did not finish within the given time and was stopped
+    Timeout(Duration),
+    /// The call was cancelled through its [`CancelToken`](crate::CancelToken)
+    Cancelled,
+    /// The model produced an output of unexpected shape
+    ShapeMismatch(String),
+    /// No embedder has been initialized
+    NotInitialized,
+    /// An argument was present but unusable
+    InvalidInput(String),
+    /// A vector's length does not match the index dimension
+    DimensionMismatch { expected: usize, actual: usize },
+    /// Reading or writing a file failed
+    Io(String),
+    /// An index or corpus file was written by an unsupported format version
+    UnsupportedIndexVersion { found: u32, supported: u32 },
+    /// An index or corpus file is truncated or not such a file at all
+    CorruptIndex(String),
+    /// Writing embeddings in an export format failed
+    Export(String),
+}
+
+impl fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbedError::RuntimeInit(msg) => write!(f, "Failed to initialize ONNX Runtime: {}", msg),
+            EmbedError::ModelLoad(msg) => write!(f, "Failed to load model: {}", msg),
+            EmbedError::TokenizerLoad(msg) => write!(f, "Failed to load tokenizer: {}", msg),
+            EmbedError::TokenizerNotFound(path) => {
+                write!(f, "Tokenizer file not found: {}", path)
+            }
+            EmbedError::InvalidTokenizer(msg) => write!(f, "Invalid tokenizer: {}", msg),
+            EmbedError::Tokenization(msg) => write!(f, "Tokenization failed: {}", msg),
+            EmbedError::EmptyInput => f.write_str("Input has no content to embed"),
+            EmbedError::InputTooLong { tokens, max_seq_len } => write!(
+                f,
+                "Input has {} tokens, exceeding the maximum of {}",
+                tokens, max_seq_len
+            ),
+            EmbedError::TooManyUnknownTokens { unknown, tokens } => write!(
+                f,
+                "{} of {} tokens are unknown to the tokenizer",
+                unknown, tokens
+            ),
+            EmbedError::Inference(msg) => write!(f, "Inference failed: {}", msg),
+            EmbedError::Timeout(limit) => {
+                write!(f, "Inference did not finish within {} ms", limit.as_millis())
+            }
+            EmbedError::Cancelled => f.write_str("Embedding was cancelled"),
+            EmbedError::ShapeMismatch(msg) => write!(f, "Unexpected model output: {}", msg),
+            EmbedError::NotInitialized => f.write_str("Embedder is not initialized"),
+            EmbedError::InvalidInput(msg) => write!(f, "Invalid input: {}", msg),
+            EmbedError::DimensionMismatch { expected, actual } => write!(
+                f,
+                "Vector has {} dimensions, expected {}",
+                actual, expected
+            ),
+            EmbedError::Io(msg) => write!(f, "I/O error: {}", msg),
+            EmbedError::UnsupportedIndexVersion { found, supported } => write!(
+                f,
+                "Index file has format version {}, this build reads version {}",
+                found, supported
+            ),
+            EmbedError::CorruptIndex(msg) => write!(f, "Corrupt index file: {}", msg),
+            EmbedError::Export(msg) => write!(f, "Export failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for EmbedError {}
+
+impl EmbedError {
+    /// Stable ERROR_* code reported over FFI
+    pub fn code(&self) -> i32 {
+        match self {
+            EmbedError::RuntimeInit(_) => ERROR_RUNTIME_INIT,
+            EmbedError::ModelLoad(_) => ERROR_MODEL_LOAD,
+            EmbedError::TokenizerLoad(_) => ERROR_TOKENIZER_LOAD,
+            EmbedError::TokenizerNotFound(_) => ERROR_TOKENIZER_NOT_FOUND,
+            EmbedError::InvalidTokenizer(_) => ERROR_INVALID_TOKENIZER,
+            EmbedError::Tokenization(_) => ERROR_TOKENIZATION,
+            EmbedError::EmptyInput => ERROR_EMPTY_INPUT,
+            EmbedError::InputTooLong { .. } => ERROR_INPUT_TOO_LONG,
+            EmbedError::TooManyUnknownTokens { .. } => ERROR_TOO_MANY_UNKNOWN,
+            EmbedError::Inference(_) => ERROR_INFERENCE,
+            EmbedError::Timeout(_) => ERROR_TIMEOUT,
+            EmbedError::Cancelled => ERROR_CANCELLED,
+            EmbedError::ShapeMismatch(_) => ERROR_SHAPE_MISMATCH,
+            EmbedError::NotInitialized => ERROR_NOT_INITIALIZED,
+            EmbedError::InvalidInput(_) => ERROR_INVALID_INPUT,
+            EmbedError::DimensionMismatch { .. } => ERROR_DIMENSION_MISMATCH,
+            EmbedError::Io(_) => ERROR_IO,
+            EmbedError::UnsupportedIndexVersion { .. } => ERROR_INDEX_VERSION,
+            EmbedError::CorruptIndex(_) => ERROR_CORRUPT_INDEX,
+            EmbedError::Export(_) => ERROR_EXPORT,
+        }
+    }
+}
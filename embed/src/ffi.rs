@@ -0,0 +1,5619 @@
+//! C FFI over the Rust API, used by the C++ database through arrow_embed.h
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::{c_char, c_float, c_void, CStr, CString};
+use std::fmt;
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, Mutex, RwLock, TryLockError};
+use std::time::Duration;
+
+use half::f16;
+use once_cell::sync::Lazy;
+
+use crate::embedder::{
+    ChunkAggregation, DEFAULT_TOKENIZER_RETRIES, EmbedKind, Embedder, EmbedderOptions,
+    EmbeddingOutput, ExecutionProvider, GraphOptimization, Normalization, PoolingStrategy,
+    TokenizerSource, embed_in_passes,
+};
+use crate::binary::{BinaryIndex, binarize, hamming_distance};
+use crate::corpus::Corpus;
+use crate::error::*;
+use crate::index::VectorIndex;
+use crate::npy::write_npy;
+use crate::pool::{SessionPool, lock_recovering, read_recovering, write_recovering};
+use crate::quantize::{dequantize_int8, dot_int8, quantize_int8};
+use crate::reranker::Reranker;
+use crate::similarity::{cosine_similarity, dot_product, l2_distance};
+use crate::{DEFAULT_MAX_SEQ_LEN, EMBEDDING_DIM};
+
+/// `provider` values accepted by arrow_embed_init_ex()
+pub const EXECUTION_PROVIDER_CPU: i32 = 0;
+pub const EXECUTION_PROVIDER_CUDA: i32 = 1;
+pub const EXECUTION_PROVIDER_COREML: i32 = 2;
+pub const EXECUTION_PROVIDER_TENSORRT: i32 = 3;
+
+/// `pooling` values accepted by arrow_embed_init_ex()
+pub const POOLING_MEAN: i32 = 0;
+pub const POOLING_CLS: i32 = 1;
+pub const POOLING_MAX: i32 = 2;
+
+/// The EXECUTION_PROVIDER_* values as an enum, for ArrowEmbedOptions.provider.
+///
+/// The field itself stays an int32_t so an out-of-range value from C is
+/// rejected with ERROR_INVALID_OPTION rather than being undefined behavior.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrowEmbedProvider {
+    Cpu = EXECUTION_PROVIDER_CPU,
+    Cuda = EXECUTION_PROVIDER_CUDA,
+    CoreMl = EXECUTION_PROVIDER_COREML,
+    TensorRt = EXECUTION_PROVIDER_TENSORRT,
+}
+
+impl ArrowEmbedProvider {
+    fn from_raw(value: i32) -> Option<Self> {
+        [Self::Cpu, Self::Cuda, Self::CoreMl, Self::TensorRt]
+            .into_iter()
+            .find(|provider| *provider as i32 == value)
+    }
+}
+
+/// The POOLING_* values as an enum, for ArrowEmbedOptions.pooling
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrowEmbedPooling {
+    Mean = POOLING_MEAN,
+    Cls = POOLING_CLS,
+    Max = POOLING_MAX,
+}
+
+impl ArrowEmbedPooling {
+    fn from_raw(value: i32) -> Option<Self> {
+        [Self::Mean, Self::Cls, Self::Max].into_iter().find(|pooling| *pooling as i32 == value)
+    }
+}
+
+/// `normalize` values accepted by arrow_embed_init_ex()
+pub const NORMALIZE_NONE: i32 = 0;
+pub const NORMALIZE_L2: i32 = 1;
+pub const NORMALIZE_L1: i32 = 2;
+
+/// `flags` values accepted by arrow_embed_text_len()
+pub const TEXT_UTF8_STRICT: u32 = 0;
+pub const TEXT_UTF8_LOSSY: u32 = 1;
+
+/// `aggregation` values accepted by arrow_embed_text_long()
+pub const CHUNK_AGGREGATION_MEAN: i32 = 0;
+pub const CHUNK_AGGREGATION_ALL: i32 = 1;
+
+/// `kind` values accepted by arrow_embed_count_tokens_as()
+pub const EMBED_KIND_RAW: i32 = 0;
+pub const EMBED_KIND_QUERY: i32 = 1;
+pub const EMBED_KIND_DOCUMENT: i32 = 2;
+
+/// `optimization_level` values accepted in ArrowEmbedOptions
+pub const GRAPH_OPTIMIZATION_DEFAULT: i32 = 0;
+pub const GRAPH_OPTIMIZATION_DISABLE: i32 = 1;
+pub const GRAPH_OPTIMIZATION_BASIC: i32 = 2;
+pub const GRAPH_OPTIMIZATION_EXTENDED: i32 = 3;
+pub const GRAPH_OPTIMIZATION_ALL: i32 = 4;
+
+/// Default handle behind the global arrow_embed_init()/arrow_embed_text() API
+static EMBEDDER: Lazy<Mutex<Option<Arc<ArrowEmbedder>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Addresses of handles returned by arrow_embed_create() and not yet destroyed
+static LIVE_HANDLES: Lazy<Mutex<HashSet<usize>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+thread_local! {
+    /// Message describing the most recent failure on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Record `message` as this thread's last error and pass `code` through
+fn set_last_error(code: i32, message: impl fmt::Display) -> i32 {
+    let message = message.to_string().replace('\0', " ");
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+    code
+}
+
+/// Record an EmbedError and return its code
+fn report(err: EmbedError) -> i32 {
+    set_last_error(err.code(), &err)
+}
+
+/// Result returned to C/C++ containing the embedding vector
+#[repr(C)]
+pub struct EmbeddingResult {
+    /// Pointer to embedding data (caller must free with free_embedding)
+    pub data: *mut c_float,
+    /// Length of the embedding vector, the model's hidden size (384 for MiniLM)
+    pub len: usize,
+    /// Error code: 0 = success, non-zero = error
+    pub error_code: i32,
+}
+
+/// Result returned to C/C++ containing a half-precision embedding vector
+#[repr(C)]
+pub struct EmbeddingF16Result {
+    /// Pointer to IEEE 754 binary16 values (caller must free with
+    /// arrow_embed_free_f16)
+    pub data: *mut u16,
+    /// Length of the embedding vector, the model's hidden size (384 for MiniLM)
+    pub len: usize,
+    /// Error code: 0 = success, non-zero = error
+    pub error_code: i32,
+}
+
+/// Result returned to C/C++ containing an int8-quantized embedding vector
+#[repr(C)]
+pub struct EmbeddingInt8Result {
+    /// Pointer to quantized components (caller must free with
+    /// arrow_embed_free_int8)
+    pub data: *mut i8,
+    /// Length of the embedding vector, the model's hidden size (384 for MiniLM)
+    pub len: usize,
+    /// Error code: 0 = success, non-zero = error
+    pub error_code: i32,
+}
+
+/// Result returned to C/C++ containing a batch of embedding vectors
+#[repr(C)]
+pub struct EmbeddingBatchResult {
+    /// Pointer to `count * dim` floats, one embedding after another
+    /// (caller must free with arrow_embed_free_batch)
+    pub data: *mut c_float,
+    /// Number of embeddings in the batch
+    pub count: usize,
+    /// Length of each embedding vector, the model's hidden size (384 for MiniLM)
+    pub dim: usize,
+    /// Error code: 0 = success, non-zero = error
+    pub error_code: i32,
+}
+
+/// Opaque handle to an embedder created with arrow_embed_create()
+///
+/// Each handle owns its own model session behind its own lock.
+pub struct ArrowEmbedder {
+    /// A single embedder, except for the global one from arrow_embed_init_pool()
+    embedders: SessionPool,
+    /// Copied out so querying it never waits on a running embed
+    dim: usize,
+}
+
+impl ArrowEmbedder {
+    fn new(embedder: Embedder) -> Arc<Self> {
+        let pool = SessionPool::from_embedders(vec![embedder]).expect("pool of one embedder");
+        Self::from_pool(pool)
+    }
+
+    fn from_pool(embedders: SessionPool) -> Arc<Self> {
+        Arc::new(ArrowEmbedder {
+            dim: embedders.dim(),
+            embedders,
+        })
+    }
+
+    /// Lock this handle's embedder and embed `text` with it
+    fn embed_to_result(&self, text: &str) -> EmbeddingResult {
+        self.embed_with(text, Embedder::embed)
+    }
+
+    /// Lock this handle's embedder and embed `text` with one of its methods
+    fn embed_with(
+        &self,
+        text: &str,
+        embed: impl FnOnce(&mut Embedder, &str) -> Result<Vec<f32>, EmbedError>,
+    ) -> EmbeddingResult {
+        embedding_result(embed(&mut self.embedders.lock(), text))
+    }
+}
+
+/// Take a reference to the default handle without holding the global lock
+/// while embedding
+fn default_embedder() -> Result<Arc<ArrowEmbedder>, i32> {
+    let guard = lock_recovering(&EMBEDDER);
+    guard.clone().ok_or_else(|| report(EmbedError::NotInitialized))
+}
+
+/// Take a reference to a live handle from arrow_embed_create(), catching
+/// null, unknown and already destroyed handles
+fn live_handle(handle: *const ArrowEmbedder) -> Result<Arc<ArrowEmbedder>, i32> {
+    if handle.is_null() {
+        return Err(set_last_error(ERROR_NULL_POINTER, "handle is null"));
+    }
+    let live = lock_recovering(&LIVE_HANDLES);
+    if !live.contains(&(handle as usize)) {
+        return Err(set_last_error(
+            ERROR_INVALID_HANDLE,
+            "handle was not created by arrow_embed_create() or was already destroyed",
+        ));
+    }
+    // The registry owns one strong reference for as long as the address is
+    // listed, so the pointee is alive here.
+    unsafe {
+        Arc::increment_strong_count(handle);
+        Ok(Arc::from_raw(handle))
+    }
+}
+
+/// Initialize the embedder with model and tokenizer paths.
+/// Must be called before embed_text().
+///
+/// Texts longer than DEFAULT_MAX_SEQ_LEN tokens are truncated.
+///
+/// # Arguments
+/// * `model_path` - Path to the ONNX model file (e.g., "models/all-MiniLM-L6-v2.onnx")
+/// * `tokenizer_name` - HuggingFace tokenizer name (e.g., "sentence-transformers/all-MiniLM-L6-v2")
+///   or path to a local tokenizer.json
+///
+/// # Returns
+/// * ERROR_OK on success, a negative ERROR_* code on failure
+/// * ERROR_RUNTIME_INIT if ONNX Runtime could not be set up
+/// * ERROR_MODEL_LOAD if the model file is missing or not a usable ONNX model
+/// * ERROR_TOKENIZER_LOAD if the tokenizer could not be fetched from the HuggingFace Hub
+/// * ERROR_TOKENIZER_NOT_FOUND if a tokenizer file path was given but does not exist
+/// * ERROR_INVALID_TOKENIZER if the tokenizer file could not be parsed
+///
+/// # Safety
+/// Both arguments must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init(model_path: *const c_char, tokenizer_name: *const c_char) -> i32 {
+    ffi_guard(|| {
+        unsafe {
+            arrow_embed_init_ex(
+                model_path,
+                tokenizer_name,
+                0,
+                0,
+                EXECUTION_PROVIDER_CPU,
+                0,
+                POOLING_MEAN,
+                1,
+            )
+        }
+    })
+}
+
+/// Initialize the embedder with an explicit sequence length limit,
+/// execution provider, pooling strategy and normalization.
+///
+/// Equivalent to arrow_embed_init_with_options() with the same fields set.
+/// Thread counts and every later option are set only through
+/// ArrowEmbedOptions, so this function keeps their defaults; use
+/// arrow_embed_init_threads() for thread counts alone.
+///
+/// # Arguments
+/// * `model_path` - Path to the ONNX model file
+/// * `tokenizer_name` - HuggingFace tokenizer name or path to a local tokenizer.json
+/// * `max_seq_len`, `strict`, `provider`, `device_id`, `pooling`, `normalize` -
+///   As the ArrowEmbedOptions fields of the same name
+///
+/// # Returns
+/// * As for arrow_embed_init_with_options()
+///
+/// # Safety
+/// `model_path` and `tokenizer_name` must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_ex(
+    model_path: *const c_char,
+    tokenizer_name: *const c_char,
+    max_seq_len: usize,
+    strict: i32,
+    provider: i32,
+    device_id: i32,
+    pooling: i32,
+    normalize: i32,
+) -> i32 {
+    ffi_guard(|| {
+        let options = ArrowEmbedOptions {
+            max_seq_len,
+            strict,
+            provider,
+            device_id,
+            pooling,
+            normalize,
+            ..arrow_embed_default_options()
+        };
+        unsafe { arrow_embed_init_with_options(model_path, tokenizer_name, &options) }
+    })
+}
+
+/// Options for arrow_embed_init_opts() and arrow_embed_init_with_options()
+///
+/// Start from arrow_embed_options_default(), which sets `struct_size`, so
+/// fields added later keep their defaults. New fields are only ever added
+/// at the end: the library reads the first `struct_size` bytes, so callers
+/// built against an older header keep working.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ArrowEmbedOptions {
+    /// sizeof(ArrowEmbedOptions) as the caller was compiled
+    pub struct_size: usize,
+    /// Path to the ONNX model file, for arrow_embed_init_opts()
+    pub model_path: *const c_char,
+    /// HuggingFace tokenizer name or path to a local tokenizer.json, for
+    /// arrow_embed_init_opts()
+    pub tokenizer_path: *const c_char,
+    /// Maximum tokens per text, 0 for DEFAULT_MAX_SEQ_LEN
+    pub max_seq_len: usize,
+    /// Non-zero to reject longer texts with ERROR_INPUT_TOO_LONG instead of truncating
+    pub strict: i32,
+    /// One of the EXECUTION_PROVIDER_* values
+    pub provider: i32,
+    /// GPU device for EXECUTION_PROVIDER_CUDA, ignored otherwise
+    pub device_id: i32,
+    /// One of the POOLING_* values
+    pub pooling: i32,
+    /// One of the NORMALIZE_* values; NORMALIZE_NONE returns embeddings as
+    /// pooled
+    pub normalize: i32,
+    /// Threads ONNX Runtime uses within one operator, 0 for ONNX Runtime's
+    /// default of one per physical core; a negative count means one
+    pub intra_threads: i32,
+    /// One of the GRAPH_OPTIMIZATION_* values
+    pub optimization_level: i32,
+    /// Non-zero for bit-identical embeddings across runs: one thread and
+    /// deterministic kernels, overriding both thread counts
+    pub deterministic: i32,
+    /// Model sessions the global embedder keeps, so that many threads can
+    /// embed at once and arrow_embed_text_batch_parallel() can split one
+    /// batch across them; 0 for one
+    pub num_sessions: usize,
+    /// Prepended to every arrow_embed_query() text, e.g. "query: "; null for none
+    pub query_prefix: *const c_char,
+    /// Prepended to every arrow_embed_passage() text, e.g. "passage: "; null for none
+    pub passage_prefix: *const c_char,
+    /// Threads ONNX Runtime uses to run independent operators at the same
+    /// time, 0 for ONNX Runtime's default of running them one after
+    /// another; a negative count means one
+    pub inter_threads: i32,
+    /// Embeddings of recently seen texts each session keeps so repeats skip
+    /// inference; 0 to disable caching
+    pub cache_capacity: usize,
+    /// Non-zero to trim texts and collapse runs of whitespace to one space
+    /// before tokenizing
+    pub trim_input: i32,
+    /// Largest fraction of a text's tokens that may be the tokenizer's
+    /// unknown token before embedding fails with ERROR_TOO_MANY_UNKNOWN;
+    /// 1.0 to accept anything
+    pub max_unknown_fraction: c_float,
+    /// Length the model's embeddings must have, so a model of another size
+    /// fails at init with ERROR_DIMENSION_MISMATCH; 0 to accept any
+    pub expected_dim: usize,
+    /// Pad every sequence to exactly this many tokens, for models exported
+    /// with a static sequence length, overriding `max_seq_len`; 0 to pad a
+    /// batch only to its longest sequence
+    pub fixed_seq_len: usize,
+    /// Keep only the first this many dimensions of each embedding, then
+    /// renormalize, for Matryoshka-trained models; at most the model's
+    /// hidden size, 0 to keep every dimension. arrow_embed_dimension()
+    /// reports this size
+    pub output_dim: usize,
+    /// Cap ONNX Runtime's CPU arena at this many bytes, growing it only by
+    /// what each allocation needs; shared by every session in the process,
+    /// so only the first init sets it. 0 leaves the arena unbounded
+    pub max_memory_bytes: usize,
+    /// Non-zero to allocate per run instead of keeping an arena: slightly
+    /// slower, with a lower peak resident size. Not allowed with
+    /// `max_memory_bytes`
+    pub disable_cpu_arena: i32,
+    /// Stop any inference pass still running after this many milliseconds,
+    /// failing the call with ERROR_TIMEOUT, e.g. to keep a batch within a
+    /// request budget; 0 lets passes run to the end
+    pub timeout_ms: u64,
+    /// Times a HuggingFace Hub tokenizer download that failed transiently
+    /// is retried, waiting 100ms, then 200ms, 400ms and so on; 3 by default,
+    /// 0 to fail on the first error. A missing repo is never retried
+    pub tokenizer_retries: u32,
+}
+
+impl ArrowEmbedOptions {
+    /// Validate the C values, recording the offending field as the last error
+    ///
+    /// # Safety
+    /// The prefix fields must be null or valid null-terminated C strings.
+    unsafe fn to_embedder_options(self) -> Result<EmbedderOptions, i32> {
+        let execution_provider = match ArrowEmbedProvider::from_raw(self.provider) {
+            Some(ArrowEmbedProvider::Cpu) => ExecutionProvider::Cpu,
+            Some(ArrowEmbedProvider::Cuda) => ExecutionProvider::Cuda {
+                device_id: self.device_id,
+            },
+            Some(ArrowEmbedProvider::CoreMl) => ExecutionProvider::CoreMl,
+            Some(ArrowEmbedProvider::TensorRt) => ExecutionProvider::TensorRt,
+            None => {
+                let message = format!("Unknown execution provider: {}", self.provider);
+                return Err(set_last_error(ERROR_INVALID_OPTION, message));
+            }
+        };
+
+        let pooling = match ArrowEmbedPooling::from_raw(self.pooling) {
+            Some(ArrowEmbedPooling::Mean) => PoolingStrategy::Mean,
+            Some(ArrowEmbedPooling::Cls) => PoolingStrategy::Cls,
+            Some(ArrowEmbedPooling::Max) => PoolingStrategy::Max,
+            None => {
+                let message = format!("Unknown pooling strategy: {}", self.pooling);
+                return Err(set_last_error(ERROR_INVALID_OPTION, message));
+            }
+        };
+
+        let normalization = match self.normalize {
+            NORMALIZE_NONE => Normalization::None,
+            NORMALIZE_L2 => Normalization::L2,
+            NORMALIZE_L1 => Normalization::L1,
+            other => {
+                let message = format!("Unknown normalization: {}", other);
+                return Err(set_last_error(ERROR_INVALID_OPTION, message));
+            }
+        };
+
+        let optimization = match self.optimization_level {
+            GRAPH_OPTIMIZATION_DEFAULT => GraphOptimization::default(),
+            GRAPH_OPTIMIZATION_DISABLE => GraphOptimization::Disable,
+            GRAPH_OPTIMIZATION_BASIC => GraphOptimization::Basic,
+            GRAPH_OPTIMIZATION_EXTENDED => GraphOptimization::Extended,
+            GRAPH_OPTIMIZATION_ALL => GraphOptimization::All,
+            other => {
+                let message = format!("Unknown graph optimization level: {}", other);
+                return Err(set_last_error(ERROR_INVALID_OPTION, message));
+            }
+        };
+
+        // A negative thread count is clamped to one thread rather than rejected
+        let intra_threads = usize::try_from(self.intra_threads).unwrap_or(1);
+        let inter_threads = usize::try_from(self.inter_threads).unwrap_or(1);
+
+        if self.max_unknown_fraction.is_nan() || self.max_unknown_fraction < 0.0 {
+            let message = format!(
+                "max_unknown_fraction must be a fraction, got {}",
+                self.max_unknown_fraction
+            );
+            return Err(set_last_error(ERROR_INVALID_OPTION, message));
+        }
+
+        let prefix = |text: *const c_char, name| {
+            if text.is_null() {
+                return Ok(String::new());
+            }
+            unsafe { text_arg(text, name) }.map(str::to_string)
+        };
+        let query_prefix = prefix(self.query_prefix, "query_prefix")?;
+        let passage_prefix = prefix(self.passage_prefix, "passage_prefix")?;
+
+        Ok(EmbedderOptions {
+            max_seq_len: match self.max_seq_len {
+                0 => DEFAULT_MAX_SEQ_LEN,
+                n => n,
+            },
+            strict_length: self.strict != 0,
+            trim_input: self.trim_input != 0,
+            max_unknown_fraction: self.max_unknown_fraction,
+            expected_dim: (self.expected_dim != 0).then_some(self.expected_dim),
+            fixed_seq_len: (self.fixed_seq_len != 0).then_some(self.fixed_seq_len),
+            output_dim: (self.output_dim != 0).then_some(self.output_dim),
+            cpu_arena: self.disable_cpu_arena == 0,
+            max_memory_bytes: (self.max_memory_bytes != 0).then_some(self.max_memory_bytes),
+            timeout: (self.timeout_ms != 0).then(|| Duration::from_millis(self.timeout_ms)),
+            tokenizer_retries: self.tokenizer_retries as usize,
+            execution_provider,
+            pooling,
+            normalization,
+            intra_threads,
+            inter_threads,
+            optimization,
+            cache_capacity: self.cache_capacity,
+            deterministic: self.deterministic != 0,
+            query_prefix,
+            passage_prefix,
+            ..Default::default()
+        })
+    }
+}
+
+/// Bytes of ArrowEmbedOptions up to its last field when `struct_size` was
+/// introduced, the smallest struct_size a caller can pass
+const OPTIONS_V1_SIZE: usize =
+    std::mem::offset_of!(ArrowEmbedOptions, cache_capacity) + std::mem::size_of::<usize>();
+
+/// Copy the caller's options over the defaults, reading only the
+/// `struct_size` bytes they declared
+///
+/// # Safety
+/// `options` must be null or point to at least `struct_size` readable bytes.
+unsafe fn read_options(options: *const ArrowEmbedOptions) -> Result<ArrowEmbedOptions, i32> {
+    let mut read = arrow_embed_options_default();
+    if options.is_null() {
+        return Ok(read);
+    }
+    let size = unsafe { ptr::addr_of!((*options).struct_size).read() };
+    let current = std::mem::size_of::<ArrowEmbedOptions>();
+    if !(OPTIONS_V1_SIZE..=current).contains(&size) {
+        let message = format!(
+            "struct_size {} does not match this library's ArrowEmbedOptions ({} bytes); \
+             start from arrow_embed_options_default()",
+            size, current
+        );
+        return Err(set_last_error(ERROR_INVALID_OPTION, message));
+    }
+    let destination = (&mut read as *mut ArrowEmbedOptions).cast::<u8>();
+    unsafe { ptr::copy_nonoverlapping(options.cast::<u8>(), destination, size) };
+    Ok(read)
+}
+
+/// Get the options arrow_embed_init() uses, with `struct_size` set and no
+/// model or tokenizer path.
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_options_default() -> ArrowEmbedOptions {
+    ArrowEmbedOptions {
+        struct_size: std::mem::size_of::<ArrowEmbedOptions>(),
+        model_path: ptr::null(),
+        tokenizer_path: ptr::null(),
+        max_seq_len: 0,
+        strict: 0,
+        provider: ArrowEmbedProvider::Cpu as i32,
+        device_id: 0,
+        pooling: ArrowEmbedPooling::Mean as i32,
+        normalize: NORMALIZE_L2,
+        intra_threads: 0,
+        optimization_level: GRAPH_OPTIMIZATION_DEFAULT,
+        deterministic: 0,
+        num_sessions: 0,
+        query_prefix: ptr::null(),
+        passage_prefix: ptr::null(),
+        inter_threads: 0,
+        cache_capacity: 0,
+        trim_input: 0,
+        max_unknown_fraction: 0.5,
+        expected_dim: 0,
+        fixed_seq_len: 0,
+        output_dim: 0,
+        max_memory_bytes: 0,
+        disable_cpu_arena: 0,
+        timeout_ms: 0,
+        tokenizer_retries: DEFAULT_TOKENIZER_RETRIES as u32,
+    }
+}
+
+/// Same as arrow_embed_options_default(), kept for existing callers.
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_default_options() -> ArrowEmbedOptions {
+    arrow_embed_options_default()
+}
+
+/// Initialize the global embedder from an ArrowEmbedOptions struct that
+/// also names the model and tokenizer.
+///
+/// # Arguments
+/// * `options` - Options from arrow_embed_options_default() with
+///   `model_path` and `tokenizer_path` set
+///
+/// # Returns
+/// * ERROR_NULL_POINTER if `options`, `model_path` or `tokenizer_path` is null
+/// * ERROR_INVALID_OPTION if `struct_size` is not the size of an
+///   ArrowEmbedOptions this library knows
+/// * otherwise as for arrow_embed_init_with_options()
+///
+/// # Safety
+/// `options` must be null or point to at least `struct_size` bytes of
+/// ArrowEmbedOptions whose strings are null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_opts(options: *const ArrowEmbedOptions) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if options.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "options is null");
+        }
+        let options = match unsafe { read_options(options) } {
+            Ok(o) => o,
+            Err(code) => return code,
+        };
+        let (model_path, tokenizer_path) = (options.model_path, options.tokenizer_path);
+        unsafe { arrow_embed_init_with_options(model_path, tokenizer_path, &options) }
+    })
+}
+
+/// Initialize the global embedder from a sentence-transformers export
+/// directory, with no paths or pooling to spell out.
+///
+/// The model is read from `onnx/model.onnx` or `model.onnx`, the tokenizer
+/// from `tokenizer.json`, and the pooling and sequence length from the
+/// directory's config files; everything else keeps its default.
+///
+/// # Arguments
+/// * `dir` - Path to the export directory
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * ERROR_MODEL_LOAD if the directory has no model or its model or pooling
+///   config can't be used
+/// * ERROR_TOKENIZER_NOT_FOUND if the directory has no tokenizer.json
+/// * other negative codes as for arrow_embed_init()
+///
+/// # Safety
+/// `dir` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_dir(dir: *const c_char) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let dir = match unsafe { text_arg(dir, "dir") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+
+        let mut embedder_guard = lock_recovering(&EMBEDDER);
+        match Embedder::from_dir(dir) {
+            Ok(embedder) => {
+                *embedder_guard = Some(ArrowEmbedder::new(embedder));
+                ERROR_OK
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Initialize the global embedder from a model and tokenizer held in
+/// memory, for applications that bundle them and have no filesystem to
+/// load from.
+///
+/// The model is copied into the ONNX Runtime session and the tokenizer
+/// parsed before this returns, so the caller may free both buffers
+/// straight after the call.
+///
+/// # Arguments
+/// * `model_data` - Bytes of the ONNX model
+/// * `model_len` - Number of bytes in `model_data`
+/// * `tokenizer_json` - Null-terminated contents of a tokenizer.json
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * ERROR_MODEL_LOAD if `model_data` is not a usable model
+/// * ERROR_INVALID_TOKENIZER if `tokenizer_json` is not a valid tokenizer
+/// * other negative codes as for arrow_embed_init()
+///
+/// # Safety
+/// `model_data` must be null or point to `model_len` readable bytes, and
+/// `tokenizer_json` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_from_memory(
+    model_data: *const u8,
+    model_len: usize,
+    tokenizer_json: *const c_char,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if model_data.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "model_data is null");
+        }
+        let tokenizer_json = match unsafe { text_arg(tokenizer_json, "tokenizer_json") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+        let model = unsafe { std::slice::from_raw_parts(model_data, model_len) };
+
+        let mut embedder_guard = lock_recovering(&EMBEDDER);
+        match Embedder::from_bytes(model, TokenizerSource::Json(tokenizer_json.to_string())) {
+            Ok(embedder) => {
+                *embedder_guard = Some(ArrowEmbedder::new(embedder));
+                ERROR_OK
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Initialize the global embedder from a model file and the contents of a
+/// tokenizer.json, so a tokenizer bundled with the application needs
+/// neither a file nor a HuggingFace Hub download.
+///
+/// The JSON is parsed before this returns; the caller may free it straight
+/// after the call.
+///
+/// # Arguments
+/// * `model_path` - Path to the ONNX model file
+/// * `tokenizer_json` - Contents of a tokenizer.json, need not be
+///   null-terminated
+/// * `json_len` - Number of bytes in `tokenizer_json`
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * ERROR_INVALID_TOKENIZER if `tokenizer_json` does not parse as a
+///   tokenizer; arrow_embed_last_error() says where
+/// * ERROR_INVALID_UTF8 if `tokenizer_json` is not valid UTF-8
+/// * other negative codes as for arrow_embed_init()
+///
+/// # Safety
+/// `model_path` must be null or a valid null-terminated C string, and
+/// `tokenizer_json` must be null or point to `json_len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_tokenizer_json(
+    model_path: *const c_char,
+    tokenizer_json: *const c_char,
+    json_len: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let model_path = match unsafe { text_arg(model_path, "model_path") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+        if tokenizer_json.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "tokenizer_json is null");
+        }
+        let json = unsafe { std::slice::from_raw_parts(tokenizer_json.cast::<u8>(), json_len) };
+        let json = match std::str::from_utf8(json) {
+            Ok(json) => json,
+            Err(e) => {
+                let message = format!("tokenizer_json is not valid UTF-8: {}", e);
+                return set_last_error(ERROR_INVALID_UTF8, message);
+            }
+        };
+
+        let tokenizer = TokenizerSource::Json(json.to_string());
+        let mut embedder_guard = lock_recovering(&EMBEDDER);
+        match Embedder::with_tokenizer(model_path, tokenizer, EmbedderOptions::default()) {
+            Ok(embedder) => {
+                *embedder_guard = Some(ArrowEmbedder::new(embedder));
+                ERROR_OK
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Initialize the embedder with options from an ArrowEmbedOptions struct.
+///
+/// If the requested execution provider cannot be registered (for example
+/// the library was built without the `cuda` feature, or no GPU is present)
+/// the model runs on CPU instead of failing; arrow_embed_provider_fallback()
+/// reports this afterwards.
+///
+/// # Arguments
+/// * `model_path` - Path to the ONNX model file
+/// * `tokenizer_name` - HuggingFace tokenizer name or path to a local tokenizer.json
+/// * `options` - Options to use, or null for arrow_embed_options_default();
+///   its `model_path` and `tokenizer_path` are ignored in favor of the
+///   arguments
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * 1 on success, but the requested provider was unavailable and the model runs on CPU;
+///   arrow_embed_last_error() says why
+/// * ERROR_INVALID_OPTION if `provider`, `pooling` or `optimization_level` is
+///   not a known value, or `struct_size` does not match
+/// * ERROR_DIMENSION_MISMATCH if `expected_dim` is set and the model's
+///   embeddings have another length
+/// * ERROR_INVALID_INPUT if `output_dim` exceeds the model's hidden size
+/// * other negative codes as for arrow_embed_init()
+///
+/// # Safety
+/// `model_path` and `tokenizer_name` must be null or valid null-terminated C
+/// strings, and `options` must be null or point to at least `struct_size`
+/// bytes of ArrowEmbedOptions whose prefix fields are null or valid
+/// null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_with_options(
+    model_path: *const c_char,
+    tokenizer_name: *const c_char,
+    options: *const ArrowEmbedOptions,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if model_path.is_null() || tokenizer_name.is_null() {
+            let message = "model_path and tokenizer_name must not be null";
+            return set_last_error(ERROR_NULL_POINTER, message);
+        }
+
+        let options = match unsafe { read_options(options) } {
+            Ok(o) => o,
+            Err(code) => return code,
+        };
+        let pool_size = options.num_sessions.max(1);
+        let options = match unsafe { options.to_embedder_options() } {
+            Ok(o) => o,
+            Err(code) => return code,
+        };
+
+        let model_path_str = match unsafe { text_arg(model_path, "model_path") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+
+        let tokenizer_name_str = match unsafe { text_arg(tokenizer_name, "tokenizer_name") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+
+        let mut embedder_guard = lock_recovering(&EMBEDDER);
+
+        match SessionPool::new(model_path_str, tokenizer_name_str, options, pool_size) {
+            Ok(pool) => {
+                let warning = pool.lock().provider_warning().map(str::to_string);
+                let status = match warning {
+                    Some(warning) => set_last_error(1, warning),
+                    None => ERROR_OK,
+                };
+                *embedder_guard = Some(ArrowEmbedder::from_pool(pool));
+                status
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Initialize the global embedder as a pool of `pool_size` model sessions,
+/// so arrow_embed_text() and the other global calls run on up to that many
+/// threads at once instead of one at a time.
+///
+/// Each session holds its own copy of the model, so memory grows with
+/// `pool_size`. Equivalent to arrow_embed_init_with_options() with only
+/// `num_sessions` changed from the defaults.
+///
+/// # Returns
+/// * ERROR_INVALID_INPUT if `pool_size` is 0
+/// * otherwise as for arrow_embed_init_with_options()
+///
+/// # Safety
+/// `model_path` and `tokenizer_name` must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_pool(
+    model_path: *const c_char,
+    tokenizer_name: *const c_char,
+    pool_size: usize,
+) -> i32 {
+    ffi_guard(|| {
+        if pool_size == 0 {
+            arrow_embed_clear_error();
+            return set_last_error(ERROR_INVALID_INPUT, "pool_size must be at least 1");
+        }
+        let options = ArrowEmbedOptions {
+            num_sessions: pool_size,
+            ..arrow_embed_default_options()
+        };
+        unsafe { arrow_embed_init_with_options(model_path, tokenizer_name, &options) }
+    })
+}
+
+/// Initialize the embedder with explicit ONNX Runtime thread counts, for
+/// sizing it to the machine it is deployed on.
+///
+/// Equivalent to arrow_embed_init_with_options() with only `intra_threads`
+/// and `inter_threads` changed from the defaults.
+///
+/// # Arguments
+/// * `model_path` - Path to the ONNX model file
+/// * `tokenizer_name` - HuggingFace tokenizer name or path to a local tokenizer.json
+/// * `num_threads` - Threads used within one operator, 0 for ONNX Runtime's
+///   default of one per physical core
+/// * `num_parallel` - Threads used to run independent operators at the same
+///   time, 0 for ONNX Runtime's default of running them one after another
+///
+/// Negative counts are clamped to one thread.
+///
+/// # Returns
+/// * As for arrow_embed_init_with_options()
+///
+/// # Safety
+/// `model_path` and `tokenizer_name` must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_init_threads(
+    model_path: *const c_char,
+    tokenizer_name: *const c_char,
+    num_threads: i32,
+    num_parallel: i32,
+) -> i32 {
+    ffi_guard(|| {
+        let options = ArrowEmbedOptions {
+            intra_threads: num_threads,
+            inter_threads: num_parallel,
+            ..arrow_embed_default_options()
+        };
+        unsafe { arrow_embed_init_with_options(model_path, tokenizer_name, &options) }
+    })
+}
+
+/// Check whether the global embedder fell back to CPU.
+///
+/// # Returns
+/// * 1 if the execution provider requested at init was unavailable and the
+///   model runs on CPU, 0 if it runs where requested
+/// * ERROR_NOT_INITIALIZED if no embedder is loaded
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_provider_fallback() -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        match default_embedder() {
+            Ok(handle) => handle.embedders.lock().provider_warning().is_some() as i32,
+            Err(code) => code,
+        }
+    })
+}
+
+/// Embedding cache counters filled in by arrow_embed_stats()
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArrowEmbedStats {
+    /// Texts answered from the cache
+    pub hits: u64,
+    /// Texts looked up but not cached, so embedded by the model
+    pub misses: u64,
+    /// Embeddings currently cached
+    pub entries: usize,
+    /// Most embeddings the cache holds, 0 when caching is disabled
+    pub capacity: usize,
+}
+
+/// Self-test results filled in by arrow_embed_self_test()
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArrowEmbedSelfTest {
+    /// Reference pairs compared; 0 if none are bundled
+    pub pairs: usize,
+    /// Largest difference between a pair's cosine similarity and the
+    /// all-MiniLM-L6-v2 reference
+    pub max_deviation: f32,
+    /// Largest difference in any dimension between a text embedded in a
+    /// batch and on its own
+    pub batch_deviation: f32,
+    /// Bound both deviations are held to
+    pub tolerance: f32,
+    /// 1 if there were pairs and both deviations are within `tolerance`
+    pub passed: i32,
+}
+
+/// Model description filled in by arrow_embed_model_info()
+///
+/// The strings belong to the caller; free each with arrow_embed_free_string().
+/// arrow_embed_model_io() describes the graph's inputs and outputs as text.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ArrowEmbedModelInfo {
+    /// File name of the model without its extension, e.g. "all-MiniLM-L6-v2"
+    pub name: *mut c_char,
+    /// Length of the vectors the model produces, matching EmbeddingResult.len
+    pub dim: usize,
+    /// Most tokens fed to the model per text
+    pub max_seq_len: usize,
+    /// Graph input names separated by commas, e.g. "input_ids,attention_mask"
+    pub input_names: *mut c_char,
+    /// Graph output the embeddings are read from
+    pub output_name: *mut c_char,
+    /// Non-zero if the model reads its embeddings from a pooled
+    /// sentence_embedding output rather than pooling token vectors
+    pub pooled_by_model: i32,
+    /// Non-zero if the model's file name or description marks it as quantized
+    pub quantized: i32,
+}
+
+/// Describe the global embedder's model, whichever model it is.
+///
+/// # Arguments
+/// * `out` - Receives the description; its strings are only set on success
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * ERROR_NULL_POINTER if `out` is null
+/// * ERROR_NOT_INITIALIZED if no embedder is loaded
+///
+/// # Safety
+/// `out` must be null or point to a writable ArrowEmbedModelInfo.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_model_info(out: *mut ArrowEmbedModelInfo) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(out) = (unsafe { out.as_mut() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "out is null");
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        let info = handle.embedders.lock().info();
+        *out = ArrowEmbedModelInfo {
+            name: owned_c_string(&info.name),
+            dim: info.dim,
+            max_seq_len: info.max_seq_len,
+            input_names: owned_c_string(&info.input_names.join(",")),
+            output_name: owned_c_string(&info.output_name),
+            pooled_by_model: (info.embedding_output == EmbeddingOutput::SentenceEmbedding) as i32,
+            quantized: info.quantized as i32,
+        };
+        ERROR_OK
+    })
+}
+
+/// Copy `text` into a C string the caller frees with arrow_embed_free_string().
+/// Graph and file names cannot hold NUL bytes, but any are dropped to be safe.
+fn owned_c_string(text: &str) -> *mut c_char {
+    let bytes: Vec<u8> = text.bytes().filter(|&b| b != 0).collect();
+    CString::new(bytes).expect("NUL bytes were removed").into_raw()
+}
+
+/// Free a string returned by this library, such as the fields of
+/// ArrowEmbedModelInfo.
+///
+/// # Safety
+/// `s` must be null or a string returned by this library, not freed before.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_free_string(s: *mut c_char) {
+    ffi_guard(|| {
+        if !s.is_null() {
+            drop(unsafe { CString::from_raw(s) });
+        }
+    })
+}
+
+/// Get the global embedder's cache counters, summed over its sessions.
+///
+/// The cache is enabled by setting ArrowEmbedOptions.cache_capacity; with
+/// it disabled every counter is 0.
+///
+/// # Arguments
+/// * `out` - Receives the counters
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * ERROR_NULL_POINTER if `out` is null
+/// * ERROR_NOT_INITIALIZED if no embedder is loaded
+///
+/// # Safety
+/// `out` must be null or point to a writable ArrowEmbedStats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_stats(out: *mut ArrowEmbedStats) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(out) = (unsafe { out.as_mut() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "out is null");
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        let stats = handle.embedders.stats();
+        *out = ArrowEmbedStats {
+            hits: stats.hits,
+            misses: stats.misses,
+            entries: stats.entries,
+            capacity: stats.capacity,
+        };
+        ERROR_OK
+    })
+}
+
+/// Check that the global embedder embeds the bundled reference texts the
+/// way sentence-transformers' all-MiniLM-L6-v2 does, to catch an ONNX
+/// Runtime or tokenizer change before trusting a stored index.
+///
+/// A model that doesn't match is reported in `out`, not as an error; other
+/// models are not expected to pass.
+///
+/// # Arguments
+/// * `out` - Receives the results
+///
+/// # Returns
+/// * ERROR_OK once the test has run, whether or not it passed
+/// * ERROR_NULL_POINTER if `out` is null
+/// * ERROR_NOT_INITIALIZED if no embedder is loaded
+/// * other negative codes as for arrow_embed_text_batch()
+///
+/// # Safety
+/// `out` must be null or point to a writable ArrowEmbedSelfTest.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_self_test(out: *mut ArrowEmbedSelfTest) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(out) = (unsafe { out.as_mut() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "out is null");
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        let results = match handle.embedders.lock().self_test() {
+            Ok(results) => results,
+            Err(e) => return report(e),
+        };
+        *out = ArrowEmbedSelfTest {
+            pairs: results.pairs,
+            max_deviation: results.max_deviation,
+            batch_deviation: results.batch_deviation,
+            tolerance: results.tolerance,
+            passed: results.passed() as i32,
+        };
+        ERROR_OK
+    })
+}
+
+/// Unload the global embedder set up by arrow_embed_init().
+///
+/// Later arrow_embed_text() calls fail with ERROR_NOT_INITIALIZED until
+/// arrow_embed_init() is called again, possibly with a different model.
+/// Calls already running on other threads finish first; the session is
+/// freed when the last of them returns. Handles are not affected.
+///
+/// # Returns
+/// * ERROR_OK, including when nothing was initialized
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_shutdown() -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let previous = lock_recovering(&EMBEDDER).take();
+        // Drop outside the lock so a slow teardown doesn't block a new init
+        drop(previous);
+        ERROR_OK
+    })
+}
+
+/// Report whether the global embedder is loaded, for readiness probes;
+/// nothing is embedded.
+///
+/// Never waits on an arrow_embed_init() in progress, which holds the global
+/// embedder while the model loads; that counts as not ready.
+///
+/// # Returns
+/// * 1 if arrow_embed_init() has succeeded and arrow_embed_shutdown() has
+///   not been called since, 0 otherwise
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_is_ready() -> i32 {
+    ffi_guard(|| match EMBEDDER.try_lock() {
+        Ok(embedder) => embedder.is_some() as i32,
+        Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().is_some() as i32,
+        Err(TryLockError::WouldBlock) => 0,
+    })
+}
+
+/// Pay the first-inference cost of the global embedder up front.
+///
+/// Call right after arrow_embed_init() and before serving requests. Runs
+/// throwaway inferences at 16, 128 and max_seq_len tokens on every
+/// embedder of a pool; safe to call again, e.g. after a reload.
+///
+/// # Arguments
+/// * `iterations` - Rounds of the three lengths to run
+///
+/// # Returns
+/// * Milliseconds the warmup took, saturating at INT32_MAX
+/// * a negative error code as for arrow_embed_text()
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_warmup(iterations: usize) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        match handle.embedders.warmup(iterations) {
+            Ok(elapsed) => i32::try_from(elapsed.as_millis()).unwrap_or(i32::MAX),
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Embed a text string and return the embedding vector.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+///
+/// # Returns
+/// * EmbeddingResult containing pointer to float array, length, and error code
+/// * error_code is ERROR_INPUT_TOO_LONG if the text is too long and the embedder is in strict mode
+/// * error_code is ERROR_EMPTY_INPUT if the text is empty or only whitespace
+/// * error_code is ERROR_TOO_MANY_UNKNOWN if more of the text than the
+///   embedder's max_unknown_fraction is outside the tokenizer's vocabulary
+/// * Caller must free the data pointer using free_embedding()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text(text: *const c_char) -> EmbeddingResult {
+    ffi_guard(|| {
+        if text.is_null() {
+            arrow_embed_clear_error();
+            let code = set_last_error(ERROR_NULL_POINTER, "text is null");
+            return EmbeddingResult::error(code);
+        }
+        let bytes = unsafe { CStr::from_ptr(text) }.to_bytes();
+        unsafe { arrow_embed_text_len(bytes.as_ptr(), bytes.len(), TEXT_UTF8_STRICT) }
+    })
+}
+
+/// Embed `len` bytes of text, which may contain NUL bytes and need not be
+/// null-terminated.
+///
+/// # Arguments
+/// * `text` - Bytes to embed
+/// * `len` - Number of bytes in `text`
+/// * `flags` - TEXT_UTF8_STRICT to reject invalid UTF-8, or TEXT_UTF8_LOSSY
+///   to replace invalid sequences (e.g. latin-1 bytes) with U+FFFD
+///
+/// # Returns
+/// * As for arrow_embed_text()
+/// * error_code is ERROR_INVALID_UTF8 if `text` is not valid UTF-8 and
+///   `flags` is TEXT_UTF8_STRICT
+/// * error_code is ERROR_INVALID_OPTION if `flags` is not a known value
+///
+/// # Safety
+/// `text` must be null or point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_len(
+    text: *const u8,
+    len: usize,
+    flags: u32,
+) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if text.is_null() {
+            return EmbeddingResult::error(set_last_error(ERROR_NULL_POINTER, "text is null"));
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(text, len) };
+        let text_str = match decode_text(bytes, flags) {
+            Ok(s) => s,
+            Err(code) => return EmbeddingResult::error(code),
+        };
+
+        match default_embedder() {
+            Ok(handle) => handle.embed_to_result(&text_str),
+            Err(code) => EmbeddingResult::error(code),
+        }
+    })
+}
+
+/// Embed `len` bytes of text that may not be valid UTF-8, such as latin-1
+/// from legacy sources.
+///
+/// Same as arrow_embed_text_len() with TEXT_UTF8_LOSSY: invalid sequences
+/// become U+FFFD before tokenizing instead of failing the call. The
+/// replaced characters are lost, so the embedding may differ from that of
+/// the text correctly decoded; transcode to UTF-8 first where the encoding
+/// is known.
+///
+/// # Arguments
+/// * `data` - Bytes to embed
+/// * `len` - Number of bytes in `data`
+///
+/// # Returns
+/// * As for arrow_embed_text(); never ERROR_INVALID_UTF8
+///
+/// # Safety
+/// `data` must be null or point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_bytes(data: *const u8, len: usize) -> EmbeddingResult {
+    unsafe { arrow_embed_text_len(data, len, TEXT_UTF8_LOSSY) }
+}
+
+/// Embed a text string, rounding the embedding to half precision.
+///
+/// Inference runs in f32 as for arrow_embed_text(); only the result is
+/// rounded, halving its size for storage. Cosine similarity between the
+/// rounded and full vectors stays above 0.999.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+///
+/// # Returns
+/// * EmbeddingF16Result whose `data` holds IEEE 754 binary16 bit patterns,
+///   freed with arrow_embed_free_f16(); error codes as for arrow_embed_text()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_f16(text: *const c_char) -> EmbeddingF16Result {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingF16Result::error(code),
+        };
+
+        match default_embedder() {
+            Ok(handle) => f16_result(handle.embedders.lock().embed_f16(text_str)),
+            Err(code) => EmbeddingF16Result::error(code),
+        }
+    })
+}
+
+/// Embed a text string, quantizing the embedding to int8.
+///
+/// Component `i` is restored as `data[i] * scale / 127`; the largest
+/// component maps to ±127.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+/// * `scale` - Receives the largest absolute component, 0 for a zero vector
+///
+/// # Returns
+/// * EmbeddingInt8Result freed with arrow_embed_free_int8(); error codes as
+///   for arrow_embed_text(), or ERROR_NULL_POINTER if `scale` is null
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string, and `scale`
+/// must be null or point to writable memory for one float.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_int8(
+    text: *const c_char,
+    scale: *mut c_float,
+) -> EmbeddingInt8Result {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if scale.is_null() {
+            return EmbeddingInt8Result::error(set_last_error(ERROR_NULL_POINTER, "scale is null"));
+        }
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingInt8Result::error(code),
+        };
+
+        let embedding = match default_embedder() {
+            Ok(handle) => handle.embedders.lock().embed(text_str),
+            Err(code) => return EmbeddingInt8Result::error(code),
+        };
+        match embedding {
+            Ok(embedding) => {
+                let (quantized, embedding_scale) = quantize_int8(&embedding);
+                unsafe { *scale = embedding_scale };
+                int8_result(quantized)
+            }
+            Err(e) => EmbeddingInt8Result::error(report(e)),
+        }
+    })
+}
+
+/// Quantize an embedding of `len` floats to int8, as arrow_embed_text_int8()
+/// does, for embeddings computed or stored elsewhere.
+///
+/// # Arguments
+/// * `values` - The embedding to quantize
+/// * `len` - Number of floats in `values`
+/// * `scale` - Receives the largest absolute component, 0 for a zero vector
+///
+/// # Returns
+/// * EmbeddingInt8Result of `len` components freed with
+///   arrow_embed_free_int8(), or ERROR_NULL_POINTER if `values` or `scale`
+///   is null
+///
+/// # Safety
+/// `values` must be null or point to at least `len` floats, and `scale`
+/// must be null or point to writable memory for one float.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_quantize(
+    values: *const c_float,
+    len: usize,
+    scale: *mut c_float,
+) -> EmbeddingInt8Result {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if values.is_null() || scale.is_null() {
+            let message = "values and scale must not be null";
+            return EmbeddingInt8Result::error(set_last_error(ERROR_NULL_POINTER, message));
+        }
+        let values = unsafe { std::slice::from_raw_parts(values, len) };
+        let (quantized, values_scale) = quantize_int8(values);
+        unsafe { *scale = values_scale };
+        int8_result(quantized)
+    })
+}
+
+/// Restore an embedding quantized by arrow_embed_quantize() or
+/// arrow_embed_text_int8(), to within `scale / 254` per component.
+///
+/// # Arguments
+/// * `data` - The quantized components
+/// * `len` - Number of components in `data`
+/// * `scale` - The scale returned alongside them
+///
+/// # Returns
+/// * EmbeddingResult of `len` floats freed with arrow_embed_free(), or
+///   ERROR_NULL_POINTER if `data` is null
+///
+/// # Safety
+/// `data` must be null or point to at least `len` int8 values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_dequantize(
+    data: *const i8,
+    len: usize,
+    scale: c_float,
+) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if data.is_null() {
+            return EmbeddingResult::error(set_last_error(ERROR_NULL_POINTER, "data is null"));
+        }
+        let data = unsafe { std::slice::from_raw_parts(data, len) };
+        embedding_result(Ok(dequantize_int8(data, scale)))
+    })
+}
+
+/// Read text bytes as UTF-8 the way `flags` asks, recording failures as the
+/// last error
+fn decode_text(bytes: &[u8], flags: u32) -> Result<Cow<'_, str>, i32> {
+    match flags {
+        TEXT_UTF8_STRICT => std::str::from_utf8(bytes).map(Cow::Borrowed).map_err(|e| {
+            set_last_error(ERROR_INVALID_UTF8, format!("text is not valid UTF-8: {}", e))
+        }),
+        TEXT_UTF8_LOSSY => Ok(String::from_utf8_lossy(bytes)),
+        other => {
+            let message = format!("Unknown text flags: {}", other);
+            Err(set_last_error(ERROR_INVALID_OPTION, message))
+        }
+    }
+}
+
+/// Fallback result of an FFI function whose body panicked
+trait PanicResult {
+    fn from_panic(code: i32) -> Self;
+}
+
+impl PanicResult for i32 {
+    fn from_panic(code: i32) -> Self {
+        code
+    }
+}
+
+impl PanicResult for i64 {
+    fn from_panic(code: i32) -> Self {
+        code as i64
+    }
+}
+
+/// Sizes and lengths report nothing
+impl PanicResult for usize {
+    fn from_panic(_: i32) -> Self {
+        0
+    }
+}
+
+/// Scores report NaN, as for any other failure
+impl PanicResult for c_float {
+    fn from_panic(_: i32) -> Self {
+        f32::NAN
+    }
+}
+
+/// Hamming distances report UINT32_MAX, as for any other failure
+impl PanicResult for u32 {
+    fn from_panic(_: i32) -> Self {
+        u32::MAX
+    }
+}
+
+impl PanicResult for () {
+    fn from_panic(_: i32) -> Self {}
+}
+
+impl<T> PanicResult for *mut T {
+    fn from_panic(_: i32) -> Self {
+        ptr::null_mut()
+    }
+}
+
+impl<T> PanicResult for *const T {
+    fn from_panic(_: i32) -> Self {
+        ptr::null()
+    }
+}
+
+impl PanicResult for EmbeddingResult {
+    fn from_panic(code: i32) -> Self {
+        EmbeddingResult::error(code)
+    }
+}
+
+impl PanicResult for EmbeddingF16Result {
+    fn from_panic(code: i32) -> Self {
+        EmbeddingF16Result::error(code)
+    }
+}
+
+impl PanicResult for EmbeddingInt8Result {
+    fn from_panic(code: i32) -> Self {
+        EmbeddingInt8Result::error(code)
+    }
+}
+
+impl PanicResult for EmbeddingBatchResult {
+    fn from_panic(code: i32) -> Self {
+        EmbeddingBatchResult::error(code)
+    }
+}
+
+/// Run the body of an `extern "C"` function, turning a panic into
+/// ERROR_PANIC instead of unwinding into the C caller.
+///
+/// The panic message is recorded as the last error. A panic while an
+/// embedder is locked does not wedge it: the next call on that embedder
+/// recovers the lock and runs normally.
+fn ffi_guard<R: PanicResult>(body: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown cause");
+            R::from_panic(set_last_error(ERROR_PANIC, format!("Panic in arrow_embed: {}", message)))
+        }
+    }
+}
+
+/// Borrow a C string argument named `name`, recording null or invalid UTF-8
+/// as the last error
+unsafe fn text_arg<'a>(text: *const c_char, name: &str) -> Result<&'a str, i32> {
+    if text.is_null() {
+        return Err(set_last_error(ERROR_NULL_POINTER, format!("{} is null", name)));
+    }
+    unsafe { CStr::from_ptr(text) }.to_str().map_err(|e| {
+        set_last_error(ERROR_INVALID_UTF8, format!("{} is not valid UTF-8: {}", name, e))
+    })
+}
+
+/// Borrow the `count` C strings at `texts`, failing on the first null or
+/// invalid entry
+unsafe fn text_args<'a>(texts: *const *const c_char, count: usize) -> Result<Vec<&'a str>, i32> {
+    if texts.is_null() {
+        return Err(set_last_error(ERROR_NULL_POINTER, "texts is null"));
+    }
+    let texts = unsafe { std::slice::from_raw_parts(texts, count) };
+    texts
+        .iter()
+        .enumerate()
+        .map(|(i, &text)| unsafe { text_arg(text, &format!("texts[{}]", i)) })
+        .collect()
+}
+
+/// Hand ownership of an embedding to the C caller
+fn embedding_result(embedding: Result<Vec<f32>, EmbedError>) -> EmbeddingResult {
+    match embedding {
+        Ok(embedding) => {
+            let len = embedding.len();
+            let mut boxed = embedding.into_boxed_slice();
+            let data = boxed.as_mut_ptr();
+            std::mem::forget(boxed); // Prevent deallocation, caller must free
+
+            EmbeddingResult {
+                data,
+                len,
+                error_code: 0,
+            }
+        }
+        Err(e) => EmbeddingResult::error(report(e)),
+    }
+}
+
+impl EmbeddingResult {
+    fn error(error_code: i32) -> Self {
+        EmbeddingResult {
+            data: ptr::null_mut(),
+            len: 0,
+            error_code,
+        }
+    }
+}
+
+fn f16_result(embedding: Result<Vec<f16>, EmbedError>) -> EmbeddingF16Result {
+    match embedding {
+        Ok(embedding) => {
+            let mut boxed: Box<[u16]> = embedding.into_iter().map(f16::to_bits).collect();
+            let len = boxed.len();
+            let data = boxed.as_mut_ptr();
+            std::mem::forget(boxed); // Prevent deallocation, caller must free
+
+            EmbeddingF16Result {
+                data,
+                len,
+                error_code: 0,
+            }
+        }
+        Err(e) => EmbeddingF16Result::error(report(e)),
+    }
+}
+
+fn int8_result(quantized: Vec<i8>) -> EmbeddingInt8Result {
+    let len = quantized.len();
+    let mut boxed = quantized.into_boxed_slice();
+    let data = boxed.as_mut_ptr();
+    std::mem::forget(boxed); // Prevent deallocation, caller must free
+
+    EmbeddingInt8Result {
+        data,
+        len,
+        error_code: 0,
+    }
+}
+
+impl EmbeddingInt8Result {
+    fn error(error_code: i32) -> Self {
+        EmbeddingInt8Result {
+            data: ptr::null_mut(),
+            len: 0,
+            error_code,
+        }
+    }
+}
+
+impl EmbeddingF16Result {
+    fn error(error_code: i32) -> Self {
+        EmbeddingF16Result {
+            data: ptr::null_mut(),
+            len: 0,
+            error_code,
+        }
+    }
+}
+
+impl EmbeddingBatchResult {
+    fn error(error_code: i32) -> Self {
+        EmbeddingBatchResult {
+            data: ptr::null_mut(),
+            count: 0,
+            dim: 0,
+            error_code,
+        }
+    }
+
+    /// Hand `flat`, `count` rows of `dim` floats, to the caller
+    fn from_flat(flat: Vec<f32>, count: usize, dim: usize) -> Self {
+        let mut boxed = flat.into_boxed_slice();
+        let data = boxed.as_mut_ptr();
+        std::mem::forget(boxed); // Prevent deallocation, caller must free
+
+        EmbeddingBatchResult {
+            data,
+            count,
+            dim,
+            error_code: 0,
+        }
+    }
+}
+
+/// Embed a search query, prepending the `query_prefix` given at init.
+///
+/// Instruction-tuned models such as E5 expect queries and documents to be
+/// marked differently; embed documents with arrow_embed_passage().
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+///
+/// # Returns
+/// * As for arrow_embed_text()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_query(text: *const c_char) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingResult::error(code),
+        };
+
+        match default_embedder() {
+            Ok(handle) => handle.embed_with(text_str, Embedder::embed_query),
+            Err(code) => EmbeddingResult::error(code),
+        }
+    })
+}
+
+/// Embed a document, prepending the `passage_prefix` given at init.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+///
+/// # Returns
+/// * As for arrow_embed_text()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_passage(text: *const c_char) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingResult::error(code),
+        };
+
+        match default_embedder() {
+            Ok(handle) => handle.embed_with(text_str, Embedder::embed_passage),
+            Err(code) => EmbeddingResult::error(code),
+        }
+    })
+}
+
+/// Embed a sentence pair, such as a premise and hypothesis, as one sequence
+/// with separate segments, the way BERT-style models are trained on pairs.
+///
+/// # Arguments
+/// * `a` - Null-terminated C string for the first segment
+/// * `b` - Null-terminated C string for the second segment
+///
+/// # Returns
+/// * As for arrow_embed_text()
+///
+/// # Safety
+/// Both arguments must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_pair(
+    a: *const c_char,
+    b: *const c_char,
+) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let (a_str, b_str) = match unsafe { (text_arg(a, "a"), text_arg(b, "b")) } {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(code), _) | (_, Err(code)) => return EmbeddingResult::error(code),
+        };
+
+        match default_embedder() {
+            Ok(handle) => handle.embed_with(a_str, |embedder, a| embedder.embed_pair(a, b_str)),
+            Err(code) => EmbeddingResult::error(code),
+        }
+    })
+}
+
+/// Embed a text string into a caller-owned buffer, with no allocation to free.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+/// * `out` - Buffer receiving the embedding
+/// * `out_cap` - Capacity of `out` in floats; at least arrow_embed_dimension()
+///
+/// # Returns
+/// * Number of floats written on success
+/// * ERROR_BUFFER_TOO_SMALL, without embedding or writing, if `out_cap` is
+///   below the embedding dimension
+/// * other negative codes as for arrow_embed_text()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string, and `out` must
+/// be null or point to `out_cap` writable floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_into(
+    text: *const c_char,
+    out: *mut c_float,
+    out_cap: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if out.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "out is null");
+        }
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        if out_cap < handle.dim {
+            let message = format!("out holds {} floats, embedding needs {}", out_cap, handle.dim);
+            return set_last_error(ERROR_BUFFER_TOO_SMALL, message);
+        }
+
+        let mut embedder = handle.embedders.lock();
+        match embedder.embed(text_str) {
+            Ok(embedding) if embedding.len() <= out_cap => {
+                let out = unsafe { std::slice::from_raw_parts_mut(out, embedding.len()) };
+                out.copy_from_slice(&embedding);
+                embedding.len() as i32
+            }
+            Ok(embedding) => {
+                let len = embedding.len();
+                let message = format!("out holds {} floats, embedding has {}", out_cap, len);
+                set_last_error(ERROR_BUFFER_TOO_SMALL, message)
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Embed a text string mean-pooled and by its [CLS] vector from a single
+/// inference pass, into two caller-owned buffers.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+/// * `out_mean` - Buffer receiving the mean-pooled embedding
+/// * `out_cls` - Buffer receiving the [CLS] embedding
+/// * `out_cap` - Capacity of each buffer in floats; at least
+///   arrow_embed_dimension()
+///
+/// # Returns
+/// * Number of floats written to each buffer on success
+/// * ERROR_BUFFER_TOO_SMALL, without embedding or writing, if `out_cap` is
+///   below the embedding dimension
+/// * ERROR_INVALID_INPUT if the model only exports pooled embeddings
+/// * other negative codes as for arrow_embed_text()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string, and `out_mean`
+/// and `out_cls` must be null or each point to `out_cap` writable floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_multi_into(
+    text: *const c_char,
+    out_mean: *mut c_float,
+    out_cls: *mut c_float,
+    out_cap: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if out_mean.is_null() || out_cls.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "out_mean and out_cls must not be null");
+        }
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        if out_cap < handle.dim {
+            let message = format!("out holds {} floats, embedding needs {}", out_cap, handle.dim);
+            return set_last_error(ERROR_BUFFER_TOO_SMALL, message);
+        }
+
+        let mut embedder = handle.embedders.lock();
+        match embedder.embed_multi(text_str) {
+            Ok((mean, cls)) => {
+                let len = mean.len().min(out_cap);
+                unsafe { std::slice::from_raw_parts_mut(out_mean, len) }
+                    .copy_from_slice(&mean[..len]);
+                unsafe { std::slice::from_raw_parts_mut(out_cls, len) }
+                    .copy_from_slice(&cls[..len]);
+                len as i32
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Embed text and write the embedding to a NumPy `.npy` file, dtype `<f4`
+/// and shape `(dim,)`, replacing any existing file.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+/// * `out_path` - Null-terminated C string naming the file to write
+///
+/// # Returns
+/// * ERROR_OK on success, ERROR_IO if the file cannot be written, or the
+///   error arrow_embed_text() would give
+///
+/// # Safety
+/// Both arguments must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_to_npy(
+    text: *const c_char,
+    out_path: *const c_char,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let args = unsafe { (text_arg(text, "text"), text_arg(out_path, "out_path")) };
+        let (text_str, path) = match args {
+            (Ok(t), Ok(p)) => (t, p),
+            (Err(code), _) | (_, Err(code)) => return code,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+
+        let embedding = handle.embedders.lock().embed(text_str);
+        match embedding.and_then(|embedding| write_npy(path, &embedding)) {
+            Ok(()) => ERROR_OK,
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Embed a document of any length as one averaged embedding of windows
+/// that start every `stride` tokens.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+/// * `window_tokens` - Tokens per window including [CLS]/[SEP], at most the
+///   embedder's max_seq_len
+/// * `stride` - Text tokens between the starts of consecutive windows, at
+///   most the text tokens a window holds
+///
+/// # Returns
+/// * As for arrow_embed_text()
+/// * error_code is ERROR_INVALID_INPUT if `stride` is 0 or too long, or
+///   `window_tokens` exceeds max_seq_len
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_document(
+    text: *const c_char,
+    window_tokens: usize,
+    stride: usize,
+) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingResult::error(code),
+        };
+
+        match default_embedder() {
+            Ok(handle) => handle.embed_with(text_str, |embedder, text| {
+                embedder.embed_document(text, window_tokens, stride)
+            }),
+            Err(code) => EmbeddingResult::error(code),
+        }
+    })
+}
+
+/// Embed a text string of any length as overlapping chunks of whole tokens.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+/// * `chunk_tokens` - Tokens per chunk including [CLS]/[SEP], at most the
+///   embedder's max_seq_len
+/// * `overlap` - Tokens each chunk repeats from the one before
+/// * `aggregation` - CHUNK_AGGREGATION_MEAN for one averaged embedding,
+///   CHUNK_AGGREGATION_ALL for one embedding per chunk
+///
+/// # Returns
+/// * EmbeddingBatchResult holding `count` embeddings of `dim` floats;
+///   `count` is 1 for CHUNK_AGGREGATION_MEAN
+/// * error_code is ERROR_INVALID_OPTION for an unknown `aggregation`,
+///   ERROR_INVALID_INPUT if the chunk size leaves no room past `overlap`,
+///   ERROR_EMPTY_INPUT for empty text
+/// * Caller must free the result using arrow_embed_free_batch()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_long(
+    text: *const c_char,
+    chunk_tokens: usize,
+    overlap: usize,
+    aggregation: i32,
+) -> EmbeddingBatchResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let aggregation = match aggregation {
+            CHUNK_AGGREGATION_MEAN => ChunkAggregation::MeanOfChunks,
+            CHUNK_AGGREGATION_ALL => ChunkAggregation::ReturnAll,
+            other => {
+                let message = format!("Unknown chunk aggregation: {}", other);
+                let code = set_last_error(ERROR_INVALID_OPTION, message);
+                return EmbeddingBatchResult::error(code);
+            }
+        };
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let mut embedder = handle.embedders.lock();
+
+        match embedder.embed_long(text_str, chunk_tokens, overlap, aggregation) {
+            Ok(embeddings) => {
+                let count = embeddings.len();
+                let flat: Vec<f32> = embeddings.into_iter().flatten().collect();
+                EmbeddingBatchResult::from_flat(flat, count, handle.dim)
+            }
+            Err(e) => EmbeddingBatchResult::error(report(e)),
+        }
+    })
+}
+
+/// Embed each token of a text string, for late-interaction scoring such as
+/// ColBERT's MaxSim.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to embed
+/// * `normalize` - Non-zero to scale each token vector to unit L2 norm
+/// * `skip_special_tokens` - Non-zero to leave out the [CLS]/[SEP] vectors
+///
+/// # Returns
+/// * EmbeddingBatchResult holding `count` token vectors of `dim` floats, one
+///   per token in order; padding never appears
+/// * error_code is ERROR_EMPTY_INPUT for empty text, ERROR_INVALID_INPUT if
+///   the model only exports pooled embeddings
+/// * Caller must free the result using arrow_embed_free_batch()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_tokens(
+    text: *const c_char,
+    normalize: i32,
+    skip_special_tokens: i32,
+) -> EmbeddingBatchResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let mut embedder = handle.embedders.lock();
+
+        match embedder.embed_tokens(text_str, normalize != 0, skip_special_tokens != 0) {
+            Ok((tokens, count, dim)) => EmbeddingBatchResult::from_flat(tokens, count, dim),
+            Err(e) => EmbeddingBatchResult::error(report(e)),
+        }
+    })
+}
+
+/// Pairwise cosine similarities of several text strings, embedded with a
+/// single inference pass.
+///
+/// # Arguments
+/// * `texts` - Array of `count` null-terminated C strings
+/// * `count` - Number of strings in `texts`
+///
+/// # Returns
+/// * EmbeddingBatchResult holding a row-major `count * count` matrix:
+///   `count` rows of `dim == count` scores, where entry `i * count + j`
+///   compares `texts[i]` with `texts[j]`
+/// * Caller must free the result using arrow_embed_free_batch()
+///
+/// # Safety
+/// `texts` must be null or point to `count` valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_similarity_matrix(
+    texts: *const *const c_char,
+    count: usize,
+) -> EmbeddingBatchResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_strs = match unsafe { text_args(texts, count) } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let mut embedder = handle.embedders.lock();
+
+        match embedder.similarity_matrix(&text_strs) {
+            Ok(matrix) => EmbeddingBatchResult::from_flat(matrix, count, count),
+            Err(e) => EmbeddingBatchResult::error(report(e)),
+        }
+    })
+}
+
+/// Tokenize a text string with the global embedder's tokenizer, without
+/// running the model.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to tokenize
+/// * `out_ids` - Buffer receiving up to `out_cap` token ids, may be null
+/// * `out_cap` - Capacity of `out_ids`
+///
+/// # Returns
+/// * Number of tokens `embed` would use, including [CLS]/[SEP]; if this is
+///   larger than `out_cap` only the first `out_cap` ids are written
+/// * a negative error code as for arrow_embed_text()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string, and `out_ids`
+/// must be null or point to `out_cap` writable elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_tokenize(
+    text: *const c_char,
+    out_ids: *mut u32,
+    out_cap: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        let embedder = handle.embedders.lock();
+        let ids = match embedder.tokenize(text_str) {
+            Ok(ids) => ids,
+            Err(e) => return report(e),
+        };
+
+        if !out_ids.is_null() {
+            let written = ids.len().min(out_cap);
+            let out = unsafe { std::slice::from_raw_parts_mut(out_ids, written) };
+            out.copy_from_slice(&ids[..written]);
+        }
+        ids.len() as i32
+    })
+}
+
+/// Count the tokens the global embedder's tokenizer produces for a text
+/// string, so callers can warn before an input gets truncated.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to count
+///
+/// # Returns
+/// * Token count before truncation, including [CLS]/[SEP]; a value above
+///   the embedder's max_seq_len means embedding will truncate the input
+///   (or reject it in strict mode)
+/// * a negative error code as for arrow_embed_text()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_count_tokens(text: *const c_char) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return code as i64,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code as i64,
+        };
+        let embedder = handle.embedders.lock();
+        match embedder.count_tokens(text_str) {
+            Ok(count) => count as i64,
+            Err(e) => report(e) as i64,
+        }
+    })
+}
+
+/// Count the tokens a text gets when embedded as `kind`, with the
+/// `query_prefix` or `passage_prefix` given at init in front of it, as
+/// arrow_embed_query() and arrow_embed_passage() embed it.
+///
+/// # Arguments
+/// * `text` - Null-terminated C string to count
+/// * `kind` - EMBED_KIND_QUERY, EMBED_KIND_DOCUMENT, or EMBED_KIND_RAW for
+///   no prefix
+///
+/// # Returns
+/// * As for arrow_embed_count_tokens(), with the prefix's tokens included
+/// * ERROR_INVALID_OPTION for an unknown `kind`
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_count_tokens_as(text: *const c_char, kind: i32) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let kind = match kind {
+            EMBED_KIND_RAW => EmbedKind::Raw,
+            EMBED_KIND_QUERY => EmbedKind::Query,
+            EMBED_KIND_DOCUMENT => EmbedKind::Document,
+            other => {
+                let message = format!("Unknown embed kind: {}", other);
+                return set_last_error(ERROR_INVALID_OPTION, message) as i64;
+            }
+        };
+        let text_str = match unsafe { text_arg(text, "text") } {
+            Ok(s) => s,
+            Err(code) => return code as i64,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code as i64,
+        };
+        let embedder = handle.embedders.lock();
+        match embedder.count_tokens_as(kind, text_str) {
+            Ok(count) => count as i64,
+            Err(e) => report(e) as i64,
+        }
+    })
+}
+
+/// Embed a sequence tokenized elsewhere, skipping the tokenizer.
+///
+/// # Arguments
+/// * `ids` - `len` token ids, including the special tokens the model
+///   expects, e.g. as arrow_embed_tokenize() writes them
+/// * `mask` - `len` attention mask values, 1 for real tokens and 0 for padding
+/// * `len` - Number of tokens
+///
+/// # Returns
+/// * EmbeddingResult as for arrow_embed_text(); caller must free it using
+///   arrow_embed_free()
+/// * ERROR_NULL_POINTER if `ids` or `mask` is null
+/// * ERROR_EMPTY_INPUT if no `mask` value is 1
+/// * ERROR_INPUT_TOO_LONG if `len` exceeds the embedder's max_seq_len;
+///   token ids are never truncated
+///
+/// # Safety
+/// `ids` and `mask` must be null or point to `len` readable elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_ids(
+    ids: *const i64,
+    mask: *const i64,
+    len: usize,
+) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if ids.is_null() || mask.is_null() {
+            let code = set_last_error(ERROR_NULL_POINTER, "ids and mask must not be null");
+            return EmbeddingResult::error(code);
+        }
+        let ids = unsafe { std::slice::from_raw_parts(ids, len) };
+        let mask = unsafe { std::slice::from_raw_parts(mask, len) };
+
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return EmbeddingResult::error(code),
+        };
+        embedding_result(handle.embedders.lock().embed_ids(ids, mask))
+    })
+}
+
+/// Embed several text strings with a single inference pass.
+///
+/// The whole batch fails if any entry is null or not valid UTF-8; no
+/// partial results are returned. arrow_embed_text_batch_parallel() splits
+/// a batch across a pool's sessions and reports failures per text.
+///
+/// # Arguments
+/// * `texts` - Array of `count` null-terminated C strings
+/// * `count` - Number of strings in `texts`
+///
+/// # Returns
+/// * EmbeddingBatchResult holding `count * dim` floats in input order
+/// * error_code is ERROR_TIMEOUT if the init options set `timeout_ms` and
+///   the batch took longer
+/// * Caller must free the result using arrow_embed_free_batch()
+///
+/// # Safety
+/// `texts` must be null or point to `count` valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_batch(
+    texts: *const *const c_char,
+    count: usize,
+) -> EmbeddingBatchResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_strs = match unsafe { text_args(texts, count) } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let mut embedder = handle.embedders.lock();
+
+        match embedder.embed_batch_flat(&text_strs) {
+            Ok(flat) => {
+                let count = text_strs.len();
+                let dim = embedder.dim();
+                EmbeddingBatchResult::from_flat(flat, count, dim)
+            }
+            Err(e) => EmbeddingBatchResult::error(report(e)),
+        }
+    })
+}
+
+/// Embed several text strings split across the global embedder's
+/// sessions, one shard per session running on its own thread.
+///
+/// Unlike arrow_embed_text_batch(), a text that cannot be embedded does not
+/// fail the batch: its row is zero-filled and its code is written to
+/// `errors`. With a single session the batch runs as one shard.
+///
+/// # Arguments
+/// * `texts` - Array of `count` null-terminated C strings
+/// * `count` - Number of strings in `texts`
+/// * `errors` - Filled with ERROR_OK or the failure code of each text
+///
+/// # Returns
+/// * EmbeddingBatchResult holding `count * dim` floats in input order;
+///   error_code is ERROR_OK even if some texts failed, and
+///   arrow_embed_last_error() describes the last failure
+/// * ERROR_NULL_POINTER if `errors` is null
+/// * other negative codes as for arrow_embed_text_batch()
+/// * Caller must free the result using arrow_embed_free_batch()
+///
+/// # Safety
+/// `texts` must be null or point to `count` valid null-terminated C strings,
+/// and `errors` must be null or point to `count` writable elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_batch_parallel(
+    texts: *const *const c_char,
+    count: usize,
+    errors: *mut i32,
+) -> EmbeddingBatchResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_strs = match unsafe { text_args(texts, count) } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        if errors.is_null() {
+            let code = set_last_error(ERROR_NULL_POINTER, "errors must not be null");
+            return EmbeddingBatchResult::error(code);
+        }
+        let errors = unsafe { std::slice::from_raw_parts_mut(errors, count) };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+
+        let results = handle.embedders.embed_batch_parallel(&text_strs);
+        let mut flat = vec![0.0; count * handle.dim];
+        let rows = flat.chunks_exact_mut(handle.dim).zip(errors.iter_mut());
+        for (result, (row, error)) in results.into_iter().zip(rows) {
+            *error = match result {
+                Ok(embedding) => {
+                    row.copy_from_slice(&embedding);
+                    ERROR_OK
+                }
+                Err(e) => report(e),
+            };
+        }
+        EmbeddingBatchResult::from_flat(flat, count, handle.dim)
+    })
+}
+
+/// Called by arrow_embed_text_batch_cb() with the number of texts embedded
+/// so far, the batch size and the caller's `user_data`; returning non-zero
+/// cancels the batch
+pub type ArrowEmbedProgressCallback =
+    Option<unsafe extern "C" fn(done: usize, total: usize, user_data: *mut c_void) -> i32>;
+
+/// Embed a large batch of text strings like arrow_embed_text_batch(), in
+/// passes of 64 texts, reporting progress after each pass.
+///
+/// `callback` runs on the calling thread between passes, never while the
+/// embedder is locked, so it may call other arrow_embed_* functions.
+///
+/// # Arguments
+/// * `texts` - Array of `count` null-terminated C strings
+/// * `count` - Number of strings in `texts`
+/// * `callback` - Called after each pass; may be null for no reports
+/// * `user_data` - Passed to `callback` untouched
+///
+/// # Returns
+/// * EmbeddingBatchResult holding `count * dim` floats in input order
+/// * error_code is ERROR_CANCELLED if `callback` returned non-zero; the
+///   passes already embedded are discarded
+/// * Caller must free the result using arrow_embed_free_batch()
+///
+/// # Safety
+/// `texts` must be null or point to `count` valid null-terminated C strings,
+/// and `callback` must be safe to call with `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_batch_cb(
+    texts: *const *const c_char,
+    count: usize,
+    callback: ArrowEmbedProgressCallback,
+    user_data: *mut c_void,
+) -> EmbeddingBatchResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let text_strs = match unsafe { text_args(texts, count) } {
+            Ok(s) => s,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return EmbeddingBatchResult::error(code),
+        };
+
+        let embedded = embed_in_passes(
+            &text_strs,
+            |batch| handle.embedders.lock().embed_batch_flat(batch),
+            |done, total| match callback {
+                Some(callback) if unsafe { callback(done, total, user_data) } != 0 => {
+                    ControlFlow::Break(())
+                }
+                _ => ControlFlow::Continue(()),
+            },
+        );
+        match embedded {
+            Ok(flat) => EmbeddingBatchResult::from_flat(flat, text_strs.len(), handle.dim),
+            Err(e) => EmbeddingBatchResult::error(report(e)),
+        }
+    })
+}
+
+/// Embed several text strings with a single inference pass, writing them
+/// into a caller-owned buffer, with no allocation to free.
+///
+/// Embedding `i` is written to `out[i * stride ..]`; floats between the end
+/// of one embedding and the start of the next are left untouched, so rows
+/// of a wider matrix can be filled in place.
+///
+/// # Arguments
+/// * `texts` - Array of `count` null-terminated C strings
+/// * `count` - Number of strings in `texts`
+/// * `out` - Buffer receiving the embeddings
+/// * `stride` - Distance in floats between the starts of consecutive
+///   embeddings; at least arrow_embed_dimension()
+/// * `out_cap` - Capacity of `out` in floats; at least
+///   `(count - 1) * stride + arrow_embed_dimension()`
+///
+/// # Returns
+/// * Number of embeddings written on success
+/// * ERROR_BUFFER_TOO_SMALL, without embedding or writing, if `stride` is
+///   below the embedding dimension or `out_cap` cannot hold every embedding
+/// * other negative codes as for arrow_embed_text_batch()
+///
+/// # Safety
+/// `texts` must be null or point to `count` valid null-terminated C strings,
+/// and `out` must be null or point to `out_cap` writable floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_batch_into(
+    texts: *const *const c_char,
+    count: usize,
+    out: *mut c_float,
+    stride: usize,
+    out_cap: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if out.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "out is null");
+        }
+        let text_strs = match unsafe { text_args(texts, count) } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code,
+        };
+        if stride < handle.dim {
+            let message = format!("stride of {} floats is below dimension {}", stride, handle.dim);
+            return set_last_error(ERROR_BUFFER_TOO_SMALL, message);
+        }
+        let needed = match count {
+            0 => 0,
+            n => (n - 1).saturating_mul(stride).saturating_add(handle.dim),
+        };
+        if out_cap < needed {
+            let message = format!("out holds {} floats, the batch needs {}", out_cap, needed);
+            return set_last_error(ERROR_BUFFER_TOO_SMALL, message);
+        }
+        if count == 0 {
+            return 0;
+        }
+
+        let mut embedder = handle.embedders.lock();
+        match embedder.embed_batch(&text_strs) {
+            Ok(embeddings) => {
+                let out = unsafe { std::slice::from_raw_parts_mut(out, needed) };
+                for (row, embedding) in out.chunks_mut(stride).zip(&embeddings) {
+                    row[..embedding.len()].copy_from_slice(embedding);
+                }
+                embeddings.len() as i32
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Create an independent embedder instance.
+///
+/// Each handle owns its own model session and lock, so handles never block
+/// each other or the global embedder set up by arrow_embed_init(), and
+/// different handles may be used from different threads at the same time.
+///
+/// # Arguments
+/// * `model_path` - Path to the ONNX model file
+/// * `tokenizer_name` - HuggingFace tokenizer name or path to a local tokenizer.json
+///
+/// # Returns
+/// * Opaque handle, or null if the arguments are invalid or loading fails;
+///   arrow_embed_last_error() says why
+/// * Caller must release the handle using arrow_embed_destroy()
+///
+/// # Safety
+/// Both arguments must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_create(
+    model_path: *const c_char,
+    tokenizer_name: *const c_char,
+) -> *mut ArrowEmbedder {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let (Ok(model_path_str), Ok(tokenizer_name_str)) = (
+            unsafe { text_arg(model_path, "model_path") },
+            unsafe { text_arg(tokenizer_name, "tokenizer_name") },
+        ) else {
+            return ptr::null_mut();
+        };
+
+        let embedder = match Embedder::new(model_path_str, tokenizer_name_str) {
+            Ok(e) => e,
+            Err(e) => {
+                report(e);
+                return ptr::null_mut();
+            }
+        };
+
+        let mut live = lock_recovering(&LIVE_HANDLES);
+        let handle = Arc::into_raw(ArrowEmbedder::new(embedder)) as *mut ArrowEmbedder;
+        live.insert(handle as usize);
+        handle
+    })
+}
+
+/// Embed a text string with a handle from arrow_embed_create().
+///
+/// # Returns
+/// * EmbeddingResult as for arrow_embed_text()
+/// * error_code is ERROR_NULL_POINTER for a null handle and ERROR_INVALID_HANDLE
+///   for one that was never created or has been destroyed
+/// * Caller must free the data pointer using arrow_embed_free()
+///
+/// # Safety
+/// `text` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_text_with(
+    handle: *mut ArrowEmbedder,
+    text: *const c_char,
+) -> EmbeddingResult {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let handle = match live_handle(handle) {
+            Ok(h) => h,
+            Err(code) => return EmbeddingResult::error(code),
+        };
+
+        match unsafe { text_arg(text, "text") } {
+            Ok(text_str) => handle.embed_to_result(text_str),
+            Err(code) => EmbeddingResult::error(code),
+        }
+    })
+}
+
+/// Release a handle created by arrow_embed_create().
+///
+/// The model is unloaded once calls already running on other threads with
+/// this handle have finished.
+///
+/// # Returns
+/// * ERROR_OK if the handle was released, or if it is null
+/// * ERROR_INVALID_HANDLE if it was never created or was already destroyed
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_destroy(handle: *mut ArrowEmbedder) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if handle.is_null() {
+            return ERROR_OK;
+        }
+        let mut live = lock_recovering(&LIVE_HANDLES);
+        if !live.remove(&(handle as usize)) {
+            return set_last_error(
+                ERROR_INVALID_HANDLE,
+                "handle was not created by arrow_embed_create() or was already destroyed",
+            );
+        }
+        // Release the registry's reference; in-flight calls hold their own
+        drop(unsafe { Arc::from_raw(handle as *const ArrowEmbedder) });
+        ERROR_OK
+    })
+}
+
+/// Free an embedding result returned by arrow_embed_text() or any other
+/// function returning an EmbeddingResult, such as arrow_embed_query() or
+/// arrow_embed_dequantize().
+///
+/// # Arguments
+/// * `result` - The EmbeddingResult to free
+///
+/// # Safety
+/// `result` must come from one of those functions and must not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_free(result: EmbeddingResult) {
+    ffi_guard(|| {
+        if !result.data.is_null() && result.len > 0 {
+            unsafe {
+                // Reconstruct the Box and let it drop
+                let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(result.data, result.len));
+            }
+        }
+    })
+}
+
+/// Free a half-precision embedding allocated by arrow_embed_text_f16().
+///
+/// # Arguments
+/// * `result` - The EmbeddingF16Result to free
+///
+/// # Safety
+/// `result` must come from arrow_embed_text_f16() and must not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_free_f16(result: EmbeddingF16Result) {
+    ffi_guard(|| {
+        if !result.data.is_null() && result.len > 0 {
+            unsafe {
+                // Reconstruct the Box and let it drop
+                let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(result.data, result.len));
+            }
+        }
+    })
+}
+
+/// Free a quantized embedding allocated by arrow_embed_text_int8() or
+/// arrow_embed_quantize().
+///
+/// # Arguments
+/// * `result` - The EmbeddingInt8Result to free
+///
+/// # Safety
+/// `result` must come from one of those functions and must not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_free_int8(result: EmbeddingInt8Result) {
+    ffi_guard(|| {
+        if !result.data.is_null() && result.len > 0 {
+            unsafe {
+                // Reconstruct the Box and let it drop
+                let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(result.data, result.len));
+            }
+        }
+    })
+}
+
+/// Free a batch result allocated by arrow_embed_text_batch(),
+/// arrow_embed_text_batch_cb(), arrow_embed_text_long(), arrow_embed_tokens()
+/// or arrow_embed_similarity_matrix().
+///
+/// # Arguments
+/// * `result` - The EmbeddingBatchResult to free
+///
+/// # Safety
+/// `result` must come from one of those functions and must not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_free_batch(result: EmbeddingBatchResult) {
+    ffi_guard(|| {
+        let len = result.count * result.dim;
+        if !result.data.is_null() && len > 0 {
+            unsafe {
+                // Reconstruct the Box and let it drop
+                let _ = Box::from_raw(ptr::slice_from_raw_parts_mut(result.data, len));
+            }
+        }
+    })
+}
+
+/// Copy a description of the most recent failure on the calling thread.
+///
+/// Every embedding, init and handle function clears the message on entry,
+/// so it always describes the last call made on this thread.
+///
+/// # Arguments
+/// * `buf` - Buffer receiving the null-terminated message, may be null
+/// * `buf_len` - Size of `buf` in bytes; longer messages are truncated
+///
+/// # Returns
+/// * Length of the full message in bytes, excluding the terminator, or 0 if
+///   the last call succeeded; pass a buffer of at least this plus one
+///
+/// # Safety
+/// `buf` must be null or point to at least `buf_len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_last_error(buf: *mut c_char, buf_len: usize) -> usize {
+    ffi_guard(|| {
+        LAST_ERROR.with(|last| {
+            let last = last.borrow();
+            let message = last.as_ref().map_or(&[][..], |m| m.as_bytes());
+            unsafe { copy_to_c_buffer(message, buf, buf_len) }
+        })
+    })
+}
+
+/// Copy `bytes` into `buf` as a null-terminated string, truncating to fit,
+/// and return the untruncated length
+unsafe fn copy_to_c_buffer(bytes: &[u8], buf: *mut c_char, buf_len: usize) -> usize {
+    if !buf.is_null() && buf_len > 0 {
+        let copied = bytes.len().min(buf_len - 1);
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr().cast::<c_char>(), buf, copied);
+            *buf.add(copied) = 0;
+        }
+    }
+    bytes.len()
+}
+
+/// Get a description of the most recent failure on the calling thread
+/// without copying it.
+///
+/// # Returns
+/// * Null-terminated message, or null if the last call succeeded
+/// * The pointer is owned by the library and stays valid until the next
+///   arrow_embed_* call on the same thread; copy it to keep it
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_last_error_message() -> *const c_char {
+    ffi_guard(|| {
+        LAST_ERROR.with(|last| match last.borrow().as_ref() {
+            Some(message) => message.as_ptr(),
+            None => ptr::null(),
+        })
+    })
+}
+
+/// Forget the last error recorded on the calling thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_clear_error() {
+    ffi_guard(|| {
+        LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    })
+}
+
+/// Cosine similarity of two L2-normalized embeddings of `len` floats.
+///
+/// # Returns
+/// * The dot product of `a` and `b`, or NaN if either pointer is null
+///
+/// # Safety
+/// `a` and `b` must be null or point to at least `len` floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_similarity(
+    a: *const c_float,
+    b: *const c_float,
+    len: usize,
+) -> c_float {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if a.is_null() || b.is_null() {
+            set_last_error(ERROR_NULL_POINTER, "a and b must not be null");
+            return f32::NAN;
+        }
+        let a = unsafe { std::slice::from_raw_parts(a, len) };
+        let b = unsafe { std::slice::from_raw_parts(b, len) };
+        cosine_similarity(a, b)
+    })
+}
+
+/// Dot product of two embeddings of `len` floats.
+///
+/// # Returns
+/// * The dot product of `a` and `b`, or NaN if either pointer is null
+///
+/// # Safety
+/// `a` and `b` must be null or point to at least `len` floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_dot(
+    a: *const c_float,
+    b: *const c_float,
+    len: usize,
+) -> c_float {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if a.is_null() || b.is_null() {
+            set_last_error(ERROR_NULL_POINTER, "a and b must not be null");
+            return f32::NAN;
+        }
+        let a = unsafe { std::slice::from_raw_parts(a, len) };
+        let b = unsafe { std::slice::from_raw_parts(b, len) };
+        dot_product(a, b)
+    })
+}
+
+/// Dot product of two int8-quantized embeddings of `len` components,
+/// estimating the dot product of the originals without dequantizing them.
+///
+/// # Returns
+/// * The scaled integer dot product, or NaN if either pointer is null
+///
+/// # Safety
+/// `a` and `b` must be null or point to at least `len` int8 values.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_dot_int8(
+    a: *const i8,
+    a_scale: c_float,
+    b: *const i8,
+    b_scale: c_float,
+    len: usize,
+) -> c_float {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if a.is_null() || b.is_null() {
+            set_last_error(ERROR_NULL_POINTER, "a and b must not be null");
+            return f32::NAN;
+        }
+        let a = unsafe { std::slice::from_raw_parts(a, len) };
+        let b = unsafe { std::slice::from_raw_parts(b, len) };
+        dot_int8(a, a_scale, b, b_scale)
+    })
+}
+
+/// Euclidean distance between two embeddings of `len` floats.
+///
+/// For L2-normalized embeddings the squared distance is
+/// `2 - 2 * arrow_embed_similarity()`.
+///
+/// # Returns
+/// * The distance between `a` and `b`, or NaN if either pointer is null
+///
+/// # Safety
+/// `a` and `b` must be null or point to at least `len` floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_l2_distance(
+    a: *const c_float,
+    b: *const c_float,
+    len: usize,
+) -> c_float {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if a.is_null() || b.is_null() {
+            set_last_error(ERROR_NULL_POINTER, "a and b must not be null");
+            return f32::NAN;
+        }
+        let a = unsafe { std::slice::from_raw_parts(a, len) };
+        let b = unsafe { std::slice::from_raw_parts(b, len) };
+        l2_distance(a, b)
+    })
+}
+
+/// Embed two texts with the global embedder and return their cosine similarity.
+///
+/// # Returns
+/// * Similarity in [-1, 1], or NaN on failure; arrow_embed_last_error() says why
+///
+/// # Safety
+/// `a` and `b` must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_compare_texts(a: *const c_char, b: *const c_char) -> c_float {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let (a, b) = match unsafe { (text_arg(a, "a"), text_arg(b, "b")) } {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => return f32::NAN,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(_) => return f32::NAN,
+        };
+        let mut embedder = handle.embedders.lock();
+        embedder.similarity(a, b).unwrap_or_else(|e| {
+            report(e);
+            f32::NAN
+        })
+    })
+}
+
+/// Get the version of this library, e.g. "0.1.0".
+///
+/// # Returns
+/// * Null-terminated string owned by the library and valid for its whole
+///   lifetime; do not free it
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Copy a description of the global embedder's model inputs and outputs.
+///
+/// For the model's name, dimension and flags as fields, use
+/// arrow_embed_model_info().
+///
+/// Each line reads `input <name>: <type>` or `output <name>: <type>`, e.g.
+/// `input input_ids: Tensor<i64>(batch_size, sequence_length)`. A last line
+/// names the output embeddings are read from: `embeddings <name>: pooled`,
+/// or `embeddings <name>: pooled by the model` when pooling is skipped.
+///
+/// # Arguments
+/// * `buf` - Buffer receiving the null-terminated description, may be null
+/// * `buf_len` - Size of `buf` in bytes; longer descriptions are truncated
+///
+/// # Returns
+/// * Length of the full description in bytes, excluding the terminator;
+///   pass a buffer of at least this plus one
+/// * ERROR_NOT_INITIALIZED if no embedder is loaded
+///
+/// # Safety
+/// `buf` must be null or point to at least `buf_len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_model_io(buf: *mut c_char, buf_len: usize) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code as i64,
+        };
+        let info = handle.embedders.lock().model_info();
+        unsafe { copy_to_c_buffer(info.as_bytes(), buf, buf_len) as i64 }
+    })
+}
+
+/// Copy an entry of the global embedder's ONNX model metadata, for
+/// checking which export is loaded.
+///
+/// # Arguments
+/// * `key` - `producer_name`, `graph_name`, `description`,
+///   `graph_description`, `domain` or `version` for the model's own fields,
+///   or any other key of its custom metadata map
+/// * `buf` - Buffer receiving the null-terminated value, may be null
+/// * `cap` - Size of `buf` in bytes; longer values are truncated
+///
+/// # Returns
+/// * Length of the full value in bytes, excluding the terminator; pass a
+///   buffer of at least this plus one
+/// * ERROR_INVALID_INPUT if the model has no such entry
+/// * ERROR_NOT_INITIALIZED if no embedder is loaded
+///
+/// # Safety
+/// `key` must be null or a valid null-terminated C string, and `buf` must
+/// be null or point to at least `cap` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_model_metadata(
+    key: *const c_char,
+    buf: *mut c_char,
+    cap: usize,
+) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let key = match unsafe { text_arg(key, "key") } {
+            Ok(k) => k,
+            Err(code) => return code as i64,
+        };
+        let handle = match default_embedder() {
+            Ok(h) => h,
+            Err(code) => return code as i64,
+        };
+        match handle.embedders.lock().model_metadata(key) {
+            Some(value) => unsafe { copy_to_c_buffer(value.as_bytes(), buf, cap) as i64 },
+            None => {
+                let message = format!("model has no metadata entry {:?}", key);
+                set_last_error(ERROR_INVALID_INPUT, message) as i64
+            }
+        }
+    })
+}
+
+/// Get the embedding dimension of the global embedder.
+///
+/// # Returns
+/// * Length of the vectors the loaded model produces, matching
+///   EmbeddingResult.len
+/// * EMBEDDING_DIM (384, all-MiniLM-L6-v2) if nothing is loaded
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_dimension() -> usize {
+    ffi_guard(|| {
+        lock_recovering(&EMBEDDER)
+            .as_ref()
+            .map_or(EMBEDDING_DIM, |handle| handle.dim)
+    })
+}
+
+/// Get the embedding dimension of a handle from arrow_embed_create().
+///
+/// # Returns
+/// * Length of the vectors the handle's model produces, or 0 if the
+///   handle is null or not live
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_embed_dimension_with(handle: *mut ArrowEmbedder) -> usize {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        live_handle(handle).map_or(0, |handle| handle.dim)
+    })
+}
+
+/// Opaque handle to a vector index created with arrow_index_create()
+///
+/// Searches may run concurrently; adds wait for them.
+pub struct ArrowIndex {
+    index: RwLock<VectorIndex>,
+}
+
+/// Create an empty vector index.
+///
+/// # Arguments
+/// * `dim` - Length of the vectors it will hold, e.g. arrow_embed_dimension()
+///
+/// # Returns
+/// * Opaque handle; caller must release it using arrow_index_destroy()
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_index_create(dim: usize) -> *mut ArrowIndex {
+    ffi_guard(|| {
+        Box::into_raw(Box::new(ArrowIndex {
+            index: RwLock::new(VectorIndex::new(dim)),
+        }))
+    })
+}
+
+/// Add a vector to an index under `id`.
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * ERROR_DIMENSION_MISMATCH if `len` is not the index dimension
+/// * ERROR_NULL_POINTER if `index` or `vector` is null
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_index_create(), and
+/// `vector` must be null or point to `len` floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_index_add(
+    index: *mut ArrowIndex,
+    id: u64,
+    vector: *const c_float,
+    len: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(index) = (unsafe { index.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "index is null");
+        };
+        if vector.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "vector is null");
+        }
+        let vector = unsafe { std::slice::from_raw_parts(vector, len) };
+
+        let mut index = write_recovering(&index.index);
+        match index.add(id, vector) {
+            Ok(()) => ERROR_OK,
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Find the `k` vectors most similar to `query`, best first.
+///
+/// # Arguments
+/// * `out_ids`, `out_scores` - Caller arrays with room for `k` entries each,
+///   receiving ids and cosine scores
+///
+/// # Returns
+/// * Number of results written, at most `k` and at most the index size
+/// * ERROR_DIMENSION_MISMATCH if `len` is not the index dimension
+/// * ERROR_NULL_POINTER if any pointer is null
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_index_create(), `query`
+/// must be null or point to `len` floats, and `out_ids`/`out_scores` must be
+/// null or point to `k` writable elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_index_search(
+    index: *const ArrowIndex,
+    query: *const c_float,
+    len: usize,
+    k: usize,
+    out_ids: *mut u64,
+    out_scores: *mut c_float,
+) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(index) = (unsafe { index.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "index is null") as i64;
+        };
+        if query.is_null() || out_ids.is_null() || out_scores.is_null() {
+            let message = "query and output arrays must not be null";
+            return set_last_error(ERROR_NULL_POINTER, message) as i64;
+        }
+        let query = unsafe { std::slice::from_raw_parts(query, len) };
+
+        let index = read_recovering(&index.index);
+        let results = match index.search(query, k) {
+            Ok(r) => r,
+            Err(e) => return report(e) as i64,
+        };
+
+        let ids = unsafe { std::slice::from_raw_parts_mut(out_ids, results.len()) };
+        let scores = unsafe { std::slice::from_raw_parts_mut(out_scores, results.len()) };
+        for (i, (id, score)) in results.iter().enumerate() {
+            ids[i] = *id;
+            scores[i] = *score;
+        }
+        results.len() as i64
+    })
+}
+
+/// Write an index to a file, replacing any existing one.
+///
+/// # Returns
+/// * ERROR_OK on success, ERROR_IO if the file cannot be written
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_index_create(), and
+/// `path` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_index_save(index: *const ArrowIndex, path: *const c_char) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(index) = (unsafe { index.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "index is null");
+        };
+        let path = match unsafe { text_arg(path, "path") } {
+            Ok(p) => p,
+            Err(code) => return code,
+        };
+        let index = read_recovering(&index.index);
+        match index.save(path) {
+            Ok(()) => ERROR_OK,
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Load an index written by arrow_index_save().
+///
+/// # Returns
+/// * Opaque handle, or null on failure; arrow_embed_last_error() says why
+///   (ERROR_INDEX_VERSION and ERROR_CORRUPT_INDEX distinguish bad files)
+/// * Caller must release the handle using arrow_index_destroy()
+///
+/// # Safety
+/// `path` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_index_load(path: *const c_char) -> *mut ArrowIndex {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Ok(path) = (unsafe { text_arg(path, "path") }) else {
+            return ptr::null_mut();
+        };
+        match VectorIndex::load(path) {
+            Ok(index) => Box::into_raw(Box::new(ArrowIndex {
+                index: RwLock::new(index),
+            })),
+            Err(e) => {
+                report(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Number of vectors stored in an index, 0 for a null handle.
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_index_create().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_index_len(index: *const ArrowIndex) -> usize {
+    ffi_guard(|| {
+        match unsafe { index.as_ref() } {
+            Some(index) => read_recovering(&index.index).len(),
+            None => 0,
+        }
+    })
+}
+
+/// Release an index created by arrow_index_create(). Null is ignored.
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_index_create(); it
+/// must not be used after this call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_index_destroy(index: *mut ArrowIndex) {
+    ffi_guard(|| {
+        if !index.is_null() {
+            drop(unsafe { Box::from_raw(index) });
+        }
+    })
+}
+
+/// Pack the signs of an embedding of `len` floats into `(len + 63) / 64`
+/// words, bit `i % 64` of word `i / 64` set when component `i` is positive.
+///
+/// # Returns
+/// * Number of words written
+/// * ERROR_BUFFER_TOO_SMALL if `out_cap` words cannot hold them
+/// * ERROR_NULL_POINTER if `values` or `out` is null
+///
+/// # Safety
+/// `values` must be null or point to `len` floats, and `out` must be null
+/// or point to `out_cap` writable words.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_binarize(
+    values: *const c_float,
+    len: usize,
+    out: *mut u64,
+    out_cap: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if values.is_null() || out.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "values and out must not be null");
+        }
+        let code = binarize(unsafe { std::slice::from_raw_parts(values, len) });
+        if code.len() > out_cap {
+            let message = format!("out holds {} words, code needs {}", out_cap, code.len());
+            return set_last_error(ERROR_BUFFER_TOO_SMALL, message);
+        }
+        unsafe { std::slice::from_raw_parts_mut(out, code.len()) }.copy_from_slice(&code);
+        code.len() as i32
+    })
+}
+
+/// Number of bits that differ between two codes of `words` words from
+/// arrow_embed_binarize().
+///
+/// # Returns
+/// * The Hamming distance, or UINT32_MAX if either pointer is null
+///
+/// # Safety
+/// `a` and `b` must be null or point to at least `words` words.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_embed_hamming_distance(
+    a: *const u64,
+    b: *const u64,
+    words: usize,
+) -> u32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        if a.is_null() || b.is_null() {
+            set_last_error(ERROR_NULL_POINTER, "a and b must not be null");
+            return u32::MAX;
+        }
+        let a = unsafe { std::slice::from_raw_parts(a, words) };
+        let b = unsafe { std::slice::from_raw_parts(b, words) };
+        hamming_distance(a, b)
+    })
+}
+
+/// Opaque handle to a binary index created with arrow_binary_index_create()
+///
+/// Searches may run concurrently; adds wait for them.
+pub struct ArrowBinaryIndex {
+    index: RwLock<BinaryIndex>,
+}
+
+/// Create an empty index of sign-quantized embeddings searched by Hamming
+/// distance.
+///
+/// # Arguments
+/// * `dim` - Length of the vectors it will hold, e.g. arrow_embed_dimension()
+/// * `keep_vectors` - Non-zero to also keep each vector as floats, 32 times
+///   the memory, so arrow_binary_index_search_rescored() can use them
+///
+/// # Returns
+/// * Opaque handle; caller must release it using arrow_binary_index_destroy()
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_binary_index_create(
+    dim: usize,
+    keep_vectors: i32,
+) -> *mut ArrowBinaryIndex {
+    ffi_guard(|| {
+        let index = match keep_vectors {
+            0 => BinaryIndex::new(dim),
+            _ => BinaryIndex::with_rescoring(dim),
+        };
+        Box::into_raw(Box::new(ArrowBinaryIndex {
+            index: RwLock::new(index),
+        }))
+    })
+}
+
+/// Binarize a vector and add it to a binary index under `id`.
+///
+/// # Returns
+/// * As for arrow_index_add()
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_binary_index_create(),
+/// and `vector` must be null or point to `len` floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_binary_index_add(
+    index: *mut ArrowBinaryIndex,
+    id: u64,
+    vector: *const c_float,
+    len: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(index) = (unsafe { index.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "index is null");
+        };
+        if vector.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "vector is null");
+        }
+        let vector = unsafe { std::slice::from_raw_parts(vector, len) };
+
+        let mut index = write_recovering(&index.index);
+        match index.add(id, vector) {
+            Ok(()) => ERROR_OK,
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Find the `k` vectors whose binary codes are nearest to `query`'s,
+/// nearest first.
+///
+/// # Arguments
+/// * `out_ids`, `out_distances` - Caller arrays with room for `k` entries
+///   each, receiving ids and Hamming distances
+///
+/// # Returns
+/// * As for arrow_index_search()
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_binary_index_create(),
+/// `query` must be null or point to `len` floats, and `out_ids` and
+/// `out_distances` must be null or point to `k` writable elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_binary_index_search(
+    index: *const ArrowBinaryIndex,
+    query: *const c_float,
+    len: usize,
+    k: usize,
+    out_ids: *mut u64,
+    out_distances: *mut u32,
+) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(index) = (unsafe { index.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "index is null") as i64;
+        };
+        if query.is_null() || out_ids.is_null() || out_distances.is_null() {
+            let message = "query and output arrays must not be null";
+            return set_last_error(ERROR_NULL_POINTER, message) as i64;
+        }
+        let query = unsafe { std::slice::from_raw_parts(query, len) };
+
+        let index = read_recovering(&index.index);
+        let results = match index.search(query, k) {
+            Ok(r) => r,
+            Err(e) => return report(e) as i64,
+        };
+
+        let ids = unsafe { std::slice::from_raw_parts_mut(out_ids, results.len()) };
+        let distances = unsafe { std::slice::from_raw_parts_mut(out_distances, results.len()) };
+        for (i, (id, distance)) in results.iter().enumerate() {
+            ids[i] = *id;
+            distances[i] = *distance;
+        }
+        results.len() as i64
+    })
+}
+
+/// Take the `candidates` vectors nearest to `query` by Hamming distance and
+/// return the `k` of them most similar to it by cosine similarity, best
+/// first.
+///
+/// # Arguments
+/// * `candidates` - How many Hamming matches to rescore, raised to `k`
+/// * `out_ids`, `out_scores` - Caller arrays with room for `k` entries each,
+///   receiving ids and cosine scores
+///
+/// # Returns
+/// * As for arrow_index_search()
+/// * ERROR_INVALID_INPUT if the index was created without `keep_vectors`
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_binary_index_create(),
+/// `query` must be null or point to `len` floats, and `out_ids` and
+/// `out_scores` must be null or point to `k` writable elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_binary_index_search_rescored(
+    index: *const ArrowBinaryIndex,
+    query: *const c_float,
+    len: usize,
+    k: usize,
+    candidates: usize,
+    out_ids: *mut u64,
+    out_scores: *mut c_float,
+) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(index) = (unsafe { index.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "index is null") as i64;
+        };
+        if query.is_null() || out_ids.is_null() || out_scores.is_null() {
+            let message = "query and output arrays must not be null";
+            return set_last_error(ERROR_NULL_POINTER, message) as i64;
+        }
+        let query = unsafe { std::slice::from_raw_parts(query, len) };
+
+        let index = read_recovering(&index.index);
+        let results = match index.search_rescored(query, k, candidates) {
+            Ok(r) => r,
+            Err(e) => return report(e) as i64,
+        };
+
+        let ids = unsafe { std::slice::from_raw_parts_mut(out_ids, results.len()) };
+        let scores = unsafe { std::slice::from_raw_parts_mut(out_scores, results.len()) };
+        for (i, (id, score)) in results.iter().enumerate() {
+            ids[i] = *id;
+            scores[i] = *score;
+        }
+        results.len() as i64
+    })
+}
+
+/// Number of vectors in a binary index, 0 for a null handle.
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_binary_index_create().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_binary_index_len(index: *const ArrowBinaryIndex) -> usize {
+    ffi_guard(|| {
+        match unsafe { index.as_ref() } {
+            Some(index) => read_recovering(&index.index).len(),
+            None => 0,
+        }
+    })
+}
+
+/// Release a binary index created by arrow_binary_index_create(). Null is
+/// ignored.
+///
+/// # Safety
+/// `index` must be null or a live handle from arrow_binary_index_create();
+/// it must not be used after this call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_binary_index_destroy(index: *mut ArrowBinaryIndex) {
+    ffi_guard(|| {
+        if !index.is_null() {
+            drop(unsafe { Box::from_raw(index) });
+        }
+    })
+}
+
+/// Opaque handle to a corpus created with arrow_corpus_new()
+///
+/// Searches may run concurrently; adds wait for them.
+pub struct ArrowCorpus {
+    corpus: RwLock<Corpus>,
+}
+
+/// Create an empty corpus of embeddings keyed by string ids.
+///
+/// # Arguments
+/// * `dim` - Length of the embeddings it will hold, e.g. arrow_embed_dimension()
+///
+/// # Returns
+/// * Opaque handle; caller must release it using arrow_corpus_destroy()
+#[unsafe(no_mangle)]
+pub extern "C" fn arrow_corpus_new(dim: usize) -> *mut ArrowCorpus {
+    ffi_guard(|| {
+        Box::into_raw(Box::new(ArrowCorpus {
+            corpus: RwLock::new(Corpus::new(dim)),
+        }))
+    })
+}
+
+/// Add an embedding to a corpus under `id`.
+///
+/// # Returns
+/// * ERROR_OK on success
+/// * ERROR_DIMENSION_MISMATCH if `len` is not the corpus dimension
+/// * ERROR_NULL_POINTER / ERROR_INVALID_UTF8 for a bad argument
+///
+/// # Safety
+/// `corpus` must be null or a live handle from arrow_corpus_new(), `id`
+/// must be null or a valid null-terminated C string, and `embedding` must
+/// be null or point to `len` floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_corpus_add(
+    corpus: *mut ArrowCorpus,
+    id: *const c_char,
+    embedding: *const c_float,
+    len: usize,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(corpus) = (unsafe { corpus.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "corpus is null");
+        };
+        let id = match unsafe { text_arg(id, "id") } {
+            Ok(id) => id,
+            Err(code) => return code,
+        };
+        if embedding.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "embedding is null");
+        }
+        let embedding = unsafe { std::slice::from_raw_parts(embedding, len) };
+
+        let mut corpus = write_recovering(&corpus.corpus);
+        match corpus.add(id, embedding) {
+            Ok(()) => ERROR_OK,
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Find the `k` embeddings most similar to `query`, best first.
+///
+/// Results are reported as rows; pass them to arrow_corpus_id() for the ids.
+///
+/// # Arguments
+/// * `out_rows`, `out_scores` - Caller arrays with room for `k` entries
+///   each, receiving rows and cosine scores
+///
+/// # Returns
+/// * Number of results written, at most `k` and at most the corpus size
+/// * ERROR_DIMENSION_MISMATCH if `len` is not the corpus dimension
+/// * ERROR_NULL_POINTER if any pointer is null
+///
+/// # Safety
+/// `corpus` must be null or a live handle from arrow_corpus_new(), `query`
+/// must be null or point to `len` floats, and `out_rows`/`out_scores` must
+/// be null or point to `k` writable elements.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_corpus_search(
+    corpus: *const ArrowCorpus,
+    query: *const c_float,
+    len: usize,
+    k: usize,
+    out_rows: *mut usize,
+    out_scores: *mut c_float,
+) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(corpus) = (unsafe { corpus.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "corpus is null") as i64;
+        };
+        if query.is_null() || out_rows.is_null() || out_scores.is_null() {
+            let message = "query and output arrays must not be null";
+            return set_last_error(ERROR_NULL_POINTER, message) as i64;
+        }
+        let query = unsafe { std::slice::from_raw_parts(query, len) };
+
+        let corpus = read_recovering(&corpus.corpus);
+        let results = match corpus.search_rows(query, k) {
+            Ok(r) => r,
+            Err(e) => return report(e) as i64,
+        };
+
+        let rows = unsafe { std::slice::from_raw_parts_mut(out_rows, results.len()) };
+        let scores = unsafe { std::slice::from_raw_parts_mut(out_scores, results.len()) };
+        for (i, (row, score)) in results.iter().enumerate() {
+            rows[i] = *row;
+            scores[i] = *score;
+        }
+        results.len() as i64
+    })
+}
+
+/// Copy the id stored at `row` of a corpus.
+///
+/// # Arguments
+/// * `row` - Insertion position, as returned by arrow_corpus_search()
+/// * `buf` - Buffer receiving the null-terminated id, may be null
+/// * `buf_len` - Size of `buf` in bytes; longer ids are truncated
+///
+/// # Returns
+/// * Length of the full id in bytes, excluding the terminator
+/// * ERROR_INVALID_INPUT if `row` is out of range
+/// * ERROR_NULL_POINTER if `corpus` is null
+///
+/// # Safety
+/// `corpus` must be null or a live handle from arrow_corpus_new(), and `buf`
+/// must be null or point to at least `buf_len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_corpus_id(
+    corpus: *const ArrowCorpus,
+    row: usize,
+    buf: *mut c_char,
+    buf_len: usize,
+) -> i64 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(corpus) = (unsafe { corpus.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "corpus is null") as i64;
+        };
+        let corpus = read_recovering(&corpus.corpus);
+        match corpus.id(row) {
+            Some(id) => unsafe { copy_to_c_buffer(id.as_bytes(), buf, buf_len) as i64 },
+            None => {
+                let message = format!("row {} is out of range for {} entries", row, corpus.len());
+                set_last_error(ERROR_INVALID_INPUT, message) as i64
+            }
+        }
+    })
+}
+
+/// Write a corpus to a file, replacing any existing one.
+///
+/// # Returns
+/// * ERROR_OK on success, ERROR_IO if the file cannot be written
+///
+/// # Safety
+/// `corpus` must be null or a live handle from arrow_corpus_new(), and
+/// `path` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_corpus_save(corpus: *const ArrowCorpus, path: *const c_char) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(corpus) = (unsafe { corpus.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "corpus is null");
+        };
+        let path = match unsafe { text_arg(path, "path") } {
+            Ok(p) => p,
+            Err(code) => return code,
+        };
+        let corpus = read_recovering(&corpus.corpus);
+        match corpus.save(path) {
+            Ok(()) => ERROR_OK,
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Load a corpus written by arrow_corpus_save().
+///
+/// If an embedder is initialized, the corpus must have its dimension so
+/// that its embeddings can be searched with the embedder's output.
+///
+/// # Returns
+/// * Opaque handle, or null on failure; arrow_embed_last_error() says why
+///   (ERROR_DIMENSION_MISMATCH for a corpus from a different model,
+///   ERROR_INDEX_VERSION and ERROR_CORRUPT_INDEX for bad files)
+/// * Caller must release the handle using arrow_corpus_destroy()
+///
+/// # Safety
+/// `path` must be null or a valid null-terminated C string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_corpus_load(path: *const c_char) -> *mut ArrowCorpus {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Ok(path) = (unsafe { text_arg(path, "path") }) else {
+            return ptr::null_mut();
+        };
+        let corpus = match Corpus::load(path) {
+            Ok(c) => c,
+            Err(e) => {
+                report(e);
+                return ptr::null_mut();
+            }
+        };
+
+        let active_dim = lock_recovering(&EMBEDDER).as_ref().map(|handle| handle.dim);
+        if let Some(expected) = active_dim
+            && corpus.dim() != expected
+        {
+            report(EmbedError::DimensionMismatch {
+                expected,
+                actual: corpus.dim(),
+            });
+            return ptr::null_mut();
+        }
+
+        Box::into_raw(Box::new(ArrowCorpus {
+            corpus: RwLock::new(corpus),
+        }))
+    })
+}
+
+/// Number of embeddings stored in a corpus, 0 for a null handle.
+///
+/// # Safety
+/// `corpus` must be null or a live handle from arrow_corpus_new().
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_corpus_len(corpus: *const ArrowCorpus) -> usize {
+    ffi_guard(|| {
+        match unsafe { corpus.as_ref() } {
+            Some(corpus) => read_recovering(&corpus.corpus).len(),
+            None => 0,
+        }
+    })
+}
+
+/// Release a corpus created by arrow_corpus_new(). Null is ignored.
+///
+/// # Safety
+/// `corpus` must be null or a live handle from arrow_corpus_new(); it must
+/// not be used after this call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_corpus_destroy(corpus: *mut ArrowCorpus) {
+    ffi_guard(|| {
+        if !corpus.is_null() {
+            drop(unsafe { Box::from_raw(corpus) });
+        }
+    })
+}
+
+/// Opaque handle to a cross-encoder created with arrow_rerank_create()
+///
+/// Calls on one handle take turns; use one handle per thread to score in
+/// parallel.
+pub struct ArrowReranker {
+    reranker: Mutex<Reranker>,
+}
+
+/// Load a cross-encoder model, such as ms-marco-MiniLM, for reranking
+/// search results.
+///
+/// # Arguments
+/// * `model_path` - Path to the ONNX model file
+/// * `tokenizer_name` - HuggingFace tokenizer name or path to a local tokenizer.json
+///
+/// # Returns
+/// * Opaque handle, or null if the arguments are invalid or loading fails;
+///   arrow_embed_last_error() says why
+/// * Caller must release the handle using arrow_rerank_destroy()
+///
+/// # Safety
+/// Both arguments must be null or valid null-terminated C strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_rerank_create(
+    model_path: *const c_char,
+    tokenizer_name: *const c_char,
+) -> *mut ArrowReranker {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let (Ok(model_path_str), Ok(tokenizer_name_str)) = (
+            unsafe { text_arg(model_path, "model_path") },
+            unsafe { text_arg(tokenizer_name, "tokenizer_name") },
+        ) else {
+            return ptr::null_mut();
+        };
+
+        match Reranker::new(model_path_str, tokenizer_name_str) {
+            Ok(reranker) => Box::into_raw(Box::new(ArrowReranker {
+                reranker: Mutex::new(reranker),
+            })),
+            Err(e) => {
+                report(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Score how relevant each passage is to a query.
+///
+/// # Arguments
+/// * `passages` - Array of `count` null-terminated C strings
+/// * `out_scores` - Caller array with room for `count` floats, receiving
+///   one relevance logit per passage in passage order; higher is more
+///   relevant. May be null when `count` is 0
+///
+/// # Returns
+/// * ERROR_OK on success, including for no passages
+/// * ERROR_NULL_POINTER if `reranker`, `query` or, with passages to score,
+///   `passages` or `out_scores` is null
+/// * ERROR_EMPTY_INPUT if the query is empty
+///
+/// # Safety
+/// `reranker` must be null or a live handle from arrow_rerank_create(),
+/// `query` must be null or a valid null-terminated C string, `passages`
+/// must be null or point to `count` valid null-terminated C strings, and
+/// `out_scores` must be null or point to `count` writable floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_rerank_score(
+    reranker: *const ArrowReranker,
+    query: *const c_char,
+    passages: *const *const c_char,
+    count: usize,
+    out_scores: *mut c_float,
+) -> i32 {
+    ffi_guard(|| {
+        arrow_embed_clear_error();
+        let Some(reranker) = (unsafe { reranker.as_ref() }) else {
+            return set_last_error(ERROR_NULL_POINTER, "reranker is null");
+        };
+        let query_str = match unsafe { text_arg(query, "query") } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+        if count == 0 {
+            return ERROR_OK;
+        }
+        if out_scores.is_null() {
+            return set_last_error(ERROR_NULL_POINTER, "out_scores is null");
+        }
+        let passage_strs = match unsafe { text_args(passages, count) } {
+            Ok(s) => s,
+            Err(code) => return code,
+        };
+
+        match lock_recovering(&reranker.reranker).score(query_str, &passage_strs) {
+            Ok(scores) => {
+                let out = unsafe { std::slice::from_raw_parts_mut(out_scores, count) };
+                out.copy_from_slice(&scores);
+                ERROR_OK
+            }
+            Err(e) => report(e),
+        }
+    })
+}
+
+/// Release a reranker created by arrow_rerank_create(). Null is ignored.
+///
+/// # Safety
+/// `reranker` must be null or a live handle from arrow_rerank_create(); it
+/// must not be used after this call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn arrow_rerank_destroy(reranker: *mut ArrowReranker) {
+    ffi_guard(|| {
+        if !reranker.is_null() {
+            drop(unsafe { Box::from_raw(reranker) });
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::ffi::CString;
+
+    /// Serializes tests that init or shut down the global embedder
+    static GLOBAL_EMBEDDER: Mutex<()> = Mutex::new(());
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn ffi_batch_matches_single_embeddings() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, 0);
+
+        let texts = [
+            CString::new("first text").unwrap(),
+            CString::new("second, longer text").unwrap(),
+        ];
+        let ptrs: Vec<*const c_char> = texts.iter().map(|t| t.as_ptr()).collect();
+
+        let batch = unsafe { arrow_embed_text_batch(ptrs.as_ptr(), ptrs.len()) };
+        assert_eq!(batch.error_code, 0);
+        assert_eq!(batch.count, texts.len());
+        assert_eq!(batch.dim, EMBEDDING_DIM);
+        let flat = unsafe { std::slice::from_raw_parts(batch.data, batch.count * batch.dim) };
+
+        for (i, text) in texts.iter().enumerate() {
+            let single = unsafe { arrow_embed_text(text.as_ptr()) };
+            assert_eq!(single.error_code, 0);
+            let single_data = unsafe { std::slice::from_raw_parts(single.data, single.len) };
+            let batched = &flat[i * EMBEDDING_DIM..(i + 1) * EMBEDDING_DIM];
+            for (a, b) in single_data.iter().zip(batched) {
+                assert!((a - b).abs() < 1e-4);
+            }
+            unsafe { arrow_embed_free(single) };
+        }
+        unsafe { arrow_embed_free_batch(batch) };
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn handles_embed_independently() {
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("shared text").unwrap();
+
+        let first = unsafe { arrow_embed_create(model.as_ptr(), tokenizer.as_ptr()) };
+        let second = unsafe { arrow_embed_create(model.as_ptr(), tokenizer.as_ptr()) };
+        assert!(!first.is_null() && !second.is_null());
+
+        let a = unsafe { arrow_embed_text_with(first, text.as_ptr()) };
+        arrow_embed_destroy(first);
+        let b = unsafe { arrow_embed_text_with(second, text.as_ptr()) };
+        assert_eq!((a.error_code, b.error_code), (0, 0));
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(a.data, a.len) },
+            unsafe { std::slice::from_raw_parts(b.data, b.len) }
+        );
+
+        unsafe {
+            arrow_embed_free(a);
+            arrow_embed_free(b);
+        }
+        arrow_embed_destroy(second);
+    }
+
+    #[test]
+    fn null_handle_is_rejected() {
+        let text = CString::new("text").unwrap();
+
+        let result = unsafe { arrow_embed_text_with(ptr::null_mut(), text.as_ptr()) };
+
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        assert_eq!(arrow_embed_destroy(ptr::null_mut()), ERROR_OK);
+    }
+
+    #[test]
+    fn unknown_handles_are_caught() {
+        let text = CString::new("text").unwrap();
+        // Never dereferenced: the registry rejects it first
+        let bogus = ptr::NonNull::<ArrowEmbedder>::dangling().as_ptr();
+
+        let result = unsafe { arrow_embed_text_with(bogus, text.as_ptr()) };
+
+        assert_eq!(result.error_code, ERROR_INVALID_HANDLE);
+        assert_eq!(arrow_embed_destroy(bogus), ERROR_INVALID_HANDLE);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn double_destroy_is_caught() {
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("text").unwrap();
+        let handle = unsafe { arrow_embed_create(model.as_ptr(), tokenizer.as_ptr()) };
+        assert!(!handle.is_null());
+
+        assert_eq!(arrow_embed_destroy(handle), ERROR_OK);
+
+        assert_eq!(arrow_embed_destroy(handle), ERROR_INVALID_HANDLE);
+        let result = unsafe { arrow_embed_text_with(handle, text.as_ptr()) };
+        assert_eq!(result.error_code, ERROR_INVALID_HANDLE);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn handles_are_usable_from_several_threads() {
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let handles: Vec<usize> = (0..2)
+            .map(|_| unsafe { arrow_embed_create(model.as_ptr(), tokenizer.as_ptr()) } as usize)
+            .collect();
+
+        std::thread::scope(|scope| {
+            for &handle in &handles {
+                scope.spawn(move || {
+                    let text = CString::new("concurrent text").unwrap();
+                    for _ in 0..10 {
+                        let handle = handle as *mut ArrowEmbedder;
+                        let result = unsafe { arrow_embed_text_with(handle, text.as_ptr()) };
+                        assert_eq!(result.error_code, ERROR_OK);
+                        unsafe { arrow_embed_free(result) };
+                    }
+                });
+            }
+        });
+
+        for handle in handles {
+            assert_eq!(arrow_embed_destroy(handle as *mut ArrowEmbedder), ERROR_OK);
+        }
+    }
+
+    #[test]
+    fn unknown_option_values_are_rejected() {
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+
+        let init = |provider, pooling| unsafe {
+            arrow_embed_init_ex(model.as_ptr(), tokenizer.as_ptr(), 0, 0, provider, 0, pooling, 1)
+        };
+
+        assert_eq!(init(42, POOLING_MEAN), ERROR_INVALID_OPTION);
+        assert_eq!(init(EXECUTION_PROVIDER_CPU, 42), ERROR_INVALID_OPTION);
+    }
+
+    #[test]
+    fn options_struct_is_validated() {
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let options = ArrowEmbedOptions {
+            provider: 42,
+            ..arrow_embed_default_options()
+        };
+
+        let code = unsafe {
+            arrow_embed_init_with_options(model.as_ptr(), tokenizer.as_ptr(), &options)
+        };
+
+        assert_eq!(code, ERROR_INVALID_OPTION);
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "Unknown execution provider: 42");
+    }
+
+    #[test]
+    fn default_options_convert_to_embedder_defaults() {
+        let options = unsafe { arrow_embed_default_options().to_embedder_options() }.unwrap();
+        let defaults = EmbedderOptions::default();
+
+        assert_eq!(options.max_seq_len, defaults.max_seq_len);
+        assert_eq!(options.strict_length, defaults.strict_length);
+        assert_eq!(options.trim_input, defaults.trim_input);
+        assert_eq!(options.max_unknown_fraction, defaults.max_unknown_fraction);
+        assert_eq!(options.execution_provider, defaults.execution_provider);
+        assert_eq!(options.pooling, defaults.pooling);
+        assert_eq!(options.normalization, defaults.normalization);
+        assert_eq!(options.intra_threads, defaults.intra_threads);
+        assert_eq!(options.inter_threads, defaults.inter_threads);
+        assert_eq!(options.optimization, defaults.optimization);
+        assert_eq!(options.deterministic, defaults.deterministic);
+        assert_eq!(options.cache_capacity, defaults.cache_capacity);
+        assert_eq!(options.expected_dim, defaults.expected_dim);
+        assert_eq!(options.fixed_seq_len, defaults.fixed_seq_len);
+        assert_eq!(options.output_dim, defaults.output_dim);
+        assert_eq!(options.cpu_arena, defaults.cpu_arena);
+        assert_eq!(options.max_memory_bytes, defaults.max_memory_bytes);
+        assert_eq!(options.timeout, defaults.timeout);
+        assert_eq!(options.tokenizer_retries, defaults.tokenizer_retries);
+    }
+
+    #[test]
+    fn unknown_fraction_must_be_a_fraction() {
+        for max_unknown_fraction in [-0.1, f32::NAN] {
+            let options = ArrowEmbedOptions {
+                max_unknown_fraction,
+                ..arrow_embed_options_default()
+            };
+            let code = unsafe { options.to_embedder_options() }.err();
+            assert_eq!(code, Some(ERROR_INVALID_OPTION));
+        }
+    }
+
+    #[test]
+    fn options_are_read_up_to_their_struct_size() {
+        let defaults = arrow_embed_options_default();
+        assert_eq!(defaults.struct_size, std::mem::size_of::<ArrowEmbedOptions>());
+
+        let custom = ArrowEmbedOptions {
+            pooling: POOLING_CLS,
+            ..arrow_embed_options_default()
+        };
+        let read = unsafe { read_options(&custom) }.unwrap();
+        assert_eq!(read.pooling, POOLING_CLS);
+
+        let model = CString::new("missing.onnx").unwrap();
+        for struct_size in [0, OPTIONS_V1_SIZE - 1, defaults.struct_size + 8] {
+            let options = ArrowEmbedOptions {
+                struct_size,
+                ..arrow_embed_options_default()
+            };
+            assert_eq!(unsafe { read_options(&options) }.err(), Some(ERROR_INVALID_OPTION));
+            let code =
+                unsafe { arrow_embed_init_with_options(model.as_ptr(), model.as_ptr(), &options) };
+            assert_eq!(code, ERROR_INVALID_OPTION);
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn init_failures_say_whether_the_model_or_tokenizer_is_at_fault() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let missing_model = CString::new("models/missing.onnx").unwrap();
+        let missing_tokenizer = CString::new("models/missing-tokenizer.json").unwrap();
+
+        let no_model = unsafe { arrow_embed_init(missing_model.as_ptr(), tokenizer.as_ptr()) };
+        let no_tokenizer = unsafe { arrow_embed_init(model.as_ptr(), missing_tokenizer.as_ptr()) };
+
+        assert_eq!(no_model, ERROR_MODEL_LOAD);
+        assert_eq!(no_tokenizer, ERROR_TOKENIZER_NOT_FOUND);
+    }
+
+    #[test]
+    fn init_opts_needs_options_and_paths() {
+        assert_eq!(unsafe { arrow_embed_init_opts(ptr::null()) }, ERROR_NULL_POINTER);
+
+        let model = CString::new(TEST_MODEL).unwrap();
+        let options = ArrowEmbedOptions {
+            model_path: model.as_ptr(),
+            ..arrow_embed_options_default()
+        };
+        assert_eq!(unsafe { arrow_embed_init_opts(&options) }, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn init_opts_loads_the_named_model() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let options = ArrowEmbedOptions {
+            model_path: model.as_ptr(),
+            tokenizer_path: tokenizer.as_ptr(),
+            pooling: ArrowEmbedPooling::Cls as i32,
+            ..arrow_embed_options_default()
+        };
+
+        assert_eq!(unsafe { arrow_embed_init_opts(&options) }, ERROR_OK);
+        assert_eq!(arrow_embed_dimension(), EMBEDDING_DIM);
+        arrow_embed_shutdown();
+    }
+
+    #[test]
+    fn pooling_values_select_strategies() {
+        let pooling = |pooling| {
+            let options = ArrowEmbedOptions {
+                pooling,
+                ..arrow_embed_default_options()
+            };
+            unsafe { options.to_embedder_options() }.map(|o| o.pooling)
+        };
+
+        assert_eq!(pooling(POOLING_MEAN), Ok(PoolingStrategy::Mean));
+        assert_eq!(pooling(POOLING_CLS), Ok(PoolingStrategy::Cls));
+        assert_eq!(pooling(POOLING_MAX), Ok(PoolingStrategy::Max));
+        assert_eq!(pooling(3), Err(ERROR_INVALID_OPTION));
+    }
+
+    #[test]
+    fn normalize_values_select_norms() {
+        let normalization = |normalize| {
+            let options = ArrowEmbedOptions {
+                normalize,
+                ..arrow_embed_default_options()
+            };
+            unsafe { options.to_embedder_options() }.map(|o| o.normalization)
+        };
+
+        assert_eq!(normalization(NORMALIZE_NONE), Ok(Normalization::None));
+        assert_eq!(normalization(NORMALIZE_L2), Ok(Normalization::L2));
+        assert_eq!(normalization(NORMALIZE_L1), Ok(Normalization::L1));
+        assert_eq!(normalization(3), Err(ERROR_INVALID_OPTION));
+    }
+
+    #[test]
+    fn out_of_range_session_options_are_rejected() {
+        let unknown_level = ArrowEmbedOptions {
+            optimization_level: 42,
+            ..arrow_embed_default_options()
+        };
+
+        let unknown_level = unsafe { unknown_level.to_embedder_options() };
+        assert_eq!(unknown_level.unwrap_err(), ERROR_INVALID_OPTION);
+    }
+
+    #[test]
+    fn negative_thread_counts_are_clamped_to_one() {
+        let options = ArrowEmbedOptions {
+            intra_threads: -1,
+            inter_threads: -2,
+            ..arrow_embed_default_options()
+        };
+
+        let options = unsafe { options.to_embedder_options() }.unwrap();
+        assert_eq!(options.intra_threads, 1);
+        assert_eq!(options.inter_threads, 1);
+    }
+
+    #[test]
+    fn zero_thread_counts_keep_the_runtime_defaults() {
+        let options = unsafe { arrow_embed_default_options().to_embedder_options() }.unwrap();
+
+        assert_eq!(options.intra_threads, 0);
+        assert_eq!(options.inter_threads, 0);
+    }
+
+    #[test]
+    fn prefixes_are_read_from_options() {
+        let query = CString::new("query: ").unwrap();
+        let invalid = [0xffu8, 0];
+        let options = ArrowEmbedOptions {
+            query_prefix: query.as_ptr(),
+            ..arrow_embed_default_options()
+        };
+        let bad_passage = ArrowEmbedOptions {
+            passage_prefix: invalid.as_ptr().cast(),
+            ..options
+        };
+
+        let converted = unsafe { options.to_embedder_options() }.unwrap();
+        assert_eq!(converted.query_prefix, "query: ");
+        assert_eq!(converted.passage_prefix, "");
+        let err = unsafe { bad_passage.to_embedder_options() }.unwrap_err();
+        assert_eq!(err, ERROR_INVALID_UTF8);
+    }
+
+    #[test]
+    #[cfg(not(feature = "cuda"))]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn cuda_without_feature_falls_back_to_cpu() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let options = ArrowEmbedOptions {
+            provider: EXECUTION_PROVIDER_CUDA,
+            ..arrow_embed_default_options()
+        };
+
+        let code = unsafe {
+            arrow_embed_init_with_options(model.as_ptr(), tokenizer.as_ptr(), &options)
+        };
+
+        assert_eq!(code, 1);
+        assert_eq!(arrow_embed_provider_fallback(), 1);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn init_rejects_a_model_of_another_dimension() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let options = ArrowEmbedOptions {
+            expected_dim: 768,
+            ..arrow_embed_default_options()
+        };
+
+        let code = unsafe {
+            arrow_embed_init_with_options(model.as_ptr(), tokenizer.as_ptr(), &options)
+        };
+
+        assert_eq!(code, ERROR_DIMENSION_MISMATCH);
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert!(message.to_str().unwrap().contains("768"));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn output_dim_sets_the_reported_dimension() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("cut to 256 dimensions").unwrap();
+        let options = ArrowEmbedOptions {
+            output_dim: 256,
+            ..arrow_embed_default_options()
+        };
+        let code = unsafe {
+            arrow_embed_init_with_options(model.as_ptr(), tokenizer.as_ptr(), &options)
+        };
+        assert_eq!(code, ERROR_OK);
+
+        let result = unsafe { arrow_embed_text(text.as_ptr()) };
+
+        assert_eq!(arrow_embed_dimension(), 256);
+        assert_eq!(result.len, 256);
+        let index = arrow_index_create(arrow_embed_dimension());
+        assert_eq!(unsafe { arrow_index_add(index, 1, result.data, result.len) }, ERROR_OK);
+        unsafe {
+            arrow_index_destroy(index);
+            arrow_embed_free(result);
+        }
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn failures_set_last_error() {
+        let text = CString::new("text").unwrap();
+        arrow_embed_clear_error();
+        assert!(arrow_embed_last_error_message().is_null());
+
+        let result = unsafe { arrow_embed_text_with(ptr::null_mut(), text.as_ptr()) };
+
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "handle is null");
+
+        arrow_embed_clear_error();
+        assert!(arrow_embed_last_error_message().is_null());
+    }
+
+    #[test]
+    fn last_error_is_copied_into_caller_buffer() {
+        let text = CString::new("text").unwrap();
+        unsafe { arrow_embed_text_with(ptr::null_mut(), text.as_ptr()) };
+
+        let needed = unsafe { arrow_embed_last_error(ptr::null_mut(), 0) };
+        assert_eq!(needed, "handle is null".len());
+
+        let mut buf = vec![0 as c_char; needed + 1];
+        assert_eq!(unsafe { arrow_embed_last_error(buf.as_mut_ptr(), buf.len()) }, needed);
+        let message = unsafe { CStr::from_ptr(buf.as_ptr()) };
+        assert_eq!(message.to_str().unwrap(), "handle is null");
+
+        let mut short = [0 as c_char; 7];
+        unsafe { arrow_embed_last_error(short.as_mut_ptr(), short.len()) };
+        let message = unsafe { CStr::from_ptr(short.as_ptr()) };
+        assert_eq!(message.to_str().unwrap(), "handle");
+    }
+
+    #[test]
+    fn each_call_clears_the_previous_error() {
+        let text = CString::new("text").unwrap();
+        unsafe { arrow_embed_text_with(ptr::null_mut(), text.as_ptr()) };
+        assert!(!arrow_embed_last_error_message().is_null());
+
+        assert_eq!(arrow_embed_destroy(ptr::null_mut()), ERROR_OK);
+
+        assert!(arrow_embed_last_error_message().is_null());
+        assert_eq!(unsafe { arrow_embed_last_error(ptr::null_mut(), 0) }, 0);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn embedder_can_be_reinitialized_after_shutdown() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("reloaded text").unwrap();
+        let embed = || {
+            let result = unsafe { arrow_embed_text(text.as_ptr()) };
+            let embedding = match result.error_code {
+                ERROR_OK => Ok(unsafe { std::slice::from_raw_parts(result.data, result.len) }),
+                code => Err(code),
+            }
+            .map(<[f32]>::to_vec);
+            unsafe { arrow_embed_free(result) };
+            embedding
+        };
+
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+        let mean = embed().unwrap();
+
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        assert_eq!(embed(), Err(ERROR_NOT_INITIALIZED));
+
+        let init = unsafe {
+            arrow_embed_init_ex(model.as_ptr(), tokenizer.as_ptr(), 0, 0, 0, 0, POOLING_CLS, 1)
+        };
+        assert_eq!(init, ERROR_OK);
+        let cls = embed().unwrap();
+        assert_eq!(cls.len(), EMBEDDING_DIM);
+        assert_ne!(mean, cls);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    #[ignore = "requires models/msmarco-distilbert-base-v4.onnx"]
+    fn wider_model_reports_its_own_dimension() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TWO_INPUT_TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TWO_INPUT_TEST_TOKENIZER).unwrap();
+        let texts = [CString::new("first").unwrap(), CString::new("second").unwrap()];
+        let ptrs: Vec<*const c_char> = texts.iter().map(|t| t.as_ptr()).collect();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        assert_eq!(arrow_embed_dimension(), 768);
+        let single = unsafe { arrow_embed_text(ptrs[0]) };
+        assert_eq!(single.len, 768);
+        unsafe { arrow_embed_free(single) };
+        let batch = unsafe { arrow_embed_text_batch(ptrs.as_ptr(), ptrs.len()) };
+        assert_eq!((batch.count, batch.dim), (2, 768));
+        unsafe { arrow_embed_free_batch(batch) };
+
+        let mut minilm_sized = [0.0f32; EMBEDDING_DIM];
+        let code = unsafe {
+            arrow_embed_text_into(ptrs[0], minilm_sized.as_mut_ptr(), minilm_sized.len())
+        };
+        assert_eq!(code, ERROR_BUFFER_TOO_SMALL);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn dimension_falls_back_without_a_model() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        assert_eq!(arrow_embed_dimension(), EMBEDDING_DIM);
+        assert_eq!(arrow_embed_dimension_with(ptr::null_mut()), 0);
+    }
+
+    #[test]
+    fn ffi_similarity_handles_null_and_orthogonal_inputs() {
+        let a = [1.0f32, 0.0];
+        let b = [0.0f32, 1.0];
+
+        assert_eq!(unsafe { arrow_embed_similarity(a.as_ptr(), a.as_ptr(), 2) }, 1.0);
+        assert_eq!(unsafe { arrow_embed_similarity(a.as_ptr(), b.as_ptr(), 2) }, 0.0);
+        assert!(unsafe { arrow_embed_similarity(a.as_ptr(), ptr::null(), 2) }.is_nan());
+    }
+
+    #[test]
+    fn ffi_distances_handle_null_inputs() {
+        let a = [3.0f32, 0.0];
+        let b = [0.0f32, 4.0];
+
+        assert_eq!(unsafe { arrow_embed_l2_distance(a.as_ptr(), b.as_ptr(), 2) }, 5.0);
+        assert_eq!(unsafe { arrow_embed_dot(a.as_ptr(), a.as_ptr(), 2) }, 9.0);
+        assert!(unsafe { arrow_embed_l2_distance(ptr::null(), b.as_ptr(), 2) }.is_nan());
+        assert!(unsafe { arrow_embed_dot(a.as_ptr(), ptr::null(), 2) }.is_nan());
+    }
+
+    #[test]
+    fn compare_texts_without_init_is_nan() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let text = CString::new("text").unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        assert!(unsafe { arrow_embed_compare_texts(text.as_ptr(), text.as_ptr()) }.is_nan());
+        assert!(unsafe { arrow_embed_compare_texts(text.as_ptr(), ptr::null()) }.is_nan());
+    }
+
+    #[test]
+    fn version_matches_the_crate() {
+        let version = unsafe { CStr::from_ptr(arrow_embed_version()) };
+
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn model_io_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let mut buf = [1 as c_char; 16];
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let len = unsafe { arrow_embed_model_io(buf.as_mut_ptr(), buf.len()) };
+
+        assert_eq!(len, ERROR_NOT_INITIALIZED as i64);
+        assert_eq!(buf[0], 1);
+    }
+
+    #[test]
+    fn empty_pool_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let code = unsafe { arrow_embed_init_pool(model.as_ptr(), tokenizer.as_ptr(), 0) };
+
+        assert_eq!(code, ERROR_INVALID_INPUT);
+        assert_eq!(arrow_embed_warmup(1), ERROR_NOT_INITIALIZED);
+        let code = unsafe { arrow_embed_init_pool(ptr::null(), tokenizer.as_ptr(), 4) };
+        assert_eq!(code, ERROR_NULL_POINTER);
+    }
+
+    /// Poison `mutex` by panicking on another thread while holding it
+    fn poison<T: Send>(mutex: &Mutex<T>) {
+        let _ = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _guard = mutex.lock();
+                    panic!("panic while holding the lock");
+                })
+                .join()
+        });
+        assert!(mutex.is_poisoned());
+    }
+
+    #[test]
+    fn poisoned_global_lock_is_recovered() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("text").unwrap();
+
+        poison(&EMBEDDER);
+
+        let result = unsafe { arrow_embed_text(text.as_ptr()) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        assert!(!EMBEDDER.is_poisoned());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn embedding_continues_after_a_panic_holding_the_embedder() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("still works").unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        let handle = default_embedder().unwrap();
+        poison(&handle.embedders.embedders[0]);
+
+        let result = unsafe { arrow_embed_text(text.as_ptr()) };
+        assert_eq!(result.error_code, ERROR_OK);
+        assert_eq!(result.len, arrow_embed_dimension());
+        unsafe { arrow_embed_free(result) };
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn index_search_continues_after_a_panic_holding_the_index() {
+        let index = arrow_index_create(2);
+        let vector = [1.0f32, 0.0];
+        assert_eq!(unsafe { arrow_index_add(index, 7, vector.as_ptr(), 2) }, ERROR_OK);
+        let lock = unsafe { &(*index).index };
+        let _ = std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _guard = lock.write();
+                    panic!("panic while holding the index");
+                })
+                .join()
+        });
+        assert!(lock.is_poisoned());
+        let (mut ids, mut scores) = ([0u64; 1], [0.0f32; 1]);
+
+        let found = unsafe {
+            arrow_index_search(index, vector.as_ptr(), 2, 1, ids.as_mut_ptr(), scores.as_mut_ptr())
+        };
+
+        assert_eq!(found, 1);
+        assert_eq!(ids[0], 7);
+        assert!(!lock.is_poisoned());
+        assert_eq!(unsafe { arrow_index_add(index, 8, vector.as_ptr(), 2) }, ERROR_OK);
+        assert_eq!(unsafe { arrow_index_len(index) }, 2);
+        unsafe { arrow_index_destroy(index) };
+    }
+
+    #[test]
+    fn text_bytes_are_decoded_by_flags() {
+        let with_nul = b"before\0after";
+        let latin1 = b"caf\xe9";
+
+        assert_eq!(decode_text(with_nul, TEXT_UTF8_STRICT).unwrap(), "before\0after");
+        assert_eq!(decode_text(latin1, TEXT_UTF8_STRICT), Err(ERROR_INVALID_UTF8));
+        assert_eq!(decode_text(latin1, TEXT_UTF8_LOSSY).unwrap(), "caf\u{fffd}");
+        assert_eq!(decode_text(with_nul, 7), Err(ERROR_INVALID_OPTION));
+    }
+
+    #[test]
+    fn length_prefixed_text_is_checked_before_embedding() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let latin1 = b"caf\xe9";
+        let embed = |flags| unsafe { arrow_embed_text_len(latin1.as_ptr(), latin1.len(), flags) };
+
+        assert_eq!(embed(TEXT_UTF8_STRICT).error_code, ERROR_INVALID_UTF8);
+        // Decoding succeeds, then there is no embedder
+        assert_eq!(embed(TEXT_UTF8_LOSSY).error_code, ERROR_NOT_INITIALIZED);
+        let result = unsafe { arrow_embed_text_len(ptr::null(), 0, TEXT_UTF8_STRICT) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        let lossy = unsafe { arrow_embed_text_bytes(latin1.as_ptr(), latin1.len()) };
+        assert_eq!(lossy.error_code, ERROR_NOT_INITIALIZED);
+        let result = unsafe { arrow_embed_text_bytes(ptr::null(), 0) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn interior_nul_does_not_truncate_the_text() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+        let embed = |text: &[u8]| {
+            let flags = TEXT_UTF8_STRICT;
+            let result = unsafe { arrow_embed_text_len(text.as_ptr(), text.len(), flags) };
+            assert_eq!(result.error_code, ERROR_OK);
+            let embedding = unsafe { std::slice::from_raw_parts(result.data, result.len) }.to_vec();
+            unsafe { arrow_embed_free(result) };
+            embedding
+        };
+
+        let whole = embed(b"red shoes\0on sale today");
+        let truncated = embed(b"red shoes");
+
+        assert_ne!(whole, truncated);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn f16_results_hold_binary16_bits() {
+        let values = vec![f16::from_f32(0.5), f16::from_f32(-1.0)];
+
+        let result = f16_result(Ok(values));
+
+        assert_eq!(result.error_code, ERROR_OK);
+        let bits = unsafe { std::slice::from_raw_parts(result.data, result.len) };
+        assert_eq!(bits, [0x3800, 0xbc00]);
+        unsafe { arrow_embed_free_f16(result) };
+        unsafe { arrow_embed_free_f16(EmbeddingF16Result::error(ERROR_INFERENCE)) };
+
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("half precision").unwrap();
+        let result = unsafe { arrow_embed_text_f16(text.as_ptr()) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        assert!(result.data.is_null());
+    }
+
+    #[test]
+    fn int8_embedding_needs_a_scale_and_an_embedder() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("quantized").unwrap();
+        let mut scale = -1.0;
+
+        let result = unsafe { arrow_embed_text_int8(text.as_ptr(), ptr::null_mut()) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        let result = unsafe { arrow_embed_text_int8(text.as_ptr(), &mut scale) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        assert_eq!(scale, -1.0);
+        unsafe { arrow_embed_free_int8(result) };
+
+        let result = int8_result(vec![127, -3]);
+        assert_eq!(unsafe { std::slice::from_raw_parts(result.data, result.len) }, [127, -3]);
+        unsafe { arrow_embed_free_int8(result) };
+    }
+
+    #[test]
+    fn quantized_vectors_round_trip_through_ffi() {
+        let values = [0.5f32, -2.0, 1.0, 0.0];
+        let mut scale = -1.0;
+
+        let quantized = unsafe { arrow_embed_quantize(values.as_ptr(), values.len(), &mut scale) };
+
+        assert_eq!(quantized.error_code, ERROR_OK);
+        assert_eq!(scale, 2.0);
+        let components = unsafe { std::slice::from_raw_parts(quantized.data, quantized.len) };
+        assert_eq!(components, [32, -127, 64, 0]);
+        let restored = unsafe { arrow_embed_dequantize(quantized.data, quantized.len, scale) };
+        assert_eq!(restored.error_code, ERROR_OK);
+        let restored_values = unsafe { std::slice::from_raw_parts(restored.data, restored.len) };
+        for (a, b) in values.iter().zip(restored_values) {
+            assert!((a - b).abs() <= scale / 254.0);
+        }
+        let dot = unsafe { arrow_embed_dot_int8(quantized.data, scale, quantized.data, scale, 4) };
+        assert!((dot - dot_product(&values, &values)).abs() < 0.05);
+        unsafe {
+            arrow_embed_free(restored);
+            arrow_embed_free_int8(quantized);
+        }
+
+        let result = unsafe { arrow_embed_quantize(ptr::null(), 4, &mut scale) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        let result = unsafe { arrow_embed_dequantize(ptr::null(), 4, scale) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        assert!(unsafe { arrow_embed_dot_int8(ptr::null(), 1.0, ptr::null(), 1.0, 4) }.is_nan());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn int8_embedding_dequantizes_close_to_the_f32_one() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+        let text = CString::new("quantized for an on-disk index").unwrap();
+        let mut scale = 0.0;
+
+        let full = unsafe { arrow_embed_text(text.as_ptr()) };
+        let quantized = unsafe { arrow_embed_text_int8(text.as_ptr(), &mut scale) };
+
+        assert_eq!(quantized.error_code, ERROR_OK);
+        let full_values = unsafe { std::slice::from_raw_parts(full.data, full.len) };
+        let values = unsafe { std::slice::from_raw_parts(quantized.data, quantized.len) };
+        let restored = crate::dequantize_int8(values, scale);
+        assert!(cosine_similarity(full_values, &restored) > 0.99);
+        unsafe {
+            arrow_embed_free(full);
+            arrow_embed_free_int8(quantized);
+        }
+        arrow_embed_shutdown();
+    }
+
+    #[test]
+    fn document_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("a long document").unwrap();
+
+        let result = unsafe { arrow_embed_document(text.as_ptr(), 128, 96) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        let result = unsafe { arrow_embed_document(ptr::null(), 128, 96) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    fn multi_into_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("hello").unwrap();
+        let mut mean = [0f32; 4];
+        let mut cls = [0f32; 4];
+
+        let code = unsafe {
+            arrow_embed_text_multi_into(text.as_ptr(), mean.as_mut_ptr(), cls.as_mut_ptr(), 4)
+        };
+        assert_eq!(code, ERROR_NOT_INITIALIZED);
+        let code = unsafe {
+            arrow_embed_text_multi_into(text.as_ptr(), mean.as_mut_ptr(), ptr::null_mut(), 4)
+        };
+        assert_eq!(code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    fn npy_export_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("hello").unwrap();
+        let path = CString::new(temp_path("never.npy").to_str().unwrap()).unwrap();
+
+        let code = unsafe { arrow_embed_text_to_npy(text.as_ptr(), path.as_ptr()) };
+        assert_eq!(code, ERROR_NOT_INITIALIZED);
+        let code = unsafe { arrow_embed_text_to_npy(text.as_ptr(), ptr::null()) };
+        assert_eq!(code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn npy_export_matches_embed_text() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("exported for numpy").unwrap();
+        let path = temp_path("ffi.npy");
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        let code = unsafe { arrow_embed_text_to_npy(text.as_ptr(), c_path.as_ptr()) };
+
+        assert_eq!(code, ERROR_OK);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!(bytes.len() - 10 - header_len, EMBEDDING_DIM * 4);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn not_ready_without_init_or_while_init_runs() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        assert_eq!(arrow_embed_is_ready(), 0);
+
+        // An init holds the global embedder for as long as the model loads
+        let loading = lock_recovering(&EMBEDDER);
+        assert_eq!(arrow_embed_is_ready(), 0);
+        drop(loading);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn ready_from_init_until_shutdown() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        assert_eq!(arrow_embed_is_ready(), 1);
+
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        assert_eq!(arrow_embed_is_ready(), 0);
+    }
+
+    #[test]
+    fn pair_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("premise").unwrap();
+
+        let result = unsafe { arrow_embed_text_pair(text.as_ptr(), text.as_ptr()) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        let result = unsafe { arrow_embed_text_pair(text.as_ptr(), ptr::null()) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    fn model_info_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let mut info = std::mem::MaybeUninit::<ArrowEmbedModelInfo>::uninit();
+
+        assert_eq!(unsafe { arrow_embed_model_info(ptr::null_mut()) }, ERROR_NULL_POINTER);
+        let code = unsafe { arrow_embed_model_info(info.as_mut_ptr()) };
+        assert_eq!(code, ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    fn returned_strings_are_freed() {
+        let name = owned_c_string("model\0name");
+
+        assert_eq!(unsafe { CStr::from_ptr(name) }.to_str().unwrap(), "modelname");
+        unsafe {
+            arrow_embed_free_string(name);
+            arrow_embed_free_string(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn model_info_describes_the_model() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+        let mut info = std::mem::MaybeUninit::<ArrowEmbedModelInfo>::uninit();
+
+        assert_eq!(unsafe { arrow_embed_model_info(info.as_mut_ptr()) }, ERROR_OK);
+
+        let info = unsafe { info.assume_init() };
+        let text = |s: *mut c_char| unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+        assert_eq!(text(info.name), "all-MiniLM-L6-v2");
+        assert_eq!(text(info.input_names), "input_ids,attention_mask,token_type_ids");
+        assert_eq!(text(info.output_name), "last_hidden_state");
+        assert_eq!((info.dim, info.max_seq_len), (EMBEDDING_DIM, DEFAULT_MAX_SEQ_LEN));
+        assert_eq!((info.pooled_by_model, info.quantized), (0, 0));
+        unsafe {
+            arrow_embed_free_string(info.name);
+            arrow_embed_free_string(info.input_names);
+            arrow_embed_free_string(info.output_name);
+        }
+        arrow_embed_shutdown();
+    }
+
+    #[test]
+    fn self_test_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let mut results = ArrowEmbedSelfTest::default();
+
+        assert_eq!(unsafe { arrow_embed_self_test(ptr::null_mut()) }, ERROR_NULL_POINTER);
+        assert_eq!(unsafe { arrow_embed_self_test(&mut results) }, ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn self_test_passes_for_minilm() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+        let mut results = ArrowEmbedSelfTest::default();
+
+        assert_eq!(unsafe { arrow_embed_self_test(&mut results) }, ERROR_OK);
+
+        assert!(results.pairs > 0);
+        assert!(results.max_deviation <= results.tolerance);
+        assert!(results.batch_deviation <= results.tolerance);
+        assert_ne!(results.passed, 0);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn stats_without_init_are_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let mut stats = ArrowEmbedStats::default();
+
+        assert_eq!(unsafe { arrow_embed_stats(ptr::null_mut()) }, ERROR_NULL_POINTER);
+        assert_eq!(unsafe { arrow_embed_stats(&mut stats) }, ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn repeated_texts_are_served_from_the_cache() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("red shoes").unwrap();
+        let options = ArrowEmbedOptions {
+            cache_capacity: 16,
+            ..arrow_embed_default_options()
+        };
+        let code = unsafe {
+            arrow_embed_init_with_options(model.as_ptr(), tokenizer.as_ptr(), &options)
+        };
+        assert_eq!(code, ERROR_OK);
+
+        for _ in 0..3 {
+            let result = unsafe { arrow_embed_text(text.as_ptr()) };
+            assert_eq!(result.error_code, ERROR_OK);
+            unsafe { arrow_embed_free(result) };
+        }
+
+        let mut stats = ArrowEmbedStats::default();
+        assert_eq!(unsafe { arrow_embed_stats(&mut stats) }, ERROR_OK);
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 1));
+        assert_eq!(stats.capacity, 16);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn ids_without_init_are_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let ids = [101i64, 7592, 102];
+        let mask = [1i64; 3];
+
+        let result = unsafe { arrow_embed_ids(ids.as_ptr(), ptr::null(), 3) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        let result = unsafe { arrow_embed_ids(ids.as_ptr(), mask.as_ptr(), 3) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        assert!(result.data.is_null());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn pooled_init_embeds_on_many_threads() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let code = unsafe { arrow_embed_init_pool(model.as_ptr(), tokenizer.as_ptr(), 4) };
+        assert_eq!(code, ERROR_OK);
+
+        std::thread::scope(|scope| {
+            for t in 0..8 {
+                scope.spawn(move || {
+                    let text = CString::new(format!("thread {t}")).unwrap();
+                    let result = unsafe { arrow_embed_text(text.as_ptr()) };
+                    assert_eq!((result.error_code, result.len), (ERROR_OK, EMBEDDING_DIM));
+                    unsafe { arrow_embed_free(result) };
+                });
+            }
+        });
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn parallel_batch_reports_failures_per_text() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let code = unsafe { arrow_embed_init_pool(model.as_ptr(), tokenizer.as_ptr(), 2) };
+        assert_eq!(code, ERROR_OK);
+        let texts = ["first", "", "third"].map(|text| CString::new(text).unwrap());
+        let text_ptrs = texts.each_ref().map(|text| text.as_ptr());
+        let mut errors = [ERROR_OK; 3];
+
+        let result =
+            unsafe { arrow_embed_text_batch_parallel(text_ptrs.as_ptr(), 3, errors.as_mut_ptr()) };
+
+        assert_eq!((result.error_code, result.count), (ERROR_OK, 3));
+        assert_eq!(errors, [ERROR_OK, ERROR_EMPTY_INPUT, ERROR_OK]);
+        let data = unsafe { std::slice::from_raw_parts(result.data, 3 * EMBEDDING_DIM) };
+        let first = &data[..EMBEDDING_DIM];
+        let failed = &data[EMBEDDING_DIM..2 * EMBEDDING_DIM];
+        assert!(failed.iter().all(|&x| x == 0.0));
+        assert!(first.iter().any(|&x| x != 0.0));
+        unsafe { arrow_embed_free_batch(result) };
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn warmup_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        assert_eq!(arrow_embed_warmup(1), ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn warmup_reports_elapsed_milliseconds_on_every_call() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let code = unsafe { arrow_embed_init_pool(model.as_ptr(), tokenizer.as_ptr(), 2) };
+        assert_eq!(code, ERROR_OK);
+
+        assert!(arrow_embed_warmup(3) >= 0);
+        assert!(arrow_embed_warmup(1) >= 0);
+        assert!(arrow_embed_warmup(0) >= 0);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn text_into_writes_caller_buffer() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("stack buffer").unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        let mut small = [7.0f32; 8];
+        let code = unsafe { arrow_embed_text_into(text.as_ptr(), small.as_mut_ptr(), small.len()) };
+        assert_eq!(code, ERROR_BUFFER_TOO_SMALL);
+        assert!(small.iter().all(|&v| v == 7.0));
+
+        let mut out = [0.0f32; EMBEDDING_DIM];
+        let written = unsafe { arrow_embed_text_into(text.as_ptr(), out.as_mut_ptr(), out.len()) };
+        assert_eq!(written, EMBEDDING_DIM as i32);
+        let single = unsafe { arrow_embed_text(text.as_ptr()) };
+        assert_eq!(unsafe { std::slice::from_raw_parts(single.data, single.len) }, &out[..]);
+        unsafe { arrow_embed_free(single) };
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn text_into_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let text = CString::new("text").unwrap();
+        let mut out = [0.0f32; EMBEDDING_DIM];
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let code = unsafe { arrow_embed_text_into(text.as_ptr(), out.as_mut_ptr(), out.len()) };
+
+        assert_eq!(code, ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn batch_into_writes_strided_rows() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let owned = [CString::new("first row").unwrap(), CString::new("second row").unwrap()];
+        let texts: Vec<*const c_char> = owned.iter().map(|t| t.as_ptr()).collect();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+        let stride = EMBEDDING_DIM + 16;
+
+        let mut short = vec![7.0f32; stride + EMBEDDING_DIM - 1];
+        let code = unsafe {
+            arrow_embed_text_batch_into(texts.as_ptr(), 2, short.as_mut_ptr(), stride, short.len())
+        };
+        assert_eq!(code, ERROR_BUFFER_TOO_SMALL);
+        assert!(short.iter().all(|&v| v == 7.0));
+
+        let mut out = vec![7.0f32; 2 * stride];
+        let written = unsafe {
+            arrow_embed_text_batch_into(texts.as_ptr(), 2, out.as_mut_ptr(), stride, out.len())
+        };
+        assert_eq!(written, 2);
+        let batch = unsafe { arrow_embed_text_batch(texts.as_ptr(), 2) };
+        let expected = unsafe { std::slice::from_raw_parts(batch.data, 2 * EMBEDDING_DIM) };
+        for (row, expected) in out.chunks(stride).zip(expected.chunks(EMBEDDING_DIM)) {
+            assert_eq!(&row[..EMBEDDING_DIM], expected);
+            assert!(row[EMBEDDING_DIM..].iter().all(|&v| v == 7.0));
+        }
+        unsafe { arrow_embed_free_batch(batch) };
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn init_dir_reports_missing_exports() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let missing = temp_path("no_such_export");
+        let missing = CString::new(missing.to_str().unwrap()).unwrap();
+
+        assert_eq!(unsafe { arrow_embed_init_dir(missing.as_ptr()) }, ERROR_MODEL_LOAD);
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert!(message.to_str().unwrap().contains("model.onnx"));
+        assert_eq!(unsafe { arrow_embed_init_dir(ptr::null()) }, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    fn model_metadata_needs_a_key_and_an_embedder() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let key = CString::new("producer_name").unwrap();
+        let mut buf = [0 as c_char; 32];
+        let lookup = |key: *const c_char, buf: &mut [c_char]| unsafe {
+            arrow_embed_model_metadata(key, buf.as_mut_ptr(), buf.len())
+        };
+
+        assert_eq!(lookup(key.as_ptr(), &mut buf), ERROR_NOT_INITIALIZED as i64);
+        assert_eq!(lookup(ptr::null(), &mut buf), ERROR_NULL_POINTER as i64);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn model_metadata_copies_values_and_rejects_missing_keys() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+        let mut buf = [0 as c_char; 32];
+        let lookup = |key: &str, buf: &mut [c_char]| {
+            let key = CString::new(key).unwrap();
+            unsafe { arrow_embed_model_metadata(key.as_ptr(), buf.as_mut_ptr(), buf.len()) }
+        };
+
+        assert_eq!(lookup("producer_name", &mut buf), "pytorch".len() as i64);
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap(), "pytorch");
+        assert_eq!(lookup("no_such_key", &mut buf), ERROR_INVALID_INPUT as i64);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn init_tokenizer_json_checks_its_arguments() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let model = CString::new(TEST_MODEL).unwrap();
+        let init = |model: *const c_char, json: &[u8]| unsafe {
+            arrow_embed_init_tokenizer_json(model, json.as_ptr().cast(), json.len())
+        };
+
+        assert_eq!(init(ptr::null(), b"{}"), ERROR_NULL_POINTER);
+        let code = unsafe { arrow_embed_init_tokenizer_json(model.as_ptr(), ptr::null(), 0) };
+        assert_eq!(code, ERROR_NULL_POINTER);
+        assert_eq!(init(model.as_ptr(), b"{\"model\": \xff}"), ERROR_INVALID_UTF8);
+        assert_eq!(arrow_embed_warmup(1), ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn init_tokenizer_json_reports_where_the_json_is_invalid() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = crate::embedder::load_tokenizer(TEST_TOKENIZER).unwrap();
+        let json = tokenizer.to_string(false).unwrap();
+        let init = |json: &str| unsafe {
+            arrow_embed_init_tokenizer_json(model.as_ptr(), json.as_ptr().cast(), json.len())
+        };
+
+        assert_eq!(init("{ not json"), ERROR_INVALID_TOKENIZER);
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert!(message.to_str().unwrap().contains("tokenizer JSON"));
+        assert_eq!(init(&json), ERROR_OK);
+        assert_eq!(arrow_embed_dimension(), EMBEDDING_DIM);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn init_from_memory_checks_its_buffers() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let model = b"not an onnx model";
+        let tokenizer = CString::new("{ not json").unwrap();
+        let init = |model: *const u8, tokenizer: *const c_char| unsafe {
+            arrow_embed_init_from_memory(model, 17, tokenizer)
+        };
+
+        assert_eq!(init(ptr::null(), tokenizer.as_ptr()), ERROR_NULL_POINTER);
+        assert_eq!(init(model.as_ptr(), ptr::null()), ERROR_NULL_POINTER);
+        assert_eq!(arrow_embed_warmup(1), ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn init_from_memory_outlives_the_callers_buffers() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = std::fs::read(TEST_MODEL).unwrap();
+        let tokenizer = crate::embedder::load_tokenizer(TEST_TOKENIZER).unwrap();
+        let json = CString::new(tokenizer.to_string(false).unwrap()).unwrap();
+
+        let code =
+            unsafe { arrow_embed_init_from_memory(model.as_ptr(), model.len(), json.as_ptr()) };
+        drop((model, json));
+
+        assert_eq!(code, ERROR_OK);
+        let text = CString::new("red shoes").unwrap();
+        let result = unsafe { arrow_embed_text(text.as_ptr()) };
+        assert_eq!((result.error_code, result.len), (ERROR_OK, EMBEDDING_DIM));
+        unsafe { arrow_embed_free(result) };
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn batch_into_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let text = CString::new("text").unwrap();
+        let texts = [text.as_ptr()];
+        let mut out = [0.0f32; EMBEDDING_DIM];
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let code = unsafe {
+            arrow_embed_text_batch_into(texts.as_ptr(), 1, out.as_mut_ptr(), out.len(), out.len())
+        };
+        assert_eq!(code, ERROR_NOT_INITIALIZED);
+        let code = unsafe {
+            arrow_embed_text_batch_into(texts.as_ptr(), 1, ptr::null_mut(), out.len(), out.len())
+        };
+        assert_eq!(code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    fn parallel_batch_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let text = CString::new("text").unwrap();
+        let texts = [text.as_ptr()];
+        let mut errors = [0i32; 1];
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let result =
+            unsafe { arrow_embed_text_batch_parallel(texts.as_ptr(), 1, errors.as_mut_ptr()) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        assert!(result.data.is_null());
+        let result = unsafe { arrow_embed_text_batch_parallel(texts.as_ptr(), 1, ptr::null_mut()) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    fn tokenize_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let text = CString::new("text").unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let code = unsafe { arrow_embed_tokenize(text.as_ptr(), ptr::null_mut(), 0) };
+
+        assert_eq!(code, ERROR_NOT_INITIALIZED);
+    }
+
+    #[test]
+    fn count_tokens_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let text = CString::new("text").unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let count = unsafe { arrow_embed_count_tokens(text.as_ptr()) };
+        assert_eq!(count, ERROR_NOT_INITIALIZED as i64);
+        let count = unsafe { arrow_embed_count_tokens(ptr::null()) };
+        assert_eq!(count, ERROR_NULL_POINTER as i64);
+    }
+
+    #[test]
+    fn count_tokens_as_without_init_is_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let text = CString::new("text").unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+
+        let count = unsafe { arrow_embed_count_tokens_as(text.as_ptr(), EMBED_KIND_QUERY) };
+        assert_eq!(count, ERROR_NOT_INITIALIZED as i64);
+        let count = unsafe { arrow_embed_count_tokens_as(ptr::null(), EMBED_KIND_RAW) };
+        assert_eq!(count, ERROR_NULL_POINTER as i64);
+        let count = unsafe { arrow_embed_count_tokens_as(text.as_ptr(), 3) };
+        assert_eq!(count, ERROR_INVALID_OPTION as i64);
+    }
+
+    #[test]
+    fn similarity_matrix_rejects_null_entries() {
+        let first = CString::new("first").unwrap();
+        let texts = [first.as_ptr(), ptr::null()];
+
+        let result = unsafe { arrow_embed_similarity_matrix(texts.as_ptr(), texts.len()) };
+
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        assert!(result.data.is_null());
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "texts[1] is null");
+    }
+
+    #[test]
+    fn panics_are_reported_instead_of_unwinding() {
+        extern "C" fn panicking_text() -> EmbeddingResult {
+            ffi_guard(|| {
+                let hidden: Vec<f32> = Vec::new();
+                EmbeddingResult::error(hidden[3] as i32)
+            })
+        }
+        extern "C" fn panicking_score() -> c_float {
+            ffi_guard(|| panic!("score {} failed", 7))
+        }
+
+        let result = panicking_text();
+        assert_eq!(result.error_code, ERROR_PANIC);
+        assert!(result.data.is_null());
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert!(message.to_str().unwrap().contains("index out of bounds"));
+
+        assert!(panicking_score().is_nan());
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert_eq!(message.to_str().unwrap(), "Panic in arrow_embed: score 7 failed");
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn empty_text_reports_empty_input() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let empty = CString::new("").unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        let result = unsafe { arrow_embed_text(empty.as_ptr()) };
+
+        assert_eq!(result.error_code, ERROR_EMPTY_INPUT);
+        assert!(result.data.is_null());
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn text_long_rejects_unknown_aggregation() {
+        let text = CString::new("text").unwrap();
+
+        let result = unsafe { arrow_embed_text_long(text.as_ptr(), 128, 16, 7) };
+
+        assert_eq!(result.error_code, ERROR_INVALID_OPTION);
+        assert!(result.data.is_null());
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn text_long_returns_one_or_all_chunks() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let long = "the quick brown fox jumps over the lazy dog ".repeat(100);
+        let text = CString::new(long).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        let all = unsafe { arrow_embed_text_long(text.as_ptr(), 128, 16, CHUNK_AGGREGATION_ALL) };
+        let mean = unsafe { arrow_embed_text_long(text.as_ptr(), 128, 16, CHUNK_AGGREGATION_MEAN) };
+
+        assert_eq!(all.error_code, ERROR_OK);
+        assert!(all.count > 1);
+        assert_eq!(all.dim, EMBEDDING_DIM);
+        assert_eq!((mean.error_code, mean.count, mean.dim), (ERROR_OK, 1, EMBEDDING_DIM));
+        unsafe {
+            arrow_embed_free_batch(all);
+            arrow_embed_free_batch(mean);
+        }
+        let too_much_overlap = unsafe { arrow_embed_text_long(text.as_ptr(), 128, 126, 0) };
+        assert_eq!(too_much_overlap.error_code, ERROR_INVALID_INPUT);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn tokens_without_init_are_rejected() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let text = CString::new("late interaction").unwrap();
+
+        let result = unsafe { arrow_embed_tokens(text.as_ptr(), 1, 1) };
+        assert_eq!(result.error_code, ERROR_NOT_INITIALIZED);
+        let result = unsafe { arrow_embed_tokens(ptr::null(), 1, 1) };
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn tokens_return_one_vector_per_token() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let text = CString::new("late interaction retrieval").unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        let all = unsafe { arrow_embed_tokens(text.as_ptr(), 1, 0) };
+        let content = unsafe { arrow_embed_tokens(text.as_ptr(), 1, 1) };
+
+        assert_eq!((all.error_code, all.dim), (ERROR_OK, EMBEDDING_DIM));
+        assert_eq!((content.error_code, content.count), (ERROR_OK, all.count - 2));
+        unsafe {
+            arrow_embed_free_batch(all);
+            arrow_embed_free_batch(content);
+        }
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn shutdown_without_init_is_harmless() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn batch_cb_reports_each_pass_and_cancels() {
+        /// Records each report; embeds a text to show no lock is held, and
+        /// cancels once `user_data`'s limit of reports is reached
+        type Reports = (Vec<(usize, usize)>, usize);
+        unsafe extern "C" fn record(done: usize, total: usize, user_data: *mut c_void) -> i32 {
+            let (reports, limit) = unsafe { &mut *(user_data as *mut Reports) };
+            reports.push((done, total));
+            let text = CString::new("reentrant").unwrap();
+            let single = unsafe { arrow_embed_text(text.as_ptr()) };
+            assert_eq!(single.error_code, 0);
+            unsafe { arrow_embed_free(single) };
+            (reports.len() >= *limit) as i32
+        }
+
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, 0);
+        let text = CString::new("progress text").unwrap();
+        let ptrs = vec![text.as_ptr(); 150];
+
+        let mut state: Reports = (Vec::new(), usize::MAX);
+        let user_data = &mut state as *mut _ as *mut c_void;
+        let batch =
+            unsafe { arrow_embed_text_batch_cb(ptrs.as_ptr(), 150, Some(record), user_data) };
+
+        assert_eq!(batch.error_code, 0);
+        assert_eq!((batch.count, batch.dim), (150, EMBEDDING_DIM));
+        assert_eq!(state.0, [(64, 150), (128, 150), (150, 150)]);
+        unsafe { arrow_embed_free_batch(batch) };
+
+        let mut state: Reports = (Vec::new(), 1);
+        let user_data = &mut state as *mut _ as *mut c_void;
+        let cancelled =
+            unsafe { arrow_embed_text_batch_cb(ptrs.as_ptr(), 150, Some(record), user_data) };
+        assert_eq!(cancelled.error_code, ERROR_CANCELLED);
+        assert!(cancelled.data.is_null());
+        assert_eq!(state.0.len(), 1);
+
+        let silent = unsafe { arrow_embed_text_batch_cb(ptrs.as_ptr(), 2, None, ptr::null_mut()) };
+        assert_eq!(silent.error_code, 0);
+        unsafe { arrow_embed_free_batch(silent) };
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn ffi_batch_rejects_null_entries() {
+        let text = CString::new("text").unwrap();
+        let ptrs = [text.as_ptr(), ptr::null()];
+
+        let result = unsafe { arrow_embed_text_batch(ptrs.as_ptr(), ptrs.len()) };
+
+        assert_eq!(result.error_code, ERROR_NULL_POINTER);
+        assert!(result.data.is_null());
+        assert_eq!(result.count, 0);
+    }
+
+    #[test]
+    fn ffi_index_finds_nearest_and_rejects_wrong_dimension() {
+        let index = arrow_index_create(2);
+        let near = [1.0f32, 0.0];
+        let far = [0.0f32, 1.0];
+        let wrong = [1.0f32, 0.0, 0.0];
+
+        unsafe {
+            assert_eq!(arrow_index_add(index, 7, near.as_ptr(), 2), ERROR_OK);
+            assert_eq!(arrow_index_add(index, 8, far.as_ptr(), 2), ERROR_OK);
+            assert_eq!(arrow_index_add(index, 9, wrong.as_ptr(), 3), ERROR_DIMENSION_MISMATCH);
+            assert_eq!(arrow_index_len(index), 2);
+        }
+
+        let mut ids = [0u64; 5];
+        let mut scores = [0f32; 5];
+        let found = unsafe {
+            arrow_index_search(index, near.as_ptr(), 2, 5, ids.as_mut_ptr(), scores.as_mut_ptr())
+        };
+
+        assert_eq!(found, 2);
+        assert_eq!(&ids[..2], &[7, 8]);
+        assert_eq!(&scores[..2], &[1.0, 0.0]);
+        unsafe { arrow_index_destroy(index) };
+    }
+
+    #[test]
+    fn ffi_binary_index_rescores_hamming_candidates() {
+        let plain = arrow_binary_index_create(3, 0);
+        let rescoring = arrow_binary_index_create(3, 1);
+        let near = [0.9f32, 0.1, -0.4];
+        let close = [0.1f32, 0.9, -0.4];
+        let far = [-0.6f32, -0.6, 0.5];
+        let mut ids = [0u64; 3];
+        let mut distances = [0u32; 3];
+        let mut scores = [0f32; 3];
+
+        unsafe {
+            for index in [plain, rescoring] {
+                assert_eq!(arrow_binary_index_add(index, 1, far.as_ptr(), 3), ERROR_OK);
+                assert_eq!(arrow_binary_index_add(index, 2, close.as_ptr(), 3), ERROR_OK);
+                assert_eq!(arrow_binary_index_add(index, 3, near.as_ptr(), 3), ERROR_OK);
+                let code = arrow_binary_index_add(index, 4, far.as_ptr(), 2);
+                assert_eq!(code, ERROR_DIMENSION_MISMATCH);
+                assert_eq!(arrow_binary_index_len(index), 3);
+            }
+            let (query, out) = (near.as_ptr(), ids.as_mut_ptr());
+            let found = arrow_binary_index_search(plain, query, 3, 3, out, distances.as_mut_ptr());
+            assert_eq!(found, 3);
+            // near and close share their signs, so only rescoring tells them apart
+            assert_eq!((ids, distances), ([2, 3, 1], [0, 0, 3]));
+
+            let scores_out = scores.as_mut_ptr();
+            let found = arrow_binary_index_search_rescored(
+                rescoring, near.as_ptr(), 3, 1, 2, ids.as_mut_ptr(), scores_out,
+            );
+            assert_eq!(found, 1);
+            assert_eq!(ids[0], 3);
+            let found = arrow_binary_index_search_rescored(
+                plain, near.as_ptr(), 3, 1, 2, ids.as_mut_ptr(), scores_out,
+            );
+            assert_eq!(found, ERROR_INVALID_INPUT as i64);
+            arrow_binary_index_destroy(plain);
+            arrow_binary_index_destroy(rescoring);
+        }
+
+        let mut code = [0u64; 1];
+        let written = unsafe { arrow_embed_binarize(near.as_ptr(), 3, code.as_mut_ptr(), 1) };
+        assert_eq!((written, code[0]), (1, 0b011));
+        let written = unsafe { arrow_embed_binarize(near.as_ptr(), 3, code.as_mut_ptr(), 0) };
+        assert_eq!(written, ERROR_BUFFER_TOO_SMALL);
+        let other = [0b110u64];
+        assert_eq!(unsafe { arrow_embed_hamming_distance(code.as_ptr(), other.as_ptr(), 1) }, 2);
+        let distance = unsafe { arrow_embed_hamming_distance(ptr::null(), other.as_ptr(), 1) };
+        assert_eq!(distance, u32::MAX);
+    }
+
+    #[test]
+    fn ffi_corpus_reports_ids_of_nearest_rows() {
+        let corpus = arrow_corpus_new(2);
+        let near = [1.0f32, 0.0];
+        let far = [0.0f32, 1.0];
+        let near_id = CString::new("near document").unwrap();
+        let far_id = CString::new("far").unwrap();
+
+        unsafe {
+            assert_eq!(arrow_corpus_add(corpus, far_id.as_ptr(), far.as_ptr(), 2), ERROR_OK);
+            assert_eq!(arrow_corpus_add(corpus, near_id.as_ptr(), near.as_ptr(), 2), ERROR_OK);
+            let code = arrow_corpus_add(corpus, ptr::null(), near.as_ptr(), 2);
+            assert_eq!(code, ERROR_NULL_POINTER);
+            assert_eq!(arrow_corpus_len(corpus), 2);
+        }
+
+        let mut rows = [0usize; 2];
+        let mut scores = [0f32; 2];
+        let found = unsafe {
+            arrow_corpus_search(corpus, near.as_ptr(), 2, 1, rows.as_mut_ptr(), scores.as_mut_ptr())
+        };
+        assert_eq!(found, 1);
+        assert_eq!((rows[0], scores[0]), (1, 1.0));
+
+        let mut buf = [0 as c_char; 5];
+        let len = unsafe { arrow_corpus_id(corpus, rows[0], buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(len, 13);
+        let truncated = unsafe { CStr::from_ptr(buf.as_ptr()) };
+        assert_eq!(truncated.to_str().unwrap(), "near");
+        let code = unsafe { arrow_corpus_id(corpus, 2, ptr::null_mut(), 0) };
+        assert_eq!(code, ERROR_INVALID_INPUT as i64);
+        unsafe { arrow_corpus_destroy(corpus) };
+    }
+
+    #[test]
+    fn ffi_corpus_survives_save_and_load() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+        let corpus = arrow_corpus_new(2);
+        let vector = [0.6f32, 0.8];
+        let id = CString::new("only").unwrap();
+        let path = CString::new(temp_path("ffi.corpus").to_str().unwrap()).unwrap();
+
+        unsafe {
+            assert_eq!(arrow_corpus_add(corpus, id.as_ptr(), vector.as_ptr(), 2), ERROR_OK);
+            assert_eq!(arrow_corpus_save(corpus, path.as_ptr()), ERROR_OK);
+            arrow_corpus_destroy(corpus);
+        }
+
+        let loaded = unsafe { arrow_corpus_load(path.as_ptr()) };
+        std::fs::remove_file(path.to_str().unwrap()).unwrap();
+        assert!(!loaded.is_null());
+        assert_eq!(unsafe { arrow_corpus_len(loaded) }, 1);
+        unsafe { arrow_corpus_destroy(loaded) };
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn ffi_corpus_load_rejects_other_dimensions() {
+        let _global = GLOBAL_EMBEDDER.lock().unwrap();
+        let model = CString::new(TEST_MODEL).unwrap();
+        let tokenizer = CString::new(TEST_TOKENIZER).unwrap();
+        let path = temp_path("wide.corpus");
+        let mut corpus = Corpus::new(768);
+        corpus.add("wide", &[1.0; 768]).unwrap();
+        corpus.save(&path).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { arrow_embed_init(model.as_ptr(), tokenizer.as_ptr()) }, ERROR_OK);
+
+        let loaded = unsafe { arrow_corpus_load(c_path.as_ptr()) };
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(loaded.is_null());
+        let message = unsafe { CStr::from_ptr(arrow_embed_last_error_message()) };
+        assert!(message.to_str().unwrap().contains("768"));
+        assert_eq!(arrow_embed_shutdown(), ERROR_OK);
+    }
+
+    #[test]
+    fn rerank_checks_its_arguments() {
+        let query = CString::new("query").unwrap();
+        let missing = CString::new("missing.onnx").unwrap();
+
+        let reranker = unsafe { arrow_rerank_create(ptr::null(), missing.as_ptr()) };
+        assert!(reranker.is_null());
+        let code = unsafe {
+            arrow_rerank_score(ptr::null(), query.as_ptr(), ptr::null(), 0, ptr::null_mut())
+        };
+        assert_eq!(code, ERROR_NULL_POINTER);
+        unsafe { arrow_rerank_destroy(ptr::null_mut()) };
+    }
+
+    #[test]
+    #[ignore = "requires models/ms-marco-MiniLM-L-6-v2.onnx"]
+    fn ffi_rerank_scores_passages_in_order() {
+        let model = CString::new(TEST_RERANKER).unwrap();
+        let tokenizer = CString::new(TEST_RERANKER_TOKENIZER).unwrap();
+        let query = CString::new("capital of france").unwrap();
+        let passages = ["Bread needs yeast to rise.", "Paris is the capital of France."]
+            .map(|p| CString::new(p).unwrap());
+        let pointers: Vec<*const c_char> = passages.iter().map(|p| p.as_ptr()).collect();
+        let reranker = unsafe { arrow_rerank_create(model.as_ptr(), tokenizer.as_ptr()) };
+        assert!(!reranker.is_null());
+        let mut scores = [0.0f32; 2];
+
+        let code = unsafe {
+            arrow_rerank_score(reranker, query.as_ptr(), pointers.as_ptr(), 2, scores.as_mut_ptr())
+        };
+        let none = unsafe {
+            arrow_rerank_score(reranker, query.as_ptr(), ptr::null(), 0, ptr::null_mut())
+        };
+
+        assert_eq!((code, none), (ERROR_OK, ERROR_OK));
+        assert!(scores[1] > scores[0]);
+        unsafe { arrow_rerank_destroy(reranker) };
+    }
+
+    #[test]
+    fn ffi_index_survives_save_and_load() {
+        let index = arrow_index_create(2);
+        let vector = [0.6f32, 0.8];
+        let path = CString::new(temp_path("ffi.idx").to_str().unwrap()).unwrap();
+
+        unsafe {
+            assert_eq!(arrow_index_add(index, 42, vector.as_ptr(), 2), ERROR_OK);
+            assert_eq!(arrow_index_save(index, path.as_ptr()), ERROR_OK);
+            arrow_index_destroy(index);
+        }
+
+        let loaded = unsafe { arrow_index_load(path.as_ptr()) };
+        assert!(!loaded.is_null());
+        let (mut id, mut score) = (0u64, 0f32);
+        let found =
+            unsafe { arrow_index_search(loaded, vector.as_ptr(), 2, 1, &mut id, &mut score) };
+        assert_eq!((found, id), (1, 42));
+        unsafe { arrow_index_destroy(loaded) };
+        std::fs::remove_file(path.to_str().unwrap()).unwrap();
+
+        assert!(unsafe { arrow_index_load(path.as_ptr()) }.is_null());
+        assert!(unsafe { arrow_embed_last_error(ptr::null_mut(), 0) } > 0);
+    }
+}
@@ -0,0 +1,320 @@
+//! Flat in-memory vector index with brute-force top-k search
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::error::EmbedError;
+use crate::similarity::cosine_similarity;
+
+/// First bytes of every index file
+const INDEX_MAGIC: &[u8; 8] = b"ARROWIDX";
+/// Format version written by save() and accepted by load()
+const INDEX_FORMAT_VERSION: u32 = 1;
+
+/// Stores embeddings by id and finds the most similar ones to a query.
+///
+/// Vectors are expected to be L2-normalized, as [`Embedder`](crate::Embedder)
+/// produces them, so scores are cosine similarities.
+#[derive(Debug, Clone)]
+pub struct VectorIndex {
+    dim: usize,
+    ids: Vec<u64>,
+    /// Row-major, `ids.len() * dim` floats
+    vectors: Vec<f32>,
+}
+
+/// Candidate ordered by score, then by insertion order for stable ties
+#[derive(Debug, PartialEq)]
+struct Scored {
+    score: f32,
+    row: usize,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| other.row.cmp(&self.row))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl VectorIndex {
+    /// Create an empty index for vectors of `dim` floats.
+    pub fn new(dim: usize) -> Self {
+        VectorIndex {
+            dim,
+            ids: Vec::new(),
+            vectors: Vec::new(),
+        }
+    }
+
+    /// Length of the vectors this index accepts
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Number of stored vectors
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Store `vector` under `id`. Ids are not deduplicated.
+    pub fn add(&mut self, id: u64, vector: &[f32]) -> Result<(), EmbedError> {
+        self.check_dim(vector)?;
+        self.ids.push(id);
+        self.vectors.extend_from_slice(vector);
+        Ok(())
+    }
+
+    /// Find the `k` stored vectors most similar to `query`, best first.
+    ///
+    /// Returns fewer than `k` results if the index holds fewer vectors.
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<(u64, f32)>, EmbedError> {
+        self.check_dim(query)?;
+        if k == 0 || self.dim == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Min-heap of the best k seen so far; its top is the one to evict
+        let mut best = BinaryHeap::with_capacity(k.min(self.len()) + 1);
+        for (row, vector) in self.vectors.chunks_exact(self.dim).enumerate() {
+            best.push(Reverse(Scored {
+                score: cosine_similarity(query, vector),
+                row,
+            }));
+            if best.len() > k {
+                best.pop();
+            }
+        }
+
+        Ok(best
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(s)| (self.ids[s.row], s.score))
+            .collect())
+    }
+
+    /// Write the index to `path`, replacing any existing file.
+    ///
+    /// Layout, all little-endian: magic "ARROWIDX", u32 version, u64 dim,
+    /// u64 count, `count` u64 ids, then `count * dim` f32 values.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), EmbedError> {
+        let file = File::create(path).map_err(io_error)?;
+        let mut out = BufWriter::new(file);
+
+        out.write_all(INDEX_MAGIC).map_err(io_error)?;
+        out.write_all(&INDEX_FORMAT_VERSION.to_le_bytes()).map_err(io_error)?;
+        out.write_all(&(self.dim as u64).to_le_bytes()).map_err(io_error)?;
+        out.write_all(&(self.ids.len() as u64).to_le_bytes()).map_err(io_error)?;
+        for id in &self.ids {
+            out.write_all(&id.to_le_bytes()).map_err(io_error)?;
+        }
+        for value in &self.vectors {
+            out.write_all(&value.to_le_bytes()).map_err(io_error)?;
+        }
+        out.flush().map_err(io_error)
+    }
+
+    /// Read an index written by [`save`](Self::save).
+    ///
+    /// Fails with [`EmbedError::UnsupportedIndexVersion`] for files from a
+    /// different format version and [`EmbedError::CorruptIndex`] for
+    /// anything that is not a complete index file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, EmbedError> {
+        let file = File::open(path).map_err(io_error)?;
+        let file_len = file.metadata().map_err(io_error)?.len();
+        let mut input = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        read_exact(&mut input, &mut magic, "magic")?;
+        if &magic != INDEX_MAGIC {
+            return Err(EmbedError::CorruptIndex("not an arrow_embed index file".to_string()));
+        }
+        let version = u32::from_le_bytes(read_array(&mut input, "version")?);
+        if version != INDEX_FORMAT_VERSION {
+            return Err(EmbedError::UnsupportedIndexVersion {
+                found: version,
+                supported: INDEX_FORMAT_VERSION,
+            });
+        }
+        let dim = u64::from_le_bytes(read_array(&mut input, "dimension")?);
+        let count = u64::from_le_bytes(read_array(&mut input, "count")?);
+
+        // Check the size up front so a corrupt count can't trigger a huge allocation
+        let header_len = 8 + 4 + 8 + 8;
+        let expected_len = dim
+            .checked_mul(4)
+            .and_then(|floats| floats.checked_add(8))
+            .and_then(|row| row.checked_mul(count))
+            .and_then(|body| body.checked_add(header_len));
+        if expected_len != Some(file_len) {
+            return Err(EmbedError::CorruptIndex(format!(
+                "header describes {} vectors of {} floats but the file is {} bytes",
+                count, dim, file_len
+            )));
+        }
+        let (dim, count) = (dim as usize, count as usize);
+
+        let mut ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            ids.push(u64::from_le_bytes(read_array(&mut input, "ids")?));
+        }
+        let mut vectors = Vec::with_capacity(count * dim);
+        for _ in 0..count * dim {
+            vectors.push(f32::from_le_bytes(read_array(&mut input, "vectors")?));
+        }
+
+        Ok(VectorIndex { dim, ids, vectors })
+    }
+
+    /// Row-major storage of every vector, in insertion order
+    pub(crate) fn raw_vectors(&self) -> &[f32] {
+        &self.vectors
+    }
+
+    fn check_dim(&self, vector: &[f32]) -> Result<(), EmbedError> {
+        if vector.len() != self.dim {
+            return Err(EmbedError::DimensionMismatch {
+                expected: self.dim,
+                actual: vector.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn io_error(e: std::io::Error) -> EmbedError {
+    EmbedError::Io(e.to_string())
+}
+
+pub(crate) fn read_exact(
+    input: &mut impl Read,
+    buf: &mut [u8],
+    what: &str,
+) -> Result<(), EmbedError> {
+    input.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => {
+            EmbedError::CorruptIndex(format!("file ends inside the {}", what))
+        }
+        _ => io_error(e),
+    })
+}
+
+pub(crate) fn read_array<const N: usize>(
+    input: &mut impl Read,
+    what: &str,
+) -> Result<[u8; N], EmbedError> {
+    let mut buf = [0u8; N];
+    read_exact(input, &mut buf, what)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ERROR_CORRUPT_INDEX, ERROR_DIMENSION_MISMATCH, ERROR_INDEX_VERSION};
+    use crate::test_util::*;
+
+    fn unit(v: &[f32]) -> Vec<f32> {
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        v.iter().map(|x| x / norm).collect()
+    }
+
+    #[test]
+    fn search_returns_best_matches_first() {
+        let mut index = VectorIndex::new(2);
+        index.add(10, &unit(&[1.0, 0.0])).unwrap();
+        index.add(20, &unit(&[0.0, 1.0])).unwrap();
+        index.add(30, &unit(&[1.0, 1.0])).unwrap();
+
+        let results = index.search(&unit(&[1.0, 0.2]), 2).unwrap();
+
+        let ids: Vec<u64> = results.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, vec![10, 30]);
+        assert!(results[0].1 > results[1].1);
+    }
+
+    #[test]
+    fn k_larger_than_index_returns_everything() {
+        let mut index = VectorIndex::new(2);
+        index.add(1, &[1.0, 0.0]).unwrap();
+        index.add(2, &[0.0, 1.0]).unwrap();
+
+        assert_eq!(index.search(&[1.0, 0.0], 10).unwrap().len(), 2);
+        assert!(index.search(&[1.0, 0.0], 0).unwrap().is_empty());
+        assert!(VectorIndex::new(2).search(&[1.0, 0.0], 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn wrong_dimension_is_rejected() {
+        let mut index = VectorIndex::new(3);
+
+        let err = index.add(1, &[1.0, 0.0]).unwrap_err();
+        assert!(matches!(err, EmbedError::DimensionMismatch { expected: 3, actual: 2 }));
+        assert_eq!(err.code(), ERROR_DIMENSION_MISMATCH);
+        assert!(index.search(&[1.0; 4], 1).is_err());
+        assert!(index.is_empty());
+    }
+
+    #[test]
+    fn save_and_load_round_trip_preserves_search() {
+        let vectors = random_unit_vectors(3000, 32, 7);
+        let mut index = VectorIndex::new(32);
+        for (id, vector) in vectors.iter().enumerate() {
+            index.add(id as u64 * 3, vector).unwrap();
+        }
+        let path = temp_path("round_trip.idx");
+
+        index.save(&path).unwrap();
+        let loaded = VectorIndex::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.dim(), loaded.len()), (32, 3000));
+        for query in random_unit_vectors(20, 32, 99) {
+            assert_eq!(index.search(&query, 10).unwrap(), loaded.search(&query, 10).unwrap());
+        }
+    }
+
+    #[test]
+    fn load_distinguishes_version_from_corruption() {
+        let mut index = VectorIndex::new(4);
+        index.add(1, &[0.5; 4]).unwrap();
+        let path = temp_path("versioned.idx");
+        index.save(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut future = bytes.clone();
+        future[8..12].copy_from_slice(&2u32.to_le_bytes());
+        let path = write_temp_file("future.idx", &future);
+        let err = VectorIndex::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, EmbedError::UnsupportedIndexVersion { found: 2, supported: 1 }));
+        assert_eq!(err.code(), ERROR_INDEX_VERSION);
+
+        let path = write_temp_file("truncated.idx", &bytes[..bytes.len() - 3]);
+        let err = VectorIndex::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.code(), ERROR_CORRUPT_INDEX);
+
+        let path = write_temp_file("not_index.idx", "definitely not an index");
+        let err = VectorIndex::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.code(), ERROR_CORRUPT_INDEX);
+    }
+}
@@ -7,12 +7,15 @@ use ndarray::{Array1, Array2, ArrayD, IxDyn};
 use std::path::Path;
 use tokenizers::Tokenizer;
 
+mod check_command;
+mod embed_command;
+#[cfg(feature = "serve")]
+mod serve_command;
+
 /// Initialize ONNX Runtime and load model from path
 fn load_model<P: AsRef<Path>>(model_path: P) -> Result<Session> {
-    // Initialize ORT (only needs to be done once globally, returns bool)
-    let _ = ort::init()
-        .with_name("arrow_embeddings")
-        .commit();
+    // Shares the library's one-time setup, so embedders loaded later reuse it
+    arrow_embed::init_environment("arrow_embed", None);
 
     // Build session from file
     Session::builder()
@@ -128,6 +131,17 @@ fn normalize_l2(embeddings: &Array2<f32>) -> Array2<f32> {
 
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("embed") => return embed_command::run(&args[1..]),
+        Some("check") => return check_command::run(&args[1..]),
+        #[cfg(feature = "serve")]
+        Some("serve") => return serve_command::run(&args[1..]),
+        #[cfg(not(feature = "serve"))]
+        Some("serve") => anyhow::bail!("serve requires building with --features serve"),
+        _ => {}
+    }
+
     // Load tokenizer
     let tokenizer = Tokenizer::from_pretrained("bert-base-cased", None)
         .map_err(|e| anyhow!("Failed to load tokenizer: {}", e))?;
@@ -0,0 +1,218 @@
+//! Finding the model, tokenizer and settings in a sentence-transformers
+//! export directory
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::embedder::{EmbedderOptions, PoolingStrategy};
+use crate::error::EmbedError;
+
+/// Where the model may sit in an export directory, most specific first
+const MODEL_FILES: [&str; 2] = ["onnx/model.onnx", "model.onnx"];
+/// Pooling module directory of exports without a modules.json
+const DEFAULT_POOLING_DIR: &str = "1_Pooling";
+
+/// Files and settings read from an export directory laid out like
+///
+/// ```text
+/// model.onnx or onnx/model.onnx
+/// tokenizer.json
+/// sentence_bert_config.json   {"max_seq_length": 256, ...}
+/// modules.json                lists the pooling module's directory
+/// 1_Pooling/config.json       {"pooling_mode_mean_tokens": true, ...}
+/// ```
+#[derive(Debug, Clone)]
+pub(crate) struct ModelDir {
+    pub(crate) model: String,
+    pub(crate) tokenizer: String,
+    pooling: Option<PoolingStrategy>,
+    max_seq_len: Option<usize>,
+}
+
+impl ModelDir {
+    /// Find the model and tokenizer in `dir` and read its settings.
+    ///
+    /// The config files are optional; settings they don't give keep their
+    /// defaults.
+    pub(crate) fn locate(dir: &Path) -> Result<Self, EmbedError> {
+        let model = MODEL_FILES.iter().map(|file| dir.join(file)).find(|path| path.is_file());
+        let Some(model) = model else {
+            return Err(EmbedError::ModelLoad(format!(
+                "no {} in {}",
+                MODEL_FILES.join(" or "),
+                dir.display()
+            )));
+        };
+        let tokenizer = dir.join("tokenizer.json");
+        if !tokenizer.is_file() {
+            return Err(EmbedError::TokenizerNotFound(tokenizer.display().to_string()));
+        }
+
+        // sentence_bert_config.json is where sentence-transformers keeps
+        // it; some exports only have it in config.json
+        let mut max_seq_len = None;
+        for file in ["sentence_bert_config.json", "config.json"] {
+            let config = read_json(&dir.join(file))?;
+            if let Some(len) = config.and_then(|c| c.get("max_seq_length")?.as_u64()) {
+                max_seq_len = Some(len as usize);
+                break;
+            }
+        }
+        let pooling = match read_json(&dir.join(pooling_dir(dir)?).join("config.json"))? {
+            Some(config) => Some(pooling_mode(&config)?),
+            None => None,
+        };
+
+        Ok(ModelDir {
+            model: path_str(model)?,
+            tokenizer: path_str(tokenizer)?,
+            pooling,
+            max_seq_len,
+        })
+    }
+
+    /// Replace the pooling and sequence length in `options` with the ones
+    /// the directory gives
+    pub(crate) fn apply(&self, options: &mut EmbedderOptions) {
+        if let Some(pooling) = self.pooling {
+            options.pooling = pooling;
+        }
+        if let Some(max_seq_len) = self.max_seq_len {
+            options.max_seq_len = max_seq_len;
+        }
+    }
+}
+
+/// Directory of the Pooling module named in modules.json, or the usual one
+fn pooling_dir(dir: &Path) -> Result<PathBuf, EmbedError> {
+    let Some(modules) = read_json(&dir.join("modules.json"))? else {
+        return Ok(PathBuf::from(DEFAULT_POOLING_DIR));
+    };
+    let pooling = modules.as_array().into_iter().flatten().find(|module| {
+        let kind = module.get("type").and_then(Value::as_str);
+        kind.is_some_and(|kind| kind.ends_with(".Pooling"))
+    });
+    let path = pooling.and_then(|module| module.get("path")).and_then(Value::as_str);
+    Ok(PathBuf::from(path.unwrap_or(DEFAULT_POOLING_DIR)))
+}
+
+/// The strategy a Pooling module's config.json turns on
+fn pooling_mode(config: &Value) -> Result<PoolingStrategy, EmbedError> {
+    let enabled = |key| config.get(key).and_then(Value::as_bool).unwrap_or(false);
+    if enabled("pooling_mode_mean_tokens") {
+        Ok(PoolingStrategy::Mean)
+    } else if enabled("pooling_mode_cls_token") {
+        Ok(PoolingStrategy::Cls)
+    } else if enabled("pooling_mode_max_tokens") {
+        Ok(PoolingStrategy::Max)
+    } else {
+        Err(EmbedError::ModelLoad(format!(
+            "pooling config enables no supported mode (mean, cls or max): {}",
+            config
+        )))
+    }
+}
+
+/// Parse `path` as JSON, or `None` if there is no such file
+fn read_json(path: &Path) -> Result<Option<Value>, EmbedError> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|e| EmbedError::Io(format!("reading {}: {}", path.display(), e)))?;
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| EmbedError::ModelLoad(format!("parsing {}: {}", path.display(), e)))
+}
+
+fn path_str(path: PathBuf) -> Result<String, EmbedError> {
+    path.into_os_string()
+        .into_string()
+        .map_err(|path| EmbedError::InvalidInput(format!("path is not UTF-8: {:?}", path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ERROR_MODEL_LOAD, ERROR_TOKENIZER_NOT_FOUND};
+    use crate::test_util::*;
+
+    /// Fresh directory holding `files`, each a relative path and contents
+    fn export_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = temp_path(name);
+        let _ = fs::remove_dir_all(&dir);
+        for (file, contents) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn sentence_transformers_layout_sets_pooling_and_length() {
+        let dir = export_dir(
+            "st_export",
+            &[
+                ("model.onnx", ""),
+                ("onnx/model.onnx", ""),
+                ("tokenizer.json", "{}"),
+                ("sentence_bert_config.json", r#"{"max_seq_length": 128}"#),
+                ("config.json", r#"{"max_seq_length": 512, "hidden_size": 768}"#),
+                (
+                    "modules.json",
+                    r#"[{"path": "pool", "type": "sentence_transformers.models.Pooling"}]"#,
+                ),
+                ("pool/config.json", r#"{"pooling_mode_cls_token": true}"#),
+            ],
+        );
+
+        let found = ModelDir::locate(&dir).unwrap();
+        let mut options = EmbedderOptions::default();
+        found.apply(&mut options);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(Path::new(&found.model), dir.join("onnx/model.onnx"));
+        assert_eq!(Path::new(&found.tokenizer), dir.join("tokenizer.json"));
+        assert_eq!(options.pooling, PoolingStrategy::Cls);
+        assert_eq!(options.max_seq_len, 128);
+    }
+
+    #[test]
+    fn missing_configs_keep_defaults() {
+        let dir = export_dir("bare_export", &[("model.onnx", ""), ("tokenizer.json", "{}")]);
+
+        let found = ModelDir::locate(&dir).unwrap();
+        let mut options = EmbedderOptions::default();
+        found.apply(&mut options);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(Path::new(&found.model), dir.join("model.onnx"));
+        assert_eq!(options.pooling, EmbedderOptions::default().pooling);
+        assert_eq!(options.max_seq_len, EmbedderOptions::default().max_seq_len);
+    }
+
+    #[test]
+    fn missing_files_and_unsupported_pooling_are_rejected() {
+        let no_model = export_dir("no_model_export", &[("tokenizer.json", "{}")]);
+        let no_tokenizer = export_dir("no_tokenizer_export", &[("model.onnx", "")]);
+        let weighted = export_dir(
+            "weighted_export",
+            &[
+                ("model.onnx", ""),
+                ("tokenizer.json", "{}"),
+                ("1_Pooling/config.json", r#"{"pooling_mode_weightedmean_tokens": true}"#),
+            ],
+        );
+
+        let results = [&no_model, &no_tokenizer, &weighted].map(|dir| ModelDir::locate(dir));
+        for dir in [no_model, no_tokenizer, weighted] {
+            fs::remove_dir_all(dir).unwrap();
+        }
+
+        let codes = results.map(|result| result.unwrap_err().code());
+        assert_eq!(codes, [ERROR_MODEL_LOAD, ERROR_TOKENIZER_NOT_FOUND, ERROR_MODEL_LOAD]);
+    }
+}
@@ -0,0 +1,78 @@
+//! Write embeddings as NumPy `.npy` files for `np.load`
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::error::EmbedError;
+use crate::index::io_error;
+
+/// First bytes of every .npy file, followed by format version 1.0
+const NPY_MAGIC: &[u8; 6] = b"\x93NUMPY";
+/// NumPy pads the header so the data starts on this boundary
+const NPY_ALIGNMENT: usize = 64;
+
+/// Write `values` to `path` as a 1-D little-endian float32 array, dtype
+/// `<f4` and shape `(len,)`, replacing any existing file.
+pub fn write_npy(path: impl AsRef<Path>, values: &[f32]) -> Result<(), EmbedError> {
+    let file = File::create(path).map_err(io_error)?;
+    let mut out = BufWriter::new(file);
+
+    out.write_all(&npy_header(values.len())).map_err(io_error)?;
+    for value in values {
+        out.write_all(&value.to_le_bytes()).map_err(io_error)?;
+    }
+    out.flush().map_err(io_error)
+}
+
+/// Magic, version, header length and the space-padded dict describing a
+/// float32 vector of `len` values
+fn npy_header(len: usize) -> Vec<u8> {
+    let dict = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({},), }}", len);
+    // Magic (6), version (2) and header length (2) come before the dict,
+    // which ends in a newline
+    let unpadded = NPY_MAGIC.len() + 4 + dict.len() + 1;
+    let padding = unpadded.next_multiple_of(NPY_ALIGNMENT) - unpadded;
+    let header_len = dict.len() + padding + 1;
+
+    let mut header = Vec::with_capacity(unpadded + padding);
+    header.extend_from_slice(NPY_MAGIC);
+    header.extend_from_slice(&[1, 0]);
+    header.extend_from_slice(&(header_len as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header.resize(header.len() + padding, b' ');
+    header.push(b'\n');
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn header_describes_a_float32_vector() {
+        let path = temp_path("embedding.npy");
+        let values = [0.25f32, -1.5, 3.0];
+
+        write_npy(&path, &values).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&bytes[..6], NPY_MAGIC);
+        assert_eq!(bytes[6..8], [1, 0]);
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let data_start = 10 + header_len;
+        assert_eq!(data_start % NPY_ALIGNMENT, 0);
+        let dict = std::str::from_utf8(&bytes[10..data_start]).unwrap();
+        assert!(dict.contains("'descr': '<f4'"));
+        assert!(dict.contains("'fortran_order': False"));
+        assert!(dict.contains("'shape': (3,)"));
+        assert!(dict.ends_with('\n'));
+        let data: Vec<f32> = bytes[data_start..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(data, values);
+    }
+}
@@ -0,0 +1,295 @@
+//! Pool of embedders over one model, for embedding on many threads at once
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use std::thread;
+use std::time::Duration;
+
+use crate::cache::CacheStats;
+use crate::embedder::{Embedder, EmbedderOptions};
+use crate::error::EmbedError;
+
+/// Several [`Embedder`]s loaded from the same model, each with its own ONNX
+/// Runtime session, so concurrent callers run inference in parallel instead
+/// of queuing on a single lock.
+///
+/// Calls are handed out round-robin, skipping embedders that are busy. Each
+/// session holds its own copy of the model weights, so memory grows with
+/// the pool size.
+pub struct SessionPool {
+    pub(crate) embedders: Vec<Mutex<Embedder>>,
+    next: AtomicUsize,
+    dim: usize,
+}
+
+impl SessionPool {
+    /// Load `size` embedders with the same options.
+    pub fn new(
+        model_path: &str,
+        tokenizer_source: &str,
+        options: EmbedderOptions,
+        size: usize,
+    ) -> Result<Self, EmbedError> {
+        if size == 0 {
+            return Err(EmbedError::InvalidInput("pool size must be at least 1".to_string()));
+        }
+        let embedders = (0..size)
+            .map(|_| Embedder::with_options(model_path, tokenizer_source, options.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_embedders(embedders)
+    }
+
+    /// Pool already loaded embedders, which must all produce vectors of the
+    /// same length.
+    pub fn from_embedders(embedders: Vec<Embedder>) -> Result<Self, EmbedError> {
+        let Some(dim) = embedders.first().map(Embedder::dim) else {
+            return Err(EmbedError::InvalidInput("pool size must be at least 1".to_string()));
+        };
+        if let Some(other) = embedders.iter().find(|e| e.dim() != dim) {
+            return Err(EmbedError::DimensionMismatch {
+                expected: dim,
+                actual: other.dim(),
+            });
+        }
+        Ok(SessionPool {
+            embedders: embedders.into_iter().map(Mutex::new).collect(),
+            next: AtomicUsize::new(0),
+            dim,
+        })
+    }
+
+    /// Number of embedders in the pool
+    pub fn size(&self) -> usize {
+        self.embedders.len()
+    }
+
+    /// Length of the vectors every embedder in the pool produces
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Cache hits, misses and sizes summed over every embedder in the pool
+    pub fn stats(&self) -> CacheStats {
+        self.embedders.iter().fold(CacheStats::default(), |total, embedder| {
+            let stats = lock_recovering(embedder).stats();
+            CacheStats {
+                hits: total.hits + stats.hits,
+                misses: total.misses + stats.misses,
+                entries: total.entries + stats.entries,
+                capacity: total.capacity + stats.capacity,
+            }
+        })
+    }
+
+    /// Warm up every embedder in the pool, one after another, as
+    /// [`Embedder::warmup`] does; returns the total time taken
+    pub fn warmup(&self, iterations: usize) -> Result<Duration, EmbedError> {
+        self.embedders
+            .iter()
+            .try_fold(Duration::ZERO, |total, embedder| {
+                Ok(total + lock_recovering(embedder).warmup(iterations)?)
+            })
+    }
+
+    /// Lock the next idle embedder, waiting for one only if all are busy.
+    ///
+    /// An embedder whose last call panicked is handed out again rather than
+    /// left poisoned: every call refills its input buffers from scratch, so
+    /// nothing from the interrupted call carries over.
+    pub fn lock(&self) -> MutexGuard<'_, Embedder> {
+        let size = self.embedders.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % size;
+        for i in 0..size {
+            let embedder = &self.embedders[(start + i) % size];
+            match embedder.try_lock() {
+                Ok(guard) => return guard,
+                Err(TryLockError::Poisoned(_)) => return lock_recovering(embedder),
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+        lock_recovering(&self.embedders[start])
+    }
+
+    /// Embed `text` with the next idle embedder.
+    pub fn embed(&self, text: &str) -> Result<Vec<f32>, EmbedError> {
+        self.lock().embed(text)
+    }
+
+    /// Embed several texts in one inference pass on the next idle embedder.
+    pub fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        self.lock().embed_batch(texts)
+    }
+
+    /// Embed `texts` split into one shard per embedder, each shard running
+    /// as a batch on its own thread. Results are in input order.
+    ///
+    /// Unlike [`embed_batch`](Self::embed_batch), one bad text does not fail
+    /// the rest: a shard that fails is retried a text at a time, so only the
+    /// texts that cannot be embedded get an error.
+    pub fn embed_batch_parallel(&self, texts: &[&str]) -> Vec<Result<Vec<f32>, EmbedError>> {
+        if texts.is_empty() {
+            return Vec::new();
+        }
+        let shard_len = texts.len().div_ceil(self.size());
+
+        thread::scope(|scope| {
+            let shards: Vec<_> = texts
+                .chunks(shard_len)
+                .map(|shard| (shard.len(), scope.spawn(move || self.embed_shard(shard))))
+                .collect();
+            shards
+                .into_iter()
+                .flat_map(|(len, shard)| {
+                    shard.join().unwrap_or_else(|_| {
+                        let panicked = || EmbedError::Inference("embedding panicked".to_string());
+                        (0..len).map(|_| Err(panicked())).collect()
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn embed_shard(&self, shard: &[&str]) -> Vec<Result<Vec<f32>, EmbedError>> {
+        let mut embedder = self.lock();
+        match embedder.embed_batch(shard) {
+            Ok(embeddings) => embeddings.into_iter().map(Ok).collect(),
+            Err(_) => shard.iter().map(|text| embedder.embed(text)).collect(),
+        }
+    }
+}
+
+/// Lock `mutex`, clearing the poison left by a panic in an earlier holder.
+///
+/// Only for data that a panic cannot leave half-updated.
+pub(crate) fn lock_recovering<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Read-lock `lock`, clearing the poison left by a panic in a writer, as
+/// [`lock_recovering`] does
+pub(crate) fn read_recovering<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| {
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Write-lock `lock`, clearing the poison left by a panic in a writer, as
+/// [`lock_recovering`] does
+pub(crate) fn write_recovering<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|poisoned| {
+        lock.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ERROR_INVALID_INPUT;
+    use crate::test_util::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn empty_pool_is_rejected() {
+        let err = SessionPool::new(TEST_MODEL, TEST_TOKENIZER, EmbedderOptions::default(), 0);
+        assert_eq!(err.err().map(|e| e.code()), Some(ERROR_INVALID_INPUT));
+        let err = SessionPool::from_embedders(Vec::new());
+        assert_eq!(err.err().map(|e| e.code()), Some(ERROR_INVALID_INPUT));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn busy_embedders_are_skipped() {
+        let pool = SessionPool::from_embedders(vec![test_embedder(), test_embedder()]).unwrap();
+
+        let first = pool.lock();
+        let second = pool.lock();
+
+        assert!(!std::ptr::eq(&*first, &*second));
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn parallel_batch_keeps_order_and_isolates_failures() {
+        let pool = SessionPool::new(TEST_MODEL, TEST_TOKENIZER, EmbedderOptions::default(), 3)
+            .unwrap();
+        let mut single = test_embedder();
+        let mut texts: Vec<String> = (0..10).map(|i| format!("document number {i}")).collect();
+        texts[4] = "   ".to_string();
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+
+        let results = pool.embed_batch_parallel(&texts);
+
+        assert_eq!(results.len(), texts.len());
+        for (text, result) in texts.iter().zip(results) {
+            match result {
+                Ok(embedding) => {
+                    let expected = single.embed(text).unwrap();
+                    for (a, b) in embedding.iter().zip(&expected) {
+                        assert!((a - b).abs() < 1e-4, "{text}");
+                    }
+                }
+                Err(e) => {
+                    assert_eq!(*text, "   ");
+                    assert!(matches!(e, EmbedError::EmptyInput));
+                }
+            }
+        }
+        assert!(pool.embed_batch_parallel(&[]).is_empty());
+    }
+
+    #[test]
+    fn poisoned_lock_is_recovered() {
+        let mutex = Mutex::new(vec![1]);
+        let _ = thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    let _guard = mutex.lock().unwrap();
+                    panic!("panic while holding the lock");
+                })
+                .join()
+        });
+        assert!(mutex.is_poisoned());
+
+        lock_recovering(&mutex).push(2);
+
+        assert!(!mutex.is_poisoned());
+        assert_eq!(*mutex.lock().unwrap(), vec![1, 2]);
+    }
+
+    /// Embed `per_thread` texts on each of 8 threads sharing `pool`
+    fn embed_on_8_threads(pool: &SessionPool, per_thread: usize) -> Duration {
+        let start = Instant::now();
+        thread::scope(|scope| {
+            for t in 0..8 {
+                scope.spawn(move || {
+                    for i in 0..per_thread {
+                        pool.embed(&format!("thread {t} document {i}")).unwrap();
+                    }
+                });
+            }
+        });
+        start.elapsed()
+    }
+
+    #[test]
+    #[ignore = "requires models/all-MiniLM-L6-v2.onnx"]
+    fn pool_scales_across_threads() {
+        // One intra-op thread per session, so the pool is the only parallelism
+        let options = || EmbedderOptions {
+            intra_threads: 1,
+            ..Default::default()
+        };
+        let single = SessionPool::new(TEST_MODEL, TEST_TOKENIZER, options(), 1).unwrap();
+        let pooled = SessionPool::new(TEST_MODEL, TEST_TOKENIZER, options(), 8).unwrap();
+
+        let serialized = embed_on_8_threads(&single, 50);
+        let parallel = embed_on_8_threads(&pooled, 50);
+
+        assert!(parallel < serialized, "one session {serialized:?}, pool {parallel:?}");
+    }
+}
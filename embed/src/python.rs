@@ -0,0 +1,68 @@
+//! Python bindings (`python` feature): `import arrow_embed`
+//!
+//! Build the module with maturin, or copy the cdylib to `arrow_embed.so`:
+//!
+//! ```python
+//! from arrow_embed import PyEmbedder
+//!
+//! embedder = PyEmbedder("models/all-MiniLM-L6-v2.onnx", "sentence-transformers/all-MiniLM-L6-v2")
+//! vector = embedder.embed("hello world")
+//! ```
+
+use std::sync::Mutex;
+
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+
+use crate::embedder::Embedder;
+use crate::error::EmbedError;
+use crate::pool::lock_recovering;
+
+/// Text embedder over an ONNX model and its tokenizer.
+///
+/// Embedding releases the GIL, so other Python threads keep running while
+/// the model does; calls on one embedder still run one at a time.
+#[pyclass(name = "PyEmbedder", module = "arrow_embed")]
+struct PyEmbedder {
+    embedder: Mutex<Embedder>,
+}
+
+#[pymethods]
+impl PyEmbedder {
+    /// Load a model; `tokenizer_name` is a HuggingFace tokenizer name or a
+    /// path to a local tokenizer.json.
+    #[new]
+    fn new(py: Python<'_>, model_path: &str, tokenizer_name: &str) -> PyResult<Self> {
+        let embedder = py.allow_threads(|| Embedder::new(model_path, tokenizer_name));
+        Ok(PyEmbedder {
+            embedder: Mutex::new(embedder.map_err(runtime_error)?),
+        })
+    }
+
+    /// Length of the vectors this embedder produces
+    #[getter]
+    fn dim(&self) -> usize {
+        lock_recovering(&self.embedder).dim()
+    }
+
+    /// Embed a single text into a list of floats.
+    fn embed(&self, py: Python<'_>, text: &str) -> PyResult<Vec<f32>> {
+        py.allow_threads(|| lock_recovering(&self.embedder).embed(text)).map_err(runtime_error)
+    }
+
+    /// Embed several texts in one inference pass, one list per text.
+    fn embed_batch(&self, py: Python<'_>, texts: Vec<String>) -> PyResult<Vec<Vec<f32>>> {
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        py.allow_threads(|| lock_recovering(&self.embedder).embed_batch(&texts))
+            .map_err(runtime_error)
+    }
+}
+
+fn runtime_error(err: EmbedError) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+#[pymodule]
+fn arrow_embed(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyEmbedder>()
+}
@@ -0,0 +1,115 @@
+//! Scalar quantization of embeddings for compact storage
+
+/// Largest magnitude a quantized component takes
+const INT8_LEVELS: f32 = 127.0;
+
+/// Quantize `v` to int8, returning the components and the scale needed to
+/// restore them with [`dequantize_int8`].
+///
+/// The scale is the largest absolute component, which maps to ±127; the
+/// rest are rounded to the nearest step. A zero vector has scale 0.
+pub fn quantize_int8(v: &[f32]) -> (Vec<i8>, f32) {
+    let scale = v.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+    if scale == 0.0 {
+        return (vec![0; v.len()], 0.0);
+    }
+    let quantized = v
+        .iter()
+        .map(|x| (x / scale * INT8_LEVELS).round().clamp(-INT8_LEVELS, INT8_LEVELS) as i8)
+        .collect();
+    (quantized, scale)
+}
+
+/// Restore an embedding quantized by [`quantize_int8`], to within half a
+/// step (`scale / 254`) per component.
+pub fn dequantize_int8(quantized: &[i8], scale: f32) -> Vec<f32> {
+    quantized.iter().map(|&q| q as f32 * scale / INT8_LEVELS).collect()
+}
+
+/// Dot product of two embeddings in their quantized form, an estimate of
+/// the dot product of the originals, without dequantizing either.
+///
+/// Components are multiplied and summed as integers, then the sum is
+/// scaled once. Returns NaN if the lengths differ.
+pub fn dot_int8(a: &[i8], a_scale: f32, b: &[i8], b_scale: f32) -> f32 {
+    if a.len() != b.len() {
+        return f32::NAN;
+    }
+    let sum: i32 = a.iter().zip(b).map(|(&x, &y)| x as i32 * y as i32).sum();
+    sum as f32 * (a_scale / INT8_LEVELS) * (b_scale / INT8_LEVELS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EMBEDDING_DIM;
+    use crate::similarity::{cosine_similarity, dot_product};
+    use crate::test_util::*;
+
+    #[test]
+    fn largest_component_maps_to_the_end_of_the_range() {
+        let (quantized, scale) = quantize_int8(&[0.5, -2.0, 1.0, 0.0]);
+
+        assert_eq!(scale, 2.0);
+        assert_eq!(quantized, vec![32, -127, 64, 0]);
+        assert_eq!(quantize_int8(&[0.0; 3]), (vec![0; 3], 0.0));
+        assert_eq!(dequantize_int8(&[0; 3], 0.0), vec![0.0; 3]);
+    }
+
+    #[test]
+    fn round_trip_keeps_embeddings_close() {
+        for vector in random_unit_vectors(100, EMBEDDING_DIM, 9) {
+            let (quantized, scale) = quantize_int8(&vector);
+            let restored = dequantize_int8(&quantized, scale);
+
+            let similarity = cosine_similarity(&vector, &restored) / l2_norm(&restored);
+            assert!(similarity > 0.99, "{}", similarity);
+            for (a, b) in vector.iter().zip(&restored) {
+                assert!((a - b).abs() <= scale / 254.0 + 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn quantized_dot_matches_the_dequantized_one() {
+        let vectors = random_unit_vectors(2, EMBEDDING_DIM, 4);
+        let (a, a_scale) = quantize_int8(&vectors[0]);
+        let (b, b_scale) = quantize_int8(&vectors[1]);
+
+        let quantized = dot_int8(&a, a_scale, &b, b_scale);
+
+        let restored = dot_product(&dequantize_int8(&a, a_scale), &dequantize_int8(&b, b_scale));
+        assert!((quantized - restored).abs() < 1e-5);
+        assert!((quantized - dot_product(&vectors[0], &vectors[1])).abs() < 0.01);
+        assert!(dot_int8(&a, a_scale, &b[1..], b_scale).is_nan());
+    }
+
+    #[test]
+    fn quantized_search_mostly_agrees_with_f32_search() {
+        let documents = random_unit_vectors(1000, EMBEDDING_DIM, 21);
+        let quantized: Vec<_> = documents.iter().map(|d| quantize_int8(d)).collect();
+        let top_10 = |scores: Vec<f32>| {
+            let mut ranked: Vec<usize> = (0..scores.len()).collect();
+            ranked.sort_by(|&i, &j| scores[j].total_cmp(&scores[i]));
+            ranked.truncate(10);
+            ranked
+        };
+
+        let mut agreeing = 0;
+        let queries = random_unit_vectors(20, EMBEDDING_DIM, 22);
+        for query in &queries {
+            let (q, q_scale) = quantize_int8(query);
+            let exact = top_10(documents.iter().map(|d| dot_product(query, d)).collect());
+            let scores = quantized.iter().map(|(d, scale)| dot_int8(&q, q_scale, d, *scale));
+            let approximate = top_10(scores.collect());
+            agreeing += exact.iter().filter(|i| approximate.contains(i)).count();
+        }
+
+        // Out of 200; random vectors score close together, the hardest case
+        assert!(agreeing >= 160, "{}", agreeing);
+    }
+
+    fn l2_norm(v: &[f32]) -> f32 {
+        v.iter().map(|x| x * x).sum::<f32>().sqrt()
+    }
+}
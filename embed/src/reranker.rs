@@ -0,0 +1,146 @@
+//! Cross-encoder reranking of retrieved passages against a query
+
+use std::collections::HashMap;
+
+use ort::session::Session;
+use tokenizers::{EncodeInput, Tokenizer};
+
+use crate::embedder::{
+    EmbedderOptions, InputBuffers, ModelInputs, configure_truncation, is_token_matrix,
+    load_tokenizer, session_inputs,
+};
+use crate::environment::{DEFAULT_ENVIRONMENT_NAME, init_environment};
+use crate::error::EmbedError;
+
+/// Query/passage pairs scored per inference pass
+const PAIRS_PER_PASS: usize = 32;
+
+/// Scores passages against a query with a cross-encoder such as
+/// ms-marco-MiniLM, which reads each (query, passage) pair as one sequence
+/// and outputs a single relevance logit.
+///
+/// Slower than comparing embeddings but more accurate, so it is typically
+/// run over the top results of a vector search.
+///
+/// ```no_run
+/// use arrow_embed::Reranker;
+///
+/// let mut reranker = Reranker::new(
+///     "models/ms-marco-MiniLM-L-6-v2.onnx",
+///     "cross-encoder/ms-marco-MiniLM-L-6-v2",
+/// )?;
+/// let scores = reranker.score("what is arrow", &["Arrow is a format", "a bow and arrow"])?;
+/// assert_eq!(scores.len(), 2);
+/// # Ok::<(), arrow_embed::EmbedError>(())
+/// ```
+pub struct Reranker {
+    session: Session,
+    tokenizer: Tokenizer,
+    inputs: ModelInputs,
+    buffers: InputBuffers,
+}
+
+impl Reranker {
+    /// Load a cross-encoder model.
+    ///
+    /// `tokenizer_source` is either a HuggingFace tokenizer name or a path
+    /// to a local tokenizer.json. Pairs longer than the default max_seq_len
+    /// are truncated, taking tokens from the longer of query and passage.
+    pub fn new(model_path: &str, tokenizer_source: &str) -> Result<Self, EmbedError> {
+        init_environment(DEFAULT_ENVIRONMENT_NAME, None);
+        let session = Session::builder()
+            .map_err(|e| EmbedError::RuntimeInit(format!("creating session builder: {}", e)))?
+            .commit_from_file(model_path)
+            .map_err(|e| EmbedError::ModelLoad(e.to_string()))?;
+        let inputs = session.inputs().iter().map(|i| (i.name(), is_token_matrix(i.dtype())));
+        let inputs = ModelInputs::classify(inputs, &HashMap::new())?;
+        if session.outputs().is_empty() {
+            return Err(EmbedError::ModelLoad("model declares no outputs".to_string()));
+        }
+
+        let options = EmbedderOptions::default();
+        let mut tokenizer = load_tokenizer(tokenizer_source)?;
+        configure_truncation(&mut tokenizer, &options)?;
+
+        Ok(Reranker {
+            session,
+            tokenizer,
+            inputs,
+            buffers: InputBuffers::with_capacity(options.max_seq_len),
+        })
+    }
+
+    /// Relevance of each passage to `query`, in passage order; higher is
+    /// more relevant.
+    ///
+    /// Scores are the model's raw logits, comparable across the passages of
+    /// one query. No passages gives no scores.
+    pub fn score(&mut self, query: &str, passages: &[&str]) -> Result<Vec<f32>, EmbedError> {
+        if query.trim().is_empty() {
+            return Err(EmbedError::EmptyInput);
+        }
+        let mut scores = Vec::with_capacity(passages.len());
+        for batch in passages.chunks(PAIRS_PER_PASS) {
+            scores.extend(self.score_batch(query, batch)?);
+        }
+        Ok(scores)
+    }
+
+    /// Score one inference pass worth of passages
+    fn score_batch(&mut self, query: &str, passages: &[&str]) -> Result<Vec<f32>, EmbedError> {
+        // Pairs are encoded as [CLS] query [SEP] passage [SEP], with the
+        // passage's token_type_ids set to 1
+        let pairs: Vec<EncodeInput> = passages.iter().map(|&p| (query, p).into()).collect();
+        let encodings = self
+            .tokenizer
+            .encode_batch(pairs, true)
+            .map_err(|e| EmbedError::Tokenization(e.to_string()))?;
+        let seq_len = encodings.iter().map(|e| e.len()).max().unwrap_or(0);
+        self.buffers.fill(&encodings, seq_len);
+
+        let shape = [passages.len(), seq_len];
+        let session_inputs = session_inputs(&self.inputs, &self.buffers, shape)?;
+        let outputs = self
+            .session
+            .run(session_inputs)
+            .map_err(|e| EmbedError::Inference(e.to_string()))?;
+        let (shape, logits) = outputs[0]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| EmbedError::ShapeMismatch(format!("extracting output tensor: {}", e)))?;
+        match shape[..] {
+            [batch] | [batch, 1] if batch as usize == passages.len() => Ok(logits.to_vec()),
+            _ => Err(EmbedError::ShapeMismatch(format!(
+                "expected one logit per pair, [{}, 1], got {:?}",
+                passages.len(),
+                shape
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    #[ignore = "requires models/ms-marco-MiniLM-L-6-v2.onnx"]
+    fn relevant_passages_score_higher_in_input_order() {
+        let mut reranker = Reranker::new(TEST_RERANKER, TEST_RERANKER_TOKENIZER).unwrap();
+        let query = "how many people live in berlin";
+        let relevant = "Berlin has a population of about 3.7 million people.";
+        let unrelated = "The recipe calls for two cups of flour.";
+        let mut passages = vec![unrelated; 40];
+        passages[33] = relevant;
+
+        let scores = reranker.score(query, &passages).unwrap();
+
+        // Spans two inference passes, yet every score lines up with its passage
+        assert_eq!(scores.len(), passages.len());
+        let best = scores.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0;
+        assert_eq!(best, 33);
+        let alone = reranker.score(query, &[unrelated]).unwrap();
+        assert!((scores[0] - alone[0]).abs() < 1e-4);
+        assert!(reranker.score(query, &[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+//! Checking a loaded model against reference similarities, to catch an ONNX
+//! Runtime or tokenizer change that silently moves embeddings
+
+use std::fmt;
+
+use crate::error::EmbedError;
+
+/// Most a similarity may move from its reference, or a batched embedding
+/// from the same text embedded alone, for [`SelfTestReport::passed`]
+pub const SELF_TEST_TOLERANCE: f32 = 1e-3;
+
+/// Pairs of reference texts with the cosine similarity all-MiniLM-L6-v2
+/// gives them, written by tools/genembeddings/reference_vectors.py
+const REFERENCE_SIMILARITIES: &str = include_str!("self_test_similarities.tsv");
+
+/// Two reference texts and the cosine similarity expected between them
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ReferencePair<'a> {
+    pub(crate) a: &'a str,
+    pub(crate) b: &'a str,
+    pub(crate) cosine: f32,
+}
+
+/// Reference pairs in `tsv`, one `text_a<TAB>text_b<TAB>cosine` per line,
+/// skipping blank lines and `#` comments
+pub(crate) fn parse_pairs(tsv: &str) -> Result<Vec<ReferencePair<'_>>, EmbedError> {
+    let lines = tsv.lines().enumerate();
+    let lines = lines.filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+    lines
+        .map(|(i, line)| {
+            let invalid = || {
+                EmbedError::InvalidInput(format!("reference line {}: {}", i + 1, line))
+            };
+            let mut fields = line.split('\t');
+            let (Some(a), Some(b), Some(cosine), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let cosine = cosine.trim().parse().map_err(|_| invalid())?;
+            Ok(ReferencePair { a, b, cosine })
+        })
+        .collect()
+}
+
+/// The pairs bundled with the library
+pub(crate) fn reference_pairs() -> Vec<ReferencePair<'static>> {
+    parse_pairs(REFERENCE_SIMILARITIES).expect("bundled reference similarities parse")
+}
+
+/// Result of [`Embedder::self_test`](crate::Embedder::self_test)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestReport {
+    /// Reference pairs compared; 0 if none are bundled
+    pub pairs: usize,
+    /// Largest difference between a pair's cosine similarity and its
+    /// reference
+    pub max_deviation: f32,
+    /// The texts of the pair that deviated most
+    pub worst_pair: Option<(String, String)>,
+    /// Largest difference in any dimension between a text embedded in a
+    /// batch and on its own
+    pub batch_deviation: f32,
+    /// Bound both deviations are held to, [`SELF_TEST_TOLERANCE`]
+    pub tolerance: f32,
+}
+
+impl SelfTestReport {
+    /// Whether there were pairs to compare and both deviations are within
+    /// the tolerance. Models other than all-MiniLM-L6-v2 are not expected
+    /// to pass
+    pub fn passed(&self) -> bool {
+        self.pairs > 0
+            && self.max_deviation <= self.tolerance
+            && self.batch_deviation <= self.tolerance
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.pairs == 0 {
+            writeln!(f, "reference pairs: none bundled")?;
+        } else {
+            writeln!(f, "reference pairs: {}", self.pairs)?;
+            writeln!(f, "max similarity deviation: {:.6}", self.max_deviation)?;
+            if let Some((a, b)) = &self.worst_pair {
+                writeln!(f, "worst pair: {:?} / {:?}", a, b)?;
+            }
+        }
+        writeln!(f, "batch vs single deviation: {:.6}", self.batch_deviation)?;
+        writeln!(f, "tolerance: {}", self.tolerance)?;
+        write!(f, "result: {}", if self.passed() { "pass" } else { "FAIL" })
+    }
+}
+
+/// Compare the similarities of `pairs` under `embedding`, which gives each
+/// reference text's vector, with their references
+pub(crate) fn compare<'v>(
+    pairs: &[ReferencePair<'_>],
+    embedding: impl Fn(&str) -> &'v [f32],
+    batch_deviation: f32,
+) -> SelfTestReport {
+    let mut report = SelfTestReport {
+        pairs: pairs.len(),
+        max_deviation: 0.0,
+        worst_pair: None,
+        batch_deviation,
+        tolerance: SELF_TEST_TOLERANCE,
+    };
+    for pair in pairs {
+        let deviation = (cosine(embedding(pair.a), embedding(pair.b)) - pair.cosine).abs();
+        // NaN from a zero vector counts as the worst
+        let deviation = if deviation.is_nan() { f32::INFINITY } else { deviation };
+        if report.worst_pair.is_none() || deviation > report.max_deviation {
+            report.max_deviation = deviation;
+            report.worst_pair = Some((pair.a.to_string(), pair.b.to_string()));
+        }
+    }
+    report
+}
+
+/// Cosine similarity of two vectors of any norm
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot = |x: &[f32], y: &[f32]| x.iter().zip(y).map(|(x, y)| x * y).sum::<f32>();
+    dot(a, b) / (dot(a, a).sqrt() * dot(b, b).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_references_parse() {
+        let pairs = reference_pairs();
+        assert!(pairs.iter().all(|pair| (-1.0..=1.0).contains(&pair.cosine)));
+    }
+
+    #[test]
+    #[ignore = "requires pairs written by tools/genembeddings/reference_vectors.py"]
+    fn bundled_references_are_present() {
+        assert!(!reference_pairs().is_empty());
+    }
+
+    #[test]
+    fn pairs_parse_skipping_comments_and_rejecting_bad_lines() {
+        let tsv = "# header\n\nred shoes\tblue shoes\t0.75\ncats\tdogs\t-0.25\n";
+
+        let pairs = parse_pairs(tsv).unwrap();
+
+        assert_eq!(pairs[0], ReferencePair { a: "red shoes", b: "blue shoes", cosine: 0.75 });
+        assert_eq!(pairs[1].cosine, -0.25);
+        for bad in ["one\ttwo", "one\ttwo\tthree", "one\ttwo\t0.5\tfour"] {
+            assert!(parse_pairs(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn report_measures_the_worst_pair() {
+        let vectors = [("x", [1.0, 0.0]), ("y", [0.0, 2.0]), ("xy", [1.0, 1.0])];
+        let embedding = |text: &str| &vectors.iter().find(|(t, _)| *t == text).unwrap().1[..];
+        let pairs = [
+            ReferencePair { a: "x", b: "y", cosine: 0.0005 },
+            ReferencePair { a: "x", b: "xy", cosine: std::f32::consts::FRAC_1_SQRT_2 },
+        ];
+
+        let report = compare(&pairs, embedding, 0.0);
+
+        assert!((report.max_deviation - 0.0005).abs() < 1e-6);
+        assert_eq!(report.worst_pair, Some(("x".to_string(), "y".to_string())));
+        assert!(report.passed());
+        assert!(report.to_string().ends_with("result: pass"));
+
+        let drifted = compare(&pairs[..1], embedding, 0.01);
+        assert!(!drifted.passed());
+        let nothing = compare(&[], embedding, 0.0);
+        assert!(!nothing.passed());
+        assert!(nothing.to_string().starts_with("reference pairs: none bundled"));
+    }
+}
@@ -0,0 +1,247 @@
+//! `arrow serve` subcommand: OpenAI-compatible embeddings over HTTP
+//!
+//! arrow serve [--model models/all-MiniLM-L6-v2.onnx] [--tokenizer NAME_OR_PATH]
+//!             [--bind 127.0.0.1:8080] [--max-batch 64]
+//!
+//! `POST /v1/embeddings` takes `{"input": "text"}` or `{"input": ["a", "b"]}`
+//! and answers in the OpenAI embeddings format. Requests with more than
+//! `--max-batch` texts get a 413 and malformed ones a 400, with an
+//! OpenAI-style `{"error": {"message": ...}}` body. Requires the `serve`
+//! feature.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{Context, Result, anyhow, bail};
+use arrow_embed::{EmbedError, Embedder};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+struct Server {
+    embedder: Mutex<Embedder>,
+    /// Reported as `model` in every response
+    model: String,
+    max_batch: usize,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingRequest {
+    input: Input,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Input {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Failed request, answered with an OpenAI-style JSON error body
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<EmbedError> for ApiError {
+    fn from(e: EmbedError) -> Self {
+        let status = match e {
+            EmbedError::EmptyInput
+            | EmbedError::InputTooLong { .. }
+            | EmbedError::TooManyUnknownTokens { .. }
+            | EmbedError::InvalidInput(_)
+            | EmbedError::Tokenization(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let kind = if self.status.is_server_error() {
+            "server_error"
+        } else {
+            "invalid_request_error"
+        };
+        let body = json!({ "error": { "message": self.message, "type": kind } });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut model = "models/all-MiniLM-L6-v2.onnx".to_string();
+    let mut tokenizer = "sentence-transformers/all-MiniLM-L6-v2".to_string();
+    let mut bind = "127.0.0.1:8080".to_string();
+    let mut max_batch = 64;
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .cloned()
+            .ok_or_else(|| anyhow!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--model" => model = value,
+            "--tokenizer" => tokenizer = value,
+            "--bind" => bind = value,
+            "--max-batch" => {
+                max_batch = value
+                    .parse()
+                    .with_context(|| format!("--max-batch must be a number, got {}", value))?
+            }
+            other => bail!("unknown option {}", other),
+        }
+    }
+    if max_batch == 0 {
+        bail!("--max-batch must be at least 1");
+    }
+
+    let embedder = Embedder::new(&model, &tokenizer)?;
+    let server = Arc::new(Server {
+        embedder: Mutex::new(embedder),
+        model: model_name(&model),
+        max_batch,
+    });
+    let app = Router::new()
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(server);
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(&bind)
+            .await
+            .with_context(|| format!("binding {}", bind))?;
+        eprintln!("Serving {} on http://{}/v1/embeddings", model, listener.local_addr()?);
+        axum::serve(listener, app).await?;
+        Ok(())
+    })
+}
+
+/// Name clients see for the model: its file name without the extension
+fn model_name(model_path: &str) -> String {
+    Path::new(model_path)
+        .file_stem()
+        .map_or_else(|| model_path.to_string(), |stem| stem.to_string_lossy().into_owned())
+}
+
+async fn embeddings(
+    State(server): State<Arc<Server>>,
+    body: Bytes,
+) -> Result<Json<Value>, ApiError> {
+    let texts = parse_request(&body, server.max_batch)?;
+
+    // Inference blocks, so keep it off the runtime's worker threads
+    let worker = Arc::clone(&server);
+    let (embeddings, tokens) = tokio::task::spawn_blocking(move || worker.embed(&texts))
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+
+    Ok(Json(response_body(&server.model, embeddings, tokens)))
+}
+
+impl Server {
+    /// Embed `texts` in one pass, also counting the tokens they use
+    fn embed(&self, texts: &[String]) -> Result<(Vec<Vec<f32>>, usize), EmbedError> {
+        // A panic in an earlier request leaves nothing half-updated to skip
+        let mut embedder = self.embedder.lock().unwrap_or_else(PoisonError::into_inner);
+        let tokens = texts
+            .iter()
+            .map(|text| embedder.count_tokens(text))
+            .sum::<Result<usize, _>>()?;
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        Ok((embedder.embed_batch(&texts)?, tokens))
+    }
+}
+
+/// Read the texts to embed from a request body
+fn parse_request(body: &[u8], max_batch: usize) -> Result<Vec<String>, ApiError> {
+    let request: EmbeddingRequest = serde_json::from_slice(body).map_err(|e| {
+        let message = format!("request body must be {{\"input\": string or [string]}}: {}", e);
+        ApiError::new(StatusCode::BAD_REQUEST, message)
+    })?;
+    let texts = match request.input {
+        Input::One(text) => vec![text],
+        Input::Many(texts) => texts,
+    };
+
+    if texts.is_empty() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "input must not be empty"));
+    }
+    if texts.len() > max_batch {
+        let message = format!(
+            "{} inputs exceed the maximum batch size of {}",
+            texts.len(),
+            max_batch
+        );
+        return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, message));
+    }
+    Ok(texts)
+}
+
+/// OpenAI embeddings response for `embeddings`, in input order
+fn response_body(model: &str, embeddings: Vec<Vec<f32>>, tokens: usize) -> Value {
+    let data: Vec<Value> = embeddings
+        .into_iter()
+        .enumerate()
+        .map(|(index, embedding)| {
+            json!({ "object": "embedding", "index": index, "embedding": embedding })
+        })
+        .collect();
+    json!({
+        "object": "list",
+        "data": data,
+        "model": model,
+        "usage": { "prompt_tokens": tokens, "total_tokens": tokens },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_may_be_one_string_or_an_array() {
+        assert_eq!(parse_request(br#"{"input": "hello"}"#, 4).unwrap(), vec!["hello"]);
+        let texts = parse_request(br#"{"model": "x", "input": ["a", "b"]}"#, 4).unwrap();
+        assert_eq!(texts, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn bad_requests_are_rejected_with_their_status() {
+        let status = |body: &[u8]| parse_request(body, 2).unwrap_err().status;
+
+        assert_eq!(status(b"{\"input\": "), StatusCode::BAD_REQUEST);
+        assert_eq!(status(br#"{"input": 42}"#), StatusCode::BAD_REQUEST);
+        assert_eq!(status(br#"{"text": "hello"}"#), StatusCode::BAD_REQUEST);
+        assert_eq!(status(br#"{"input": []}"#), StatusCode::BAD_REQUEST);
+        assert_eq!(status(br#"{"input": ["a", "b", "c"]}"#), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn response_follows_the_openai_format() {
+        let body = response_body("all-MiniLM-L6-v2", vec![vec![1.0, 0.0], vec![0.0, 1.0]], 7);
+
+        assert_eq!(body["object"], "list");
+        assert_eq!(body["model"], "all-MiniLM-L6-v2");
+        assert_eq!(body["data"][1]["index"], 1);
+        assert_eq!(body["data"][1]["embedding"], json!([0.0, 1.0]));
+        assert_eq!(body["usage"]["total_tokens"], 7);
+        assert_eq!(model_name("models/all-MiniLM-L6-v2.onnx"), "all-MiniLM-L6-v2");
+    }
+}
@@ -0,0 +1,109 @@
+//! Similarity between embedding vectors
+
+use ndarray::{Array2, ArrayView2};
+
+/// Cosine similarity of two L2-normalized embeddings.
+///
+/// Embeddings from [`Embedder`](crate::Embedder) are normalized by default,
+/// so this is their dot product. Returns NaN if the lengths differ.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    dot_product(a, b)
+}
+
+/// Dot product of two embeddings. Returns NaN if the lengths differ.
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::NAN;
+    }
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Euclidean distance between two embeddings. Returns NaN if the lengths
+/// differ.
+///
+/// For L2-normalized embeddings this ranks the same as cosine similarity,
+/// since `l2_distance² = 2 - 2 * cosine`.
+pub fn l2_distance(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return f32::NAN;
+    }
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// Row-major `n * n` matrix of pairwise dot products of `n` equal-length
+/// embeddings, computed as `E · Eᵀ`
+pub(crate) fn similarity_matrix(embeddings: &[Vec<f32>]) -> Vec<f32> {
+    let n = embeddings.len();
+    let dim = embeddings.first().map_or(0, Vec::len);
+    let flat: Vec<f32> = embeddings.iter().flatten().copied().collect();
+    let e = ArrayView2::from_shape((n, dim), &flat).expect("embeddings have equal lengths");
+    let scores: Array2<f32> = e.dot(&e.t());
+    scores.into_raw_vec_and_offset().0
+}
+
+/// Scale `v` to unit length in place; zero vectors are left unchanged
+pub(crate) fn normalize_in_place(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 1e-12 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_vectors_score_one() {
+        let mut v = vec![0.3, -1.2, 2.0, 0.5];
+        normalize_in_place(&mut v);
+
+        assert!((cosine_similarity(&v, &v) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn orthogonal_vectors_score_zero() {
+        let a = [1.0, 0.0, 0.0];
+        let b = [0.0, 1.0, 0.0];
+
+        assert_eq!(cosine_similarity(&a, &b), 0.0);
+    }
+
+    #[test]
+    fn matrix_matches_pairwise_similarity() {
+        let mut vectors = vec![vec![1.0, 0.0, 0.0], vec![0.6, 0.8, 0.0], vec![0.0, 0.0, 2.0]];
+        vectors.iter_mut().for_each(|v| normalize_in_place(v));
+
+        let matrix = similarity_matrix(&vectors);
+
+        assert_eq!(matrix.len(), 9);
+        for (i, a) in vectors.iter().enumerate() {
+            for (j, b) in vectors.iter().enumerate() {
+                assert!((matrix[i * 3 + j] - cosine_similarity(a, b)).abs() < 1e-6);
+            }
+        }
+        assert!(similarity_matrix(&[]).is_empty());
+    }
+
+    #[test]
+    fn l2_distance_agrees_with_cosine_on_normalized_vectors() {
+        let mut a = vec![0.3, -1.2, 2.0, 0.5];
+        let mut b = vec![-0.7, 0.4, 1.1, 1.9];
+        normalize_in_place(&mut a);
+        normalize_in_place(&mut b);
+
+        let distance = l2_distance(&a, &b);
+
+        assert!((distance * distance - (2.0 - 2.0 * cosine_similarity(&a, &b))).abs() < 1e-5);
+        assert_eq!(l2_distance(&a, &a), 0.0);
+        assert_eq!(l2_distance(&[0.0, 0.0], &[3.0, 4.0]), 5.0);
+        assert_eq!(dot_product(&[1.0, 2.0], &[3.0, -4.0]), -5.0);
+    }
+
+    #[test]
+    fn mismatched_lengths_are_nan() {
+        assert!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]).is_nan());
+        assert!(dot_product(&[1.0], &[]).is_nan());
+        assert!(l2_distance(&[1.0], &[]).is_nan());
+    }
+}
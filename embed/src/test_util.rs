@@ -0,0 +1,78 @@
+//! Fixtures shared by the unit tests
+
+use tokenizers::Tokenizer;
+
+use crate::embedder::Embedder;
+
+pub const TEST_MODEL: &str = "models/all-MiniLM-L6-v2.onnx";
+pub const TEST_TOKENIZER: &str = "sentence-transformers/all-MiniLM-L6-v2";
+/// DistilBERT export that declares only input_ids and attention_mask
+pub const TWO_INPUT_TEST_MODEL: &str = "models/msmarco-distilbert-base-v4.onnx";
+pub const TWO_INPUT_TEST_TOKENIZER: &str = "sentence-transformers/msmarco-distilbert-base-v4";
+/// Cross-encoder that scores (query, passage) pairs with one logit
+pub const TEST_RERANKER: &str = "models/ms-marco-MiniLM-L-6-v2.onnx";
+pub const TEST_RERANKER_TOKENIZER: &str = "cross-encoder/ms-marco-MiniLM-L-6-v2";
+
+/// Minimal word-level tokenizer that loads without network access
+pub const TINY_TOKENIZER_JSON: &str = r#"{
+    "version": "1.0",
+    "truncation": null,
+    "padding": null,
+    "added_tokens": [],
+    "normalizer": null,
+    "pre_tokenizer": { "type": "Whitespace" },
+    "post_processor": null,
+    "decoder": null,
+    "model": {
+        "type": "WordLevel",
+        "vocab": { "[UNK]": 0, "hello": 1, "world": 2 },
+        "unk_token": "[UNK]"
+    }
+}"#;
+
+/// TINY_TOKENIZER_JSON with BERT-style [CLS]/[SEP] post-processing
+pub fn bert_style_tokenizer() -> Tokenizer {
+    let json = TINY_TOKENIZER_JSON
+        .replace(
+            r#""post_processor": null"#,
+            r#""post_processor": {
+                "type": "BertProcessing",
+                "sep": ["[SEP]", 4],
+                "cls": ["[CLS]", 3]
+            }"#,
+        )
+        .replace(r#""world": 2"#, r#""world": 2, "[CLS]": 3, "[SEP]": 4"#);
+    json.parse().unwrap()
+}
+
+pub fn write_temp_file(name: &str, contents: impl AsRef<[u8]>) -> std::path::PathBuf {
+    let path = temp_path(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Per-process path in the temp dir, so parallel test runs don't collide
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    let file_name = format!("arrow_embed_{}_{}", std::process::id(), name);
+    std::env::temp_dir().join(file_name)
+}
+
+/// Deterministic pseudo-random unit vectors (no rand dependency)
+pub fn random_unit_vectors(count: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((state >> 40) as f32 / (1u64 << 24) as f32) - 0.5
+    };
+    (0..count)
+        .map(|_| {
+            let v: Vec<f32> = (0..dim).map(|_| next()).collect();
+            let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            v.into_iter().map(|x| x / norm).collect()
+        })
+        .collect()
+}
+
+pub fn test_embedder() -> Embedder {
+    Embedder::new(TEST_MODEL, TEST_TOKENIZER).expect("failed to load test embedder")
+}
@@ -18,11 +18,12 @@ namespace arrow::cli {
 /// @param textPath Path to text file (one line per vector)
 /// @param idsPath Path to IDs file (unused currently)
 /// @param outputPath Path to save the collection
+/// @param dims Floats per embedding, i.e. the embedding model's dimension
 inline void ingest(const std::string& embeddingsPath = "embeddings.bin",
                    const std::string& textPath = "wikitext.txt",
                    const std::string& idsPath = "",
-                   const std::string& outputPath = "wiki_collection") {
-  const size_t dims = 384;
+                   const std::string& outputPath = "wiki_collection",
+                   size_t dims = 384) {
   const size_t batchSize = 10000;  // Insert 10K vectors at a time
 
   std::cout << "Starting ingestion from " << embeddingsPath << " and " << textPath
@@ -54,7 +55,7 @@ inline void ingest(const std::string& embeddingsPath = "embeddings.bin",
 
   std::string textLine;
   while (std::getline(textFile, textLine)) {
-    // Read embedding (384 float32 values)
+    // Read embedding (dims float32 values)
     std::vector<float> embedding(dims);
     embeddingsFile.read(reinterpret_cast<char*>(embedding.data()),
                        dims * sizeof(float));
@@ -6,6 +6,7 @@
 //   ./arrowDB search <query_text> [-c <collection>] [-t <text_file>] [-m <model.onnx>]
 //   ./arrowDB query -f <query_file> [-c <collection>] [-t <text_file>]
 //   ./arrowDB ingest -e <embeddings_file> -i <ids_file> -t <text_file> [-o <output>]
+//                    [-d <dims>]
 
 #include "args.h"
 #include <arrow/arrow.h>>
@@ -25,7 +26,7 @@ void printUsage() {
   std::cerr << "  ./arrowDB query -f <query_file> [-c <collection>] "
                "[-t <text_file>]\n";
   std::cerr << "  ./arrowDB ingest -e <embeddings_file> -i <ids_file> "
-               "-t <text_file> [-o <output>]\n";
+               "-t <text_file> [-o <output>] [-d <dims>]\n";
 }
 
 } // namespace
@@ -57,15 +58,16 @@ int main(int argc, char* argv[]) {
     std::string idsFile = args.get("i");
     std::string textFile = args.get("t");
     std::string outputPath = args.get("o", "collection_output");
+    size_t dims = std::stoul(args.get("d", "384"));
 
     if (embeddingsFile.empty() || textFile.empty()) {
       std::cerr << "Error: ingest command requires -e and -t flags\n";
       std::cerr << "Usage: ./arrowDB ingest -e <embeddings_file> "
-                   "-t <text_file> [-o <output_path>]\n";
+                   "-t <text_file> [-o <output_path>] [-d <dims>]\n";
       return 1;
     }
 
-    arrow::cli::ingest(embeddingsFile, textFile, idsFile, outputPath);
+    arrow::cli::ingest(embeddingsFile, textFile, idsFile, outputPath, dims);
 
   } else if (args.command == "search") {
     // Collect all remaining arguments as the query text
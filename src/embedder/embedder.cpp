@@ -7,13 +7,20 @@ int32_t arrow_embed_init(const char *model_path, const char *tokenizer_name);
 EmbeddingResult arrow_embed_text(const char *text);
 void arrow_embed_free(EmbeddingResult result);
 size_t arrow_embed_dimension();
+const char *arrow_embed_last_error_message();
+}
+
+static const char *lastError() {
+  const char *msg = arrow_embed_last_error_message();
+  return msg ? msg : "unknown error";
 }
 
 Embedder::Embedder(const std::string_view &modelPath,
                    const std::string_view &tokenizerName) {
   int32_t res = arrow_embed_init(modelPath.data(), tokenizerName.data());
   if (res != 0) {
-    std::cerr << "Error: Failed to initialize embedder (code: " << res << ")\n";
+    std::cerr << "Error: Failed to initialize embedder (code: " << res
+              << "): " << lastError() << "\n";
     ok_ = false;
     return;
   }
@@ -25,13 +32,14 @@ std::vector<float> Embedder::embed(const char* text) {
 
   if (res.error_code != 0) {
     std::cerr << "Error: Failed to embed query text (code: "
-              << res.error_code << ")\n";
+              << res.error_code << "): " << lastError() << "\n";
     return {};
   }
 
-  if (res.len != EMBEDDING_DIM) {
+  size_t expected = arrow_embed_dimension();
+  if (res.len != expected) {
     std::cerr << "Error: Embedding dimension mismatch. Expected "
-              <<  EMBEDDING_DIM << ", got " << res.len << "\n";
+              <<  expected << ", got " << res.len << "\n";
     arrow_embed_free(res);
     return {};
   }
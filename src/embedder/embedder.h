@@ -8,6 +8,8 @@
 
 class Embedder {
 public:
+    // tokenizerName is a HuggingFace Hub name or a path to a local
+    // tokenizer.json; a local file is used without touching the network.
     explicit Embedder(
     const std::string_view &modelPath = "models/all-MiniLM-L6-v2.onnx",
     const std::string_view &tokenizerName = "sentence-transformers/all-MiniLM-L6-v2"
@@ -0,0 +1,52 @@
+# Writes reference embeddings from sentence-transformers for the Rust
+# regression test in embed/src/embedder.rs (embeddings_match_sentence_transformers),
+# and the pairwise similarities bundled for Embedder::self_test.
+from itertools import combinations
+from pathlib import Path
+
+from sentence_transformers import SentenceTransformer
+
+TEXTS = [
+    "This is an example sentence.",
+    "The quick brown fox jumps over the lazy dog.",
+    "Vector databases index embeddings for similarity search.",
+]
+
+SELF_TEST_TEXTS = [
+    "A man is playing a guitar.",
+    "Someone is strumming a guitar on stage.",
+    "The stock market fell sharply today.",
+    "Shares dropped after the earnings report.",
+    "A cat sleeps on the windowsill.",
+    "How do I reset my password?",
+]
+
+EMBED = Path(__file__).resolve().parents[2] / "embed"
+OUT = EMBED / "tests" / "fixtures" / "reference_embeddings.tsv"
+SIMILARITIES = EMBED / "src" / "self_test_similarities.tsv"
+
+model = SentenceTransformer("all-MiniLM-L6-v2", device="cpu")
+embeddings = model.encode(TEXTS, convert_to_numpy=True, normalize_embeddings=True)
+
+OUT.parent.mkdir(parents=True, exist_ok=True)
+with open(OUT, "w") as f:
+    for text, emb in zip(TEXTS, embeddings):
+        f.write(text + "\t" + " ".join(f"{v:.8f}" for v in emb) + "\n")
+
+print("Wrote", len(TEXTS), "reference embeddings to", OUT)
+
+vectors = dict(zip(SELF_TEST_TEXTS, model.encode(SELF_TEST_TEXTS, normalize_embeddings=True)))
+pairs = list(combinations(SELF_TEST_TEXTS, 2))
+with open(SIMILARITIES, "w") as f:
+    f.write(
+        "# Reference cosine similarities for Embedder::self_test, from\n"
+        "# sentence-transformers' all-MiniLM-L6-v2 on CPU.\n"
+        "#\n"
+        "# Regenerate with tools/genembeddings/reference_vectors.py, which rewrites\n"
+        "# this file: one `text_a<TAB>text_b<TAB>cosine` line per pair of reference\n"
+        "# texts. Lines starting with # are ignored.\n"
+    )
+    for a, b in pairs:
+        f.write(f"{a}\t{b}\t{float(vectors[a] @ vectors[b]):.6f}\n")
+
+print("Wrote", len(pairs), "reference similarities to", SIMILARITIES)